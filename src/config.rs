@@ -2,19 +2,32 @@ use anyhow::Context;
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::default::Default;
-use std::{str, env};
-use crate::database::models::punishment::PunishmentType;
+use std::{str, env, str::FromStr, collections::HashMap};
+use crate::database::models::punishment::{PunishmentType, PunishmentKind, PunishmentConflictPolicy};
+use crate::database::models::{rank_threshold::RankThreshold, milestone::Milestone, participant::MatchParticipationOptions};
+use crate::database::models::achievement::AchievementRarity;
 use crate::util::webhook::WebhookUtils;
+use crate::util::compression::CompressionAlgorithm;
+use crate::util::weapon::WeaponNames;
+use crate::util::tracking::StatTracking;
+use crate::database::write_concern::WriteConcerns;
+use crate::socket::{leaderboard::{period::PeriodBoundaries, decay::DecayOptions}, event_time::{ClockSkewOptions, ClockSkewPolicy}, r#match::result_verification::ResultVerification, objective::objective_validation::ObjectiveValidation};
+use crate::socket::leaderboard::dynamic::{DynamicScoreDefinition, DynamicScoreType, parse_dynamic_score_types};
+use crate::util::time::{get_u64_time_millis, PlaytimeRounding, PlaytimeSecondsMigration};
+use crate::socket::player::player_xp_listener::{XP_WOOL_OBJECTIVE, XP_FLAG_OBJECTIVE, XP_FLAG_TIME_BOUNS, XP_POINT_CAPTURE_MAX, XP_DESTROYABLE_WHOLE};
+use strum_macros::Display;
 
 use super::database::models::level_color::LevelColor;
 use super::database::models::join_sound::JoinSound;
+use super::database::models::join_message::JoinMessage;
 use super::database::models::broadcast::Broadcast;
 use super::util::file::{read_file, deserialize_properties_file};
 
 #[derive(Debug)]
 pub enum ConfigDeserializeError {
     IOError(std::io::Error),
-    ParseError(ConfigParseError)
+    ParseError(ConfigParseError),
+    InvalidData(String)
 }
 
 #[derive(Debug)]
@@ -46,7 +59,8 @@ impl std::fmt::Display for ConfigDeserializeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let message = match self {
             Self::IOError(io_err) => io_err.to_string(),
-            Self::ParseError(parse_err) => parse_err.to_string()
+            Self::ParseError(parse_err) => parse_err.to_string(),
+            Self::InvalidData(message) => message.clone()
         };
         write!(f, "{}", message)
     }
@@ -68,9 +82,35 @@ impl From<ConfigParseError> for ConfigDeserializeError {
 }
 
 const TOKEN_ENV_VARIABLE : &'static str = "MARS_API_TOKEN";
+const ADMIN_TOKEN_ENV_VARIABLE : &str = "MARS_API_ADMIN_TOKEN";
+const PREVIOUS_TOKEN_ENV_VARIABLE : &str = "MARS_API_PREVIOUS_TOKEN";
+const REALM_TOKENS_ENV_VARIABLE : &str = "MARS_API_REALM_TOKENS";
+
+// `realm=token` pairs separated by commas. servers connecting with a realm's token keep their leaderboards,
+// matches and sessions apart from everyone else's, servers using the main token are in the default realm
+fn parse_realm_tokens(raw: &str) -> Result<HashMap<String, String>, ConfigDeserializeError> {
+    let mut realm_tokens = HashMap::new();
+    for pair in raw.split(',').map(|pair| pair.trim()).filter(|pair| !pair.is_empty()) {
+        let (realm, token) = match pair.split_once('=') {
+            Some((realm, token)) if !token.trim().is_empty() => (realm.trim(), token.trim()),
+            _ => return Err(ConfigDeserializeError::InvalidData(format!("{} entry '{}' is not realm=token", REALM_TOKENS_ENV_VARIABLE, pair)))
+        };
+        if realm.is_empty() || !realm.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(ConfigDeserializeError::InvalidData(format!("Realm '{}' may only use letters, digits, '-' and '_'", realm)));
+        };
+        if realm_tokens.insert(realm.to_owned(), token.to_owned()).is_some() {
+            return Err(ConfigDeserializeError::InvalidData(format!("Realm '{}' is configured twice", realm)));
+        };
+    };
+    Ok(realm_tokens)
+}
 
 pub async fn deserialize_mars_config() -> anyhow::Result<MarsConfig> {
     let token = env::var(TOKEN_ENV_VARIABLE).context(format!("Missing API environment variable {}", TOKEN_ENV_VARIABLE))?;
+    // admin operations are disabled entirely when no admin token is configured
+    let admin_token = env::var(ADMIN_TOKEN_ENV_VARIABLE).ok().filter(|token| !token.is_empty());
+    let previous_token = env::var(PREVIOUS_TOKEN_ENV_VARIABLE).ok().filter(|token| !token.is_empty());
+    let realm_tokens = parse_realm_tokens(&env::var(REALM_TOKENS_ENV_VARIABLE).unwrap_or_default())?;
     let (options, data) = tokio::try_join!(
         deserialize_mars_options(),
         deserialize_mars_data()
@@ -78,9 +118,12 @@ pub async fn deserialize_mars_config() -> anyhow::Result<MarsConfig> {
     let webhooks = WebhookUtils::new(
         &(if options.reports_webhook_url.is_empty() { None } else { Some(options.reports_webhook_url.clone()) }), 
         &(if options.punishments_webhook_url.is_empty() { None } else { Some(options.punishments_webhook_url.clone()) }), 
-        &(if options.notes_webhook_url.is_empty() { None } else { Some(options.notes_webhook_url.clone()) })
+        &(if options.notes_webhook_url.is_empty() { None } else { Some(options.notes_webhook_url.clone()) }),
+        options.avatars.clone()
     );
-    Ok(MarsConfig { token, options, data, webhooks })
+    // without an explicit rotation time the grace window starts when the API does
+    let previous_token_expires_at = options.token_rotated_at.unwrap_or(get_u64_time_millis()) + options.token_grace_period;
+    Ok(MarsConfig { token, admin_token, previous_token, previous_token_expires_at, realm_tokens, options, data, webhooks })
 }
 
 async fn deserialize_mars_options() -> Result<MarsConfigOptions, ConfigDeserializeError> {
@@ -95,9 +138,119 @@ async fn deserialize_mars_options() -> Result<MarsConfigOptions, ConfigDeseriali
             "redis-host" => { config.redis_host = Some(v.to_string()); },
             "enable-ip-hashing" => { if let Ok(b) = v.to_string().parse::<bool>() { config.enable_ip_hashing = b; } },
             "webhooks.punishments" => { config.punishments_webhook_url = v.to_string(); },
+            "punishments.reason-rules.enabled" => { if let Ok(b) = v.to_string().parse::<bool>() { config.punishment_reasons.enabled = b; } },
+            "punishments.reason-min-length" => { if let Ok(i) = v.to_string().parse::<usize>() { config.punishment_reasons.min_length = i; } },
+            "punishments.reason-blocklist" => { config.punishment_reasons.blocklist = v.split(',').map(|reason| reason.trim().to_lowercase()).filter(|reason| !reason.is_empty()).collect(); },
+            "punishments.template-required" => { config.punishment_reasons.template_required = v.split(',').filter_map(|kind| PunishmentKind::from_str(&kind.trim().to_uppercase()).ok()).collect(); },
+            "punishments.conflict-policy" => { if let Ok(policy) = PunishmentConflictPolicy::from_str(v) { config.punishment_conflict_policy = policy; } },
+            "punishments.alt-link-confidence" => { if let Ok(i) = v.to_string().parse::<f64>() { config.punishment_alt_link_confidence = i.clamp(0.0, 1.0); } },
+            "punishments.appeal-url" => { config.punishment_appeal_url = Some(v.to_string()).filter(|url| !url.is_empty()); },
             "webhooks.reports" => { config.reports_webhook_url = v.to_string(); },
             "webhooks.notes" => { config.notes_webhook_url = v.to_string(); },
             "webhooks.debug" => { config.debug_log_webhook_url = v.to_string(); },
+            "achievements.broadcast-rarity" => { if let Ok(rarity) = AchievementRarity::from_str(&v.to_uppercase()) { config.achievement_broadcast_rarity = rarity; } },
+            "records.allow-untracked" => { if let Ok(b) = v.to_string().parse::<bool>() { config.allow_untracked_records = b; } },
+            "reports.cooldown" => { if let Ok(i) = v.to_string().parse::<u64>() { config.report_cooldown = i; } },
+            "reports.rate-limit" => { if let Ok(i) = v.to_string().parse::<u64>() { config.report_rate_limit = i; } },
+            "reports.rate-limit-window" => { if let Ok(i) = v.to_string().parse::<u64>() { config.report_rate_limit_window = i; } },
+            "players.ip-limit" => { if let Ok(i) = v.to_string().parse::<usize>() { config.player_ip_limit = i.max(1); } },
+            "players.name-history-limit" => { if let Ok(i) = v.to_string().parse::<usize>() { config.name_history_limit = i.max(1); } },
+            "assists.damage-share" => { if let Ok(f) = v.to_string().parse::<f64>() { config.assist_damage_share = f; } },
+            "assists.window" => { if let Ok(i) = v.to_string().parse::<u64>() { config.assist_window = i; } },
+            "compression.enabled" => { if let Ok(b) = v.to_string().parse::<bool>() { config.compression_enabled = b; } },
+            "body-limits.default" => { if let Ok(i) = v.to_string().parse::<usize>() { config.body_limit_default = i; } },
+            "body-limits.batch" => { if let Ok(i) = v.to_string().parse::<usize>() { config.body_limit_batch = i; } },
+            "body-limits.import" => { if let Ok(i) = v.to_string().parse::<usize>() { config.body_limit_import = i; } },
+            "compression.threshold" => { if let Ok(i) = v.to_string().parse::<usize>() { config.compression_threshold = i; } },
+            "compression.algorithm" => { if let Ok(algorithm) = CompressionAlgorithm::from_str(&v.to_lowercase()) { config.compression_algorithm = algorithm; } },
+            "prestige.level-cap" => { if let Ok(i) = v.to_string().parse::<u32>() { config.prestige_level_cap = i.max(1); } },
+            "prestige.reset-xp" => { if let Ok(b) = v.to_string().parse::<bool>() { config.prestige_reset_xp = b; } },
+            "prestige.tags" => { config.prestige_tag_ids = v.split(',').map(|id| id.trim().to_owned()).filter(|id| !id.is_empty()).collect(); },
+            "cache.warm-on-startup" => { if let Ok(b) = v.to_string().parse::<bool>() { config.cache_warm_on_startup = b; } },
+            "events.clock-skew-tolerance" => { if let Ok(i) = v.to_string().parse::<u64>() { config.clock_skew.tolerance = i; } },
+            "events.clock-skew-policy" => { if let Ok(policy) = ClockSkewPolicy::from_str(v) { config.clock_skew.policy = policy; } },
+            "kill-decay.enabled" => { if let Ok(b) = v.to_string().parse::<bool>() { config.kill_decay_enabled = b; } },
+            "kill-decay.window" => { if let Ok(i) = v.to_string().parse::<u64>() { config.kill_decay_window = i; } },
+            "kill-decay.factor" => { if let Ok(f) = v.to_string().parse::<f64>() { config.kill_decay_factor = f.clamp(0.0, 1.0); } },
+            "kill-decay.min-credit" => { if let Ok(f) = v.to_string().parse::<f64>() { config.kill_decay_min_credit = f.clamp(0.0, 1.0); } },
+            "xp.notify-window" => { if let Ok(i) = v.to_string().parse::<u64>() { config.xp_notify_window = i; } },
+            "deaths.flush-interval" => { if let Ok(i) = v.to_string().parse::<u64>() { config.death_flush_interval = i; } },
+            "deaths.max-buffer" => { if let Ok(i) = v.to_string().parse::<usize>() { config.death_max_buffer = i; } },
+            "damage-events.enabled" => { if let Ok(b) = v.to_string().parse::<bool>() { config.damage_events_enabled = b; } },
+            "damage-events.max-bytes" => { if let Ok(i) = v.to_string().parse::<u64>() { config.damage_events_max_bytes = i; } },
+            "leaderboards.min-playtime" => { if let Ok(i) = v.to_string().parse::<u64>() { config.leaderboard_min_playtime = i; } },
+            "leaderboards.timezone" => { if let Ok(timezone) = chrono_tz::Tz::from_str(v.trim()) { config.leaderboard_periods.timezone = timezone; } },
+            "leaderboards.week-start" => { if let Ok(weekday) = chrono::Weekday::from_str(v.trim()) { config.leaderboard_periods.week_start = weekday; } },
+            "leaderboards.inactive-after" => { if let Ok(i) = v.to_string().parse::<u64>() { config.leaderboard_inactive_after = i; } },
+            "leaderboards.inactive-sweep-interval" => { if let Ok(i) = v.to_string().parse::<u64>() { config.leaderboard_inactive_sweep_interval = i.max(60_000); } },
+            "leaderboards.flush-interval" => { if let Ok(i) = v.to_string().parse::<u64>() { config.leaderboard_flush_interval = i; } },
+            key if key.starts_with("xp.objective.") => {
+                let reward = match v.to_string().parse::<u32>() { Ok(i) => i, Err(_) => return };
+                let rewards = &mut config.objective_xp;
+                match &key["xp.objective.".len()..] {
+                    "wool-capture" => rewards.wool_capture = reward,
+                    "wool-pickup" => rewards.wool_pickup = reward,
+                    "wool-defend" => rewards.wool_defend = reward,
+                    "flag-capture" => rewards.flag_capture = reward,
+                    "flag-capture-time-bonus" => rewards.flag_capture_time_bonus = reward,
+                    "flag-pickup" => rewards.flag_pickup = reward,
+                    "flag-defend" => rewards.flag_defend = reward,
+                    "control-point-capture" => rewards.control_point_capture = reward,
+                    "destroyable" => rewards.destroyable = reward,
+                    "core-leak" => rewards.core_leak = reward,
+                    _ => {}
+                };
+            },
+            "matches.min-participation" => { if let Ok(i) = v.to_string().parse::<u64>() { config.match_participation.min_playtime = i; } },
+            "matches.min-participation-fraction" => { if let Ok(f) = v.to_string().parse::<f64>() { config.match_participation.min_fraction = f.clamp(0.0, 1.0); } },
+            "matches.min-participation-fraction-cap" => { if let Ok(i) = v.to_string().parse::<u64>() { config.match_participation.fraction_cap = i; } },
+            key if key.starts_with("matches.result-verification.") => { config.result_verification.insert(&key["matches.result-verification.".len()..], v); },
+            key if key.starts_with("stats.tracking.") => { config.stat_tracking.insert(&key["stats.tracking.".len()..], v); },
+            key if key.starts_with("weapons.name.") => { config.weapon_names.insert(&key["weapons.name.".len()..], v); },
+            "auto-mute.enabled" => { if let Ok(b) = v.to_string().parse::<bool>() { config.auto_mute.enabled = b; } },
+            "auto-mute.window" => { if let Ok(i) = v.to_string().parse::<u64>() { config.auto_mute.window = i; } },
+            "auto-mute.length" => { if let Ok(i) = v.to_string().parse::<i64>() { config.auto_mute.length = i.max(1); } },
+            key if key.starts_with("auto-mute.threshold.") => {
+                if let Ok(i) = v.to_string().parse::<u32>() { config.auto_mute.thresholds.insert(key["auto-mute.threshold.".len()..].to_lowercase(), i.max(1)); }
+            },
+            "auto-mute.shadow" => { if let Ok(b) = v.to_string().parse::<bool>() { config.auto_mute.shadow = b; } },
+            key if key.starts_with("auto-mute.shadow.") => {
+                if let Ok(b) = v.to_string().parse::<bool>() { config.auto_mute.shadow_categories.insert(key["auto-mute.shadow.".len()..].to_lowercase(), b); }
+            },
+            "abandonment.empty-timeout" => { if let Ok(i) = v.to_string().parse::<u64>() { config.abandonment.empty_timeout = i; } },
+            "abandonment.sweep-interval" => { if let Ok(i) = v.to_string().parse::<u64>() { config.abandonment.sweep_interval = i; } },
+            "abandonment.count-partial-kills" => { if let Ok(b) = v.to_string().parse::<bool>() { config.abandonment.count_partial_kills = b; } },
+            "mongo.max-pool-size" => { if let Ok(i) = v.to_string().parse::<u32>() { config.mongo_max_pool_size = i.max(1); } },
+            key if key.starts_with("mongo.write-concern.") => { config.write_concerns.insert(&key["mongo.write-concern.".len()..], &v.to_string()); },
+            "match-end.write-concurrency" => { if let Ok(i) = v.to_string().parse::<usize>() { config.match_end_write_concurrency = Some(i.max(1)); } },
+            "playtime.rounding" => { if let Ok(rounding) = PlaytimeRounding::from_str(&v.to_string()) { config.playtime_rounding = rounding; } },
+            "playtime.seconds-migration" => { if let Ok(migration) = PlaytimeSecondsMigration::from_str(&v.to_string()) { config.playtime_seconds_migration = migration; } },
+            "confirmation.required" => { if let Ok(b) = v.to_string().parse::<bool>() { config.confirmation_required = b; } },
+            "confirmation.ttl" => { if let Ok(i) = v.to_string().parse::<u64>() { config.confirmation_ttl = i.max(1_000); } },
+            "leaderboards.reconcile-interval" => { if let Ok(i) = v.to_string().parse::<u64>() { config.leaderboard_reconcile_interval = i; } },
+            "leaderboards.reconcile-sample-size" => { if let Ok(i) = v.to_string().parse::<u32>() { config.leaderboard_reconcile_sample_size = i.max(1); } },
+            "leaderboards.decay.half-life" => { if let Ok(i) = v.to_string().parse::<u64>() { config.leaderboard_decay.half_life = i; } },
+            "leaderboards.decay.interval" => { if let Ok(i) = v.to_string().parse::<u64>() { config.leaderboard_decay.interval = i.max(60_000); } },
+            "leaderboards.decay.boards" => { config.leaderboard_decay.boards = v.split(',').map(|board| board.trim().to_uppercase()).filter(|board| !board.is_empty()).collect(); },
+            "avatars.base-url" => { config.avatars.base_url = v.trim().trim_end_matches('/').to_string(); },
+            "avatars.face-path" => { config.avatars.face_path = v.trim().to_string(); },
+            "avatars.body-path" => { config.avatars.body_path = v.trim().to_string(); },
+            "avatars.params" => { config.avatars.params = v.trim().trim_start_matches(['?', '&']).to_string(); },
+            "objectives.validation" => { if let Ok(mode) = ObjectiveValidation::from_str(v.trim()) { config.objective_validation = mode; } },
+            "leaderboards.max-pending" => { if let Ok(i) = v.to_string().parse::<usize>() { config.leaderboard_max_pending = i; } },
+            "lists.max-results" => { if let Ok(i) = v.to_string().parse::<u32>() { config.list_max_results = i.max(1); } },
+            "sessions.retention" => { if let Ok(i) = v.to_string().parse::<u64>() { config.session_rotation.retention = i; } },
+            "sessions.rotation-interval" => { if let Ok(i) = v.to_string().parse::<u64>() { config.session_rotation.interval = i; } },
+            "documents.batch-size" => { if let Ok(i) = v.to_string().parse::<usize>() { config.document_batch_size = i.max(1); } },
+            "sessions.rotation-batch-size" => { if let Ok(i) = v.to_string().parse::<i64>() { config.session_rotation.batch_size = i.max(1); } },
+            "sessions.archive" => { if let Ok(b) = v.to_string().parse::<bool>() { config.session_rotation.archive = b; } },
+            "database.strict-decoding" => { if let Ok(b) = v.to_string().parse::<bool>() { config.strict_decoding = b; } },
+            "staff-presence.required" => { if let Ok(i) = v.to_string().parse::<usize>() { config.staff_presence_required = i; } },
+            "evasion.name-distance" => { if let Ok(i) = v.to_string().parse::<usize>() { config.evasion_name_distance = i; } },
+            "sessions.resume-grace" => { if let Ok(i) = v.to_string().parse::<u64>() { config.session_resume_grace = i; } },
+            "sessions.stale-after" => { if let Ok(i) = v.to_string().parse::<u64>() { config.session_stale_after = i; } },
+            "token.rotated-at" => { if let Ok(i) = v.to_string().parse::<u64>() { config.token_rotated_at = Some(i); } },
+            "token.grace-period" => { if let Ok(i) = v.to_string().parse::<u64>() { config.token_grace_period = i; } },
             _ => {}
         }
     });
@@ -109,29 +262,65 @@ async fn deserialize_mars_data() -> Result<MarsConfigData, ConfigDeserializeErro
     let join_sounds_path = env::var("MARS_JOIN_SOUNDS_PATH").unwrap_or("./join_sounds.yml".to_string());
     let broadcasts_path = env::var("MARS_BROADCASTS_PATH").unwrap_or("./broadcasts.yml".to_string());
     let pun_types_path = env::var("MARS_PUNTYPES_PATH").unwrap_or("./punishment_types.yml".to_string());
+    let join_messages_path = env::var("MARS_JOIN_MESSAGES_PATH").unwrap_or("./join_messages.yml".to_string());
+    let dynamic_leaderboards_path = env::var("MARS_DYNAMIC_LEADERBOARDS_PATH").unwrap_or("./dynamic_leaderboards.yml".to_string());
+    let rank_thresholds_path = env::var("MARS_RANK_THRESHOLDS_PATH").unwrap_or("./rank_thresholds.yml".to_string());
+    let milestones_path = env::var("MARS_MILESTONES_PATH").unwrap_or("./milestones.yml".to_string());
 
     let (
         level_colors, 
         join_sounds, 
         broadcasts, 
-        punishment_types
+        punishment_types,
+        join_messages,
+        dynamic_leaderboards,
+        rank_thresholds,
+        milestones
     ) = match tokio::try_join!(
         deserialize_mars_data_component::<Vec<LevelColor>>(&level_colors_path),
         deserialize_mars_data_component::<Vec<JoinSound>>(&join_sounds_path),
         deserialize_mars_data_component::<Vec<Broadcast>>(&broadcasts_path),
-        deserialize_mars_data_component::<Vec<PunishmentType>>(&pun_types_path)
+        deserialize_mars_data_component::<Vec<PunishmentType>>(&pun_types_path),
+        deserialize_optional_mars_data_component::<Vec<JoinMessage>>(&join_messages_path),
+        deserialize_optional_mars_data_component::<Vec<DynamicScoreDefinition>>(&dynamic_leaderboards_path),
+        deserialize_optional_mars_data_component::<Vec<RankThreshold>>(&rank_thresholds_path),
+        deserialize_optional_mars_data_component::<Vec<Milestone>>(&milestones_path)
     ) {
         Ok(values) => values,
         Err(e) => return Err(e)
     };
+    for join_message in join_messages.iter() {
+        join_message.validate().map_err(ConfigDeserializeError::InvalidData)?;
+    };
+    for rank_threshold in rank_thresholds.iter() {
+        rank_threshold.validate().map_err(ConfigDeserializeError::InvalidData)?;
+    };
+    for milestone in milestones.iter() {
+        milestone.validate().map_err(ConfigDeserializeError::InvalidData)?;
+    };
+    let dynamic_score_types = parse_dynamic_score_types(&dynamic_leaderboards).map_err(ConfigDeserializeError::InvalidData)?;
     Ok(MarsConfigData { 
         level_colors,
         join_sounds,
         broadcasts,
-        punishment_types
+        punishment_types,
+        join_messages,
+        dynamic_score_types,
+        rank_thresholds,
+        milestones
     })
 }
 
+// for files added after deployments already existed, a missing file loads as the default
+async fn deserialize_optional_mars_data_component<T: DeserializeOwned + Default>(
+    file_path: &String,
+) -> Result<T, ConfigDeserializeError> {
+    match deserialize_mars_data_component::<T>(file_path).await {
+        Err(ConfigDeserializeError::IOError(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        result => result
+    }
+}
+
 async fn deserialize_mars_data_component<T: DeserializeOwned>(
     file_path: &String,
 ) -> Result<T, ConfigDeserializeError> {
@@ -148,11 +337,46 @@ async fn deserialize_mars_data_component<T: DeserializeOwned>(
 
 pub struct MarsConfig {
     pub token: String,
+    pub admin_token: Option<String>,
+    pub previous_token: Option<String>,
+    pub previous_token_expires_at: u64,
+    // realm -> the token its servers authenticate with, empty for a single-realm deployment
+    pub realm_tokens: HashMap<String, String>,
     pub options: MarsConfigOptions,
     pub data: MarsConfigData,
     pub webhooks: WebhookUtils
 }
 
+#[derive(Display, Debug, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase")]
+pub enum TokenVersion {
+    Current,
+    Previous
+}
+
+impl MarsConfig {
+    // the previous token keeps working during the grace window so servers can be rolled over
+    // one at a time after a rotation, anything older than it is unknown and rejected
+    pub fn verify_token(&self, provided: &str) -> Option<TokenVersion> {
+        if provided == self.token {
+            return Some(TokenVersion::Current);
+        };
+        match &self.previous_token {
+            Some(previous) if previous == provided && get_u64_time_millis() < self.previous_token_expires_at => Some(TokenVersion::Previous),
+            _ => None
+        }
+    }
+
+    // the realm whose token this is, realm tokens are not rotated
+    pub fn verify_realm_token(&self, provided: &str) -> Option<String> {
+        self.realm_tokens.iter().find(|(_, token)| token.as_str() == provided).map(|(realm, _)| realm.clone())
+    }
+
+    pub fn is_realm(&self, realm: &str) -> bool {
+        self.realm_tokens.contains_key(realm)
+    }
+}
+
 // impl Default for MarsConfig {
 //     fn default() -> Self {
 //         MarsConfig {
@@ -171,9 +395,265 @@ pub struct MarsConfigOptions {
     pub redis_host: Option<String>,
     pub enable_ip_hashing: bool,
     pub punishments_webhook_url: String,
+    // shown on the public view of a punishment
+    pub punishment_appeal_url: Option<String>,
+    pub punishment_reasons: PunishmentReasonRules,
+    // share of a target's ips an alt needs to be linked to their punishments, 0 turns linking off
+    pub punishment_alt_link_confidence: f64,
+    pub punishment_conflict_policy: PunishmentConflictPolicy,
     pub reports_webhook_url: String,
     pub notes_webhook_url: String,
-    pub debug_log_webhook_url: String
+    pub debug_log_webhook_url: String,
+    pub token_rotated_at: Option<u64>,
+    pub token_grace_period: u64,
+    pub report_cooldown: u64,
+    pub report_rate_limit: u64,
+    pub report_rate_limit_window: u64,
+    pub allow_untracked_records: bool,
+    pub achievement_broadcast_rarity: AchievementRarity,
+    pub name_history_limit: usize,
+    pub player_ip_limit: usize,
+    // documents held at once by jobs that walk a whole collection, e.g. leaderboard rebuilds
+    pub document_batch_size: usize,
+    pub assist_damage_share: f64,
+    pub assist_window: u64,
+    pub compression_enabled: bool,
+    pub compression_threshold: usize,
+    // bytes, see `util::body_limit`
+    pub body_limit_default: usize,
+    pub body_limit_batch: usize,
+    pub body_limit_import: usize,
+    pub compression_algorithm: CompressionAlgorithm,
+    pub prestige_level_cap: u32,
+    pub prestige_reset_xp: bool,
+    // granted in order, the first tag on the first prestige
+    pub prestige_tag_ids: Vec<String>,
+    pub cache_warm_on_startup: bool,
+    pub clock_skew: ClockSkewOptions,
+    pub kill_decay_enabled: bool,
+    pub kill_decay_window: u64,
+    pub kill_decay_factor: f64,
+    pub kill_decay_min_credit: f64,
+    pub xp_notify_window: u64,
+    pub damage_events_enabled: bool,
+    pub damage_events_max_bytes: u64,
+    // game playtime in ms a player needs before anything they do counts on the leaderboards
+    pub leaderboard_min_playtime: u64,
+    // ms between batched leaderboard writes, 0 writes every increment immediately
+    pub leaderboard_flush_interval: u64,
+    pub objective_xp: ObjectiveXpRewards,
+    pub weapon_names: WeaponNames,
+    pub leaderboard_periods: PeriodBoundaries,
+    pub auto_mute: AutoMuteOptions,
+    pub session_stale_after: u64,
+    // a player logging in on the server they left at most this many ms ago picks their session back up, 0 always opens a new one
+    pub session_resume_grace: u64,
+    // joining players whose name is this many edits or fewer from a recently banned player's are flagged to staff, 0 turns it off
+    pub evasion_name_distance: usize,
+    // online staff `/mc/players/online/staff` reports as enough
+    pub staff_presence_required: usize,
+    pub session_rotation: SessionRotationOptions,
+    pub abandonment: AbandonmentOptions,
+    pub stat_tracking: StatTracking,
+    pub match_participation: MatchParticipationOptions,
+    pub result_verification: ResultVerification,
+    // players who have not joined for this long are left off the all-time boards until they join again, 0 keeps everyone
+    pub leaderboard_inactive_after: u64,
+    pub leaderboard_inactive_sweep_interval: u64,
+    // 0 writes every death right away instead of buffering
+    pub death_flush_interval: u64,
+    pub death_max_buffer: usize,
+    pub playtime_rounding: PlaytimeRounding,
+    pub playtime_seconds_migration: PlaytimeSecondsMigration,
+    pub mongo_max_pool_size: u32,
+    pub write_concerns: WriteConcerns,
+    // player saves at match end that may run at once across every server, half the pool when unset
+    pub match_end_write_concurrency: Option<usize>,
+    // destructive admin operations hand out a token first and only run once it is sent back
+    pub confirmation_required: bool,
+    pub confirmation_ttl: u64,
+    // 0 turns the periodic leaderboard reconciliation off
+    pub leaderboard_reconcile_interval: u64,
+    pub leaderboard_reconcile_sample_size: u32,
+    // buffered leaderboard increments kept while redis is down, counted per player and board key
+    pub leaderboard_max_pending: usize,
+    // time-decayed trending boards kept next to the period boards
+    pub leaderboard_decay: DecayOptions,
+    pub avatars: AvatarSource,
+    // how objective events that don't fit the state of their match are handled
+    pub objective_validation: ObjectiveValidation,
+    // unpaginated list endpoints return at most this many items, `X-Total-Count` still counts all of them
+    pub list_max_results: u32,
+    // list endpoints fail on a document that doesn't deserialize instead of skipping and reporting it
+    pub strict_decoding: bool
+}
+
+impl MarsConfigOptions {
+    pub fn get_match_end_write_concurrency(&self) -> usize {
+        self.match_end_write_concurrency.unwrap_or((self.mongo_max_pool_size as usize / 2).max(1))
+    }
+}
+
+// where player heads and bodies are rendered from. `{id}` in a path is the player's uuid and `{size}` the
+// requested size in pixels, `params` is added to the query of every url
+#[derive(Debug, Clone)]
+pub struct AvatarSource {
+    pub base_url: String,
+    pub face_path: String,
+    pub body_path: String,
+    pub params: String
+}
+
+impl Default for AvatarSource {
+    fn default() -> Self {
+        AvatarSource {
+            base_url: String::from("https://crafatar.com"),
+            face_path: String::from("/avatars/{id}?size={size}"),
+            body_path: String::from("/renders/body/{id}"),
+            params: String::from("helm")
+        }
+    }
+}
+
+impl AvatarSource {
+    fn build(&self, path: &str, id: &str, size: u32) -> String {
+        let path = path.replace("{id}", id).replace("{size}", &size.to_string());
+        let mut url = format!("{}{}", self.base_url, path);
+        if !self.params.is_empty() {
+            url.push(if path.contains('?') { '&' } else { '?' });
+            url.push_str(&self.params);
+        };
+        url
+    }
+
+    pub fn face_url(&self, id: &str, size: u32) -> String {
+        self.build(&self.face_path, id, size)
+    }
+
+    // renders ignore `{size}` unless the path uses it
+    pub fn body_url(&self, id: &str, size: u32) -> String {
+        self.build(&self.body_path, id, size)
+    }
+}
+
+// checked against the reason message of punishments issued by staff, reasons taken unchanged from a
+// punishment type always pass
+#[derive(Debug, Clone)]
+pub struct PunishmentReasonRules {
+    // off by default, none of the rules below are checked until it is turned on
+    pub enabled: bool,
+    // counted without color codes or surrounding whitespace
+    pub min_length: usize,
+    // lowercase, matched against the whole message once punctuation around it is stripped
+    pub blocklist: Vec<String>,
+    // kinds that can only be issued with one of the configured punishment types as the reason
+    pub template_required: Vec<PunishmentKind>
+}
+
+impl Default for PunishmentReasonRules {
+    fn default() -> Self {
+        PunishmentReasonRules {
+            enabled: false,
+            min_length: 3,
+            blocklist: ["n/a", "na", "none", "no reason", "reason", "idk", "asdf"].iter().map(|reason| reason.to_string()).collect(),
+            template_required: Vec::new()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionRotationOptions {
+    // ms after ending that a session is rotated out, 0 keeps sessions forever
+    pub retention: u64,
+    pub interval: u64,
+    pub batch_size: i64,
+    // moves rotated sessions to the archive collection instead of deleting them
+    pub archive: bool
+}
+
+impl Default for SessionRotationOptions {
+    fn default() -> Self {
+        SessionRotationOptions { retention: 0, interval: 3_600_000, batch_size: 1_000, archive: true }
+    }
+}
+
+// a server counts as dead once it is older than `session_stale_after`, these cover the rest of abandonment
+#[derive(Debug, Clone)]
+pub struct AbandonmentOptions {
+    // how long an in-progress match can sit with nobody in a party
+    pub empty_timeout: u64,
+    // 0 turns the sweeper off, matches are then only abandoned when their server starts up again
+    pub sweep_interval: u64,
+    // kills, deaths and assists are credited as they happen, when false they are taken back again
+    pub count_partial_kills: bool
+}
+
+impl Default for AbandonmentOptions {
+    fn default() -> Self {
+        AbandonmentOptions { empty_timeout: 300_000, sweep_interval: 60_000, count_partial_kills: true }
+    }
+}
+
+// report categories are the lowercased report reason, a category without a threshold never mutes anyone
+#[derive(Debug, Clone)]
+pub struct AutoMuteOptions {
+    pub enabled: bool,
+    pub window: u64,
+    pub length: i64,
+    // distinct reporters needed within `window`, not report count, so one player reporting repeatedly is not enough
+    pub thresholds: HashMap<String, u32>,
+    // a rule in shadow mode records the mute it would issue to the review queue instead, per category
+    // with `shadow` for the ones that are not set
+    pub shadow: bool,
+    pub shadow_categories: HashMap<String, bool>
+}
+
+impl Default for AutoMuteOptions {
+    fn default() -> Self {
+        AutoMuteOptions { enabled: false, window: 3_600_000, length: 3_600_000, thresholds: HashMap::new(), shadow: false, shadow_categories: HashMap::new() }
+    }
+}
+
+impl AutoMuteOptions {
+    pub fn is_shadowed(&self, category: &str) -> bool {
+        self.shadow_categories.get(category).copied().unwrap_or(self.shadow)
+    }
+}
+
+// base xp per objective event, the defaults are what was hardcoded before these were configurable
+#[derive(Debug, Clone)]
+pub struct ObjectiveXpRewards {
+    pub wool_capture: u32,
+    pub wool_pickup: u32,
+    pub wool_defend: u32,
+    pub flag_capture: u32,
+    // the most extra xp for a quick capture, one less for every second the flag was held
+    pub flag_capture_time_bonus: u32,
+    pub flag_pickup: u32,
+    pub flag_defend: u32,
+    // shared between everyone capturing, see `PlayerXPListener::on_control_point_capture`
+    pub control_point_capture: u32,
+    // for the whole destroyable, split by the blocks each player broke
+    pub destroyable: u32,
+    // for the whole core, split by each player's share of the leak
+    pub core_leak: u32
+}
+
+impl Default for ObjectiveXpRewards {
+    fn default() -> Self {
+        ObjectiveXpRewards {
+            wool_capture: XP_WOOL_OBJECTIVE,
+            wool_pickup: XP_WOOL_OBJECTIVE,
+            wool_defend: XP_WOOL_OBJECTIVE,
+            flag_capture: XP_FLAG_OBJECTIVE,
+            flag_capture_time_bonus: XP_FLAG_TIME_BOUNS,
+            flag_pickup: XP_FLAG_OBJECTIVE,
+            flag_defend: XP_FLAG_OBJECTIVE,
+            control_point_capture: XP_POINT_CAPTURE_MAX,
+            destroyable: XP_DESTROYABLE_WHOLE,
+            core_leak: XP_DESTROYABLE_WHOLE
+        }
+    }
 }
 
 impl Default for MarsConfigOptions {
@@ -185,9 +665,77 @@ impl Default for MarsConfigOptions {
             redis_host: None, 
             enable_ip_hashing: false,
             punishments_webhook_url: String::new(),
+            punishment_appeal_url: None,
+            punishment_reasons: PunishmentReasonRules::default(),
+            punishment_alt_link_confidence: 0.0,
+            punishment_conflict_policy: PunishmentConflictPolicy::Independent,
             reports_webhook_url: String::new(),
             notes_webhook_url: String::new(),
             debug_log_webhook_url: String::new(),
+            token_rotated_at: None,
+            token_grace_period: 86_400_000,
+            report_cooldown: 600_000,
+            report_rate_limit: 5,
+            report_rate_limit_window: 60_000,
+            allow_untracked_records: false,
+            achievement_broadcast_rarity: AchievementRarity::Rare,
+            name_history_limit: 20,
+            player_ip_limit: 20,
+            document_batch_size: 500,
+            assist_damage_share: 0.3,
+            assist_window: 15_000,
+            compression_enabled: false,
+            compression_threshold: 8192,
+            body_limit_default: 1_048_576,
+            body_limit_batch: 4_194_304,
+            body_limit_import: 16_777_216,
+            compression_algorithm: CompressionAlgorithm::Gzip,
+            prestige_level_cap: 100,
+            prestige_reset_xp: true,
+            prestige_tag_ids: Vec::new(),
+            cache_warm_on_startup: false,
+            clock_skew: ClockSkewOptions::default(),
+            kill_decay_enabled: false,
+            kill_decay_window: 300_000,
+            kill_decay_factor: 0.5,
+            kill_decay_min_credit: 0.1,
+            xp_notify_window: 1_000,
+            damage_events_enabled: false,
+            damage_events_max_bytes: 1_073_741_824,
+            leaderboard_min_playtime: 0,
+            leaderboard_flush_interval: 500,
+            objective_xp: ObjectiveXpRewards::default(),
+            weapon_names: WeaponNames::default(),
+            leaderboard_periods: PeriodBoundaries::default(),
+            auto_mute: AutoMuteOptions::default(),
+            session_stale_after: 300_000,
+            session_resume_grace: 0,
+            evasion_name_distance: 0,
+            staff_presence_required: 1,
+            session_rotation: SessionRotationOptions::default(),
+            abandonment: AbandonmentOptions::default(),
+            stat_tracking: StatTracking::default(),
+            match_participation: MatchParticipationOptions::default(),
+            result_verification: ResultVerification::default(),
+            leaderboard_inactive_after: 0,
+            leaderboard_inactive_sweep_interval: 3_600_000,
+            death_flush_interval: 2_000,
+            death_max_buffer: 200,
+            playtime_rounding: PlaytimeRounding::Minutes,
+            playtime_seconds_migration: PlaytimeSecondsMigration::Off,
+            mongo_max_pool_size: 8,
+            write_concerns: WriteConcerns::default(),
+            match_end_write_concurrency: None,
+            confirmation_required: true,
+            confirmation_ttl: 60_000,
+            leaderboard_reconcile_interval: 600_000,
+            leaderboard_reconcile_sample_size: 100,
+            leaderboard_max_pending: 200_000,
+            leaderboard_decay: DecayOptions::default(),
+            avatars: AvatarSource::default(),
+            objective_validation: ObjectiveValidation::Lenient,
+            list_max_results: 500,
+            strict_decoding: false
         }
     }
 }
@@ -197,5 +745,14 @@ pub struct MarsConfigData {
     pub level_colors: Vec<LevelColor>,
     pub join_sounds: Vec<JoinSound>,
     pub broadcasts: Vec<Broadcast>,
-    pub punishment_types: Vec<PunishmentType>
+    pub punishment_types: Vec<PunishmentType>,
+    #[serde(default)]
+    pub join_messages: Vec<JoinMessage>,
+    // parsed from `dynamic_leaderboards.yml` by `deserialize_mars_data`
+    #[serde(skip)]
+    pub dynamic_score_types: Vec<DynamicScoreType>,
+    #[serde(default)]
+    pub rank_thresholds: Vec<RankThreshold>,
+    #[serde(default)]
+    pub milestones: Vec<Milestone>
 }
@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+use serde::Serialize;
+
+use super::Database;
+
+// combined stats over a set of players, e.g. a clan roster
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregateStats {
+    // players found, the ones in `unknown_ids` are not counted anywhere
+    pub players: u64,
+    pub kills: u64,
+    pub deaths: u64,
+    pub assists: u64,
+    pub first_bloods: u64,
+    pub wins: u64,
+    pub losses: u64,
+    pub ties: u64,
+    pub matches: u64,
+    pub xp: u64,
+    pub server_playtime: u64,
+    pub game_playtime: u64,
+    // summed kills over summed deaths, not an average of the players' ratios
+    pub kill_death_ratio: f64,
+    pub average_kills: f64,
+    pub average_server_playtime: f64,
+    pub unknown_ids: Vec<String>
+}
+
+// `$sum` hands back whichever integer width fits, or a double once a counter held one
+fn get_total(group: &Document, key: &str) -> u64 {
+    match group.get(key) {
+        Some(Bson::Int32(value)) => (*value).max(0) as u64,
+        Some(Bson::Int64(value)) => (*value).max(0) as u64,
+        Some(Bson::Double(value)) => value.max(0.0) as u64,
+        _ => 0
+    }
+}
+
+impl Database {
+    // one `$match` + `$group`, the players themselves never leave mongo
+    pub async fn aggregate_player_stats(&self, player_ids: &[String]) -> anyhow::Result<AggregateStats> {
+        let pipeline = vec![
+            doc! { "$match": { "_id": { "$in": player_ids } } },
+            doc! { "$group": {
+                "_id": Bson::Null,
+                "ids": { "$push": "$_id" },
+                "kills": { "$sum": "$stats.kills" },
+                "deaths": { "$sum": "$stats.deaths" },
+                "assists": { "$sum": "$stats.assists" },
+                "firstBloods": { "$sum": "$stats.firstBloods" },
+                "wins": { "$sum": "$stats.wins" },
+                "losses": { "$sum": "$stats.losses" },
+                "ties": { "$sum": "$stats.ties" },
+                "matches": { "$sum": "$stats.matches" },
+                "xp": { "$sum": "$stats.xp" },
+                "serverPlaytime": { "$sum": "$stats.serverPlaytime" },
+                "gamePlaytime": { "$sum": "$stats.gamePlaytime" }
+            } }
+        ];
+        let mut cursor = self.players.clone_with_type::<Document>().aggregate(pipeline, None).await?;
+        let mut stats = AggregateStats::default();
+        let mut found : HashSet<String> = HashSet::new();
+        if let Some(group) = cursor.try_next().await? {
+            if let Ok(ids) = group.get_array("ids") {
+                found.extend(ids.iter().filter_map(|id| id.as_str().map(|id| id.to_owned())));
+            };
+            stats.kills = get_total(&group, "kills");
+            stats.deaths = get_total(&group, "deaths");
+            stats.assists = get_total(&group, "assists");
+            stats.first_bloods = get_total(&group, "firstBloods");
+            stats.wins = get_total(&group, "wins");
+            stats.losses = get_total(&group, "losses");
+            stats.ties = get_total(&group, "ties");
+            stats.matches = get_total(&group, "matches");
+            stats.xp = get_total(&group, "xp");
+            stats.server_playtime = get_total(&group, "serverPlaytime");
+            stats.game_playtime = get_total(&group, "gamePlaytime");
+        };
+        stats.players = found.len() as u64;
+        stats.unknown_ids = player_ids.iter().filter(|id| !found.contains(*id)).cloned().collect();
+        stats.kill_death_ratio = stats.kills as f64 / stats.deaths.max(1) as f64;
+        if stats.players > 0 {
+            stats.average_kills = stats.kills as f64 / stats.players as f64;
+            stats.average_server_playtime = stats.server_playtime as f64 / stats.players as f64;
+        };
+        Ok(stats)
+    }
+}
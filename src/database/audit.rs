@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use mongodb::bson::{doc, Bson, Document};
+
+use super::{Database, KeysetPage, models::audit_log::{AuditAction, AuditLogEntry}};
+
+pub const AUDIT_LOG_DEFAULT_PAGE_SIZE : i64 = 50;
+pub const AUDIT_LOG_MAX_PAGE_SIZE : i64 = 200;
+
+#[derive(Default)]
+pub struct AuditLogQuery {
+    pub actor_id: Option<String>,
+    pub action: Option<AuditAction>,
+    // matches the target's id or name
+    pub target: Option<String>,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    // id of the last entry on the previous page
+    pub after: Option<String>,
+    pub limit: Option<i64>
+}
+
+impl Database {
+    // newest first, along with how many entries match across every page
+    pub async fn query_audit_log(&self, query: AuditLogQuery) -> anyhow::Result<(KeysetPage<AuditLogEntry>, u64)> {
+        let mut filter = Document::new();
+        if let Some(actor_id) = query.actor_id {
+            filter.insert("actor.id", actor_id);
+        };
+        if let Some(action) = query.action {
+            filter.insert("action", action.to_string());
+        };
+        if let Some(target) = query.target {
+            filter.insert("$or", vec![doc! { "target.id": &target }, doc! { "target.name": &target }]);
+        };
+        if query.from.is_some() || query.to.is_some() {
+            let mut range = Document::new();
+            if let Some(from) = query.from {
+                range.insert("$gte", from as i64);
+            };
+            if let Some(to) = query.to {
+                range.insert("$lt", to as i64);
+            };
+            filter.insert("createdAt", range);
+        };
+        let limit = query.limit.unwrap_or(AUDIT_LOG_DEFAULT_PAGE_SIZE).clamp(1, AUDIT_LOG_MAX_PAGE_SIZE);
+        let total = self.audit_log.count_documents(filter.clone(), None).await?;
+        let page = Database::paginate_keyset(&self.audit_log, Some(filter), "_id", true, query.after.map(Bson::String), limit).await?;
+        Ok((page, total))
+    }
+}
+
+// best-effort and off the request path, a failed write is logged but never fails the operation it records
+pub fn record_audit(database: &Arc<Database>, entry: AuditLogEntry) {
+    let database = database.clone();
+    tokio::spawn(async move {
+        if let Err(e) = database.audit_log.insert_one(&entry, None).await {
+            error!(
+                "AUDIT LOG WRITE FAILED for {} on {:?} by {:?}: {}",
+                entry.action,
+                entry.target.as_ref().map(|target| &target.id),
+                entry.actor.as_ref().map(|actor| &actor.name),
+                e
+            );
+        };
+    });
+}
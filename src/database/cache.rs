@@ -1,6 +1,6 @@
-use std::{marker::PhantomData, time::Duration, sync::Arc};
+use std::{marker::PhantomData, time::Duration, sync::{Arc, atomic::{AtomicU64, Ordering}}};
 
-use futures::Future;
+use futures::{Future, future::join_all};
 use mars_api_rs_macro::IdentifiableDocument;
 use mobc::{Pool, Manager};
 use mongodb::bson::doc;
@@ -9,7 +9,7 @@ use rocket::serde::json;
 use serde::{Serialize, de::DeserializeOwned};
 use anyhow::anyhow;
 
-use crate::{config::ConfigMissingFieldError, util::r#macro::unwrap_helper};
+use crate::{config::ConfigMissingFieldError, util::{metrics::Metrics, r#macro::unwrap_helper, time::get_u64_time_millis}};
 
 use super::{Database, CollectionOwner};
 
@@ -28,7 +28,9 @@ impl<R> Cache<R> where R: CollectionOwner<R> + DeserializeOwned + Unpin + Sync +
 
     pub async fn query(&self, key: &str) -> Option<R> {
         let resource_key = self.generate_formatted_key(key);
-        if let Ok(value) = self.redis.get(&resource_key).await { Some(value) } else { None }
+        let value = self.redis.get(&resource_key).await.ok();
+        self.redis.metrics.record_cache_lookup(&self.resource_name, value.is_some());
+        value
     }
 
     pub async fn get(&self, database: &Database, key: &str) -> Option<R> {
@@ -58,12 +60,38 @@ impl<R> Cache<R> where R: CollectionOwner<R> + DeserializeOwned + Unpin + Sync +
         self.set_with_expiry(database, key, value, persist, None).await;
     }
 
+    // redis may be the only copy of values that aren't persisted, so they go to mongo whenever the write to it fails
     pub async fn set_with_expiry(&self, database: &Database, key: &str, value: &R, persist: bool, expiry_ms: Option<usize>) {
         let resource_key = self.generate_formatted_key(key);
         if persist {
             database.save(value).await;
         }
-        self.redis.set_with_expiry(&resource_key, value, expiry_ms).await;
+        if !self.redis.set_with_expiry(&resource_key, value, expiry_ms).await && !persist {
+            database.save(value).await;
+        };
+    }
+
+    // caches under the document id for `lifetime_ms`, without touching mongo
+    pub async fn store(&self, value: &R) {
+        let resource_key = self.generate_formatted_key(&value.get_id_value());
+        self.redis.set_with_expiry(&resource_key, value, Some(self.lifetime_ms as usize)).await;
+    }
+
+    // best-effort, a document that fails to cache is simply loaded from mongo on first use
+    pub async fn warm(&self, database: &Database) -> usize {
+        let documents = database.get_all_documents::<R>().await;
+        join_all(documents.iter().map(|document| self.store(document))).await;
+        documents.len()
+    }
+
+    pub async fn evict(&self, key: &str) {
+        let resource_key = self.generate_formatted_key(key);
+        self.redis.delete(&resource_key).await;
+    }
+
+    // drops every cached entry of this resource, e.g. after a migration changed the stored shape
+    pub async fn evict_all(&self) -> anyhow::Result<u64> {
+        self.redis.delete_prefix(&format!("{}:", self.resource_name)).await
     }
 
     pub async fn persist_cached_value(&self, database: &Database, key: &String) {
@@ -95,7 +123,7 @@ impl Manager for RedisConnectionManager {
     }
 
     async fn check(&self, mut conn: Self::Connection) -> Result<Self::Connection, Self::Error> {
-        redis::cmd("PING").query_async(&mut conn).await?;
+        redis::cmd("PING").query_async::<_, ()>(&mut conn).await?;
         Ok(conn)
     }
 }
@@ -105,8 +133,9 @@ const CACHE_POOL_MAX_OPEN: u64 = 16; // max connections
 const CACHE_POOL_MAX_IDLE: u64 = 8; // max unused connections
 const CACHE_POOL_TIMEOUT_SECONDS: u64 = 1; // await a connection from pool @ 1 second max
 const CACHE_POOL_EXPIRE_SECONDS: u64 = 60; // inactive connections die after a minute
+const REDIS_RETRY_INTERVAL_MS: u64 = 5_000; // while redis is down, one caller this often gets to try it again
 
-pub async fn get_redis_pool(redis_host: &Option<String>) -> anyhow::Result<RedisAdapter> {
+pub async fn get_redis_pool(redis_host: &Option<String>, metrics: Arc<Metrics>) -> anyhow::Result<RedisAdapter> {
     match redis_host {
         None => Err(ConfigMissingFieldError {field_name: String::from("redis-host") }.into()),
         Some(redis_host) => {
@@ -120,7 +149,7 @@ pub async fn get_redis_pool(redis_host: &Option<String>) -> anyhow::Result<Redis
                 .max_idle(CACHE_POOL_MAX_IDLE)
                 .max_lifetime(Some(Duration::from_secs(CACHE_POOL_EXPIRE_SECONDS)))
                 .build(manager);
-            let redis_adapter = RedisAdapter { pool };
+            let redis_adapter = RedisAdapter::new(pool, metrics);
             if !redis_adapter.ping().await {
                 return Err(anyhow!("Could not connect to Redis. Is it running?"));
             };
@@ -130,34 +159,102 @@ pub async fn get_redis_pool(redis_host: &Option<String>) -> anyhow::Result<Redis
     }
 }
 
+// every operation fails fast while redis is unreachable instead of waiting out the pool timeout,
+// callers already treat a failed operation as a cache miss or a skipped write
 pub struct RedisAdapter {
-    pub pool: Pool<RedisConnectionManager>
+    pub pool: Pool<RedisConnectionManager>,
+    // 0 while redis is reachable, otherwise when it stopped being
+    unavailable_since: AtomicU64,
+    retry_at: AtomicU64,
+    skipped: AtomicU64,
+    pub metrics: Arc<Metrics>
 }
 
 impl RedisAdapter {
+    pub fn new(pool: Pool<RedisConnectionManager>, metrics: Arc<Metrics>) -> Self {
+        RedisAdapter { pool, unavailable_since: AtomicU64::new(0), retry_at: AtomicU64::new(0), skipped: AtomicU64::new(0), metrics }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.unavailable_since.load(Ordering::Relaxed) == 0
+    }
+
+    pub fn unavailable_since(&self) -> Option<u64> {
+        Some(self.unavailable_since.load(Ordering::Relaxed)).filter(|since| *since != 0)
+    }
+
+    // operations turned away without trying redis since the API started
+    pub fn skipped_operations(&self) -> u64 {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    async fn connection(&self) -> anyhow::Result<mobc::Connection<RedisConnectionManager>> {
+        let now = get_u64_time_millis();
+        if !self.is_available() {
+            let retry_at = self.retry_at.load(Ordering::Relaxed);
+            if now < retry_at || self.retry_at.compare_exchange(retry_at, now + REDIS_RETRY_INTERVAL_MS, Ordering::Relaxed, Ordering::Relaxed).is_err() {
+                self.skipped.fetch_add(1, Ordering::Relaxed);
+                self.metrics.record_redis_error();
+                return Err(anyhow!("Redis is unavailable"));
+            };
+        };
+        match self.pool.get().await {
+            Ok(conn) => {
+                let since = self.unavailable_since.swap(0, Ordering::Relaxed);
+                if since != 0 {
+                    info!("Redis is reachable again after {}ms", now.saturating_sub(since));
+                };
+                Ok(conn)
+            },
+            Err(e) => {
+                self.metrics.record_redis_error();
+                self.retry_at.store(now + REDIS_RETRY_INTERVAL_MS, Ordering::Relaxed);
+                if self.unavailable_since.compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+                    warn!("Redis is unavailable, leaderboards and caches are degraded until it returns: {}", e);
+                };
+                Err(e.into())
+            }
+        }
+    }
+
     pub async fn ping(&self) -> bool {
         let mut conn = unwrap_helper::result_return_default!(self.pool.get().await, false);
         redis::cmd("PING").arg("we love warzone").query_async::<Connection, String>(&mut conn).await.is_ok()
     }
 
-    pub async fn set<T>(&self, key: &str, value: &T) where T: Serialize {
+    pub async fn set<T>(&self, key: &str, value: &T) -> bool where T: Serialize {
         self.set_with_expiry(key, value, None).await
     }
 
-    pub async fn set_with_expiry<T>(&self, key: &str, value: &T, expiry_ms: Option<usize>) where T: Serialize {
-        let mut conn = match self.pool.get().await {
+    // whether the value was written, callers that can't lose it keep it elsewhere when it wasn't
+    pub async fn set_with_expiry<T>(&self, key: &str, value: &T, expiry_ms: Option<usize>) -> bool where T: Serialize {
+        let mut conn = match self.connection().await {
             Ok(conn) => conn,
-            Err(_) => return
+            Err(_) => return false
         };
         match json::to_string(value) {
             Ok(stringified) => { 
-                if expiry_ms.is_some() {
-                    let _ : RedisResult<String> = conn.pset_ex(&key, &stringified, expiry_ms.unwrap()).await;
+                let result : RedisResult<String> = if let Some(expiry_ms) = expiry_ms {
+                    conn.pset_ex(key, &stringified, expiry_ms).await
                 } else {
-                    let _ = redis::cmd("SET").arg(key).arg(&stringified).query_async::<Connection, String>(&mut conn).await; 
+                    redis::cmd("SET").arg(key).arg(&stringified).query_async::<Connection, String>(&mut conn).await
+                };
+                if result.is_err() {
+                    self.metrics.record_redis_error();
                 };
+                result.is_ok()
             },
-            _ => {}
+            _ => false
+        }
+    }
+
+    pub async fn delete(&self, key: &str) {
+        let mut conn = match self.connection().await {
+            Ok(conn) => conn,
+            Err(_) => return
+        };
+        if redis::cmd("DEL").arg(key).query_async::<Connection, ()>(&mut conn).await.is_err() {
+            self.metrics.record_redis_error();
         };
     }
 
@@ -169,13 +266,97 @@ impl RedisAdapter {
     }
 
     pub async fn get<T>(&self, key: &str) -> anyhow::Result<T> where T: DeserializeOwned {
-        let mut conn = self.pool.get().await?;
+        let mut conn = self.connection().await?;
         let raw : String = redis::cmd("GET").arg(key).query_async::<Connection, String>(&mut conn).await?;
         Ok(json::from_str::<T>(&raw)?)
     }
 
+    pub async fn increment(&self, key: &str) {
+        let mut conn = match self.connection().await {
+            Ok(conn) => conn,
+            Err(_) => return
+        };
+        if redis::cmd("INCR").arg(key).query_async::<Connection, u64>(&mut conn).await.is_err() {
+            self.metrics.record_redis_error();
+        };
+    }
+
+    // the expiry is only set by the increment that creates the key, so the window is fixed rather than sliding
+    pub async fn increment_with_expiry(&self, key: &str, expiry_ms: u64) -> anyhow::Result<u64> {
+        let mut conn = self.connection().await?;
+        let count = redis::cmd("INCR").arg(key).query_async::<Connection, u64>(&mut conn).await?;
+        if count == 1 {
+            redis::cmd("PEXPIRE").arg(key).arg(expiry_ms).query_async::<Connection, ()>(&mut conn).await?;
+        };
+        Ok(count)
+    }
+
+    // the prefix is matched literally, glob characters in it are escaped
+    fn prefix_pattern(prefix: &str) -> String {
+        let mut pattern = String::new();
+        for c in prefix.chars() {
+            if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+                pattern.push('\\');
+            };
+            pattern.push(c);
+        };
+        pattern.push('*');
+        pattern
+    }
+
+    // walks the keyspace with SCAN rather than KEYS so a large cache does not block redis
+    pub async fn scan_prefix(&self, prefix: &str, limit: usize) -> anyhow::Result<Vec<String>> {
+        let pattern = Self::prefix_pattern(prefix);
+        let mut conn = self.connection().await?;
+        let mut cursor = 0u64;
+        let mut found : Vec<String> = Vec::new();
+        loop {
+            let (next, keys) : (u64, Vec<String>) = redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(500)
+                .query_async::<Connection, (u64, Vec<String>)>(&mut conn).await?;
+            found.extend(keys);
+            if next == 0 || found.len() >= limit {
+                found.truncate(limit);
+                return Ok(found);
+            };
+            cursor = next;
+        }
+    }
+
+    pub async fn delete_prefix(&self, prefix: &str) -> anyhow::Result<u64> {
+        let pattern = Self::prefix_pattern(prefix);
+        let mut conn = self.connection().await?;
+        let mut cursor = 0u64;
+        let mut deleted = 0u64;
+        loop {
+            let (next, keys) : (u64, Vec<String>) = redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(&pattern).arg("COUNT").arg(500)
+                .query_async::<Connection, (u64, Vec<String>)>(&mut conn).await?;
+            if !keys.is_empty() {
+                deleted += redis::cmd("DEL").arg(&keys).query_async::<Connection, u64>(&mut conn).await?;
+            };
+            if next == 0 {
+                return Ok(deleted);
+            };
+            cursor = next;
+        }
+    }
+
+    // remaining lifetime in ms per key, in one round trip. -1 is a key without an expiry, -2 one that no longer exists
+    pub async fn ttls(&self, keys: &[String]) -> anyhow::Result<Vec<i64>> {
+        let mut conn = self.connection().await?;
+        let mut pipe = redis::pipe();
+        for key in keys.iter() {
+            pipe.cmd("PTTL").arg(key);
+        };
+        Ok(pipe.query_async::<Connection, Vec<i64>>(&mut conn).await?)
+    }
+
+    pub async fn delete_key(&self, key: &str) -> anyhow::Result<bool> {
+        let mut conn = self.connection().await?;
+        Ok(redis::cmd("DEL").arg(key).query_async::<Connection, u64>(&mut conn).await? > 0)
+    }
+
     pub async fn submit<T, O: Future<Output = T>, F: FnOnce(mobc::Connection<RedisConnectionManager>) -> O>(&self, task: F) -> anyhow::Result<T> {
-        let conn : mobc::Connection<RedisConnectionManager> = self.pool.get().await?;
+        let conn : mobc::Connection<RedisConnectionManager> = self.connection().await?;
         Ok(task(conn).await)
     }
 }
@@ -0,0 +1,162 @@
+use mongodb::{bson::{doc, Bson, Document}, options::{FindOneAndUpdateOptions, FindOneOptions, ReturnDocument}};
+use serde::{Deserialize, Serialize};
+
+use crate::socket::leaderboard::ScoreType;
+
+use super::{Database, models::player::SimplePlayer};
+
+pub const STAT_CORRECTION_PERMISSION : &str = "mars.stats.correct";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatCorrection {
+    pub player_id: String,
+    // a leaderboard score type, e.g. `KILLS`
+    pub stat: String,
+    pub delta: i64
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatCorrectionResult {
+    pub player_id: String,
+    pub stat: String,
+    pub delta: i64,
+    pub success: bool,
+    pub error: Option<String>,
+    pub before: Option<i64>,
+    pub after: Option<i64>
+}
+
+impl StatCorrectionResult {
+    pub fn failed(correction: &StatCorrection, error: impl Into<String>) -> Self {
+        StatCorrectionResult {
+            player_id: correction.player_id.clone(),
+            stat: correction.stat.clone(),
+            delta: correction.delta,
+            success: false,
+            error: Some(error.into()),
+            before: None,
+            after: None
+        }
+    }
+}
+
+pub struct AppliedCorrection {
+    pub player: SimplePlayer,
+    pub before: i64,
+    pub after: i64
+}
+
+#[derive(Debug)]
+pub enum StatCorrectionError {
+    MissingPlayer,
+    // the stat's current value, which the delta would take below 0 or past what the field holds
+    OutOfRange(i64),
+    Other(mongodb::error::Error)
+}
+
+impl From<mongodb::error::Error> for StatCorrectionError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StatCorrectionError::Other(e)
+    }
+}
+
+// the overall counter behind a score type in `realm`'s stats and the largest value it holds. per-gamemode stats are
+// not corrected, and scores built from several counters (messages) or kept as a maximum (killstreaks) can't be
+pub fn correctable_field(score_type: &ScoreType, realm: &Option<String>) -> Option<(String, i64)> {
+    let u32_max = u32::MAX as i64;
+    let (field, max) = match score_type {
+        ScoreType::Kills => ("kills".to_owned(), u32_max),
+        ScoreType::Deaths => ("deaths".to_owned(), u32_max),
+        ScoreType::Assists => ("assists".to_owned(), u32_max),
+        ScoreType::FirstBloods => ("firstBloods".to_owned(), u32_max),
+        ScoreType::Wins => ("wins".to_owned(), u32_max),
+        ScoreType::Losses => ("losses".to_owned(), u32_max),
+        ScoreType::Ties => ("ties".to_owned(), u32_max),
+        ScoreType::Xp => ("xp".to_owned(), u32_max),
+        ScoreType::MatchesPlayed => ("matches".to_owned(), u32_max),
+        ScoreType::ServerPlaytime => ("serverPlaytime".to_owned(), i64::MAX),
+        ScoreType::GamePlaytime => ("gamePlaytime".to_owned(), i64::MAX),
+        ScoreType::CoreLeaks => ("objectives.coreLeaks".to_owned(), u32_max),
+        ScoreType::CoreBlockDestroys => ("objectives.coreBlockDestroys".to_owned(), u32_max),
+        ScoreType::DestroyableDestroys => ("objectives.destroyableDestroys".to_owned(), u32_max),
+        ScoreType::DestroyableBlockDestroys => ("objectives.destroyableBlockDestroys".to_owned(), u32_max),
+        ScoreType::FlagCaptures => ("objectives.flagCaptures".to_owned(), u32_max),
+        ScoreType::FlagDrops => ("objectives.flagDrops".to_owned(), u32_max),
+        ScoreType::FlagPickups => ("objectives.flagPickups".to_owned(), u32_max),
+        ScoreType::FlagDefends => ("objectives.flagDefends".to_owned(), u32_max),
+        ScoreType::FlagHoldTime => ("objectives.totalFlagHoldTime".to_owned(), i64::MAX),
+        ScoreType::WoolCaptures => ("objectives.woolCaptures".to_owned(), u32_max),
+        ScoreType::WoolDrops => ("objectives.woolDrops".to_owned(), u32_max),
+        ScoreType::WoolPickups => ("objectives.woolPickups".to_owned(), u32_max),
+        ScoreType::WoolDefends => ("objectives.woolDefends".to_owned(), u32_max),
+        ScoreType::ControlPointCaptures => ("objectives.controlPointCaptures".to_owned(), u32_max),
+        ScoreType::MessagesSent | ScoreType::HighestKillstreak => return None
+    };
+    match realm {
+        Some(realm) => Some((format!("realmStats.{}.stats.{}", realm, field), max)),
+        None => Some((format!("stats.{}", field), max))
+    }
+}
+
+fn get_counter(document: &Document, path: &str) -> i64 {
+    let mut parts = path.split('.').peekable();
+    let mut current = document;
+    while let Some(part) = parts.next() {
+        match (current.get(part), parts.peek()) {
+            (Some(Bson::Document(inner)), Some(_)) => current = inner,
+            (Some(Bson::Int32(value)), None) => return *value as i64,
+            (Some(Bson::Int64(value)), None) => return *value,
+            (Some(Bson::Double(value)), None) => return *value as i64,
+            _ => return 0
+        };
+    };
+    0
+}
+
+impl Database {
+    // one `$inc` whose filter only matches while the result stays in range, so two corrections racing on
+    // the same counter can't take it below 0 between them
+    pub async fn correct_stat(&self, player_id: &str, field: &str, max: i64, delta: i64) -> Result<AppliedCorrection, StatCorrectionError> {
+        let mut filter = doc! { "_id": player_id };
+        let range = if delta < 0 {
+            doc! { "$gte": delta.saturating_neg() }
+        } else {
+            doc! { "$lte": max.saturating_sub(delta) }
+        };
+        if delta < 0 || delta > max {
+            filter.insert(field, range);
+        } else {
+            // a counter that was never written reads as 0
+            let mut in_range = Document::new();
+            in_range.insert(field, range);
+            let mut missing = Document::new();
+            missing.insert(field, doc! { "$exists": false });
+            filter.insert("$or", vec![in_range, missing]);
+        };
+        let mut increment = Document::new();
+        increment.insert(field, delta);
+        let mut projection = doc! { "name": 1 };
+        projection.insert(field, 1);
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::Before)
+            .projection(projection.clone())
+            .build();
+        let players = self.players.clone_with_type::<Document>();
+        match players.find_one_and_update(filter, doc! { "$inc": increment }, options).await? {
+            Some(before) => {
+                let before_value = get_counter(&before, field);
+                let name = before.get_str("name").unwrap_or_default().to_owned();
+                Ok(AppliedCorrection { player: SimplePlayer { id: player_id.to_owned(), name }, before: before_value, after: before_value + delta })
+            },
+            None => {
+                let options = FindOneOptions::builder().projection(projection).build();
+                match players.find_one(doc! { "_id": player_id }, options).await? {
+                    Some(current) => Err(StatCorrectionError::OutOfRange(get_counter(&current, field))),
+                    None => Err(StatCorrectionError::MissingPlayer)
+                }
+            }
+        }
+    }
+}
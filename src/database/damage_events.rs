@@ -0,0 +1,119 @@
+use std::{sync::Arc, time::Duration};
+
+use mongodb::{bson::{doc, Document}, options::{CreateCollectionOptions, InsertManyOptions}};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, Sender, Receiver};
+use anyhow::anyhow;
+
+use super::{Database, CollectionOwner, models::damage_event::DamageEvent};
+
+const DAMAGE_EVENT_BATCH_SIZE : usize = 500;
+const DAMAGE_EVENT_FLUSH_INTERVAL_MS : u64 = 2_000;
+const DAMAGE_EVENT_QUEUE_CAPACITY : usize = 20 * DAMAGE_EVENT_BATCH_SIZE;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WeaponDamage {
+    // `None` for damage dealt without a held item
+    pub weapon: Option<String>,
+    pub hits: u64,
+    pub total: f64,
+    pub average: f64,
+    pub min: f64,
+    pub max: f64
+}
+
+impl Database {
+    // retention is bounded by size, mongo drops the oldest events once `max_bytes` is reached. an existing
+    // collection that isn't capped would grow without bound, so it fails startup instead of being used
+    pub async fn ensure_damage_event_collection(&self, max_bytes: u64) -> anyhow::Result<()> {
+        let name = DamageEvent::get_collection_name();
+        if let Some(existing) = self.mongo.list_collections(doc! { "name": name }, None).await?.try_next().await? {
+            if !existing.options.capped.unwrap_or(false) {
+                return Err(anyhow!("Collection '{}' exists but is not capped, convert it with convertToCapped or drop it", name));
+            };
+            return Ok(());
+        };
+        self.mongo.create_collection(name, CreateCollectionOptions::builder().capped(true).size(max_bytes).build()).await?;
+        info!("Created capped collection '{}' ({} bytes)", name, max_bytes);
+        Ok(())
+    }
+
+    pub async fn weapon_damage_distribution(&self, since: Option<u64>, match_id: Option<&str>) -> anyhow::Result<Vec<WeaponDamage>> {
+        let mut filter = Document::new();
+        if let Some(since) = since {
+            filter.insert("createdAt", doc! { "$gte": since as i64 });
+        };
+        if let Some(match_id) = match_id {
+            filter.insert("matchId", match_id);
+        };
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! { "$group": {
+                "_id": "$weapon",
+                "hits": { "$sum": 1 },
+                "total": { "$sum": "$damage" },
+                "average": { "$avg": "$damage" },
+                "min": { "$min": "$damage" },
+                "max": { "$max": "$damage" }
+            } },
+            doc! { "$sort": { "total": -1 } }
+        ];
+        let mut cursor = self.damage_events.clone_with_type::<Document>().aggregate(pipeline, None).await?;
+        let mut distribution = Vec::new();
+        while let Some(group) = cursor.try_next().await? {
+            distribution.push(WeaponDamage {
+                weapon: group.get_str("_id").ok().map(|weapon| weapon.to_owned()),
+                hits: group.get_i32("hits").map(|hits| hits as u64).or_else(|_| group.get_i64("hits").map(|hits| hits as u64)).unwrap_or(0),
+                total: group.get_f64("total").unwrap_or(0.0),
+                average: group.get_f64("average").unwrap_or(0.0),
+                min: group.get_f64("min").unwrap_or(0.0),
+                max: group.get_f64("max").unwrap_or(0.0)
+            });
+        };
+        Ok(distribution)
+    }
+}
+
+// damage events are queued from the socket and written in batches by a background task,
+// so recording a hit never waits on mongo. the queue holds `DAMAGE_EVENT_QUEUE_CAPACITY` events,
+// while mongo can't keep up further events are dropped instead of piling up in memory
+pub fn spawn_damage_event_writer(database: Arc<Database>) -> Sender<DamageEvent> {
+    let (sender, receiver) = mpsc::channel::<DamageEvent>(DAMAGE_EVENT_QUEUE_CAPACITY);
+    tokio::spawn(write_damage_events(database, receiver));
+    sender
+}
+
+async fn write_damage_events(database: Arc<Database>, mut receiver: Receiver<DamageEvent>) {
+    let mut batch : Vec<DamageEvent> = Vec::with_capacity(DAMAGE_EVENT_BATCH_SIZE);
+    let mut flush = tokio::time::interval(Duration::from_millis(DAMAGE_EVENT_FLUSH_INTERVAL_MS));
+    loop {
+        let flush_now = tokio::select! {
+            event = receiver.recv() => match event {
+                Some(event) => {
+                    batch.push(event);
+                    batch.len() >= DAMAGE_EVENT_BATCH_SIZE
+                },
+                None => {
+                    insert_damage_events(&database, batch).await;
+                    break;
+                }
+            },
+            _ = flush.tick() => true
+        };
+        if flush_now {
+            insert_damage_events(&database, std::mem::replace(&mut batch, Vec::with_capacity(DAMAGE_EVENT_BATCH_SIZE))).await;
+        };
+    }
+}
+
+async fn insert_damage_events(database: &Database, events: Vec<DamageEvent>) {
+    if events.is_empty() {
+        return;
+    };
+    let count = events.len();
+    if let Err(e) = database.damage_events.insert_many(events, InsertManyOptions::builder().ordered(false).build()).await {
+        warn!("Could not write {} damage events: {}", count, e);
+    };
+}
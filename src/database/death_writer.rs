@@ -0,0 +1,123 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use mongodb::{error::ErrorKind, options::InsertManyOptions};
+use tokio::sync::{mpsc::{self, UnboundedSender, UnboundedReceiver}, oneshot};
+
+use super::{Database, DUPLICATE_KEY_ERROR_CODE, models::death::Death};
+
+enum DeathWrite {
+    Record(Box<Death>),
+    // `None` flushes every match, the sender is told how many deaths were written
+    Flush(Option<String>, oneshot::Sender<usize>)
+}
+
+// deaths are buffered per match and written with one insert per match, either every `flush_interval` ms,
+// once a match has `max_buffer` deaths waiting, or when the match ends
+#[derive(Clone)]
+pub struct DeathWriter {
+    sender: UnboundedSender<DeathWrite>
+}
+
+impl DeathWriter {
+    // a `flush_interval` of 0 writes every death as it comes in
+    pub fn spawn(database: Arc<Database>, flush_interval: u64, max_buffer: usize) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel::<DeathWrite>();
+        let max_buffer = if flush_interval == 0 { 1 } else { max_buffer.max(1) };
+        tokio::spawn(write_deaths(database, receiver, flush_interval.max(1), max_buffer));
+        DeathWriter { sender }
+    }
+
+    pub fn record(&self, death: Death) {
+        if let Err(e) = self.sender.send(DeathWrite::Record(Box::new(death))) {
+            if let DeathWrite::Record(death) = e.0 {
+                warn!("Death writer has stopped, death {} was not recorded", death.id);
+            };
+        };
+    }
+
+    // waits until every death buffered for the match so far is written
+    pub async fn flush_match(&self, match_id: &str) -> usize {
+        self.flush(Some(match_id.to_owned())).await
+    }
+
+    pub async fn flush_all(&self) -> usize {
+        self.flush(None).await
+    }
+
+    async fn flush(&self, match_id: Option<String>) -> usize {
+        let (ack, written) = oneshot::channel();
+        if self.sender.send(DeathWrite::Flush(match_id, ack)).is_err() {
+            return 0;
+        };
+        written.await.unwrap_or(0)
+    }
+}
+
+async fn write_deaths(database: Arc<Database>, mut receiver: UnboundedReceiver<DeathWrite>, flush_interval: u64, max_buffer: usize) {
+    let mut buffers : HashMap<String, Vec<Death>> = HashMap::new();
+    let mut flush = tokio::time::interval(Duration::from_millis(flush_interval));
+    loop {
+        tokio::select! {
+            write = receiver.recv() => match write {
+                Some(DeathWrite::Record(death)) => {
+                    let buffer = buffers.entry(death.match_id.clone()).or_default();
+                    buffer.push(*death);
+                    if buffer.len() >= max_buffer {
+                        insert_deaths(&database, std::mem::take(buffer)).await;
+                    };
+                },
+                Some(DeathWrite::Flush(match_id, ack)) => {
+                    let mut written = 0;
+                    match match_id {
+                        Some(match_id) => if let Some(buffer) = buffers.remove(&match_id) {
+                            written += insert_deaths(&database, buffer).await;
+                        },
+                        None => for (_, buffer) in buffers.drain() {
+                            written += insert_deaths(&database, buffer).await;
+                        }
+                    };
+                    let _ = ack.send(written);
+                },
+                None => {
+                    for (_, buffer) in buffers.drain() {
+                        insert_deaths(&database, buffer).await;
+                    };
+                    break;
+                }
+            },
+            _ = flush.tick() => {
+                for (_, buffer) in buffers.drain() {
+                    insert_deaths(&database, buffer).await;
+                };
+            }
+        };
+    }
+}
+
+// unordered, so one duplicate does not stop the rest of the batch
+async fn insert_deaths(database: &Database, deaths: Vec<Death>) -> usize {
+    if deaths.is_empty() {
+        return 0;
+    };
+    let count = deaths.len();
+    match database.deaths.insert_many(deaths, InsertManyOptions::builder().ordered(false).build()).await {
+        Ok(result) => result.inserted_ids.len(),
+        Err(e) => match e.kind.as_ref() {
+            ErrorKind::BulkWrite(failure) => {
+                let write_errors = failure.write_errors.as_deref().unwrap_or(&[]);
+                let duplicates = write_errors.iter().filter(|error| error.code == DUPLICATE_KEY_ERROR_CODE).count();
+                if duplicates > 0 {
+                    warn!("{} of {} deaths were already recorded", duplicates, count);
+                };
+                if write_errors.len() > duplicates || failure.write_concern_error.is_some() {
+                    warn!("Could not write {} of {} deaths: {}", write_errors.len() - duplicates, count, e);
+                };
+                count - write_errors.len()
+            },
+            _ => {
+                warn!("Could not write {} deaths: {}", count, e);
+                0
+            }
+        }
+    }
+}
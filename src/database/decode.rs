@@ -0,0 +1,84 @@
+use futures::TryStreamExt;
+use mongodb::{bson::{Bson, Document}, options::FindOptions, Collection};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+// a document that was read but didn't match the model, usually schema drift
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DecodeFailure {
+    pub id: Option<String>,
+    pub error: String
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Query(mongodb::error::Error),
+    // only in strict mode, the first document that failed
+    Decode { collection: String, failure: DecodeFailure }
+}
+
+impl From<mongodb::error::Error> for LoadError {
+    fn from(e: mongodb::error::Error) -> Self {
+        LoadError::Query(e)
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Query(e) => write!(f, "{}", e),
+            Self::Decode { collection, failure } => write!(
+                f, "A document in '{}' could not be loaded (id {}): {}", 
+                collection, failure.id.as_deref().unwrap_or("unknown"), failure.error
+            )
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+pub struct LoadedDocuments<T> {
+    pub documents: Vec<T>,
+    pub failures: Vec<DecodeFailure>
+}
+
+fn id_string(id: &Bson) -> String {
+    match id {
+        Bson::String(id) => id.clone(),
+        Bson::ObjectId(id) => id.to_hex(),
+        id => id.to_string()
+    }
+}
+
+// unlike `consume_cursor_into_owning_vec` the documents that fail to deserialize are handed back with
+// their ids, or fail the whole load when `strict`
+pub async fn load_documents<T: DeserializeOwned>(
+    coll: &Collection<T>,
+    filter: impl Into<Option<Document>>,
+    options: impl Into<Option<FindOptions>>,
+    strict: bool
+) -> Result<LoadedDocuments<T>, LoadError> {
+    let mut cursor = coll.clone_with_type::<Document>().find(filter, options).await?;
+    let mut loaded = LoadedDocuments { documents: Vec::new(), failures: Vec::new() };
+    while let Some(document) = cursor.try_next().await? {
+        let id = document.get("_id").map(id_string);
+        match mongodb::bson::from_document::<T>(document) {
+            Ok(document) => loaded.documents.push(document),
+            Err(e) => {
+                let failure = DecodeFailure { id, error: e.to_string() };
+                if strict {
+                    return Err(LoadError::Decode { collection: coll.name().to_owned(), failure });
+                };
+                loaded.failures.push(failure);
+            }
+        };
+    };
+    if !loaded.failures.is_empty() {
+        warn!(
+            "{} documents in '{}' could not be deserialized: {}", 
+            loaded.failures.len(), coll.name(), 
+            loaded.failures.iter().map(|failure| failure.id.as_deref().unwrap_or("unknown")).collect::<Vec<_>>().join(", ")
+        );
+    };
+    Ok(loaded)
+}
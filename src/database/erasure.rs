@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+
+use mongodb::{bson::{doc, Document}, options::UpdateOptions, ClientSession, Collection};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use super::{Database, CollectionOwner, models::{player::Player, session::Session, death::Death, punishment::Punishment, r#match::Match, report::Report, audit_log::AuditLogEntry, shadow_punishment::ShadowPunishment, leaderboard_snapshot::LeaderboardSnapshot, damage_event::DamageEvent}};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum ErasureMode {
+    // removes the player and everything only they own, shared records are anonymized
+    Delete,
+    // replaces the name and ips everywhere but keeps the player and their aggregate stats
+    Anonymize
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionErasure {
+    pub collection: String,
+    pub deleted: u64,
+    pub anonymized: u64
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErasureReport {
+    pub player_id: String,
+    pub mode: ErasureMode,
+    pub anonymized_name: String,
+    pub collections: Vec<CollectionErasure>
+}
+
+impl ErasureReport {
+    fn record<T: CollectionOwner<T>>(&mut self, deleted: u64, anonymized: u64) {
+        let name = T::get_collection_name();
+        match self.collections.iter_mut().find(|erasure| erasure.collection == name) {
+            Some(erasure) => {
+                erasure.deleted += deleted;
+                erasure.anonymized += anonymized;
+            },
+            None => self.collections.push(CollectionErasure { collection: name.to_owned(), deleted, anonymized })
+        };
+    }
+}
+
+pub fn anonymized_name(player_id: &str) -> String {
+    format!("Erased{}", player_id.chars().filter(|c| c.is_ascii_alphanumeric()).take(8).collect::<String>())
+}
+
+// stands in for the player id in damage events. a capped collection only takes updates that keep a
+// document's size, so it is exactly as long as the id it replaces
+fn erased_id(player_id: &str) -> String {
+    let mut id : String = anonymized_name(player_id).chars().take(player_id.len()).collect();
+    while id.len() < player_id.len() {
+        id.push('0');
+    };
+    id
+}
+
+impl Database {
+    // runs in a single transaction, so the deployment has to be a replica set. damage events are
+    // rewritten once it commits. redis (leaderboards, the player cache) is not covered and must be cleaned up by the caller
+    pub async fn erase_player_data(&self, player_id: &str, mode: ErasureMode) -> anyhow::Result<ErasureReport> {
+        let mut session = self.players.client().start_session(None).await?;
+        session.start_transaction(None).await?;
+        match self.erase_player_data_with_session(player_id, mode, &mut session).await {
+            Ok(mut report) => {
+                session.commit_transaction().await?;
+                // capped collections can't be written in a transaction
+                let erased = self.erase_damage_events(player_id).await?;
+                report.record::<DamageEvent>(0, erased);
+                Ok(report)
+            },
+            Err(e) => {
+                let _ = session.abort_transaction().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn erase_player_data_with_session(&self, player_id: &str, mode: ErasureMode, session: &mut ClientSession) -> anyhow::Result<ErasureReport> {
+        let name = anonymized_name(player_id);
+        let mut report = ErasureReport { player_id: player_id.to_owned(), mode, anonymized_name: name.clone(), collections: Vec::new() };
+
+        match mode {
+            ErasureMode::Delete => {
+                let deleted = self.leaderboard_snapshots.update_many_with_session(doc! { "entries.id": player_id }, doc! {
+                    "$pull": { "entries": { "id": player_id } }
+                }, None, session).await?.modified_count;
+                report.record::<LeaderboardSnapshot>(deleted, 0);
+                let deleted = self.players.delete_one_with_session(doc! { "_id": player_id }, None, session).await?.deleted_count;
+                report.record::<Player>(deleted, 0);
+                let deleted = self.sessions.delete_many_with_session(doc! { "player.id": player_id }, None, session).await?.deleted_count;
+                report.record::<Session>(deleted, 0);
+                let deleted = self.session_archive.delete_many_with_session(doc! { "player.id": player_id }, None, session).await?.deleted_count;
+                report.record::<Session>(deleted, 0);
+                let deleted = self.deaths.delete_many_with_session(doc! { "victim.id": player_id }, None, session).await?.deleted_count;
+                report.record::<Death>(deleted, 0);
+            },
+            ErasureMode::Anonymize => {
+                let anonymized = self.players.update_one_with_session(doc! { "_id": player_id }, doc! {
+                    "$set": { "name": &name, "nameLower": name.to_lowercase(), "ips": [], "nameHistory": [] }
+                }, None, session).await?.modified_count;
+                report.record::<Player>(0, anonymized);
+                for sessions in [&self.sessions, &self.session_archive] {
+                    let anonymized = sessions.update_many_with_session(doc! { "player.id": player_id }, doc! {
+                        "$set": { "player.name": &name, "ip": "" }
+                    }, None, session).await?.modified_count;
+                    report.record::<Session>(0, anonymized);
+                };
+                let anonymized = Self::anonymize_field(&self.deaths, "victim", player_id, &name, session).await?;
+                report.record::<Death>(0, anonymized);
+                let anonymized = Self::anonymize_array_field(&self.leaderboard_snapshots, "entries", "", player_id, &name, session).await?;
+                report.record::<LeaderboardSnapshot>(0, anonymized);
+            }
+        };
+
+        // notes this player wrote on other players stay, only the author is renamed
+        let anonymized = self.players.update_many_with_session(doc! { "notes.author.id": player_id }, doc! {
+            "$set": { "notes.$[note].author.name": &name }
+        }, UpdateOptions::builder().array_filters(vec![doc! { "note.author.id": player_id }]).build(), session).await?.modified_count;
+        report.record::<Player>(0, anonymized);
+
+        let anonymized = Self::anonymize_field(&self.deaths, "attacker", player_id, &name, session).await?;
+        report.record::<Death>(0, anonymized);
+
+        // punishments are kept in both modes so moderation history stays intact
+        let anonymized = self.punishments.update_many_with_session(doc! { "target.id": player_id }, doc! {
+            "$set": { "target.name": &name, "targetIps": [] }
+        }, None, session).await?.modified_count;
+        report.record::<Punishment>(0, anonymized);
+        let anonymized = Self::anonymize_field(&self.punishments, "punisher", player_id, &name, session).await?;
+        report.record::<Punishment>(0, anonymized);
+
+        let anonymized = self.shadow_punishments.update_many_with_session(doc! { "punishment.target.id": player_id }, doc! {
+            "$set": { "punishment.target.name": &name, "punishment.targetIps": [] }
+        }, None, session).await?.modified_count;
+        report.record::<ShadowPunishment>(0, anonymized);
+        let anonymized = Self::anonymize_field(&self.shadow_punishments, "punishment.punisher", player_id, &name, session).await?;
+        report.record::<ShadowPunishment>(0, anonymized);
+
+        // the audit trail is kept like punishments, only who it names is renamed
+        for field in ["actor", "target"] {
+            let anonymized = Self::anonymize_field(&self.audit_log, field, player_id, &name, session).await?;
+            report.record::<AuditLogEntry>(0, anonymized);
+        };
+
+        let anonymized = Self::anonymize_field(&self.reports, "target", player_id, &name, session).await?;
+        report.record::<Report>(0, anonymized);
+        let anonymized = Self::anonymize_field(&self.reports, "reporter", player_id, &name, session).await?;
+        report.record::<Report>(0, anonymized);
+
+        // participants are keyed by player id
+        let participant_key = format!("participants.{}", player_id);
+        let mut participant_filter = Document::new();
+        participant_filter.insert(&participant_key, doc! { "$exists": true });
+        let mut participant_set = Document::new();
+        participant_set.insert(format!("{}.name", participant_key), &name);
+        let anonymized = self.matches.update_many_with_session(
+            participant_filter, doc! { "$set": participant_set }, None, session
+        ).await?.modified_count;
+        report.record::<Match>(0, anonymized);
+        let anonymized = Self::anonymize_array_field(&self.matches, "scoreboard", "player.", player_id, &name, session).await?;
+        report.record::<Match>(0, anonymized);
+        for field in ["firstBlood.attacker", "firstBlood.victim"] {
+            let anonymized = Self::anonymize_field(&self.matches, field, player_id, &name, session).await?;
+            report.record::<Match>(0, anonymized);
+        };
+
+        Ok(report)
+    }
+
+    async fn erase_damage_events(&self, player_id: &str) -> anyhow::Result<u64> {
+        let erased = erased_id(player_id);
+        let mut modified = self.damage_events.update_many(doc! { "victimId": player_id }, doc! { "$set": { "victimId": &erased } }, None).await?.modified_count;
+        modified += self.damage_events.update_many(doc! { "attackerId": player_id }, doc! { "$set": { "attackerId": &erased } }, None).await?.modified_count;
+        Ok(modified)
+    }
+
+    // what `erase_player_data` would touch, by collection, without changing anything
+    pub async fn erasure_effect(&self, player_id: &str) -> anyhow::Result<BTreeMap<String, u64>> {
+        let mut effect = BTreeMap::new();
+        effect.insert(Player::get_collection_name().to_owned(), self.players.count_documents(doc! { "_id": player_id }, None).await?);
+        effect.insert(Session::get_collection_name().to_owned(), self.sessions.count_documents(doc! { "player.id": player_id }, None).await?
+            + self.session_archive.count_documents(doc! { "player.id": player_id }, None).await?);
+        effect.insert(Death::get_collection_name().to_owned(), self.deaths.count_documents(doc! {
+            "$or": [{ "victim.id": player_id }, { "attacker.id": player_id }]
+        }, None).await?);
+        effect.insert(Punishment::get_collection_name().to_owned(), self.punishments.count_documents(doc! {
+            "$or": [{ "target.id": player_id }, { "punisher.id": player_id }]
+        }, None).await?);
+        effect.insert(Report::get_collection_name().to_owned(), self.reports.count_documents(doc! {
+            "$or": [{ "target.id": player_id }, { "reporter.id": player_id }]
+        }, None).await?);
+        effect.insert(ShadowPunishment::get_collection_name().to_owned(), self.shadow_punishments.count_documents(doc! {
+            "$or": [{ "punishment.target.id": player_id }, { "punishment.punisher.id": player_id }]
+        }, None).await?);
+        effect.insert(AuditLogEntry::get_collection_name().to_owned(), self.audit_log.count_documents(doc! {
+            "$or": [{ "actor.id": player_id }, { "target.id": player_id }]
+        }, None).await?);
+        effect.insert(LeaderboardSnapshot::get_collection_name().to_owned(), self.leaderboard_snapshots.count_documents(doc! { "entries.id": player_id }, None).await?);
+        effect.insert(DamageEvent::get_collection_name().to_owned(), self.damage_events.count_documents(doc! {
+            "$or": [{ "victimId": player_id }, { "attackerId": player_id }]
+        }, None).await?);
+        let mut participant_filter = Document::new();
+        participant_filter.insert(format!("participants.{}", player_id), doc! { "$exists": true });
+        effect.insert(Match::get_collection_name().to_owned(), self.matches.count_documents(doc! {
+            "$or": [participant_filter, { "scoreboard.player.id": player_id }, { "firstBlood.attacker.id": player_id }, { "firstBlood.victim.id": player_id }]
+        }, None).await?);
+        Ok(effect)
+    }
+
+    async fn anonymize_field<T>(collection: &Collection<T>, field: &str, player_id: &str, name: &str, session: &mut ClientSession) -> anyhow::Result<u64> {
+        let mut filter = Document::new();
+        filter.insert(format!("{}.id", field), player_id);
+        let mut set = Document::new();
+        set.insert(format!("{}.name", field), name);
+        Ok(collection.update_many_with_session(filter, doc! { "$set": set }, None, session).await?.modified_count)
+    }
+
+    // renames the player in every element of `array` that is them, `prefix` leads from an element to its `id` and `name`
+    async fn anonymize_array_field<T>(collection: &Collection<T>, array: &str, prefix: &str, player_id: &str, name: &str, session: &mut ClientSession) -> anyhow::Result<u64> {
+        let mut filter = Document::new();
+        filter.insert(format!("{}.{}id", array, prefix), player_id);
+        let mut set = Document::new();
+        set.insert(format!("{}.$[element].{}name", array, prefix), name);
+        let mut array_filter = Document::new();
+        array_filter.insert(format!("element.{}id", prefix), player_id);
+        let options = UpdateOptions::builder().array_filters(vec![array_filter]).build();
+        Ok(collection.update_many_with_session(filter, doc! { "$set": set }, options, session).await?.modified_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::erased_id;
+
+    #[test]
+    fn erased_ids_keep_the_length_of_the_id() {
+        for player_id in ["1b2c3d4e-0000-4a5b-8c9d-0123456789ab", "short"] {
+            assert_eq!(erased_id(player_id).len(), player_id.len());
+            assert_ne!(erased_id(player_id), player_id);
+        };
+    }
+}
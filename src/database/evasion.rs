@@ -0,0 +1,62 @@
+use mongodb::{bson::doc, IndexModel};
+
+use crate::util::{string::edit_distance, time::get_u64_time_millis};
+
+use super::{Database, models::{player::SimplePlayer, punishment::{Punishment, PunishmentKind}}};
+
+// how far back a ban is recent enough for its target's name to be compared
+const SIMILAR_NAME_BAN_WINDOW_MS : u64 = 30 * 86_400_000;
+// the recent bans are kept in redis this long, so a burst of joins loads them once
+pub const RECENT_BANNED_CACHE_KEY : &str = "recent_banned";
+pub const RECENT_BANNED_CACHE_MS : usize = 60_000;
+
+impl Database {
+    // the recent bans `find_recent_banned` loads once the cached list has expired
+    pub async fn ensure_recent_ban_index(&self) -> anyhow::Result<()> {
+        self.punishments.create_index(IndexModel::builder().keys(doc! { "action.kind": 1, "issuedAt": -1 }).build(), None).await?;
+        Ok(())
+    }
+
+    // targets of active bans issued in the last 30 days, each once
+    pub async fn find_recent_banned(&self) -> anyhow::Result<Vec<SimplePlayer>> {
+        let banned_after = get_u64_time_millis().saturating_sub(SIMILAR_NAME_BAN_WINDOW_MS);
+        let cursor = self.punishments.find(doc! {
+            "action.kind": { "$in": [PunishmentKind::Ban.to_string(), PunishmentKind::IpBan.to_string()] },
+            "issuedAt": { "$gte": banned_after as i64 },
+            "reversion": null
+        }, None).await?;
+        let mut banned : Vec<SimplePlayer> = Vec::new();
+        for punishment in Database::consume_cursor_into_owning_vec::<Punishment>(cursor).await.into_iter() {
+            if punishment.is_active() && !banned.iter().any(|player| player.id == punishment.target.id) {
+                banned.push(punishment.target);
+            };
+        };
+        Ok(banned)
+    }
+}
+
+// the banned players whose name is within `max_distance` edits of `name`, ignoring case. only a hint for
+// staff, plenty of unrelated players have similar names
+pub fn name_similar_banned(name: &str, banned: &[SimplePlayer], max_distance: usize) -> Vec<SimplePlayer> {
+    let name = name.to_lowercase();
+    banned.iter().filter(|player| edit_distance(&name, &player.name.to_lowercase()) <= max_distance).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::models::player::SimplePlayer;
+
+    use super::name_similar_banned;
+
+    #[test]
+    fn similar_names_ignore_case() {
+        let banned = vec![
+            SimplePlayer { name: String::from("Notch"), id: String::from("notch") },
+            SimplePlayer { name: String::from("Dinnerbone"), id: String::from("dinnerbone") }
+        ];
+        let similar = name_similar_banned("n0TCH", &banned, 1);
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].id, "notch");
+        assert!(name_similar_banned("Jeb", &banned, 1).is_empty());
+    }
+}
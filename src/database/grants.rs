@@ -0,0 +1,92 @@
+use futures::TryStreamExt;
+use mongodb::{bson::{doc, Document}, options::FindOptions};
+use serde::{Deserialize, Serialize};
+
+use super::Database;
+
+// who a bulk grant applies to
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PlayerSelector {
+    PlayerIds(Vec<String>),
+    // anyone with a session still open or ended at or after this time
+    PlayedSince(u64)
+}
+
+#[derive(Clone, Copy)]
+pub enum PlayerGrant {
+    Tag,
+    Rank
+}
+
+impl PlayerGrant {
+    fn field(&self) -> &'static str {
+        match self {
+            Self::Tag => "tagIds",
+            Self::Rank => "rankIds"
+        }
+    }
+
+    // where grants that staff can only revoke with `force` are recorded
+    fn protected_field(&self) -> Option<&'static str> {
+        match self {
+            Self::Tag => None,
+            Self::Rank => Some("autoRankIds")
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkGrantResponse {
+    pub modified: u64,
+    // selected players left alone because the grant was automatic and `force` wasn't given
+    pub protected: u64,
+    // names of the modified players, so their cached copies can be dropped
+    #[serde(skip)]
+    pub player_names: Vec<String>
+}
+
+impl Database {
+    async fn selector_filter(&self, selector: &PlayerSelector) -> anyhow::Result<Document> {
+        let player_ids : Vec<String> = match selector {
+            PlayerSelector::PlayerIds(player_ids) => player_ids.clone(),
+            PlayerSelector::PlayedSince(since) => {
+                let filter = doc! { "$or": [{ "endedAt": { "$gte": *since as i64 } }, { "endedAt": null }] };
+                self.sessions.distinct("player.id", filter, None).await?.into_iter()
+                    .filter_map(|id| id.as_str().map(|id| id.to_owned())).collect()
+            }
+        };
+        Ok(doc! { "_id": { "$in": player_ids } })
+    }
+
+    // `$addToSet` and `$pull` both leave players that already match alone, so only real changes are counted.
+    // revoking skips players who were granted it automatically unless `force` is set, like revoking from one player
+    pub async fn bulk_grant(&self, grant: PlayerGrant, id: &str, selector: &PlayerSelector, revoke: bool, force: bool) -> anyhow::Result<BulkGrantResponse> {
+        let field = grant.field();
+        let mut filter = self.selector_filter(selector).await?;
+        let mut protected = 0;
+        if revoke {
+            filter.insert(field, id);
+            if let Some(protected_field) = grant.protected_field().filter(|_| !force) {
+                let mut protected_filter = filter.clone();
+                protected_filter.insert(protected_field, id);
+                protected = self.players.count_documents(protected_filter, None).await?;
+                filter.insert(protected_field, doc! { "$ne": id });
+            };
+        } else {
+            filter.insert(field, doc! { "$ne": id });
+        };
+
+        let projection = FindOptions::builder().projection(doc! { "name": 1 }).build();
+        let player_names : Vec<String> = self.players.clone_with_type::<Document>().find(filter.clone(), projection).await?
+            .try_collect::<Vec<Document>>().await?.iter()
+            .filter_map(|player| player.get_str("name").ok().map(|name| name.to_owned())).collect();
+
+        let mut update = Document::new();
+        update.insert(field, id);
+        let update = if revoke { doc! { "$pull": update } } else { doc! { "$addToSet": update } };
+        let modified = self.players.update_many(filter, update, None).await?.modified_count;
+        Ok(BulkGrantResponse { modified, protected, player_names })
+    }
+}
@@ -0,0 +1,83 @@
+use futures::TryStreamExt;
+use mongodb::{bson::{doc, Document}, IndexModel};
+use serde::{Deserialize, Serialize};
+
+use super::{Database, models::player::SimplePlayer};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeadToHead {
+    pub player: SimplePlayer,
+    pub opponent: SimplePlayer,
+    // times `player` killed `opponent`
+    pub kills: u64,
+    // times `opponent` killed `player`
+    pub deaths: u64,
+    // matches where at least one of them killed the other. matches don't record who won,
+    // so there is no win/loss record between the two
+    pub matches: u64
+}
+
+#[derive(Debug)]
+pub enum HeadToHeadError {
+    // deaths between the two that only carry names, counted from the ones that could be found
+    MissingPlayerIds(u64),
+    Other(mongodb::error::Error)
+}
+
+impl From<mongodb::error::Error> for HeadToHeadError {
+    fn from(e: mongodb::error::Error) -> Self {
+        HeadToHeadError::Other(e)
+    }
+}
+
+impl Database {
+    pub async fn ensure_head_to_head_indexes(&self) -> anyhow::Result<()> {
+        self.deaths.create_indexes(vec![
+            IndexModel::builder().keys(doc! { "attacker.id": 1, "victim.id": 1 }).build(),
+            IndexModel::builder().keys(doc! { "victim.id": 1, "attacker.name": 1 }).build()
+        ], None).await?;
+        Ok(())
+    }
+
+    pub async fn head_to_head(&self, player: &SimplePlayer, opponent: &SimplePlayer) -> Result<HeadToHead, HeadToHeadError> {
+        let pipeline = vec![
+            doc! { "$match": { "$or": [
+                { "attacker.id": &player.id, "victim.id": &opponent.id },
+                { "attacker.id": &opponent.id, "victim.id": &player.id }
+            ] } },
+            doc! { "$group": {
+                "_id": "$attacker.id",
+                "count": { "$sum": 1 },
+                "matches": { "$addToSet": "$matchId" }
+            } }
+        ];
+        let mut cursor = self.deaths.clone_with_type::<Document>().aggregate(pipeline, None).await?;
+        let mut head_to_head = HeadToHead { player: player.clone(), opponent: opponent.clone(), kills: 0, deaths: 0, matches: 0 };
+        let mut match_ids : Vec<String> = Vec::new();
+        while let Some(group) = cursor.try_next().await? {
+            let count = group.get_i32("count").map(|count| count as u64).or_else(|_| group.get_i64("count").map(|count| count as u64)).unwrap_or(0);
+            match group.get_str("_id") {
+                Ok(attacker_id) if attacker_id == player.id => head_to_head.kills = count,
+                Ok(_) => head_to_head.deaths = count,
+                Err(_) => continue
+            };
+            if let Ok(matches) = group.get_array("matches") {
+                match_ids.extend(matches.iter().filter_map(|match_id| match_id.as_str().map(|match_id| match_id.to_owned())));
+            };
+        };
+        match_ids.sort();
+        match_ids.dedup();
+        head_to_head.matches = match_ids.len() as u64;
+
+        // older deaths may name the attacker without an id, those would be silently left out of the counts
+        let untracked = self.deaths.count_documents(doc! { "$or": [
+            { "victim.id": &player.id, "attacker.name": &opponent.name, "attacker.id": { "$exists": false } },
+            { "victim.id": &opponent.id, "attacker.name": &player.name, "attacker.id": { "$exists": false } }
+        ] }, None).await?;
+        if untracked > 0 {
+            return Err(HeadToHeadError::MissingPlayerIds(untracked));
+        };
+        Ok(head_to_head)
+    }
+}
@@ -0,0 +1,38 @@
+use mongodb::{bson::{doc, Bson}, options::UpdateModifications, IndexModel};
+
+use super::{Database, KeysetPage, models::r#match::Match};
+
+impl Database {
+    pub async fn ensure_match_history_indexes(&self) -> anyhow::Result<()> {
+        self.matches.create_index(IndexModel::builder().keys(doc! { "participantIds": 1, "loadedAt": -1 }).build(), None).await?;
+        Ok(())
+    }
+
+    // fills `participantIds` on matches stored before it was kept, from the keys of their participants
+    pub async fn migrate_match_participant_ids(&self) {
+        let pipeline = vec![doc! { "$set": { "participantIds": { "$map": {
+            "input": { "$objectToArray": { "$ifNull": ["$participants", {}] } },
+            "as": "participant",
+            "in": "$$participant.k"
+        } } } }];
+        match self.matches.update_many(doc! {
+            "participantIds": { "$exists": false }
+        }, UpdateModifications::Pipeline(pipeline), None).await {
+            Ok(result) => if result.modified_count > 0 { info!("Indexed participants of {} matches", result.modified_count) },
+            Err(e) => warn!("Could not index match participants: {}", e)
+        };
+    }
+
+    // newest first, `after` is the `loadedAt` of the last match of the previous page. only matches of `realm`,
+    // matches of the default realm have none
+    pub async fn get_player_matches(&self, player_id: &str, realm: &Option<String>, after: Option<u64>, limit: i64) -> anyhow::Result<KeysetPage<Match>> {
+        Database::paginate_keyset(
+            &self.matches,
+            Some(doc! { "participantIds": player_id, "realm": realm.clone() }),
+            "loadedAt",
+            true,
+            after.map(|after| Bson::Int64(after as i64)),
+            limit
+        ).await
+    }
+}
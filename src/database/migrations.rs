@@ -0,0 +1,34 @@
+use mongodb::{bson::{doc, Document}, options::UpdateOptions};
+
+use crate::util::time::get_u64_time_millis;
+
+use super::Database;
+
+pub const MIGRATION_COLLECTION_NAME : &str = "migrations";
+
+// startup migrations that have to scan a whole collection record that they finished here, so they
+// run once instead of on every start. a migration that fails part way is not marked and runs again
+impl Database {
+    pub async fn has_migrated(&self, name: &str) -> bool {
+        let migrations = self.mongo.collection::<Document>(MIGRATION_COLLECTION_NAME);
+        match migrations.find_one(doc! { "_id": name }, None).await {
+            Ok(marker) => marker.is_some(),
+            Err(e) => {
+                // running a migration twice is harmless, skipping one is not
+                warn!("Could not check whether migration '{}' ran: {}", name, e);
+                false
+            }
+        }
+    }
+
+    pub async fn mark_migrated(&self, name: &str) {
+        let migrations = self.mongo.collection::<Document>(MIGRATION_COLLECTION_NAME);
+        if let Err(e) = migrations.update_one(
+            doc! { "_id": name },
+            doc! { "$setOnInsert": { "completedAt": get_u64_time_millis() as i64 } },
+            UpdateOptions::builder().upsert(Some(true)).build()
+        ).await {
+            warn!("Could not mark migration '{}' as done, it runs again on the next start: {}", name, e);
+        };
+    }
+}
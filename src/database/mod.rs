@@ -1,38 +1,108 @@
-use std::{str::FromStr, time::Duration};
+use std::{str::FromStr, time::Duration, collections::HashMap, future::Future, sync::Arc};
 
 use mars_api_rs_macro::IdentifiableDocument;
-use mongodb::{options::{ClientOptions, FindOneOptions, UpdateOptions}, Client, Collection, bson::{doc, oid::ObjectId}, Cursor, results::DeleteResult};
+use mongodb::{error::{ErrorKind, WriteFailure}, options::{ClientOptions, FindOneAndUpdateOptions, FindOneOptions, FindOptions, ReturnDocument, UpdateOptions, UpdateModifications, IndexOptions}, IndexModel, Client, Collection, bson::{doc, oid::ObjectId, Bson, Document}, Cursor, results::DeleteResult};
 use models::tag::Tag;
 use rand::Rng;
 use rocket::serde::DeserializeOwned;
-use futures::StreamExt;
+use futures::{Stream, StreamExt, TryStreamExt};
 use serde::Serialize;
 use anyhow::anyhow;
 
-use crate::{database::models::player::Player, util::r#macro::unwrap_helper};
-use crate::util::validation::verbose_result_ok;
+use crate::{database::models::{player::{Player, SimplePlayer}, damage::DAMAGE_SCALE}, util::r#macro::unwrap_helper};
+use crate::util::{validation::verbose_result_ok, metrics::{Metrics, MongoCommandMetrics}};
 
-use self::models::{achievement::Achievement, death::Death, level::Level, r#match::Match, punishment::Punishment, rank::Rank, session::Session};
+use self::decode::{LoadedDocuments, LoadError, load_documents};
+use self::write_concern::WriteConcerns;
+use self::models::{achievement::Achievement, death::Death, level::Level, r#match::Match, punishment::{Punishment, LinkedAccount, generate_punishment_code, normalize_punishment_code}, rank::Rank, session::Session, leaderboard_snapshot::LeaderboardSnapshot, report::Report, shadow_punishment::ShadowPunishment, damage_event::DamageEvent, audit_log::AuditLogEntry};
 
 pub mod models;
 pub mod cache;
+pub mod erasure;
+pub mod usage;
+pub mod damage_events;
+pub mod death_writer;
+pub mod grants;
+pub mod audit;
+pub mod head_to_head;
+pub mod search;
+pub mod rotation;
+pub mod decode;
+pub mod match_history;
+pub mod corrections;
+pub mod aggregate;
+pub mod evasion;
+pub mod resolve;
+pub mod write_concern;
+pub mod migrations;
 
 pub trait CollectionOwner<T> {
     fn get_collection(database: &Database) -> &Collection<T>;
     fn get_collection_name() -> &'static str;
 }
 
+pub(crate) const DUPLICATE_KEY_ERROR_CODE : i32 = 11000;
+const PUNISHMENT_CODE_ATTEMPTS : u32 = 5;
+
+#[derive(Debug)]
+pub enum InsertError {
+    // a document with the same _id already exists, the caller decides whether to update it instead
+    DuplicateKey,
+    Other(mongodb::error::Error)
+}
+
+impl From<mongodb::error::Error> for InsertError {
+    fn from(e: mongodb::error::Error) -> Self {
+        match e.kind.as_ref() {
+            ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == DUPLICATE_KEY_ERROR_CODE => InsertError::DuplicateKey,
+            _ => InsertError::Other(e)
+        }
+    }
+}
+
+impl std::fmt::Display for InsertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DuplicateKey => write!(f, "A document with that id already exists"),
+            Self::Other(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl std::error::Error for InsertError {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DocumentProgress {
+    // documents read so far, including the current batch
+    pub processed: u64,
+    pub total: u64
+}
+
+#[derive(Serialize)]
+pub struct KeysetPage<T> {
+    pub items: Vec<T>,
+    // value of the sort field to pass as `after` for the next page, absent on the last page
+    pub next: Option<Bson>
+}
+
 pub struct Database {
     pub mongo: mongodb::Database,
     pub tags: Collection<Tag>,
     pub achievements: Collection<Achievement>,
     pub players: Collection<Player>,
     pub sessions: Collection<Session>,
+    // ended sessions moved out of `sessions` by rotation
+    pub session_archive: Collection<Session>,
     pub punishments: Collection<Punishment>,
     pub ranks: Collection<Rank>,
     pub matches: Collection<Match>,
     pub deaths: Collection<Death>,
-    pub levels: Collection<Level>
+    pub levels: Collection<Level>,
+    pub leaderboard_snapshots: Collection<LeaderboardSnapshot>,
+    pub reports: Collection<Report>,
+    pub shadow_punishments: Collection<ShadowPunishment>,
+    pub damage_events: Collection<DamageEvent>,
+    pub audit_log: Collection<AuditLogEntry>
 }
 
 impl Database {
@@ -55,17 +125,67 @@ impl Database {
 
     pub async fn get_all_documents<T>(&self) -> Vec<T> 
         where T: DeserializeOwned + Serialize + IdentifiableDocument + CollectionOwner<T> + Unpin + Send + Sync {
-        // Self::consume_cursor_into_owning_vec_option(T::get_collection(&self).find(doc! {}, None).await.ok()).await
-        let cursor = match T::get_collection(&self).find(None, None).await {
-            Ok(cursor) => cursor,
+        match self.load_all_documents::<T>(false).await {
+            Ok(loaded) => loaded.documents,
             Err(e) => {
                 warn!("Error retrieving documents from '{}': {}", T::get_collection_name(), e);
-                return Vec::new();
+                Vec::new()
             }
+        }
+    }
+
+    // for callers that report the documents that could not be loaded, see `decode::load_documents`
+    pub async fn load_all_documents<T>(&self, strict: bool) -> Result<LoadedDocuments<T>, LoadError>
+        where T: DeserializeOwned + Serialize + IdentifiableDocument + CollectionOwner<T> + Unpin + Send + Sync {
+        load_documents(T::get_collection(self), None, None, strict).await
+    }
+
+    // hands the collection to `f` `batch_size` documents at a time, only one batch is held at once. `total` is the
+    // estimated count from when the walk started, documents that fail to deserialize are skipped but still counted
+    pub async fn for_each_document<T, F, Fut>(&self, batch_size: usize, mut f: F) -> u64
+        where T: DeserializeOwned + Serialize + IdentifiableDocument + CollectionOwner<T> + Unpin + Send + Sync,
+              F: FnMut(Vec<T>, DocumentProgress) -> Fut,
+              Fut: Future<Output = ()> {
+        let batch_size = batch_size.max(1);
+        let collection = T::get_collection(self).clone_with_type::<Document>();
+        let total = collection.estimated_document_count(None).await.unwrap_or(0);
+        let options = FindOptions::builder().batch_size(u32::try_from(batch_size).unwrap_or(u32::MAX)).build();
+        let cursor = match collection.find(None, options).await {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                warn!("Error paging through '{}': {}", T::get_collection_name(), e);
+                return 0;
+            }
+        };
+        let mut batches = Box::pin(cursor.chunks(batch_size));
+        let mut processed = 0u64;
+        while let Some(batch) = batches.next().await {
+            processed += batch.len() as u64;
+            let documents : Vec<T> = batch.into_iter()
+                .filter_map(|document| verbose_result_ok(String::from("Cursor error"), document))
+                .filter_map(|document| verbose_result_ok(String::from("Deserialization error"), mongodb::bson::from_document::<T>(document)))
+                .collect();
+            f(documents, DocumentProgress { processed, total: total.max(processed) }).await;
         };
-        Self::consume_cursor_into_owning_vec_option(Some(cursor)).await
+        processed
     }
 
+    // yields documents as the cursor fetches batches instead of collecting them, documents that fail to deserialize are skipped
+    pub async fn stream_collection<T>(&self) -> impl Stream<Item = T>
+        where T: DeserializeOwned + Serialize + IdentifiableDocument + CollectionOwner<T> + Unpin + Send + Sync {
+        let cursor = match T::get_collection(self).find(None, None).await {
+            Ok(cursor) => Some(cursor),
+            Err(e) => {
+                warn!("Error streaming documents from '{}': {}", T::get_collection_name(), e);
+                None
+            }
+        };
+        futures::stream::iter(cursor).flatten().filter_map(|result| futures::future::ready(
+            verbose_result_ok(String::from("Deserialization error"), result)
+        ))
+    }
+
+    // exact id or name in one query, `find_by_search` covers name history and prefixes
     pub async fn find_by_id_or_name<T>(&self, text: &str) -> Option<T>
         where T: DeserializeOwned + Serialize + IdentifiableDocument + CollectionOwner<T> + Unpin + Send + Sync {
             T::get_collection(&self).find_one(doc! {"$or": [{"nameLower": text.to_lowercase() }, {"_id": &text }]}, None).await.ok().unwrap_or(None)
@@ -118,25 +238,92 @@ impl Database {
         }
     }
 
+    // newest first, sessions that have been archived are not included
+    pub async fn get_recent_player_sessions(&self, player: &Player, limit: i64) -> Vec<Session> {
+        let opts = FindOptions::builder().sort(doc! { "createdAt": -1 }).limit(limit).build();
+        let cursor = unwrap_helper::result_return_default!(self.sessions.find(doc! { "player.id": player.id.to_owned() }, opts).await, Vec::new());
+        Database::consume_cursor_into_owning_vec(cursor).await
+    }
+
+    // active sessions keyed by player id, one query regardless of how many players are asked for
+    pub async fn get_active_sessions_for_players(&self, player_ids: &[String]) -> HashMap<String, Session> {
+        let cursor = unwrap_helper::result_return_default!(self.sessions.find(doc! {
+            "endedAt": null, "player.id": { "$in": player_ids }
+        }, None).await, HashMap::new());
+        Database::consume_cursor_into_owning_vec(cursor).await.into_iter()
+            .map(|session| (session.player.id.clone(), session))
+            .collect()
+    }
+
+    // stored copies of matches that never ended, the cached copy may be further along
+    pub async fn get_open_matches(&self) -> Vec<Match> {
+        Database::consume_cursor_into_owning_vec_option(self.matches.find(doc! {
+            "endedAt": null
+        }, None).await.ok()).await
+    }
+
+    pub async fn get_servers_with_active_sessions(&self) -> Vec<String> {
+        match self.sessions.distinct("serverId", doc! { "endedAt": null }, None).await {
+            Ok(server_ids) => server_ids.into_iter().filter_map(|id| id.as_str().map(|id| id.to_owned())).collect(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    // players with an open session of `realm` on one of `live_server_ids`, once each even if they have several
+    pub async fn get_online_players(&self, live_server_ids: &[String], realm: &Option<String>) -> Vec<SimplePlayer> {
+        let sessions = Database::consume_cursor_into_owning_vec_option(self.sessions.find(doc! {
+            "endedAt": null, "serverId": { "$in": live_server_ids }, "realm": realm.clone()
+        }, None).await.ok()).await;
+        let mut players : HashMap<String, SimplePlayer> = HashMap::new();
+        for session in sessions.into_iter() {
+            players.entry(session.player.id.clone()).or_insert(session.player);
+        };
+        let mut players : Vec<SimplePlayer> = players.into_values().collect();
+        players.sort_by_key(|player| player.name.to_lowercase());
+        players
+    }
+
+    // online players, as `get_online_players` counts them, holding at least one rank flagged as staff
+    pub async fn count_online_staff(&self, live_server_ids: &[String], realm: &Option<String>) -> usize {
+        let player_ids : Vec<String> = self.get_online_players(live_server_ids, realm).await.into_iter().map(|player| player.id).collect();
+        if player_ids.is_empty() {
+            return 0;
+        };
+        let staff_rank_ids : Vec<String> = Database::consume_cursor_into_owning_vec_option(self.ranks.find(doc! { "staff": true }, None).await.ok()).await
+            .into_iter().map(|rank| rank.id).collect();
+        if staff_rank_ids.is_empty() {
+            return 0;
+        };
+        self.players.count_documents(doc! {
+            "_id": { "$in": player_ids }, "rankIds": { "$in": staff_rank_ids }
+        }, None).await.unwrap_or(0) as usize
+    }
+
     pub async fn get_player_punishments(&self, player: &Player) -> Vec<Punishment> {
-        if let Ok(punishments_cursor) = self.punishments.find(doc! { "target.id": player.id.to_owned() }, None).await {
-            let mut puns : Vec<Punishment> = vec![];
-            for pun_result in punishments_cursor.collect::<Vec<Result<_, _>>>().await.into_iter() {
-                if let Ok(pun) = pun_result { 
-                    puns.push(pun);
-                };
-            };
-            puns
-        } else { vec![] }
+        match self.load_player_punishments(player, false).await {
+            Ok(loaded) => loaded.documents,
+            Err(_) => vec![]
+        }
+    }
+
+    pub async fn load_player_punishments(&self, player: &Player, strict: bool) -> Result<LoadedDocuments<Punishment>, LoadError> {
+        load_documents(&self.punishments, doc! { "target.id": player.id.to_owned() }, None, strict).await
+    }
+
+    // the first `limit` punishments and how many the player has in total
+    // newest first, so a player with more punishments than `limit` gets their most recent ones
+    pub async fn get_player_punishments_limited(&self, player: &Player, limit: i64, strict: bool) -> Result<(LoadedDocuments<Punishment>, u64), LoadError> {
+        let filter = doc! { "target.id": player.id.to_owned() };
+        let total = self.punishments.count_documents(filter.clone(), None).await?;
+        let opts = FindOptions::builder().sort(doc! { "issuedAt": -1 }).limit(limit).build();
+        Ok((load_documents(&self.punishments, filter, opts, strict).await?, total))
     }
 
 
     pub async fn get_active_player_punishments(&self, player: &Player) -> Vec<Punishment> {
         let mut puns : Vec<Punishment> = self.get_player_punishments(player).await;
         puns.retain(|p| p.is_active());
-        puns.sort_by(|p1, p2| {
-            p1.issued_at.partial_cmp(&(p2.issued_at)).unwrap_or(std::cmp::Ordering::Equal)
-        });
+        puns.sort_by_key(|p| p.issued_at);
         puns
     }
 
@@ -147,13 +334,77 @@ impl Database {
         }
     }
 
+    // reopens the session if it was on `server_id` and ended at `ended_after` or later. the update only matches
+    // while the session is still ended as it was read, so two logins can't both pick it up
+    pub async fn resume_session(&self, session_id: &str, server_id: &str, ended_after: u64, ip: &str, now: u64) -> Option<Session> {
+        let session = self.sessions.find_one(doc! { "_id": session_id }, None).await.ok().flatten()?;
+        if !session.is_resumable(server_id, ended_after) {
+            return None;
+        };
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        self.sessions.find_one_and_update(doc! {
+            "_id": session_id, "endedAt": session.ended_at.map(|ended_at| ended_at.millis() as i64)
+        }, doc! {
+            "$set": { "endedAt": Bson::Null, "resumedAt": now as i64, "ip": ip }
+        }, options).await.ok().flatten()
+    }
+
+    pub async fn ensure_player_ip_index(&self) -> anyhow::Result<()> {
+        self.players.create_index(IndexModel::builder().keys(doc! { "ips.ip": 1 }).build(), None).await?;
+        Ok(())
+    }
+
     pub async fn get_alts_for_player(&self, player: &Player) -> Vec<Player> {
         let cursor = unwrap_helper::result_return_default!(self.players.find(doc! {
-            "ips": {"$in": &player.ips}, "_id": {"$ne": &player.id}
+            "ips.ip": {"$in": player.ip_addresses()}, "_id": {"$ne": &player.id}
         }, None).await, Vec::new());
         Database::consume_cursor_into_owning_vec(cursor).await
     }
 
+    // alts sharing at least `min_confidence` of the player's ips, closest first
+    pub async fn get_linked_accounts(&self, player: &Player, min_confidence: f64) -> Vec<LinkedAccount> {
+        let ips = player.ip_addresses();
+        if ips.is_empty() {
+            return Vec::new();
+        };
+        let mut linked : Vec<LinkedAccount> = self.get_alts_for_player(player).await.iter().filter_map(|alt| {
+            let alt_ips = alt.ip_addresses();
+            let shared_ips = ips.iter().filter(|ip| alt_ips.contains(ip)).count() as u32;
+            let confidence = shared_ips as f64 / ips.len() as f64;
+            (confidence >= min_confidence).then(|| LinkedAccount { player: alt.to_simple(), confidence, shared_ips, applied_punishment_id: None })
+        }).collect();
+        linked.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        linked
+    }
+
+    pub async fn get_players_on_ips(&self, ips: &[String], exclude_id: &str) -> Vec<Player> {
+        if ips.is_empty() {
+            return Vec::new();
+        };
+        let cursor = unwrap_helper::result_return_default!(self.players.find(doc! {
+            "ips.ip": {"$in": ips}, "_id": {"$ne": exclude_id}
+        }, None).await, Vec::new());
+        Database::consume_cursor_into_owning_vec(cursor).await
+    }
+
+    // `id/name` of players whose last join falls in (`after`, `before`], in the form the leaderboards use
+    pub async fn get_players_last_joined_between(&self, after: u64, before: u64) -> Vec<String> {
+        let options = FindOptions::builder().projection(doc! { "_id": 1, "name": 1 }).build();
+        let cursor = unwrap_helper::result_return_default!(self.players.clone_with_type::<Document>().find(doc! {
+            "lastJoinedAt": { "$gt": after as f64, "$lte": before as f64 }
+        }, options).await, Vec::new());
+        Database::consume_cursor_into_owning_vec(cursor).await.iter().filter_map(|player| {
+            Some(format!("{}/{}", player.get_str("_id").ok()?, player.get_str("name").ok()?))
+        }).collect()
+    }
+
+    // up to `size` random players, ones that fail to decode are left out
+    pub async fn sample_players(&self, size: u32) -> anyhow::Result<Vec<Player>> {
+        let cursor = self.players.aggregate(vec![doc! { "$sample": { "size": size as i64 } }], None).await?;
+        let documents : Vec<Document> = cursor.try_collect().await?;
+        Ok(documents.into_iter().filter_map(|document| mongodb::bson::from_document::<Player>(document).ok()).collect())
+    }
+
     pub async fn save<R>(&self, record: &R) where R: CollectionOwner<R> + Serialize + IdentifiableDocument {
         let collection = R::get_collection(&self);
         let bson = mongodb::bson::to_bson(record).unwrap();
@@ -164,16 +415,315 @@ impl Database {
         }, doc! { "$set": serialized }, Some(update_opts)).await;
     }
 
-    pub async fn insert_one<R>(&self, record: &R) where R: CollectionOwner<R> + Serialize + IdentifiableDocument {
+    pub async fn insert_one<R>(&self, record: &R) -> Result<(), InsertError> where R: CollectionOwner<R> + Serialize + IdentifiableDocument {
         let collection = R::get_collection(&self);
-        // let bson = mongodb::bson::to_bson(record).unwrap();
-        // let serialized = bson.as_document().unwrap().clone();
-        // let update_opts = UpdateOptions::builder().upsert(Some(true)).build();
-        // let doc = doc! {};
-        let _ = collection.insert_one(record, None).await;
-        // let _ = collection.update_one(doc! {
-        //     "_id": record.get_id_value()
-        // }, doc! { "$set": serialized }, Some(update_opts)).await;
+        match collection.insert_one(record, None).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(InsertError::from(e))
+        }
+    }
+
+    // codes are unique through a partial index, so two inserts racing for the same code cannot both
+    // succeed. the loser gets a duplicate key error and tries again with a fresh code, unless it lost
+    // on the id, which callers that pick their own ids use to guard against issuing twice
+    // the punishments it `supersedes` are only marked once it is in, so a failed insert deactivates nothing
+    pub async fn insert_punishment(&self, punishment: &mut Punishment) -> Result<(), InsertError> {
+        for _ in 0..PUNISHMENT_CODE_ATTEMPTS {
+            punishment.code = Some(generate_punishment_code());
+            match self.insert_one(punishment).await {
+                // the id itself was taken, a new code won't help
+                Err(InsertError::DuplicateKey) if Database::find_by_id(&self.punishments, &punishment.id).await.is_some() => return Err(InsertError::DuplicateKey),
+                Err(InsertError::DuplicateKey) => continue,
+                Ok(()) => {
+                    self.mark_superseded(punishment).await;
+                    return Ok(());
+                },
+                result => return result
+            };
+        };
+        warn!("Could not find a free code for punishment {} after {} attempts", punishment.id, PUNISHMENT_CODE_ATTEMPTS);
+        Err(InsertError::DuplicateKey)
+    }
+
+    async fn mark_superseded(&self, punishment: &Punishment) {
+        if punishment.supersedes.is_empty() {
+            return;
+        };
+        if let Err(e) = self.punishments.update_many(
+            doc! { "_id": { "$in": &punishment.supersedes }, "supersededBy": null },
+            doc! { "$set": { "supersededBy": &punishment.id } },
+            None
+        ).await {
+            warn!("Could not mark the punishments superseded by {}: {}", punishment.id, e);
+        };
+    }
+
+    // undoes `mark_superseded` once the superseding punishment is reverted, returns the punishments that count again
+    pub async fn restore_superseded(&self, punishment: &Punishment) -> Vec<Punishment> {
+        let filter = doc! { "supersededBy": &punishment.id };
+        let superseded = Self::consume_cursor_into_owning_vec_option(self.punishments.find(filter.clone(), None).await.ok()).await;
+        if superseded.is_empty() {
+            return superseded;
+        };
+        if let Err(e) = self.punishments.update_many(filter, doc! { "$set": { "supersededBy": Bson::Null } }, None).await {
+            warn!("Could not restore the punishments superseded by {}: {}", punishment.id, e);
+            return Vec::new();
+        };
+        superseded.into_iter().map(|mut restored| {
+            restored.superseded_by = None;
+            restored
+        }).collect()
+    }
+
+    pub async fn ensure_punishment_code_index(&self) -> anyhow::Result<()> {
+        let options = IndexOptions::builder()
+            .unique(true)
+            .partial_filter_expression(doc! { "code": { "$type": "string" } })
+            .build();
+        self.punishments.create_index(IndexModel::builder().keys(doc! { "code": 1 }).options(options).build(), None).await?;
+        Ok(())
+    }
+
+    pub async fn find_punishment_by_code(&self, code: &str) -> Option<Punishment> {
+        self.punishments.find_one(doc! { "code": normalize_punishment_code(code) }, None).await.ok().unwrap_or(None)
+    }
+
+    // pages on the last seen value of an indexed field instead of skipping, so deep pages cost the
+    // same as the first one. the sort field should be unique (or close to it) or ties at a page
+    // boundary will be skipped.
+    pub async fn paginate_keyset<T: DeserializeOwned>(
+        coll: &Collection<T>, 
+        filter: Option<Document>,
+        sort_field: &str, 
+        descending: bool,
+        after: Option<Bson>, 
+        limit: i64
+    ) -> anyhow::Result<KeysetPage<T>> {
+        let mut filter = filter.unwrap_or_default();
+        if let Some(after) = after {
+            let mut bound = Document::new();
+            bound.insert(if descending { "$lt" } else { "$gt" }, after);
+            filter.insert(sort_field, bound);
+        };
+        let mut sort = Document::new();
+        sort.insert(sort_field, if descending { -1 } else { 1 });
+        let opts = FindOptions::builder()
+            .sort(sort)
+            .limit(limit)
+            .build();
+        let documents : Vec<Document> = coll.clone_with_type::<Document>().find(filter, opts).await?
+            .collect::<Vec<_>>().await.into_iter()
+            .filter_map(|result| verbose_result_ok(String::from("Pagination error"), result))
+            .collect();
+
+        let next = if documents.len() as i64 == limit { 
+            documents.last().and_then(|document| document.get(sort_field).cloned()) 
+        } else { None };
+        let items = documents.into_iter()
+            .filter_map(|document| verbose_result_ok(String::from("Deserialization error"), mongodb::bson::from_document::<T>(document)))
+            .collect();
+        Ok(KeysetPage { items, next })
+    }
+
+    // copies the legacy single active tag into the prefix slot, players that already have a prefix slot are skipped
+    pub async fn migrate_active_tag_slots(&self) {
+        let pipeline = vec![doc! { "$set": { "activeTagIds.prefix": "$activeTagId" } }];
+        match self.players.update_many(doc! {
+            "activeTagId": { "$ne": null }, "activeTagIds.prefix": { "$exists": false }
+        }, UpdateModifications::Pipeline(pipeline), None).await {
+            Ok(result) => if result.modified_count > 0 { info!("Migrated {} active tags into tag slots", result.modified_count) },
+            Err(e) => warn!("Could not migrate active tags into tag slots: {}", e)
+        };
+    }
+
+    // turns damage totals stored as float health points into the integer thousandths stored now, totals
+    // that are already integers are left alone. per-match participant totals are left as they were, both read the same
+    pub async fn migrate_damage_fixed_point(&self) -> anyhow::Result<u64> {
+        let fields = ["damageGiven", "damageTaken", "damageGivenBow"];
+        let fixed_point = |stats: &str| {
+            let mut set = Document::new();
+            for field in fields.iter() {
+                let value = format!("{}.{}", stats, field);
+                set.insert(*field, doc! { "$cond": [
+                    { "$eq": [{ "$type": &value }, "double"] },
+                    { "$toLong": { "$round": [{ "$multiply": [&value, DAMAGE_SCALE] }, 0] } },
+                    &value
+                ] });
+            };
+            set
+        };
+        let floats = |stats: &str| fields.iter().map(|field| {
+            Bson::Document(doc! { "$eq": [{ "$type": format!("{}.{}", stats, field) }, "double"] })
+        }).collect::<Vec<Bson>>();
+        let gamemodes = doc! { "$objectToArray": { "$ifNull": ["$gamemodeStats", {}] } };
+
+        let filter = doc! { "$expr": { "$or": [
+            { "$or": floats("$stats") },
+            { "$anyElementTrue": [{ "$map": { "input": gamemodes.clone(), "as": "gamemode", "in": { "$or": floats("$$gamemode.v") } } }] }
+        ] } };
+        let mut stats_set = Document::new();
+        for (field, value) in fixed_point("$stats").into_iter() {
+            stats_set.insert(format!("stats.{}", field), value);
+        };
+        stats_set.insert("gamemodeStats", doc! { "$arrayToObject": { "$map": {
+            "input": gamemodes,
+            "as": "gamemode",
+            "in": { "k": "$$gamemode.k", "v": { "$mergeObjects": ["$$gamemode.v", fixed_point("$$gamemode.v")] } }
+        } } });
+        let result = self.players.update_many(filter, UpdateModifications::Pipeline(vec![doc! { "$set": stats_set }]), None).await?;
+        Ok(result.modified_count)
+    }
+
+    // playtimes are milliseconds stored as longs. older writers left doubles and ints behind, which are
+    // rounded and converted here, negative totals from overflowed ints are reset to 0
+    pub async fn migrate_playtime_units(&self) -> anyhow::Result<u64> {
+        let playtime = |stats: &str, field: &str| doc! { "$ifNull": [format!("{}.{}", stats, field), 0] };
+        let normalized = |stats: &str| doc! {
+            "serverPlaytime": { "$toLong": { "$max": [{ "$round": [playtime(stats, "serverPlaytime"), 0] }, 0] } },
+            "gamePlaytime": { "$toLong": { "$max": [{ "$round": [playtime(stats, "gamePlaytime"), 0] }, 0] } }
+        };
+        let mixed = |stats: &str| ["serverPlaytime", "gamePlaytime"].iter().map(|field| {
+            let value = playtime(stats, field);
+            Bson::Document(doc! { "$or": [
+                { "$ne": [{ "$type": value.clone() }, "long"] },
+                { "$lt": [value, 0] }
+            ] })
+        }).collect::<Vec<Bson>>();
+        let gamemodes = doc! { "$objectToArray": { "$ifNull": ["$gamemodeStats", {}] } };
+
+        let filter = doc! { "$expr": { "$or": [
+            { "$or": mixed("$stats") },
+            { "$anyElementTrue": [{ "$map": { "input": gamemodes.clone(), "as": "gamemode", "in": { "$or": mixed("$$gamemode.v") } } }] }
+        ] } };
+        let mut stats_set = Document::new();
+        for (field, value) in normalized("$stats").into_iter() {
+            stats_set.insert(format!("stats.{}", field), value);
+        };
+        stats_set.insert("gamemodeStats", doc! { "$arrayToObject": { "$map": {
+            "input": gamemodes,
+            "as": "gamemode",
+            "in": { "k": "$$gamemode.k", "v": { "$mergeObjects": ["$$gamemode.v", normalized("$$gamemode.v")] } }
+        } } });
+        let result = self.players.update_many(filter, UpdateModifications::Pipeline(vec![doc! { "$set": stats_set }]), None).await?;
+        Ok(result.modified_count)
+    }
+
+    // scales up playtimes that look like they were written in seconds: game playtime under a second per match
+    // played, or server playtime under a hundredth of the game playtime. nothing on a player says which unit
+    // its totals are in, so every player picked is logged, and a `dry_run` only logs and counts them
+    pub async fn migrate_playtime_seconds(&self, dry_run: bool) -> anyhow::Result<u64> {
+        let playtime = |stats: &str, field: &str| doc! { "$ifNull": [format!("{}.{}", stats, field), 0] };
+        let game_in_seconds = |stats: &str| doc! { "$and": [
+            { "$gt": [playtime(stats, "gamePlaytime"), 0] },
+            { "$lt": [playtime(stats, "gamePlaytime"), { "$multiply": [playtime(stats, "matches"), 1000] }] }
+        ] };
+        let game_millis = |stats: &str| doc! { "$cond": [
+            game_in_seconds(stats),
+            { "$multiply": [playtime(stats, "gamePlaytime"), 1000] },
+            playtime(stats, "gamePlaytime")
+        ] };
+        let server_in_seconds = |stats: &str| doc! { "$and": [
+            { "$gt": [playtime(stats, "serverPlaytime"), 0] },
+            { "$lt": [{ "$multiply": [playtime(stats, "serverPlaytime"), 100] }, game_millis(stats)] }
+        ] };
+        let server_millis = |stats: &str| doc! { "$cond": [
+            server_in_seconds(stats),
+            { "$multiply": [playtime(stats, "serverPlaytime"), 1000] },
+            playtime(stats, "serverPlaytime")
+        ] };
+        let scaled = |stats: &str| doc! { "serverPlaytime": server_millis(stats), "gamePlaytime": game_millis(stats) };
+        let in_seconds = |stats: &str| doc! { "$or": [game_in_seconds(stats), server_in_seconds(stats)] };
+        let gamemodes = doc! { "$objectToArray": { "$ifNull": ["$gamemodeStats", {}] } };
+
+        let filter = doc! { "$expr": { "$or": [
+            in_seconds("$stats"),
+            { "$anyElementTrue": [{ "$map": { "input": gamemodes.clone(), "as": "gamemode", "in": in_seconds("$$gamemode.v") } }] }
+        ] } };
+        let mut stats_set = Document::new();
+        for (field, value) in scaled("$stats").into_iter() {
+            stats_set.insert(format!("stats.{}", field), value);
+        };
+        stats_set.insert("gamemodeStats", doc! { "$arrayToObject": { "$map": {
+            "input": gamemodes,
+            "as": "gamemode",
+            "in": { "k": "$$gamemode.k", "v": { "$mergeObjects": ["$$gamemode.v", scaled("$$gamemode.v")] } }
+        } } });
+
+        let options = FindOptions::builder().projection(doc! { "_id": 1, "name": 1, "stats.serverPlaytime": 1, "stats.gamePlaytime": 1 }).build();
+        let mut cursor = self.players.clone_with_type::<Document>().find(filter.clone(), options).await?;
+        let mut rescaled = 0;
+        while let Some(player) = cursor.try_next().await? {
+            let id = player.get_str("_id").unwrap_or_default().to_owned();
+            let stats = player.get_document("stats").ok();
+            info!(
+                "{} playtime of {} ({}) from seconds, server playtime {:?}, game playtime {:?}",
+                if dry_run { "Would scale up" } else { "Scaling up" }, player.get_str("name").unwrap_or_default(), id,
+                stats.and_then(|stats| stats.get("serverPlaytime")), stats.and_then(|stats| stats.get("gamePlaytime"))
+            );
+            if !dry_run {
+                let mut matches = filter.clone();
+                matches.insert("_id", &id);
+                self.players.update_one(matches, UpdateModifications::Pipeline(vec![doc! { "$set": stats_set.clone() }]), None).await?;
+            };
+            rescaled += 1;
+        };
+        Ok(rescaled)
+    }
+
+    // turns the plain ip list into ip entries, all marked as last seen at the player's last join. the old
+    // list was in the order ips were first seen, so past `limit` the ones seen first are dropped
+    pub async fn migrate_player_ips(&self, limit: usize) -> anyhow::Result<u64> {
+        let pipeline = vec![doc! { "$set": { "ips": { "$slice": [{ "$map": {
+            "input": "$ips",
+            "in": { "ip": "$$this", "lastSeenAt": "$lastJoinedAt" }
+        } }, -(limit.min(i32::MAX as usize) as i32)] } } }];
+        let result = self.players.update_many(doc! {
+            "ips": { "$elemMatch": { "$type": "string" } }
+        }, UpdateModifications::Pipeline(pipeline), None).await?;
+        Ok(result.modified_count)
+    }
+
+    // seeds the history of players from before name tracking with their current name
+    pub async fn migrate_name_history(&self) {
+        let pipeline = vec![doc! { "$set": { "nameHistory": [{ "name": "$name", "nameLower": "$nameLower", "firstSeenAt": "$firstJoinedAt" }] } }];
+        match self.players.update_many(doc! {
+            "nameHistory": { "$exists": false }
+        }, UpdateModifications::Pipeline(pipeline), None).await {
+            Ok(result) => if result.modified_count > 0 { info!("Seeded name history for {} players", result.modified_count) },
+            Err(e) => warn!("Could not seed name history: {}", e)
+        };
+    }
+
+    pub async fn get_players_by_rank(&self, rank_id: &str) -> Vec<Player> {
+        Self::consume_cursor_into_owning_vec_option(self.players.find(doc! { "rankIds": rank_id }, None).await.ok()).await
+    }
+
+    // moves every holder of `from` to `to`, or strips `from` when `to` is None. players who already
+    // hold `to` keep a single copy, and a second run finds nothing left to modify. without `force`
+    // players granted `from` by a rank threshold are left alone
+    pub async fn reassign_rank(&self, from: &str, to: Option<&str>, force: bool) -> anyhow::Result<u64> {
+        let mut filter = doc! { "rankIds": from };
+        if !force {
+            filter.insert("autoRankIds", doc! { "$ne": from });
+        };
+        let result = match to {
+            Some(to) => {
+                let pipeline = vec![doc! { "$set": { "rankIds": { "$reduce": {
+                    "input": { "$map": { "input": "$rankIds", "in": { "$cond": [{ "$eq": ["$$this", from] }, to, "$$this"] } } },
+                    "initialValue": [],
+                    "in": { "$cond": [{ "$in": ["$$this", "$$value"] }, "$$value", { "$concatArrays": ["$$value", ["$$this"]] }] }
+                } } } }];
+                self.players.update_many(filter, UpdateModifications::Pipeline(pipeline), None).await?
+            },
+            None => self.players.update_many(filter, doc! { "$pull": { "rankIds": from } }, None).await?
+        };
+        Ok(result.modified_count)
+    }
+
+    // most recent player to have used the name, for staff looking up past offenders
+    pub async fn find_player_by_past_name(&self, name: &str) -> Option<Player> {
+        let opts = FindOneOptions::builder().sort(doc! { "lastJoinedAt": -1 }).build();
+        self.players.find_one(doc! { "nameHistory.nameLower": name.to_lowercase() }, opts).await.unwrap_or(None)
     }
 
     pub async fn find_by_name<R>(&self, name: &str) -> Option<R>
@@ -188,12 +738,13 @@ pub async fn ping_database(mongo: &mongodb::Database) -> bool {
     mongo.run_command(doc! { "ping": 1 }, None).await.is_ok()
 }
 
-pub async fn connect(db_url: &String, min_pool_size: Option<u32>, max_pool_size: Option<u32>) -> anyhow::Result<Database> {
+pub async fn connect(db_url: &String, min_pool_size: Option<u32>, max_pool_size: Option<u32>, write_concerns: &WriteConcerns, metrics: Arc<Metrics>) -> anyhow::Result<Database> {
     let mut client_options = ClientOptions::parse(db_url).await?;
     client_options.min_pool_size = min_pool_size;
     client_options.max_pool_size = max_pool_size;
     client_options.connect_timeout = Some(Duration::new(5, 0));
     client_options.server_selection_timeout = Some(Duration::new(5, 0));
+    client_options.command_event_handler = Some(Arc::new(MongoCommandMetrics(metrics)));
 
 
     let client = Client::with_options(client_options)?;
@@ -202,19 +753,45 @@ pub async fn connect(db_url: &String, min_pool_size: Option<u32>, max_pool_size:
         return Err(anyhow!("Could not connect to the database. Is it running?"));
     };
 
-    let tags = db.collection::<Tag>(Tag::get_collection_name());
-    let achievements = db.collection::<Achievement>(Achievement::get_collection_name());
-    let players = db.collection::<Player>(Player::get_collection_name());
-    let sessions = db.collection::<Session>(Session::get_collection_name());
-    let punishments = db.collection::<Punishment>(Punishment::get_collection_name());
-    let ranks = db.collection::<Rank>(Rank::get_collection_name());
-    let matches = db.collection::<Match>(Match::get_collection_name());
-    let levels = db.collection::<Level>(Level::get_collection_name());
-    let deaths = db.collection::<Death>(Death::get_collection_name());
+    let tags = write_concerns.collection::<Tag>(&db, Tag::get_collection_name());
+    let achievements = write_concerns.collection::<Achievement>(&db, Achievement::get_collection_name());
+    let players = write_concerns.collection::<Player>(&db, Player::get_collection_name());
+    let sessions = write_concerns.collection::<Session>(&db, Session::get_collection_name());
+    let session_archive = write_concerns.collection::<Session>(&db, rotation::SESSION_ARCHIVE_COLLECTION_NAME);
+    let punishments = write_concerns.collection::<Punishment>(&db, Punishment::get_collection_name());
+    let ranks = write_concerns.collection::<Rank>(&db, Rank::get_collection_name());
+    let matches = write_concerns.collection::<Match>(&db, Match::get_collection_name());
+    let levels = write_concerns.collection::<Level>(&db, Level::get_collection_name());
+    let deaths = write_concerns.collection::<Death>(&db, Death::get_collection_name());
+    let leaderboard_snapshots = write_concerns.collection::<LeaderboardSnapshot>(&db, LeaderboardSnapshot::get_collection_name());
+    let reports = write_concerns.collection::<Report>(&db, Report::get_collection_name());
+    let shadow_punishments = write_concerns.collection::<ShadowPunishment>(&db, ShadowPunishment::get_collection_name());
+    let damage_events = write_concerns.collection::<DamageEvent>(&db, DamageEvent::get_collection_name());
+    let audit_log = write_concerns.collection::<AuditLogEntry>(&db, AuditLogEntry::get_collection_name());
 
     info!("Connected to database successfully.");
     Ok(Database { 
-        mongo: db, tags, achievements, players, sessions, 
-        punishments, ranks, matches, levels, deaths 
+        mongo: db, tags, achievements, players, sessions, session_archive,
+        punishments, ranks, matches, levels, deaths,
+        leaderboard_snapshots, reports, shadow_punishments, damage_events, audit_log
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use mongodb::{bson::{self, doc}, error::{Error, ErrorKind, WriteError, WriteFailure}};
+
+    use super::{InsertError, DUPLICATE_KEY_ERROR_CODE};
+
+    fn write_error(code: i32) -> Error {
+        let write_error: WriteError = bson::from_document(doc! { "code": code, "errmsg": "E11000 duplicate key error" }).unwrap();
+        ErrorKind::Write(WriteFailure::WriteError(write_error)).into()
+    }
+
+    #[test]
+    fn a_second_insert_of_an_id_is_a_duplicate() {
+        assert!(matches!(InsertError::from(write_error(DUPLICATE_KEY_ERROR_CODE)), InsertError::DuplicateKey));
+        // validation failures and the like stay errors, they are not worth an update instead
+        assert!(matches!(InsertError::from(write_error(121)), InsertError::Other(_)));
+    }
+}
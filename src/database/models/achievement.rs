@@ -1,9 +1,12 @@
 use mars_api_rs_macro::IdentifiableDocument;
 use mars_api_rs_derive::IdentifiableDocument;
 use serde::{Deserialize, Serialize};
+use strum_macros::EnumString;
 
 use crate::database::CollectionOwner;
 
+use super::player::PlayerStats;
+
 impl CollectionOwner<Achievement> for Achievement {
     fn get_collection(database: &crate::database::Database) -> &mongodb::Collection<Achievement> {
         &database.achievements
@@ -24,7 +27,30 @@ pub struct Achievement {
     pub description: String,
     #[serde(rename = "category")]
     pub metadata: Option<AchievementMetadata>,
-    pub agent: Agent
+    pub agent: Agent,
+    #[serde(default)]
+    pub rarity: AchievementRarity,
+    // forces a network-wide announcement regardless of rarity
+    #[serde(default)]
+    pub broadcast: bool
+}
+
+impl Achievement {
+    pub fn is_network_broadcast(&self, rarity_threshold: &AchievementRarity) -> bool {
+        self.broadcast || &self.rarity >= rarity_threshold
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, EnumString)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum AchievementRarity {
+    #[default]
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -98,6 +124,32 @@ pub enum Agent {
     TotalLossesAgentParams { losses: u32 }
 }
 
+impl Agent {
+    // (current, target) for agents that count up over a player's lifetime stats,
+    // agents that depend on a single moment in a match have no meaningful progress
+    pub fn progress(&self, stats: &PlayerStats) -> Option<(u64, u64)> {
+        let objectives = &stats.objectives;
+        let (current, target) = match self {
+            Agent::TotalKillsAgentParams { target_kills } => (stats.kills as u64, *target_kills as u64),
+            Agent::LevelUpAgentParams { level } => (stats.get_level() as u64, *level as u64),
+            Agent::WoolCaptureAgentParams { captures } => (objectives.wool_captures as u64, *captures as u64),
+            Agent::FirstBloodAgentParams { target } => (stats.first_bloods as u64, *target as u64),
+            Agent::FlagCaptureAgentParams { captures } => (objectives.flag_captures as u64, *captures as u64),
+            Agent::FlagDefendAgentParams { defends } => (objectives.flag_defends as u64, *defends as u64),
+            Agent::WoolDefendAgentParams { defends } => (objectives.wool_defends as u64, *defends as u64),
+            Agent::MonumentDamageAgentParams { breaks } => (objectives.destroyable_block_destroys as u64, *breaks as u64),
+            Agent::MonumentDestroyAgentParams { destroys } => (objectives.destroyable_destroys as u64, *destroys as u64),
+            Agent::PlayTimeAgentParams { hours } => (stats.game_playtime / 3_600_000, *hours),
+            Agent::ControlPointCaptureAgentParams { captures } => (objectives.control_point_captures as u64, *captures as u64),
+            Agent::TotalWinsAgentParams { wins } => (stats.wins() as u64, *wins as u64),
+            Agent::TotalDeathsAgentParams { deaths } => (stats.deaths as u64, *deaths as u64),
+            Agent::TotalLossesAgentParams { losses } => (stats.losses() as u64, *losses as u64),
+            _ => return None
+        };
+        Some((current.min(target), target))
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum RecordType {
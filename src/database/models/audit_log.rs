@@ -0,0 +1,126 @@
+use mars_api_rs_derive::IdentifiableDocument;
+use mars_api_rs_macro::IdentifiableDocument;
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+use strum_macros::{Display, EnumString};
+
+use crate::{database::CollectionOwner, util::time::Timestamp};
+
+use super::player::SimplePlayer;
+
+// viewing the audit log requires a viewer whose ranks grant this
+pub const AUDIT_LOG_PERMISSION : &str = "mars.audit.view";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum AuditAction {
+    PunishmentIssue,
+    PunishmentRevert,
+    RankCreate,
+    RankUpdate,
+    RankDelete,
+    RankReassign,
+    RankGrant,
+    RankRevoke,
+    TagCreate,
+    TagUpdate,
+    TagDelete,
+    TagGrant,
+    TagRevoke,
+    NoteAdd,
+    NoteDelete,
+    StatsFreeze,
+    StatsUnfreeze,
+    StatsCorrect,
+    PlayerPrestige,
+    PlayerDataDelete,
+    LeaderboardReset,
+    LeaderboardRebuild,
+    ReportClose,
+    ShadowReview
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditTarget {
+    pub id: String,
+    pub name: String
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, IdentifiableDocument)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+    // object id hex, so ids sort in the order entries were written and can be paged on
+    #[id]
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub action: AuditAction,
+    // the staff member behind the action when the request names one
+    pub actor: Option<SimplePlayer>,
+    // the server whose token made the request, none for the admin token
+    pub server_id: Option<String>,
+    pub target: Option<AuditTarget>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    // why staff took the action, when the request gives a reason
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub created_at: Timestamp
+}
+
+impl AuditLogEntry {
+    pub fn new(action: AuditAction) -> Self {
+        AuditLogEntry {
+            id: ObjectId::new().to_hex(),
+            action,
+            actor: None,
+            server_id: None,
+            target: None,
+            before: None,
+            after: None,
+            reason: None,
+            created_at: Timestamp::now()
+        }
+    }
+
+    pub fn actor(mut self, actor: Option<SimplePlayer>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn server(mut self, server_id: &str) -> Self {
+        self.server_id = Some(server_id.to_owned());
+        self
+    }
+
+    pub fn target(mut self, id: &str, name: &str) -> Self {
+        self.target = Some(AuditTarget { id: id.to_owned(), name: name.to_owned() });
+        self
+    }
+
+    pub fn before(mut self, before: impl Into<String>) -> Self {
+        self.before = Some(before.into());
+        self
+    }
+
+    pub fn after(mut self, after: impl Into<String>) -> Self {
+        self.after = Some(after.into());
+        self
+    }
+
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+impl CollectionOwner<AuditLogEntry> for AuditLogEntry {
+    fn get_collection(database: &crate::database::Database) -> &mongodb::Collection<AuditLogEntry> {
+        &database.audit_log
+    }
+
+    fn get_collection_name() -> &'static str {
+        "audit_log"
+    }
+}
@@ -0,0 +1,95 @@
+use std::ops::{Add, AddAssign};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, Visitor}};
+
+// damage totals are kept in thousandths of a health point (a heart is two points) so that summing
+// them is exact. that integer is what mongo stores and what json carries, so stored and recomputed totals always agree
+pub const DAMAGE_SCALE : f64 = 1000.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Damage(i64);
+
+impl Damage {
+    pub fn from_points(points: f64) -> Self {
+        Damage((points * DAMAGE_SCALE).round() as i64)
+    }
+}
+
+impl Add for Damage {
+    type Output = Damage;
+
+    fn add(self, other: Damage) -> Damage {
+        Damage(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for Damage {
+    fn add_assign(&mut self, other: Damage) {
+        *self = *self + other;
+    }
+}
+
+impl Serialize for Damage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.0)
+    }
+}
+
+// integers are thousandths, which is all that is written now. floats are health points, which mongo
+// stored and json carried before damage was fixed-point, so those documents and cached players still read
+struct DamageVisitor;
+
+impl<'de> Visitor<'de> for DamageVisitor {
+    type Value = Damage;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a damage amount")
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Damage, E> {
+        Ok(Damage::from_points(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Damage, E> {
+        Ok(Damage(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Damage, E> {
+        self.visit_i64(i64::try_from(value).unwrap_or(i64::MAX))
+    }
+}
+
+impl<'de> Deserialize<'de> for Damage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DamageVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{self, Bson};
+    use rocket::serde::json::serde_json;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Stats {
+        damage: Damage
+    }
+
+    #[test]
+    fn damage_is_written_as_thousandths_and_read_from_points_too() {
+        let damage = Damage::from_points(1.0) + Damage::from_points(0.1) + Damage::from_points(0.2);
+
+        let stored = bson::to_raw_document_buf(&Stats { damage }).unwrap().to_document().unwrap();
+        assert_eq!(stored.get("damage"), Some(&Bson::Int64(1300)));
+        assert_eq!(bson::from_document::<Stats>(stored).unwrap().damage, damage);
+        // totals written as floats before damage was fixed-point
+        assert_eq!(bson::from_bson::<Damage>(Bson::Double(1.3000000000000003)).unwrap(), damage);
+
+        let json = serde_json::to_string(&damage).unwrap();
+        assert_eq!(json, "1300");
+        assert_eq!(serde_json::from_str::<Damage>(&json).unwrap(), damage);
+        assert_eq!(serde_json::from_str::<Damage>("1.3").unwrap(), damage);
+    }
+}
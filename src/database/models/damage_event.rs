@@ -0,0 +1,35 @@
+use mars_api_rs_derive::IdentifiableDocument;
+use mars_api_rs_macro::IdentifiableDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::{database::CollectionOwner, util::time::Timestamp};
+
+use super::death::DamageCause;
+
+// one hit as reported by the server. only player ids are kept, documents in a capped
+// collection cannot be deleted or resized so names could never be erased later
+#[derive(Deserialize, Serialize, IdentifiableDocument, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DamageEvent {
+    #[id]
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub victim_id: String,
+    pub attacker_id: Option<String>,
+    pub weapon: Option<String>,
+    pub cause: DamageCause,
+    pub damage: f64,
+    pub server_id: String,
+    pub match_id: Option<String>,
+    pub created_at: Timestamp
+}
+
+impl CollectionOwner<DamageEvent> for DamageEvent {
+    fn get_collection(database: &crate::database::Database) -> &mongodb::Collection<DamageEvent> {
+        &database.damage_events
+    }
+
+    fn get_collection_name() -> &'static str {
+        "damage_event"
+    }
+}
@@ -1,8 +1,9 @@
 use mars_api_rs_derive::IdentifiableDocument;
 use mars_api_rs_macro::IdentifiableDocument;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer, Deserializer};
+use strum::IntoEnumIterator;
 
-use crate::database::CollectionOwner;
+use crate::{database::CollectionOwner, util::time::Timestamp};
 
 use super::player::SimplePlayer;
 
@@ -21,7 +22,7 @@ pub struct Death {
     pub cause: DamageCause,
     pub server_id: String,
     pub match_id: String,
-    pub created_at: u64
+    pub created_at: Timestamp
 }
 
 impl CollectionOwner<Death> for Death {
@@ -34,8 +35,8 @@ impl CollectionOwner<Death> for Death {
     }
 }
 
-#[derive(Deserialize, Serialize, PartialEq, Eq, Clone)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+// stored with every death and damage event under `key`, so variants can be renamed but their keys never change
+#[derive(Debug, PartialEq, Eq, Clone, strum_macros::EnumIter)]
 pub enum DamageCause {
     Melee,
     Projectile,
@@ -52,5 +53,89 @@ pub enum DamageCause {
     Shock,
     Spleef,
     Void,
-    Unknown
+    // `UNKNOWN` from the plugin, or a cause this build does not know, kept as is so it is written back unchanged
+    Unknown(String)
+}
+
+impl DamageCause {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::Melee => "MELEE",
+            Self::Projectile => "PROJECTILE",
+            Self::Explosion => "EXPLOSION",
+            Self::Fire => "FIRE",
+            Self::Lava => "LAVA",
+            Self::Potion => "POTION",
+            Self::Flatten => "FLATTEN",
+            Self::Fall => "FALL",
+            Self::Prick => "PRICK",
+            Self::Drown => "DROWN",
+            Self::Starve => "STARVE",
+            Self::Suffocate => "SUFFOCATE",
+            Self::Shock => "SHOCK",
+            Self::Spleef => "SPLEEF",
+            Self::Void => "VOID",
+            Self::Unknown(key) => key
+        }
+    }
+
+    pub fn from_key(key: &str) -> Self {
+        Self::iter().find(|cause| !matches!(cause, Self::Unknown(_)) && cause.key() == key)
+            .unwrap_or_else(|| Self::Unknown(key.to_owned()))
+    }
+}
+
+impl Serialize for DamageCause {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.key())
+    }
+}
+
+impl<'de> Deserialize<'de> for DamageCause {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        Ok(Self::from_key(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn damage_causes_keep_their_stored_keys() {
+        let stored = [
+            (DamageCause::Melee, "MELEE"),
+            (DamageCause::Projectile, "PROJECTILE"),
+            (DamageCause::Explosion, "EXPLOSION"),
+            (DamageCause::Fire, "FIRE"),
+            (DamageCause::Lava, "LAVA"),
+            (DamageCause::Potion, "POTION"),
+            (DamageCause::Flatten, "FLATTEN"),
+            (DamageCause::Fall, "FALL"),
+            (DamageCause::Prick, "PRICK"),
+            (DamageCause::Drown, "DROWN"),
+            (DamageCause::Starve, "STARVE"),
+            (DamageCause::Suffocate, "SUFFOCATE"),
+            (DamageCause::Shock, "SHOCK"),
+            (DamageCause::Spleef, "SPLEEF"),
+            (DamageCause::Void, "VOID")
+        ];
+        assert_eq!(stored.len(), DamageCause::iter().count() - 1);
+        for (cause, key) in stored {
+            let bson = mongodb::bson::to_bson(&cause).unwrap();
+            assert_eq!(bson, mongodb::bson::Bson::String(key.to_owned()));
+            assert_eq!(mongodb::bson::from_bson::<DamageCause>(bson).unwrap(), cause);
+        };
+    }
+
+    #[test]
+    fn unknown_damage_causes_round_trip() {
+        for key in ["UNKNOWN", "FREEZE"] {
+            let bson = mongodb::bson::Bson::String(key.to_owned());
+            let cause : DamageCause = mongodb::bson::from_bson(bson.clone()).unwrap();
+            assert_eq!(cause, DamageCause::Unknown(key.to_owned()));
+            assert_eq!(mongodb::bson::to_bson(&cause).unwrap(), bson);
+        };
+    }
 }
@@ -0,0 +1,54 @@
+use serde::{Serialize, Deserialize};
+
+use super::player::Player;
+
+pub const JOIN_MESSAGE_PLAYER_PLACEHOLDER : &str = "player";
+pub const JOIN_MESSAGE_MAX_LENGTH : usize = 128;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinMessage {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Vec<String>,
+    // `{player}` is replaced with the joining player's name
+    pub template: String,
+    // holding any of these ranks unlocks the message, an empty list makes it available to everyone
+    #[serde(default)]
+    pub rank_ids: Vec<String>,
+    pub gui_icon: String,
+    pub gui_slot: u32
+}
+
+impl JoinMessage {
+    pub fn validate(&self) -> Result<(), String> {
+        let length = self.template.chars().count();
+        if length == 0 || length > JOIN_MESSAGE_MAX_LENGTH {
+            return Err(format!("Join message '{}' must be between 1 and {} characters long", self.id, JOIN_MESSAGE_MAX_LENGTH));
+        };
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            if rest[..start].contains('}') {
+                return Err(format!("Join message '{}' has an unopened placeholder", self.id));
+            };
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(format!("Join message '{}' has an unclosed placeholder", self.id))
+            };
+            let placeholder = &rest[start + 1..end];
+            if placeholder != JOIN_MESSAGE_PLAYER_PLACEHOLDER {
+                return Err(format!("Join message '{}' uses unknown placeholder '{{{}}}'", self.id, placeholder));
+            };
+            rest = &rest[end + 1..];
+        };
+        if rest.contains('}') {
+            return Err(format!("Join message '{}' has an unopened placeholder", self.id));
+        };
+        Ok(())
+    }
+
+    pub fn is_unlocked_by(&self, player: &Player) -> bool {
+        self.rank_ids.is_empty() || self.rank_ids.iter().any(|rank_id| player.rank_ids.contains(rank_id))
+    }
+}
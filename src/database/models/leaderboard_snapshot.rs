@@ -0,0 +1,30 @@
+use mars_api_rs_macro::IdentifiableDocument;
+use mars_api_rs_derive::IdentifiableDocument;
+use serde::{Serialize, Deserialize};
+
+use crate::{database::CollectionOwner, socket::leaderboard::LeaderboardEntry, util::time::Timestamp};
+
+// standings archived right before a leaderboard key is cleared
+#[derive(Serialize, Deserialize, IdentifiableDocument)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardSnapshot {
+    #[id]
+    #[serde(rename = "_id")]
+    pub id: String,
+    // a built-in score type or the name of a dynamic board
+    pub score_type: String,
+    pub period: String,
+    pub key: String,
+    pub entries: Vec<LeaderboardEntry>,
+    pub created_at: Timestamp
+}
+
+impl CollectionOwner<LeaderboardSnapshot> for LeaderboardSnapshot {
+    fn get_collection(database: &crate::database::Database) -> &mongodb::Collection<LeaderboardSnapshot> {
+        &database.leaderboard_snapshots
+    }
+
+    fn get_collection_name() -> &'static str {
+        "leaderboard_snapshot"
+    }
+}
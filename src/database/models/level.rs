@@ -1,8 +1,9 @@
 use mars_api_rs_macro::IdentifiableDocument;
 use mars_api_rs_derive::IdentifiableDocument;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use strum::IntoEnumIterator;
 
-use crate::database::CollectionOwner;
+use crate::{database::CollectionOwner, util::time::Timestamp};
 
 use super::{r#match::GoalCollection, player::{PlayerRecord, ProjectileRecord, FirstBloodRecord}};
 
@@ -12,12 +13,12 @@ pub struct Level {
     #[id]
     #[serde(rename = "_id")]
     pub id: String,
-    pub loaded_at: u64,
+    pub loaded_at: Timestamp,
     pub name: String,
     pub name_lower: String,
     pub version: String,
     pub gamemodes: Vec<LevelGamemode>,
-    pub updated_at: u64,
+    pub updated_at: Timestamp,
     pub authors: Vec<LevelContributor>,
     pub contributors: Vec<LevelContributor>,
     #[serde(default)]
@@ -49,6 +50,10 @@ pub struct LevelRecords {
     #[serde(default)]
     pub fastest_flag_capture: Option<PlayerRecord<u64>>,
     #[serde(default)]
+    pub fastest_control_point_capture: Option<PlayerRecord<u64>>,
+    #[serde(default)]
+    pub fastest_core_leak: Option<PlayerRecord<u64>>,
+    #[serde(default)]
     pub fastest_first_blood: Option<FirstBloodRecord>,
     #[serde(default)]
     pub kills_in_match: Option<PlayerRecord<u32>>,
@@ -63,6 +68,8 @@ impl Default for LevelRecords {
             longest_projectile_kill: Default::default(), 
             fastest_wool_capture: Default::default(), 
             fastest_flag_capture: Default::default(), 
+            fastest_control_point_capture: Default::default(), 
+            fastest_core_leak: Default::default(), 
             fastest_first_blood: Default::default(), 
             kills_in_match: Default::default(), 
             deaths_in_match: Default::default() 
@@ -77,9 +84,8 @@ pub struct LevelContributor {
     contribution: Option<String>
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, strum_macros::EnumProperty, Hash, PartialEq, Eq)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
-#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+// the key is what `gamemodeStats` is stored under, so variants can be renamed but their keys never change
+#[derive(Debug, Clone, strum_macros::EnumProperty, strum_macros::EnumIter, Hash, PartialEq, Eq)]
 pub enum LevelGamemode {
     #[strum(props(fancy = "Attack/Defend"))]
     AttackDefend,
@@ -128,5 +134,114 @@ pub enum LevelGamemode {
     #[strum(props(fancy = "Survival Games"))]
     SurvivalGames,
     #[strum(props(fancy = "Deathmatch"))]
-    Deathmatch
+    Deathmatch,
+    // anything stored under a key this build does not know, kept as is so it is written back unchanged
+    #[strum(props(fancy = "Unknown"))]
+    Unknown(String)
+}
+
+impl LevelGamemode {
+    pub fn key(&self) -> &str {
+        match self {
+            Self::AttackDefend => "ATTACK_DEFEND",
+            Self::Arcade => "ARCADE",
+            Self::Bedwars => "BEDWARS",
+            Self::Blitz => "BLITZ",
+            Self::BlitzRage => "BLITZ_RAGE",
+            Self::Bridge => "BRIDGE",
+            Self::CaptureTheFlag => "CAPTURE_THE_FLAG",
+            Self::ControlThePoint => "CONTROL_THE_POINT",
+            Self::CaptureTheWool => "CAPTURE_THE_WOOL",
+            Self::DestroyTheCore => "DESTROY_THE_CORE",
+            Self::DestroyTheMonument => "DESTROY_THE_MONUMENT",
+            Self::FreeForAll => "FREE_FOR_ALL",
+            Self::FlagFootball => "FLAG_FOOTBALL",
+            Self::Infection => "INFECTION",
+            Self::KingOfTheHill => "KING_OF_THE_HILL",
+            Self::KingOfTheFlag => "KING_OF_THE_FLAG",
+            Self::Mixed => "MIXED",
+            Self::Payload => "PAYLOAD",
+            Self::Rage => "RAGE",
+            Self::RaceForWool => "RACE_FOR_WOOL",
+            Self::Scorebox => "SCOREBOX",
+            Self::Skywars => "SKYWARS",
+            Self::SurvivalGames => "SURVIVAL_GAMES",
+            Self::Deathmatch => "DEATHMATCH",
+            Self::Unknown(key) => key
+        }
+    }
+
+    // unknown keys are logged and kept rather than failing the whole document
+    pub fn from_key(key: &str) -> Self {
+        match Self::iter().find(|gamemode| !matches!(gamemode, Self::Unknown(_)) && gamemode.key() == key) {
+            Some(gamemode) => gamemode,
+            None => {
+                warn!("Unknown gamemode key '{}'", key);
+                Self::Unknown(key.to_owned())
+            }
+        }
+    }
+}
+
+impl Serialize for LevelGamemode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.key())
+    }
+}
+
+impl<'de> Deserialize<'de> for LevelGamemode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let key = String::deserialize(deserializer)?;
+        Ok(Self::from_key(&key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what `gamemodeStats` and level documents hold, changing one of these strands every stored document
+    #[test]
+    fn gamemodes_keep_their_stored_keys() {
+        let stored = [
+            (LevelGamemode::AttackDefend, "ATTACK_DEFEND"),
+            (LevelGamemode::Arcade, "ARCADE"),
+            (LevelGamemode::Bedwars, "BEDWARS"),
+            (LevelGamemode::Blitz, "BLITZ"),
+            (LevelGamemode::BlitzRage, "BLITZ_RAGE"),
+            (LevelGamemode::Bridge, "BRIDGE"),
+            (LevelGamemode::CaptureTheFlag, "CAPTURE_THE_FLAG"),
+            (LevelGamemode::ControlThePoint, "CONTROL_THE_POINT"),
+            (LevelGamemode::CaptureTheWool, "CAPTURE_THE_WOOL"),
+            (LevelGamemode::DestroyTheCore, "DESTROY_THE_CORE"),
+            (LevelGamemode::DestroyTheMonument, "DESTROY_THE_MONUMENT"),
+            (LevelGamemode::FreeForAll, "FREE_FOR_ALL"),
+            (LevelGamemode::FlagFootball, "FLAG_FOOTBALL"),
+            (LevelGamemode::Infection, "INFECTION"),
+            (LevelGamemode::KingOfTheHill, "KING_OF_THE_HILL"),
+            (LevelGamemode::KingOfTheFlag, "KING_OF_THE_FLAG"),
+            (LevelGamemode::Mixed, "MIXED"),
+            (LevelGamemode::Payload, "PAYLOAD"),
+            (LevelGamemode::Rage, "RAGE"),
+            (LevelGamemode::RaceForWool, "RACE_FOR_WOOL"),
+            (LevelGamemode::Scorebox, "SCOREBOX"),
+            (LevelGamemode::Skywars, "SKYWARS"),
+            (LevelGamemode::SurvivalGames, "SURVIVAL_GAMES"),
+            (LevelGamemode::Deathmatch, "DEATHMATCH")
+        ];
+        assert_eq!(stored.len(), LevelGamemode::iter().count() - 1);
+        for (gamemode, key) in stored {
+            let bson = mongodb::bson::to_bson(&gamemode).unwrap();
+            assert_eq!(bson, mongodb::bson::Bson::String(key.to_owned()));
+            assert_eq!(mongodb::bson::from_bson::<LevelGamemode>(bson).unwrap(), gamemode);
+        };
+    }
+
+    #[test]
+    fn unknown_gamemodes_round_trip() {
+        let bson = mongodb::bson::Bson::String("TOWER_DEFENSE".to_owned());
+        let gamemode : LevelGamemode = mongodb::bson::from_bson(bson.clone()).unwrap();
+        assert_eq!(gamemode, LevelGamemode::Unknown("TOWER_DEFENSE".to_owned()));
+        assert_eq!(mongodb::bson::to_bson(&gamemode).unwrap(), bson);
+    }
 }
@@ -3,10 +3,11 @@ use std::collections::{HashSet, HashMap};
 use mars_api_rs_derive::IdentifiableDocument;
 use mars_api_rs_macro::IdentifiableDocument;
 use serde::{Serialize, Deserialize};
+use strum_macros::Display;
 
-use crate::{database::CollectionOwner, util::time::get_u64_time_millis, socket::{participant::participant_context::PlayerMatchResult, r#match::match_events::MatchEndData}};
+use crate::{database::CollectionOwner, util::{time::Timestamp, tracking::StatCategory}, socket::{participant::participant_context::PlayerMatchResult, r#match::match_events::MatchEndData, objective::objective_validation::ObjectiveState}};
 
-use super::{player::SimplePlayer, level::{Level, LevelGamemode}, participant::{Participant}};
+use super::{player::SimplePlayer, level::{Level, LevelGamemode}, participant::{Participant, MatchContribution, MatchParticipationOptions}};
 
 #[derive(Serialize, Deserialize, IdentifiableDocument)]
 #[serde(rename_all = "camelCase")]
@@ -14,16 +15,36 @@ pub struct Match {
     #[id]
     #[serde(rename = "_id")]
     pub id: String,
-    pub loaded_at: u64,
+    pub loaded_at: Timestamp,
     #[serde(default)]
-    pub started_at: Option<u64>,
+    pub started_at: Option<Timestamp>,
     #[serde(default)]
-    pub ended_at: Option<u64>,
+    pub ended_at: Option<Timestamp>,
     pub level: Level,
     pub parties: HashMap<String, Party>,
     pub participants: HashMap<String, Participant>,
+    // the keys of `participants`, kept as an array so a player's matches can be looked up by index
+    #[serde(default)]
+    pub participant_ids: Vec<String>,
     pub server_id: String,
-    pub first_blood: Option<FirstBlood>
+    pub first_blood: Option<FirstBlood>,
+    // captured when the match ends, matches from before this was recorded have none
+    #[serde(default)]
+    pub scoreboard: Vec<MatchContribution>,
+    // set instead of a normal end when the server died or everyone left, no outcome is ever credited
+    #[serde(default)]
+    pub abandoned: Option<MatchAbandonment>,
+    // player id -> how the match ended for them, only set for matches that ended normally
+    #[serde(default)]
+    pub results: HashMap<String, PlayerMatchResult>,
+    #[serde(default)]
+    pub objectives: ObjectiveState,
+    // the realm of the server that played it, `None` for the default realm
+    #[serde(default)]
+    pub realm: Option<String>,
+    // decided from `stats.tracking.*` when the match loads, none for matches loaded before that existed
+    #[serde(default)]
+    pub tracked_stats: Option<Vec<StatCategory>>
 }
 
 impl Match {
@@ -31,26 +52,83 @@ impl Match {
         !self.level.gamemodes.contains(&LevelGamemode::Arcade)
     }
 
+    pub fn is_tracking(&self, category: StatCategory) -> bool {
+        match &self.tracked_stats {
+            Some(tracked_stats) => tracked_stats.contains(&category),
+            None => category == StatCategory::Xp || self.is_tracking_stats()
+        }
+    }
+
+    // map records follow the records category, personal records can opt in to untracked matches
+    pub fn is_tracking_records(&self, allow_untracked: bool) -> bool {
+        self.is_tracking(StatCategory::Records) || allow_untracked
+    }
+
+    // stats of a category the match does not credit still go to the arcade gamemode stats
+    pub fn get_credited_gamemodes(&self, category: StatCategory) -> Vec<LevelGamemode> {
+        if self.is_tracking(category) { self.level.gamemodes.clone() } else { vec![LevelGamemode::Arcade] }
+    }
+
     pub fn get_state(&self) -> MatchState {
-        if let None = self.started_at {
+        if self.abandoned.is_some() {
+            MatchState::Abandoned
+        } else if self.started_at.is_none() {
             MatchState::Pre
-        } else if let None = self.ended_at {
+        } else if self.ended_at.is_none() {
             MatchState::InProgress
         } else {
             MatchState::Post
         }
     }
 
+    // the stored scoreboard once the match is over, otherwise built from the participants as they are now
+    pub fn get_scoreboard(&self) -> Vec<MatchContribution> {
+        if !self.scoreboard.is_empty() {
+            return self.scoreboard.clone();
+        };
+        let mut scoreboard : Vec<MatchContribution> = self.participants.values().map(|participant| participant.get_contribution()).collect();
+        scoreboard.sort_by(|a, b| b.kills.cmp(&a.kills).then(a.deaths.cmp(&b.deaths)));
+        scoreboard
+    }
+
+    pub fn abandon(&mut self, reason: AbandonReason, at: u64) {
+        self.abandoned = Some(MatchAbandonment { reason, at });
+        if self.ended_at.is_none() {
+            self.ended_at = Some(Timestamp::from_millis(at).max(self.started_at.unwrap_or(self.loaded_at)));
+        };
+    }
+
+    // when the last player still in a party left, none if someone is still playing
+    pub fn get_emptied_at(&self) -> Option<u64> {
+        if self.participants.values().any(|participant| participant.party_name.is_some()) {
+            return None;
+        };
+        let last_left = self.participants.values().filter_map(|participant| participant.last_left_party_at).max();
+        Some(last_left.unwrap_or(0).max(self.started_at.unwrap_or(self.loaded_at).millis()))
+    }
+
     pub fn save_participants(&mut self, participants: Vec<Participant>) {
         for participant in participants {
+            if !self.participant_ids.contains(&participant.id) {
+                self.participant_ids.push(participant.id.clone());
+            };
             self.participants.insert(participant.id.clone(), participant);
         }
     }
 
+    // how far into the match `at` is, for records this is the checked event time and never the wall clock
+    pub fn elapsed_at(&self, at: u64) -> u64 {
+        at.saturating_sub(self.started_at.unwrap_or(self.loaded_at).millis())
+    }
+
     pub fn get_length(&self) -> u64 {
-        let start = self.started_at.unwrap_or(0);
-        let end = self.ended_at.unwrap_or(get_u64_time_millis());
-        end - start
+        let start = self.started_at.unwrap_or_default();
+        let end = self.ended_at.unwrap_or_else(Timestamp::now);
+        end.millis_since(start)
+    }
+
+    pub fn get_minimum_playtime(&self, options: &MatchParticipationOptions) -> f64 {
+        options.required_playtime(self.get_length())
     }
 
     pub fn get_participant_match_result(&self, participant: &Participant, end: &MatchEndData) -> PlayerMatchResult {
@@ -68,6 +146,12 @@ impl Match {
         }
     }
 
+    // the player's line of the stored scoreboard, or of the live one for matches that never ended
+    pub fn get_contribution_of(&self, player_id: &str) -> Option<MatchContribution> {
+        self.scoreboard.iter().find(|contribution| contribution.player.id == player_id).cloned()
+            .or_else(|| self.participants.get(player_id).map(|participant| participant.get_contribution()))
+    }
+
     pub fn get_participant(&self, id: &String) -> &Participant {
         self.participants.get(id).unwrap()
     }
@@ -87,7 +171,31 @@ impl CollectionOwner<Match> for Match {
 pub enum MatchState {
     Pre,
     InProgress,
-    Post
+    Post,
+    Abandoned
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum AbandonReason {
+    // the server started up again without ending its match
+    ServerRestart,
+    // the server stopped reporting for longer than `sessions.stale-after`
+    ServerTimeout,
+    // the server loaded another match without ending this one
+    Replaced,
+    // every player left and nobody came back within `abandonment.empty-timeout`
+    Empty,
+    // the server ended it with a result that fails `matches.result-verification`
+    InvalidResult
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchAbandonment {
+    pub reason: AbandonReason,
+    pub at: u64
 }
 
 #[derive(Serialize, Deserialize)]
@@ -167,3 +275,56 @@ pub struct ControlPointGoal {
 }
 
 fn default_simpleplayer_set() -> HashSet<SimplePlayer> { HashSet::new() }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rocket::serde::json::{json, from_value};
+
+    use crate::database::models::player::{PlayerRecord, SimplePlayer};
+    use crate::socket::event_time::{ClockSkewOptions, ClockSkewPolicy, EventTime};
+    use crate::util::{time::Timestamp, tracking::StatCategory};
+
+    use super::Match;
+
+    // a match that ran from 0 to ten minutes on a level of `gamemodes`, with nobody in it
+    pub(crate) fn ten_minute_match(gamemodes: &[&str]) -> Match {
+        from_value(json!({
+            "_id": "match", "loadedAt": 0, "startedAt": 0, "endedAt": 600_000, "serverId": "server", "firstBlood": null,
+            "parties": {}, "participants": {},
+            "level": {
+                "_id": "level", "loadedAt": 0, "name": "Level", "nameLower": "level", "version": "1.0", "gamemodes": gamemodes,
+                "updatedAt": 0, "authors": [], "contributors": [], "records": {}
+            }
+        })).unwrap()
+    }
+
+    #[test]
+    fn untracked_matches_only_set_records_when_allowed() {
+        let competitive = ten_minute_match(&["CAPTURE_THE_WOOL"]);
+        let arcade = ten_minute_match(&["ARCADE"]);
+        assert!(competitive.is_tracking_records(false));
+        assert!(!arcade.is_tracking_records(false));
+        assert!(arcade.is_tracking_records(true));
+        assert!(!arcade.is_tracking(StatCategory::Records));
+    }
+
+    #[test]
+    fn skewed_events_do_not_set_records() {
+        const NOW: u64 = 1_700_000_000_000;
+        let mut current_match = ten_minute_match(&["DESTROY_THE_CORE"]);
+        current_match.started_at = Some(Timestamp::from_millis(NOW - 60_000));
+        current_match.ended_at = None;
+        let record = Some(PlayerRecord { match_id: "old".to_owned(), player: SimplePlayer { name: "Pear".to_owned(), id: "pear".to_owned() }, value: 30_000 });
+        // the server claims the leak happened a millisecond into the match
+        let sent_at = Some(NOW - 59_999);
+
+        let clamp = ClockSkewOptions { tolerance: 5_000, policy: ClockSkewPolicy::Clamp };
+        let EventTime::Clamped(_, at) = clamp.validate(sent_at, NOW, false) else { panic!("skewed event was not clamped") };
+        assert_eq!(current_match.elapsed_at(at), 55_000);
+        assert!(!PlayerRecord::is_beaten(&record, current_match.elapsed_at(at)));
+        assert!(PlayerRecord::is_beaten(&record, current_match.elapsed_at(NOW - 40_000)));
+
+        let reject = ClockSkewOptions { tolerance: 5_000, policy: ClockSkewPolicy::Reject };
+        assert!(matches!(reject.validate(sent_at, NOW, false), EventTime::Rejected(_)));
+    }
+}
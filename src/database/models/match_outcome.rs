@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::socket::participant::participant_context::PlayerMatchResult;
+
+use super::level::LevelGamemode;
+
+// how a match ended for one player. win, loss and tie are reported for every gamemode,
+// servers can report others such as `SURVIVED` for gamemodes they don't map onto
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MatchOutcome(String);
+
+pub type OutcomeCounts = HashMap<MatchOutcome, u32>;
+
+impl MatchOutcome {
+    pub const WIN : &'static str = "WIN";
+    pub const LOSS : &'static str = "LOSS";
+    pub const TIE : &'static str = "TIE";
+
+    pub fn new(name: &str) -> Self {
+        Self(name.trim().to_uppercase().replace([' ', '-'], "_"))
+    }
+
+    pub fn win() -> Self {
+        Self::new(Self::WIN)
+    }
+
+    pub fn loss() -> Self {
+        Self::new(Self::LOSS)
+    }
+
+    pub fn tie() -> Self {
+        Self::new(Self::TIE)
+    }
+
+    // spectators (`Intermediate`) have no outcome
+    pub fn from_result(result: &PlayerMatchResult) -> Option<Self> {
+        match result {
+            PlayerMatchResult::Win => Some(Self::win()),
+            PlayerMatchResult::Lose => Some(Self::loss()),
+            PlayerMatchResult::Tie => Some(Self::tie()),
+            PlayerMatchResult::Intermediate => None
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl LevelGamemode {
+    // outcomes that count towards the win rate in this gamemode
+    pub fn win_outcomes(&self) -> Vec<MatchOutcome> {
+        match self {
+            LevelGamemode::Blitz | LevelGamemode::BlitzRage | LevelGamemode::Infection 
+                | LevelGamemode::SurvivalGames | LevelGamemode::Skywars => vec![MatchOutcome::win(), MatchOutcome::new("SURVIVED")],
+            _ => vec![MatchOutcome::win()]
+        }
+    }
+}
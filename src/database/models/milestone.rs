@@ -0,0 +1,72 @@
+use serde::{Serialize, Deserialize};
+
+use crate::{database::{audit::record_audit, models::{tag::Tag, audit_log::{AuditLogEntry, AuditAction}}}, socket::{event_type::EventType, leaderboard::ScoreType, player::player_events::PlayerMilestoneData, server::server_context::ServerBroadcast}, MarsAPIState};
+
+use super::player::Player;
+
+// one entry of `milestones.yml`, e.g. `{ tagId: kills-1000, stat: KILLS, threshold: 1000 }`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Milestone {
+    pub tag_id: String,
+    pub stat: ScoreType,
+    pub threshold: u64
+}
+
+impl Milestone {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.threshold == 0 {
+            return Err(format!("Milestone for tag '{}' must have a threshold above 0", self.tag_id));
+        };
+        Ok(())
+    }
+
+    pub fn is_reached_by(&self, player: &Player) -> bool {
+        player.stats.get_score(&self.stat) >= self.threshold
+    }
+}
+
+impl Player {
+    // like rank thresholds, each tag is awarded by a milestone at most once, so a tag staff took away
+    // stays gone and a player already past a threshold is never notified again
+    pub fn reached_milestones<'a>(&self, milestones: &'a [Milestone]) -> Vec<&'a Milestone> {
+        milestones.iter()
+            .filter(|milestone| !self.milestone_tag_ids.contains(&milestone.tag_id) && milestone.is_reached_by(self))
+            .collect()
+    }
+}
+
+// called after stats change at the end of a match or session, the caller saves the player. tags that
+// don't exist are skipped and stay eligible in case one with the same id is created again
+pub async fn grant_milestones(state: &MarsAPIState, player: &mut Player) {
+    let mut reached : Vec<Milestone> = Vec::new();
+    for milestone in player.reached_milestones(&state.config.data.milestones) {
+        if !reached.iter().any(|other| other.tag_id == milestone.tag_id) {
+            reached.push(milestone.clone());
+        };
+    };
+    for milestone in reached.iter() {
+        match state.tag_cache.get(&state.database, &milestone.tag_id).await {
+            Some(tag) => grant_milestone(state, player, milestone, &tag),
+            None => warn!("Milestone points at tag '{}', which does not exist", milestone.tag_id)
+        };
+    };
+}
+
+fn grant_milestone(state: &MarsAPIState, player: &mut Player, milestone: &Milestone, tag: &Tag) {
+    player.milestone_tag_ids.push(tag.id.clone());
+    if player.tag_ids.contains(&tag.id) {
+        return;
+    };
+    player.tag_ids.push(tag.id.clone());
+    info!("{} reached {} {} and was granted tag {}", player.name, milestone.threshold, milestone.stat, tag.name);
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::TagGrant)
+        .target(&player.id, &player.name)
+        .after(format!("{} (milestone: {} {})", tag.name, milestone.threshold, milestone.stat)));
+    ServerBroadcast::send_from_api(&state.server_broadcast, &EventType::PlayerMilestone, PlayerMilestoneData {
+        player_id: player.id.clone(),
+        tag_id: tag.id.clone(),
+        stat: milestone.stat.clone(),
+        threshold: milestone.threshold
+    });
+}
@@ -6,9 +6,19 @@ pub mod punishment;
 pub mod level;
 pub mod session;
 pub mod rank;
+pub mod rank_threshold;
+pub mod milestone;
 pub mod r#match;
 pub mod participant;
 pub mod death;
 pub mod join_sound;
+pub mod join_message;
+pub mod audit_log;
 pub mod server;
 pub mod achievement;
+pub mod leaderboard_snapshot;
+pub mod report;
+pub mod shadow_punishment;
+pub mod damage_event;
+pub mod match_outcome;
+pub mod damage;
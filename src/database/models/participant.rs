@@ -2,9 +2,9 @@ use std::collections::HashMap;
 
 use serde::{Serialize, Deserialize};
 
-use crate::{util::time::get_u64_time_millis, MarsAPIState, socket::{r#match::match_events::MatchEndData, participant::participant_context::PlayerMatchResult}};
+use crate::{util::time::{get_u64_time_millis, Timestamp}, MarsAPIState, socket::{r#match::match_events::MatchEndData, participant::participant_context::PlayerMatchResult}};
 
-use super::{player::{PlayerObjectiveStatistics, PlayerMessages, Player, SimplePlayer}, r#match::Match};
+use super::{player::{PlayerObjectiveStatistics, PlayerMessages, Player, SimplePlayer}, r#match::Match, damage::Damage};
 
 #[derive(Serialize, Deserialize, Hash, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -24,7 +24,65 @@ pub struct Participant {
     pub first_joined_match_at: u64,
     pub joined_party_at: Option<u64>,
     pub last_left_party_at: Option<u64>,
-    pub stats: ParticipantStats
+    pub stats: ParticipantStats,
+    // whether the boards credit the participant, decided at match start and party join so crediting a board
+    // doesn't need the player. `None` for participants of matches started before this was recorded
+    pub leaderboard_eligible: Option<bool>,
+    // what the participant's kills, deaths and assists actually added, see `abandonment::abandon_match`
+    pub credited: KillCredits
+}
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KillCounts {
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32
+}
+
+impl KillCounts {
+    pub fn is_empty(&self) -> bool {
+        *self == KillCounts::default()
+    }
+}
+
+// board credits are kept per day they were made on, so they come off the period keys they went to
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BoardKillCredit {
+    // when the first of them was made
+    pub at: u64,
+    #[serde(flatten)]
+    pub counts: KillCounts
+}
+
+// frozen players get nothing on their profile, players below the playtime gate nothing on the boards
+// and untracked matches neither, so each is recorded as it is made
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KillCredits {
+    pub profile: KillCounts,
+    // per-gamemode counters, which untracked matches still credit to `ARCADE`
+    pub gamemodes: KillCounts,
+    pub boards: Vec<BoardKillCredit>
+}
+
+impl KillCredits {
+    // `same_day` tells whether a board credit made at the given time went to the same keys as one made now
+    pub fn credit_boards(&mut self, same_day: impl Fn(u64) -> bool, credit: impl Fn(&mut KillCounts)) {
+        match self.boards.last_mut() {
+            Some(last) if same_day(last.at) => credit(&mut last.counts),
+            _ => {
+                let mut counts = KillCounts::default();
+                credit(&mut counts);
+                self.boards.push(BoardKillCredit { at: get_u64_time_millis(), counts });
+            }
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profile.is_empty() && self.gamemodes.is_empty() && self.boards.iter().all(|credit| credit.counts.is_empty())
+    }
 }
 
 impl Participant {
@@ -43,6 +101,29 @@ impl Participant {
         }
     }
 
+    // presence is derived from the party join/leave timestamps against the match start/end, and
+    // a participant who did not play long enough to be counted cannot be present for anything
+    pub fn get_presence(&self, current_match: &Match, options: &MatchParticipationOptions) -> MatchPresence {
+        let minimum_playtime = current_match.get_minimum_playtime(options);
+        let participated = (self.stats.game_playtime as f64) > minimum_playtime;
+        if !participated {
+            return MatchPresence::default();
+        };
+
+        let started_at = current_match.started_at.unwrap_or_default().millis();
+        let ended_at = current_match.ended_at.unwrap_or_else(Timestamp::now).millis();
+        let time_elapsed_before_joining = self.first_joined_match_at.saturating_sub(started_at);
+
+        let present_start = (time_elapsed_before_joining as f64) < minimum_playtime;
+        let present_end = match self.joined_party_at {
+            Some(joined_party_at) => joined_party_at <= ended_at,
+            None => false
+        };
+        let present_full = present_start && present_end && self.stats.time_away < 20_000;
+
+        MatchPresence { participated, present_start, present_full, present_end }
+    }
+
     pub fn from_simple(simple: SimpleParticipant) -> Self {
         let time_millis = get_u64_time_millis();
         Participant {
@@ -54,6 +135,8 @@ impl Participant {
             joined_party_at: Some(time_millis),
             last_left_party_at: None,
             stats: Default::default(),
+            leaderboard_eligible: None,
+            credited: KillCredits::default()
         }
     }
 
@@ -61,6 +144,13 @@ impl Participant {
         state.player_cache.get(state.database.as_ref(), &self.name.to_lowercase()).await.expect("Expected player in cache")
     }
 
+    pub async fn is_frozen(&self, state: &MarsAPIState) -> bool {
+        match state.player_cache.get(state.database.as_ref(), &self.get_name_lower()).await {
+            Some(player) => player.frozen,
+            None => false
+        }
+    }
+
     pub fn get_name_lower(&self) -> String {
         self.name.to_lowercase()
     }
@@ -76,6 +166,67 @@ impl Participant {
     pub fn get_id_name(&self) -> String {
         format!("{}/{}", &self.id, &self.name)
     }
+
+    // anyone not in a party when the match ended gave up on it, however long they played
+    pub fn get_contribution(&self) -> MatchContribution {
+        MatchContribution {
+            player: self.get_simple_player(),
+            party_name: self.party_name.clone().or_else(|| self.last_party_name.clone()),
+            kills: self.stats.kills,
+            deaths: self.stats.deaths,
+            assists: self.stats.assists,
+            objectives: self.stats.objectives.clone(),
+            damage_given: self.stats.damage_given,
+            damage_taken: self.stats.damage_taken,
+            game_playtime: self.stats.game_playtime,
+            left_early: self.party_name.is_none()
+        }
+    }
+}
+
+// what one player did in one match, kept on the match so the scoreboard survives later stat changes
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchContribution {
+    pub player: SimplePlayer,
+    pub party_name: Option<String>,
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    pub objectives: PlayerObjectiveStatistics,
+    pub damage_given: Damage,
+    pub damage_taken: Damage,
+    pub game_playtime: u64,
+    pub left_early: bool
+}
+
+// how long a participant has to play for the match and its result to count: the longer of `min_playtime`
+// and `min_fraction` of the match, with the fraction never asking for more than `fraction_cap` ms
+#[derive(Debug, Clone)]
+pub struct MatchParticipationOptions {
+    pub min_playtime: u64,
+    pub min_fraction: f64,
+    pub fraction_cap: u64
+}
+
+impl Default for MatchParticipationOptions {
+    fn default() -> Self {
+        MatchParticipationOptions { min_playtime: 0, min_fraction: 0.10, fraction_cap: 60_000 }
+    }
+}
+
+impl MatchParticipationOptions {
+    pub fn required_playtime(&self, match_length: u64) -> f64 {
+        (self.min_fraction * match_length as f64).min(self.fraction_cap as f64).max(self.min_playtime as f64)
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchPresence {
+    pub participated: bool,
+    pub present_start: bool,
+    pub present_full: bool,
+    pub present_end: bool
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -85,6 +236,7 @@ pub struct ParticipantStats {
     pub time_away: u64,
     pub kills: u32,
     pub deaths: u32,
+    pub assists: u32,
     pub void_kills: u32,
     pub void_deaths: u32,
     pub objectives: PlayerObjectiveStatistics,
@@ -92,9 +244,9 @@ pub struct ParticipantStats {
     pub bow_shots_hit: u32,
     pub blocks_placed: HashMap<String, u32>,
     pub blocks_broken: HashMap<String, u32>,
-    pub damage_taken: f64,
-    pub damage_given: f64,
-    pub damage_given_bow: f64,
+    pub damage_taken: Damage,
+    pub damage_given: Damage,
+    pub damage_given_bow: Damage,
     pub messages: PlayerMessages,
     pub weapon_kills: HashMap<String, u32>,
     pub weapon_deaths: HashMap<String, u32>,
@@ -120,3 +272,57 @@ impl Default for Duel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::database::models::{player::PlayerStats, r#match::tests::ten_minute_match};
+
+    use super::{MatchParticipationOptions, Participant, ParticipantStats};
+
+    fn participant(first_joined_match_at: u64, joined_party_at: Option<u64>, game_playtime: u64, time_away: u64) -> Participant {
+        Participant {
+            party_name: joined_party_at.map(|_| String::from("red")),
+            first_joined_match_at,
+            joined_party_at,
+            stats: ParticipantStats { game_playtime, time_away, ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn players_under_the_minimum_get_no_result() {
+        let options = MatchParticipationOptions { min_playtime: 120_000, ..Default::default() };
+        let current_match = ten_minute_match(&[]);
+        // 10% of the match would only ask for 60 seconds, the minimum asks for more
+        assert_eq!(current_match.get_minimum_playtime(&options), 120_000.0);
+        let presence = participant(0, Some(0), 100_000, 0).get_presence(&current_match, &options);
+        assert!(!presence.participated);
+        assert!(!presence.present_end);
+        assert!(participant(0, Some(0), 130_000, 0).get_presence(&current_match, &options).participated);
+    }
+
+    #[test]
+    fn presence_counts_hold_after_joins_and_leaves() {
+        let options = MatchParticipationOptions::default();
+        let current_match = ten_minute_match(&[]);
+        let participants = [
+            // whole match
+            participant(0, Some(0), 600_000, 0),
+            // joined late and stayed
+            participant(300_000, Some(300_000), 300_000, 0),
+            // from the start, left halfway
+            participant(0, None, 300_000, 0),
+            // from the start to the end, but away for a while
+            participant(0, Some(500_000), 500_000, 100_000),
+            // too short to count for anything
+            participant(0, Some(590_000), 10_000, 0)
+        ];
+        let mut stats = PlayerStats::default();
+        for participant in &participants {
+            stats.record_presence(&participant.get_presence(&current_match, &options));
+            assert!(stats.has_valid_presence());
+        }
+        assert_eq!(stats.matches, 4);
+        assert_eq!((stats.matches_present_start, stats.matches_present_full, stats.matches_present_end), (3, 1, 3));
+    }
+}
@@ -6,9 +6,9 @@ use mongodb::Collection;
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 
-use crate::{database::CollectionOwner, socket::{leaderboard::ScoreType, player::{player_xp_listener::{PlayerXPListener, XP_PER_LEVEL}, player_events::PlayerXPGainData}, server::server_context::{ServerContext}, event_type::EventType}};
+use crate::{config::{AvatarSource, MarsConfigOptions}, database::CollectionOwner, socket::{leaderboard::ScoreType, player::{player_xp_listener::{PlayerXPListener, XP_PER_LEVEL}, player_events::{PlayerXPGainData, PlayerPrestigeData}}, server::server_context::ServerContext}, util::{time::Timestamp, tracking::StatCategory, weapon::top_counts}};
 
-use super::{punishment::StaffNote, level::LevelGamemode, r#match::Match};
+use super::{tag::TagSlot, punishment::StaffNote, level::LevelGamemode, r#match::Match, participant::MatchPresence, session::Session, match_outcome::{MatchOutcome, OutcomeCounts}, damage::Damage};
 
 #[derive(Debug, Serialize, Deserialize, Clone, IdentifiableDocument)]
 #[serde(rename_all = "camelCase")]
@@ -19,16 +19,53 @@ pub struct Player {
     pub name: String,
     pub name_lower: String,
     pub last_session_id: Option<String>,
-    pub first_joined_at: f64,
-    pub last_joined_at: f64,
-    pub ips: Vec<String>,
+    pub first_joined_at: Timestamp,
+    pub last_joined_at: Timestamp,
+    // hashed, least recently seen first
+    pub ips: Vec<PlayerIp>,
     pub notes: Vec<StaffNote>,
     pub rank_ids: Vec<String>,
+    // ranks ever granted by a rank threshold, including ones staff revoked since
+    #[serde(default)]
+    pub auto_rank_ids: Vec<String>,
     pub tag_ids: Vec<String>,
+    // tags ever granted by a milestone, including ones staff revoked since
+    #[serde(default)]
+    pub milestone_tag_ids: Vec<String>,
+    // mirrors the prefix slot for clients that predate tag slots
     pub active_tag_id: Option<String>,
+    #[serde(default)]
+    pub active_tag_ids: HashMap<TagSlot, String>,
     pub stats: PlayerStats,
     pub gamemode_stats: HashMap<LevelGamemode, GamemodeStats>,
-    pub active_join_sound_id: Option<String>
+    pub active_join_sound_id: Option<String>,
+    #[serde(default)]
+    pub active_join_message_id: Option<String>,
+    #[serde(default)]
+    pub frozen: bool,
+    // every name the player has joined with, oldest first and including the current one
+    #[serde(default)]
+    pub name_history: Vec<NameHistoryEntry>,
+    #[serde(default)]
+    pub prestige: u32,
+    // total xp when the player last prestiged, levels count from here when xp is not reset
+    #[serde(default)]
+    pub prestige_xp: u32,
+    // xp taken off by prestiges that reset it, so the lifetime level survives them
+    #[serde(default)]
+    pub reset_xp: u32,
+    // what the servers of each realm credited, `stats` and `gamemode_stats` only hold the default realm's
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub realm_stats: HashMap<String, RealmStats>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RealmStats {
+    #[serde(default)]
+    pub stats: PlayerStats,
+    #[serde(default)]
+    pub gamemode_stats: HashMap<LevelGamemode, GamemodeStats>
 }
 
 impl Player {
@@ -40,34 +77,198 @@ impl Player {
         format!("{}/{}", self.id, self.name)
     }
 
+    // returns the previous name if it changed, the oldest entries are dropped past `limit`
+    pub fn record_name(&mut self, name: &str, limit: usize) -> Option<String> {
+        if self.name_history.is_empty() {
+            self.name_history.push(NameHistoryEntry::new(&self.name, self.first_joined_at));
+        };
+        if self.name == name {
+            return None;
+        };
+        self.name_history.push(NameHistoryEntry::new(name, Timestamp::now()));
+        if self.name_history.len() > limit {
+            let excess = self.name_history.len() - limit;
+            self.name_history.drain(..excess);
+        };
+        let previous_name = std::mem::replace(&mut self.name, name.to_owned());
+        self.name_lower = self.name.to_lowercase();
+        Some(previous_name)
+    }
+
+    // moves the ip to the end as the most recently seen, the least recently seen are dropped past `limit`
+    pub fn record_ip(&mut self, ip: &str, limit: usize) {
+        self.ips.retain(|known| known.ip != ip);
+        self.ips.push(PlayerIp { ip: ip.to_owned(), last_seen_at: Timestamp::now() });
+        if self.ips.len() > limit {
+            let excess = self.ips.len() - limit;
+            self.ips.drain(..excess);
+        };
+    }
+
+    pub fn ip_addresses(&self) -> Vec<String> {
+        self.ips.iter().map(|known| known.ip.clone()).collect()
+    }
+
+    pub fn get_prestige_level(&self) -> u32 {
+        (self.stats.xp.saturating_sub(self.prestige_xp) + XP_PER_LEVEL) / XP_PER_LEVEL
+    }
+
+    // the level from every bit of xp ever earned, prestiging doesn't lower it
+    pub fn get_lifetime_level(&self) -> u32 {
+        (self.stats.xp.saturating_add(self.reset_xp).saturating_add(XP_PER_LEVEL)) / XP_PER_LEVEL
+    }
+
+    // xp earned since the current level started, the level curve is linear
+    pub fn get_xp_into_level(&self) -> u32 {
+        self.stats.xp.saturating_sub(self.prestige_xp) % XP_PER_LEVEL
+    }
+
+    pub fn get_progression_display(&self) -> String {
+        if self.prestige == 0 {
+            self.get_prestige_level().to_string()
+        } else {
+            format!("P{}-{}", self.prestige, self.get_prestige_level())
+        }
+    }
+
+    // the cosmetic tag for the new prestige is granted here. the event is for the caller to send once the
+    // player is saved, to every server since the request doesn't come from a socket connection
+    pub fn prestige(&mut self, options: &MarsConfigOptions) -> Result<PlayerPrestigeData, PrestigeError> {
+        let level = self.get_prestige_level();
+        if level < options.prestige_level_cap {
+            return Err(PrestigeError::BelowCap { level, cap: options.prestige_level_cap });
+        };
+        self.prestige += 1;
+        if options.prestige_reset_xp {
+            self.reset_xp = self.reset_xp.saturating_add(self.stats.xp);
+            self.stats.xp = 0;
+            self.prestige_xp = 0;
+        } else {
+            self.prestige_xp = self.stats.xp;
+        };
+        let unlocked_tag_id = options.prestige_tag_ids.get(self.prestige as usize - 1).cloned();
+        if let Some(tag_id) = &unlocked_tag_id {
+            if !self.tag_ids.contains(tag_id) {
+                self.tag_ids.push(tag_id.clone());
+            };
+        };
+        Ok(PlayerPrestigeData {
+            player_id: self.id.clone(),
+            prestige: self.prestige,
+            display: self.get_progression_display(),
+            unlocked_tag_id
+        })
+    }
+
     pub fn sanitized_copy(&self) -> Player {
         let mut clone = self.clone();
         clone.ips = Vec::new();
         clone.notes = Vec::new();
         clone.last_session_id = None;
+        clone.frozen = false;
         clone
     }
 
+    pub fn hide_senior_notes(&mut self) {
+        self.notes.retain(|note| !note.is_visible_to_senior_only());
+    }
+
+    // for responses without a viewer, who never counts as senior
+    pub fn without_senior_notes(mut self) -> Self {
+        self.hide_senior_notes();
+        self
+    }
+
     pub async fn modify_gamemode_stats<F, Fut>(
         &mut self, 
         current_match: &Match, 
+        category: StatCategory,
         modify: F
     ) where F: Fn(&mut GamemodeStats) -> Fut, Fut: Future<Output = ()> {
-        let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+        let gamemodes = current_match.get_credited_gamemodes(category);
         for gamemode in gamemodes {
-            modify(self.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut GamemodeStats::default())).await;
+            modify(self.gamemode_stats.entry(gamemode).or_default()).await;
         }
     }
 
+    pub fn set_active_tag(&mut self, slot: TagSlot, tag_id: Option<String>) {
+        match &tag_id {
+            Some(tag_id) => { self.active_tag_ids.insert(slot, tag_id.clone()); },
+            None => { self.active_tag_ids.remove(&slot); }
+        };
+        if slot == TagSlot::Prefix {
+            self.active_tag_id = tag_id;
+        };
+    }
+
+    // clears the tag from every slot it was active in
+    pub fn clear_active_tag(&mut self, tag_id: &String) {
+        self.active_tag_ids.retain(|_, active_id| active_id != tag_id);
+        if self.active_tag_id.as_ref() == Some(tag_id) {
+            self.active_tag_id = None;
+        };
+    }
+
+    // returns true if any of the stat blocks had drifted presence counts
+    pub fn repair_presence_stats(&mut self) -> bool {
+        let mut repaired = self.stats.repair_presence();
+        for stats in self.gamemode_stats.values_mut() {
+            repaired |= stats.repair_presence();
+        }
+        repaired
+    }
+
     // TODO: Multipliers
-    pub async fn add_xp(&mut self, server_context: &mut ServerContext, raw_xp: u32, reason: &String, notify: bool, raw_only: bool) {
-        let original_level = self.stats.get_level();
+    // stats are always recorded, players below the playtime gate are just kept off the leaderboards
+    pub fn is_leaderboard_eligible(&self, min_playtime: u64) -> bool {
+        self.stats.game_playtime >= min_playtime
+    }
+
+    // the stats credited in `realm`, `None` for a realm the player has never played in
+    pub fn stats_in(&self, realm: &Option<String>) -> Option<&PlayerStats> {
+        match realm {
+            Some(realm) => self.realm_stats.get(realm).map(|realm_stats| &realm_stats.stats),
+            None => Some(&self.stats)
+        }
+    }
+
+    pub fn stats_in_mut(&mut self, realm: &Option<String>) -> &mut PlayerStats {
+        match realm {
+            Some(realm) => &mut self.realm_stats.entry(realm.clone()).or_default().stats,
+            None => &mut self.stats
+        }
+    }
+
+    pub fn is_leaderboard_eligible_in(&self, realm: &Option<String>, min_playtime: u64) -> bool {
+        self.stats_in(realm).map(|stats| stats.game_playtime >= min_playtime).unwrap_or(false)
+    }
+
+    // puts `realm`'s stats where the default realm's are and the other way around, calling it again undoes it.
+    // listeners always write `stats`, the socket swaps a realm server's players in before and back out after
+    pub fn swap_realm_stats(&mut self, realm: &Option<String>) {
+        let realm = match realm {
+            Some(realm) => realm,
+            None => return
+        };
+        let realm_stats = self.realm_stats.entry(realm.clone()).or_default();
+        std::mem::swap(&mut self.stats, &mut realm_stats.stats);
+        std::mem::swap(&mut self.gamemode_stats, &mut realm_stats.gamemode_stats);
+    }
+
+    pub async fn add_xp(&mut self, server_context: &mut ServerContext, current_match: &Match, raw_xp: u32, reason: &str, notify: bool, raw_only: bool) {
+        if self.frozen || !current_match.is_tracking(StatCategory::Xp) {
+            return;
+        };
+        // prestiged players never get the beginner multiplier back, even if their xp was reset
+        let original_level = if self.prestige > 0 { u32::MAX } else { self.stats.get_level() };
         let target_xp_increment = if raw_only { raw_xp } else { u32::max(PlayerXPListener::gain(raw_xp, original_level), raw_xp) };
         self.stats.xp += target_xp_increment;
 
-        server_context.call(&EventType::PlayerXpGain, PlayerXPGainData { player_id: self.id.clone(), gain: target_xp_increment, reason: reason.clone(), notify }).await;
+        server_context.notify_xp_gain(PlayerXPGainData { player_id: self.id.clone(), gain: target_xp_increment, reason: reason.to_owned(), notify, count: 1 }).await;
 
-        server_context.api_state.leaderboards.xp.increment(&self.id_name(), Some(target_xp_increment)).await;
+        if self.is_leaderboard_eligible(server_context.api_state.config.options.leaderboard_min_playtime) {
+            server_context.leaderboards().xp.increment(&self.id_name(), Some(target_xp_increment as u64)).await;
+        };
     }
 }
 
@@ -78,7 +279,7 @@ impl CollectionOwner<Player> for Player {
 
 pub type GamemodeStats = PlayerStats;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerStats {
     #[serde(default)]
@@ -92,6 +293,8 @@ pub struct PlayerStats {
     #[serde(default)]
     pub deaths: u32,
     #[serde(default)]
+    pub assists: u32,
+    #[serde(default)]
     pub void_kills: u32,
     #[serde(default)]
     pub void_deaths: u32,
@@ -105,16 +308,16 @@ pub struct PlayerStats {
     pub bow_shots_taken: u32,
     #[serde(default)]
     pub bow_shots_hit: u32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub blocks_placed: HashMap<String, u32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub blocks_broken: HashMap<String, u32>,
     #[serde(default)]
-    pub damage_taken: f64,
+    pub damage_taken: Damage,
     #[serde(default)]
-    pub damage_given: f64,
+    pub damage_given: Damage,
     #[serde(default)]
-    pub damage_given_bow: f64,
+    pub damage_given_bow: Damage,
     #[serde(default)]
     pub messages: PlayerMessages,
     #[serde(default)]
@@ -123,6 +326,12 @@ pub struct PlayerStats {
     pub losses: u32,
     #[serde(default)]
     pub ties: u32,
+    // matches per outcome a server reported besides win/loss/tie, e.g. `SURVIVED`
+    #[serde(default)]
+    pub outcomes: OutcomeCounts,
+    // matches that only count as won through one of those outcomes
+    #[serde(default)]
+    pub custom_wins: u32,
     #[serde(default)]
     pub matches: u32,
     #[serde(default)]
@@ -131,17 +340,17 @@ pub struct PlayerStats {
     pub matches_present_full: u32,
     #[serde(default)]
     pub matches_present_end: u32,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "PlayerRecords::is_empty")]
     pub records: PlayerRecords,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub weapon_kills: HashMap<String, u32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub weapon_deaths: HashMap<String, u32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub killstreaks: HashMap<String, u32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub killstreaks_ended: HashMap<String, u32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub achievements: HashMap<String, AchievementData>
 }
 
@@ -150,84 +359,130 @@ impl PlayerStats {
         (self.xp + XP_PER_LEVEL) / XP_PER_LEVEL
     }
 
-    pub fn get_score(&self, score_type: &ScoreType) -> u32 {
+    // by raw weapon key, see `WeaponNames::top` for display names
+    pub fn top_weapons(&self, n: usize) -> Vec<(String, u32)> {
+        top_counts(&self.weapon_kills, n)
+    }
+
+    pub fn record_presence(&mut self, presence: &MatchPresence) {
+        if !presence.participated {
+            return;
+        };
+        self.matches += 1;
+        if presence.present_start { self.matches_present_start += 1; };
+        if presence.present_full { self.matches_present_full += 1; };
+        if presence.present_end { self.matches_present_end += 1; };
+    }
+
+    // present_full implies present_start and present_end, all of which imply a counted match
+    pub fn has_valid_presence(&self) -> bool {
+        self.matches_present_start <= self.matches
+            && self.matches_present_end <= self.matches
+            && self.matches_present_full <= self.matches_present_start
+            && self.matches_present_full <= self.matches_present_end
+    }
+
+    // clamps counts that drifted before presence was derived in one place
+    pub fn repair_presence(&mut self) -> bool {
+        if self.has_valid_presence() {
+            return false;
+        };
+        self.matches_present_start = self.matches_present_start.min(self.matches);
+        self.matches_present_end = self.matches_present_end.min(self.matches);
+        self.matches_present_full = self.matches_present_full
+            .min(self.matches_present_start)
+            .min(self.matches_present_end);
+        true
+    }
+
+    pub fn outcome_count(&self, outcome: &MatchOutcome) -> u32 {
+        match outcome.name() {
+            MatchOutcome::WIN => self.wins,
+            MatchOutcome::LOSS => self.losses,
+            MatchOutcome::TIE => self.ties,
+            _ => self.outcomes.get(outcome).copied().unwrap_or(0)
+        }
+    }
+
+    // every outcome of one match, see `MatchEndData::get_outcomes`. `win_outcomes` are those of
+    // the gamemode these stats are for, a match is won at most once whichever of them it ended in
+    pub fn record_outcomes(&mut self, outcomes: &[MatchOutcome], win_outcomes: &[MatchOutcome]) {
+        for outcome in outcomes {
+            match outcome.name() {
+                MatchOutcome::WIN => self.wins += 1,
+                MatchOutcome::LOSS => self.losses += 1,
+                MatchOutcome::TIE => self.ties += 1,
+                _ => *self.outcomes.entry(outcome.clone()).or_insert(0) += 1
+            };
+        };
+        if !outcomes.contains(&MatchOutcome::win()) && outcomes.iter().any(|outcome| win_outcomes.contains(outcome)) {
+            self.custom_wins += 1;
+        };
+    }
+
+    pub fn wins(&self) -> u32 {
+        self.wins
+    }
+
+    pub fn losses(&self) -> u32 {
+        self.losses
+    }
+
+    pub fn ties(&self) -> u32 {
+        self.ties
+    }
+
+    // share of matches with a result that ended in one of `win_outcomes`, see `LevelGamemode::win_outcomes`
+    pub fn win_rate(&self, win_outcomes: &[MatchOutcome]) -> f64 {
+        let total = self.wins + self.losses + self.ties;
+        if total == 0 {
+            return 0.0;
+        };
+        let won = if win_outcomes.contains(&MatchOutcome::win()) { self.wins + self.custom_wins } else {
+            win_outcomes.iter().map(|outcome| self.outcome_count(outcome)).sum()
+        };
+        (won as f64 / total as f64).min(1.0)
+    }
+
+    pub fn get_score(&self, score_type: &ScoreType) -> u64 {
         match score_type {
-            ScoreType::Kills => self.kills,
-            ScoreType::Deaths => self.deaths,
-            ScoreType::FirstBloods => self.first_bloods,
-            ScoreType::Wins => self.wins,
-            ScoreType::Losses => self.losses,
-            ScoreType::Ties => self.ties,
-            ScoreType::Xp => self.xp,
-            ScoreType::MessagesSent => self.messages.total(),
-            ScoreType::MatchesPlayed => self.matches,
-            // breaks 02/07/2106 05:28:15 AM UTC
-            // u32 should be compatible w/ existing database because Java uses two's complement to
-            // represent ints
-            ScoreType::ServerPlaytime => u32::try_from(self.server_playtime).unwrap_or(u32::MAX),
-            ScoreType::GamePlaytime => u32::try_from(self.game_playtime).unwrap_or(u32::MAX),
-            ScoreType::CoreLeaks => self.objectives.core_leaks,
-            ScoreType::CoreBlockDestroys => self.objectives.core_block_destroys,
-            ScoreType::DestroyableDestroys => self.objectives.destroyable_destroys,
-            ScoreType::DestroyableBlockDestroys => self.objectives.destroyable_block_destroys,
-            ScoreType::FlagCaptures => self.objectives.flag_captures,
-            ScoreType::FlagDrops => self.objectives.flag_drops,
-            ScoreType::FlagPickups => self.objectives.flag_pickups,
-            ScoreType::FlagDefends => self.objectives.flag_defends,
-            ScoreType::FlagHoldTime => u32::try_from(self.objectives.total_flag_hold_time).unwrap_or(u32::MAX),
-            ScoreType::WoolCaptures => self.objectives.wool_captures,
-            ScoreType::WoolDrops => self.objectives.wool_drops,
-            ScoreType::WoolPickups => self.objectives.wool_pickups,
-            ScoreType::WoolDefends => self.objectives.wool_defends,
-            ScoreType::ControlPointCaptures => self.objectives.control_point_captures,
+            ScoreType::Kills => self.kills as u64,
+            ScoreType::Deaths => self.deaths as u64,
+            ScoreType::Assists => self.assists as u64,
+            ScoreType::FirstBloods => self.first_bloods as u64,
+            ScoreType::Wins => self.wins() as u64,
+            ScoreType::Losses => self.losses() as u64,
+            ScoreType::Ties => self.ties() as u64,
+            ScoreType::Xp => self.xp as u64,
+            ScoreType::MessagesSent => self.messages.total() as u64,
+            ScoreType::MatchesPlayed => self.matches as u64,
+            // millisecond totals in full, redis keeps integer scores exact up to 2^53
+            ScoreType::ServerPlaytime => self.server_playtime,
+            ScoreType::GamePlaytime => self.game_playtime,
+            ScoreType::CoreLeaks => self.objectives.core_leaks as u64,
+            ScoreType::CoreBlockDestroys => self.objectives.core_block_destroys as u64,
+            ScoreType::DestroyableDestroys => self.objectives.destroyable_destroys as u64,
+            ScoreType::DestroyableBlockDestroys => self.objectives.destroyable_block_destroys as u64,
+            ScoreType::FlagCaptures => self.objectives.flag_captures as u64,
+            ScoreType::FlagDrops => self.objectives.flag_drops as u64,
+            ScoreType::FlagPickups => self.objectives.flag_pickups as u64,
+            ScoreType::FlagDefends => self.objectives.flag_defends as u64,
+            ScoreType::FlagHoldTime => self.objectives.total_flag_hold_time,
+            ScoreType::WoolCaptures => self.objectives.wool_captures as u64,
+            ScoreType::WoolDrops => self.objectives.wool_drops as u64,
+            ScoreType::WoolPickups => self.objectives.wool_pickups as u64,
+            ScoreType::WoolDefends => self.objectives.wool_defends as u64,
+            ScoreType::ControlPointCaptures => self.objectives.control_point_captures as u64,
             ScoreType::HighestKillstreak => {
                 let key = self.killstreaks.keys().map(|ksstr| ksstr.parse::<u32>().unwrap_or(0))
                     .max().unwrap_or(100u32);
                 let value = self.killstreaks.get(&key.to_string()).unwrap_or(&0).clone();
-                value
+                value as u64
             },
         }
     }
 }
 
-impl Default for PlayerStats {
-    fn default() -> Self {
-        PlayerStats {
-            xp: 0,
-            server_playtime: 0,
-            game_playtime: 0,
-            kills: 0,
-            deaths: 0,
-            void_kills: 0,
-            void_deaths: 0,
-            first_bloods: 0,
-            first_bloods_suffered: 0,
-            objectives: PlayerObjectiveStatistics::default(),
-            bow_shots_taken: 0,
-            bow_shots_hit: 0,
-            blocks_placed: HashMap::new(),
-            blocks_broken: HashMap::new(),
-            damage_taken: 0.0,
-            damage_given: 0.0,
-            damage_given_bow: 0.0,
-            messages: PlayerMessages::default(),
-            wins: 0,
-            losses: 0,
-            ties: 0,
-            matches: 0,
-            matches_present_start: 0,
-            matches_present_full: 0,
-            matches_present_end: 0,
-            records: PlayerRecords::default(),
-            weapon_kills: HashMap::new(),
-            weapon_deaths: HashMap::new(),
-            killstreaks: HashMap::new(),
-            killstreaks_ended: HashMap::new(),
-            achievements: HashMap::new()
-        }
-    }
-}
-
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerObjectiveStatistics {
@@ -271,19 +526,24 @@ impl Default for PlayerObjectiveStatistics {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerRecords {
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub longest_session: Option<SessionRecord>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub longest_projectile_kill: Option<ProjectileRecord>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub fastest_wool_capture: Option<PlayerRecord<u64>>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub fastest_flag_capture: Option<PlayerRecord<u64>>,
-    #[serde(default)]
+    // time into the match, these objectives have no held time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fastest_control_point_capture: Option<PlayerRecord<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fastest_core_leak: Option<PlayerRecord<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub fastest_first_blood: Option<FirstBloodRecord>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub kills_in_match: Option<PlayerRecord<u32>>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub deaths_in_match: Option<PlayerRecord<u32>>
 }
 
@@ -294,6 +554,8 @@ impl Default for PlayerRecords {
             longest_projectile_kill: None,
             fastest_wool_capture: None,
             fastest_flag_capture: None,
+            fastest_control_point_capture: None,
+            fastest_core_leak: None,
             fastest_first_blood: None,
             kills_in_match: None,
             deaths_in_match: None
@@ -301,6 +563,28 @@ impl Default for PlayerRecords {
     }
 }
 
+impl PlayerRecords {
+    // a resumed session counts as one, with the playtime of all its parts
+    pub fn record_session(&mut self, session: &Session) {
+        if self.longest_session.as_ref().map(|record| session.playtime > record.length).unwrap_or(true) {
+            self.longest_session = Some(SessionRecord { session_id: session.id.clone(), length: session.playtime });
+        };
+    }
+
+    // players who never set a record store no `records` at all, missing fields read back as none
+    pub fn is_empty(&self) -> bool {
+        self.longest_session.is_none()
+            && self.longest_projectile_kill.is_none()
+            && self.fastest_wool_capture.is_none()
+            && self.fastest_flag_capture.is_none()
+            && self.fastest_control_point_capture.is_none()
+            && self.fastest_core_leak.is_none()
+            && self.fastest_first_blood.is_none()
+            && self.kills_in_match.is_none()
+            && self.deaths_in_match.is_none()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerRecord<T> {
@@ -309,6 +593,39 @@ pub struct PlayerRecord<T> {
     pub value: T
 }
 
+impl PlayerRecord<u64> {
+    // for fastest-time records, whether `time` takes over from `record`
+    pub fn is_beaten(record: &Option<Self>, time: u64) -> bool {
+        !matches!(record, Some(record) if record.value <= time)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrestigeError {
+    BelowCap { level: u32, cap: u32 }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerIp {
+    pub ip: String,
+    pub last_seen_at: Timestamp
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NameHistoryEntry {
+    pub name: String,
+    pub name_lower: String,
+    pub first_seen_at: Timestamp
+}
+
+impl NameHistoryEntry {
+    pub fn new(name: &str, first_seen_at: Timestamp) -> Self {
+        Self { name: name.to_owned(), name_lower: name.to_lowercase(), first_seen_at }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SessionRecord {
@@ -340,8 +657,16 @@ pub struct SimplePlayer {
 }
 
 impl SimplePlayer {
-    pub fn get_mini_icon_url(&self) -> String {
-        format!("https://crafatar.com/avatars/{}?helm&size=50", self.id)
+    pub fn get_mini_icon_url(&self, avatars: &AvatarSource) -> String {
+        avatars.face_url(&self.id, 50)
+    }
+
+    pub fn get_face_url(&self, avatars: &AvatarSource, size: u32) -> String {
+        avatars.face_url(&self.id, size)
+    }
+
+    pub fn get_body_url(&self, avatars: &AvatarSource, size: u32) -> String {
+        avatars.body_url(&self.id, size)
     }
 }
 
@@ -373,3 +698,60 @@ impl Default for PlayerMessages {
         PlayerMessages { staff: 0, global: 0, team: 0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use mongodb::bson;
+    use rocket::serde::json::{json, from_value};
+
+    use crate::{database::models::{level::LevelGamemode, r#match::tests::ten_minute_match}, util::tracking::StatCategory};
+
+    use super::{Player, PlayerStats};
+
+    fn player() -> Player {
+        from_value(json!({
+            "_id": "id", "name": "Player", "nameLower": "player", "lastSessionId": null, "firstJoinedAt": 0, "lastJoinedAt": 0,
+            "ips": [], "notes": [], "rankIds": [], "tagIds": [], "activeTagId": null, "stats": {}, "gamemodeStats": {},
+            "activeJoinSoundId": null
+        })).unwrap()
+    }
+
+    #[test]
+    fn empty_stats_round_trip_to_the_default() {
+        let document = bson::to_document(&PlayerStats::default()).unwrap();
+        for key in ["blocksPlaced", "blocksBroken", "records", "weaponKills", "weaponDeaths", "killstreaks", "killstreaksEnded", "achievements"] {
+            assert!(!document.contains_key(key), "{key} should be left out");
+        }
+        let read: PlayerStats = bson::from_document(document.clone()).unwrap();
+        assert_eq!(bson::to_document(&read).unwrap(), document);
+        assert!(read.records.is_empty());
+        assert!(read.blocks_placed.is_empty() && read.weapon_kills.is_empty() && read.achievements.is_empty());
+    }
+
+    #[test]
+    fn first_match_in_a_gamemode_keeps_its_stats() {
+        let current_match = ten_minute_match(&["CAPTURE_THE_WOOL"]);
+        let mut player = player();
+        block_on(player.modify_gamemode_stats(&current_match, StatCategory::Kills, |stats| {
+            stats.kills += 1;
+            async {}
+        }));
+        assert_eq!(player.gamemode_stats.get(&LevelGamemode::CaptureTheWool).map(|stats| stats.kills), Some(1));
+    }
+
+    #[test]
+    fn drifted_presence_is_clamped_back() {
+        let mut player = player();
+        player.stats.matches = 5;
+        player.stats.matches_present_start = 7;
+        player.stats.matches_present_end = 4;
+        player.stats.matches_present_full = 6;
+        player.gamemode_stats.entry(LevelGamemode::CaptureTheWool).or_default().matches_present_full = 1;
+        assert!(player.repair_presence_stats());
+        assert!(player.stats.has_valid_presence());
+        assert_eq!((player.stats.matches_present_start, player.stats.matches_present_full, player.stats.matches_present_end), (5, 4, 4));
+        assert!(player.gamemode_stats.values().all(|stats| stats.has_valid_presence()));
+        assert!(!player.repair_presence_stats());
+    }
+}
@@ -1,8 +1,9 @@
 use mars_api_rs_derive::IdentifiableDocument;
 use mars_api_rs_macro::IdentifiableDocument;
+use rand::Rng;
 use serde::{Serialize, Deserialize};
-use strum_macros::Display;
-use crate::{database::CollectionOwner, util::time::get_u64_time_millis};
+use strum_macros::{Display, EnumIter, EnumString};
+use crate::{database::CollectionOwner, util::time::{get_u64_time_millis, Timestamp}};
 
 use super::player::SimplePlayer;
 
@@ -13,7 +14,7 @@ pub struct Punishment {
     #[serde(rename = "_id")]
     pub id: String,
     pub reason: PunishmentReason,
-    pub issued_at: f64,
+    pub issued_at: Timestamp,
     pub silent: bool,
     pub offence: u32,
     pub action: PunishmentAction,
@@ -26,7 +27,53 @@ pub struct Punishment {
     #[serde(default)]
     pub reversion: Option<PunishmentReversion>,
     #[serde(default)]
-    pub server_id: Option<String>
+    pub server_id: Option<String>,
+    // issued by the API itself rather than staff, and waiting for someone to review it
+    #[serde(default)]
+    pub automated: bool,
+    // short reference shown to the target, assigned when the punishment is inserted
+    #[serde(default)]
+    pub code: Option<String>,
+    // alts detected when it was issued, they are recorded only and not punished until someone applies it to them
+    #[serde(default)]
+    pub linked_accounts: Vec<LinkedAccount>,
+    // the `punishments.alt-link-confidence` the accounts were linked with, none when linking was off
+    #[serde(default)]
+    pub link_confidence: Option<f64>,
+    // when the last linked account that had not been punished yet was
+    #[serde(default)]
+    pub linked_applied_at: Option<Timestamp>,
+    // active punishments of lesser kinds this one deactivated when it was issued, see `PunishmentConflictPolicy`
+    #[serde(default)]
+    pub supersedes: Vec<String>,
+    #[serde(default)]
+    pub superseded_by: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkedAccount {
+    pub player: SimplePlayer,
+    // the share of the target's ips this account was seen on
+    pub confidence: f64,
+    pub shared_ips: u32,
+    // set once the punishment was applied to this account
+    #[serde(default)]
+    pub applied_punishment_id: Option<String>
+}
+
+// no 0/O or 1/I/L, codes get read back off screenshots
+const PUNISHMENT_CODE_ALPHABET : &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+pub const PUNISHMENT_CODE_LENGTH : usize = 6;
+
+pub fn generate_punishment_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..PUNISHMENT_CODE_LENGTH).map(|_| PUNISHMENT_CODE_ALPHABET[rng.gen_range(0..PUNISHMENT_CODE_ALPHABET.len())] as char).collect()
+}
+
+// codes are matched case-insensitively and players tend to copy the leading `#`
+pub fn normalize_punishment_code(code: &str) -> String {
+    code.trim().trim_start_matches('#').to_uppercase()
 }
 
 impl Punishment {
@@ -34,12 +81,12 @@ impl Punishment {
         if self.action.length == -1 {
             -1
         } else {
-            (self.issued_at as i64) + self.action.length
+            (self.issued_at.millis() as i64) + self.action.length
         }
     }
 
     pub fn is_active(&self) -> bool {
-        if self.reversion.is_some() {
+        if self.reversion.is_some() || self.superseded_by.is_some() {
             return false;
         } else {
             return self.action.length == -1 || (get_u64_time_millis() as i64) < self.expires_at()
@@ -81,12 +128,36 @@ pub struct PunishmentAction {
 }
 
 impl PunishmentAction {
+    pub fn new(kind: PunishmentKind, length: i64) -> Self {
+        PunishmentAction { kind, length }
+    }
+
+    pub fn length(&self) -> i64 {
+        self.length
+    }
+
+    // warnings and kicks carry a length in the punishment types but it has no effect
+    pub fn is_timed(&self) -> bool {
+        matches!(self.kind, PunishmentKind::Mute | PunishmentKind::Ban | PunishmentKind::IpBan)
+    }
+
     pub fn is_ban(&self) -> bool {
         self.kind == PunishmentKind::Ban || self.kind == PunishmentKind::IpBan
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Display)]
+// what issuing a punishment does about the target's active punishments of other kinds. `independent`
+// leaves them alone, `supersede` deactivates the lesser ones (a ban ends a mute) and `reject` refuses a
+// lesser punishment while a greater one is active. warnings and kicks never conflict with anything
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum PunishmentConflictPolicy {
+    Independent,
+    Supersede,
+    Reject
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Display, EnumString, EnumIter)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum PunishmentKind {
@@ -97,13 +168,59 @@ pub enum PunishmentKind {
     IpBan
 }
 
+impl PunishmentKind {
+    // how the kinds that stay active compare, an ip ban covers everything a ban does and a ban everything a mute does
+    pub fn severity(&self) -> Option<u8> {
+        match self {
+            PunishmentKind::Warn | PunishmentKind::Kick => None,
+            PunishmentKind::Mute => Some(1),
+            PunishmentKind::Ban => Some(2),
+            PunishmentKind::IpBan => Some(3)
+        }
+    }
+}
+
+pub const SENIOR_NOTE_PERMISSION : &str = "mars.notes.senior";
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct StaffNote {
     pub id: u32,
     pub author: SimplePlayer,
     pub content: String,
-    pub created_at: u64
+    pub created_at: Timestamp,
+    // notes from before types existed read as general notes every staff member can see
+    #[serde(default, rename = "type")]
+    pub note_type: NoteType,
+    #[serde(default)]
+    pub visibility: NoteVisibility
+}
+
+impl StaffNote {
+    pub fn is_visible_to_senior_only(&self) -> bool {
+        self.visibility == NoteVisibility::Senior
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Display, EnumString)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum NoteType {
+    Warning,
+    Watchlist,
+    #[default]
+    General,
+    // written by the API alongside an action, e.g. freezing stats, and never through the notes endpoint
+    System
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum NoteVisibility {
+    #[default]
+    Staff,
+    // needs `SENIOR_NOTE_PERMISSION` to be returned
+    Senior
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -113,10 +230,20 @@ pub struct PunishmentReason {
     short: String
 }
 
+impl PunishmentReason {
+    pub fn new(name: &str, message: &str, short: &str) -> Self {
+        PunishmentReason { name: name.to_owned(), message: message.to_owned(), short: short.to_owned() }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PunishmentReversion {
-    pub reverted_at: u64,
+    pub reverted_at: Timestamp,
     pub reverter: SimplePlayer,
     pub reason: String
 }
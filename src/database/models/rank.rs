@@ -3,7 +3,12 @@ use mars_api_rs_macro::IdentifiableDocument;
 use mongodb::bson::doc;
 use serde::{Serialize, Deserialize};
 
-use crate::database::{CollectionOwner, Database};
+use crate::{database::{CollectionOwner, Database, models::player::Player}, util::time::Timestamp};
+
+// temporary lengths are limited by `max_punishment_length`, permanent punishments need this instead
+pub const PERMANENT_PUNISHMENT_PERMISSION : &str = "mars.punish.permanent";
+// lets a punisher skip the reason rules by setting `overrideReasonRules`
+pub const REASON_OVERRIDE_PERMISSION : &str = "mars.punish.reason-override";
 
 #[derive(Deserialize, Serialize, Debug, IdentifiableDocument)]
 #[serde(rename_all = "camelCase")]
@@ -21,7 +26,12 @@ pub struct Rank {
     pub permissions: Vec<String>,
     pub staff: bool,
     pub apply_on_join: bool,
-    pub created_at: f64
+    // punishment lengths in milliseconds this rank may issue, -1 as the maximum lifts the cap on temporary punishments
+    #[serde(default)]
+    pub min_punishment_length: Option<i64>,
+    #[serde(default)]
+    pub max_punishment_length: Option<i64>,
+    pub created_at: Timestamp
 }
 
 impl CollectionOwner<Rank> for Rank {
@@ -35,6 +45,11 @@ impl CollectionOwner<Rank> for Rank {
 }
 
 impl Rank {
+    // the before/after text the audit log keeps for rank edits
+    pub fn audit_summary(&self) -> String {
+        format!("{} (priority {}, staff {}, apply on join {}, permissions: {})", self.name, self.priority, self.staff, self.apply_on_join, self.permissions.join(", "))
+    }
+
     pub async fn find_default(database: &Database) -> Vec<Rank> {
         let cursor = match Rank::get_collection(database).find(doc! {
             "applyOnJoin": true
@@ -44,4 +59,61 @@ impl Rank {
         };
         Database::consume_cursor_into_owning_vec(cursor).await
     }
+
+    pub async fn find_for_player(database: &Database, player: &Player) -> Vec<Rank> {
+        let cursor = match Rank::get_collection(database).find(doc! {
+            "_id": { "$in": &player.rank_ids }
+        }, None).await {
+            Ok(ranks_cursor) => ranks_cursor,
+            Err(_) => return Vec::new()
+        };
+        Database::consume_cursor_into_owning_vec(cursor).await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PunishmentLengthViolation {
+    Permanent,
+    TooShort(i64),
+    TooLong(i64)
+}
+
+// limits combine across the issuer's ranks using the most permissive value, ranks without limits don't restrict anything
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PunishmentLengthLimits {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub allow_permanent: bool
+}
+
+impl PunishmentLengthLimits {
+    pub fn from_ranks(ranks: &[Rank]) -> Self {
+        ranks.iter().fold(Self::default(), |limits, rank| Self {
+            min: match (limits.min, rank.min_punishment_length) {
+                (Some(current), Some(min)) => Some(current.min(min)),
+                (current, min) => current.or(min)
+            },
+            max: match (limits.max, rank.max_punishment_length) {
+                (Some(-1), _) | (_, Some(-1)) => Some(-1),
+                (Some(current), Some(max)) => Some(current.max(max)),
+                (current, max) => current.or(max)
+            },
+            allow_permanent: limits.allow_permanent || rank.permissions.iter().any(|perm| perm == PERMANENT_PUNISHMENT_PERMISSION)
+        })
+    }
+
+    pub fn check(&self, length: i64) -> Result<(), PunishmentLengthViolation> {
+        if length == -1 {
+            return if self.allow_permanent { Ok(()) } else { Err(PunishmentLengthViolation::Permanent) };
+        };
+        if let Some(min) = self.min {
+            if length < min {
+                return Err(PunishmentLengthViolation::TooShort(min));
+            };
+        };
+        match self.max {
+            Some(max) if max != -1 && length > max => Err(PunishmentLengthViolation::TooLong(max)),
+            _ => Ok(())
+        }
+    }
 }
@@ -0,0 +1,99 @@
+use serde::{Serialize, Deserialize};
+use strum_macros::Display;
+
+use crate::{database::{Database, audit::record_audit, models::{rank::Rank, audit_log::{AuditLogEntry, AuditAction}}}, socket::{event_type::EventType, player::player_events::PlayerRankGrantData, server::server_context::ServerBroadcast}, MarsAPIState};
+
+use super::player::Player;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum RankMetric {
+    // milliseconds, like the stats they come from
+    ServerPlaytime,
+    GamePlaytime,
+    Xp,
+    Matches
+}
+
+impl RankMetric {
+    pub fn of(&self, player: &Player) -> u64 {
+        match self {
+            RankMetric::ServerPlaytime => player.stats.server_playtime,
+            RankMetric::GamePlaytime => player.stats.game_playtime,
+            RankMetric::Xp => player.stats.xp as u64,
+            RankMetric::Matches => player.stats.matches as u64
+        }
+    }
+}
+
+// one entry of `rank_thresholds.yml`, e.g. `{ rankId: veteran, metric: SERVER_PLAYTIME, threshold: 360000000 }`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RankThreshold {
+    pub rank_id: String,
+    pub metric: RankMetric,
+    pub threshold: u64
+}
+
+impl RankThreshold {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.threshold == 0 {
+            return Err(format!("Rank threshold for '{}' must be above 0", self.rank_id));
+        };
+        Ok(())
+    }
+
+    pub fn is_reached_by(&self, player: &Player) -> bool {
+        self.metric.of(player) >= self.threshold
+    }
+}
+
+impl Player {
+    // thresholds crossed by ranks that were never granted automatically. each rank is auto-granted at most once,
+    // so one staff revoked stays revoked and re-evaluating a player already past a threshold changes nothing.
+    // a rank the player already holds from staff is left as it is and not counted as automatic
+    pub fn reached_rank_thresholds<'a>(&self, thresholds: &'a [RankThreshold]) -> Vec<&'a RankThreshold> {
+        thresholds.iter()
+            .filter(|threshold| !self.auto_rank_ids.contains(&threshold.rank_id) && !self.rank_ids.contains(&threshold.rank_id))
+            .filter(|threshold| threshold.is_reached_by(self))
+            .collect()
+    }
+}
+
+// called when a session ends, returns the ranks granted. the caller saves the player and only then hands them
+// to `announce_rank_grants`. ranks that no longer exist are skipped and stay eligible in case one with the same id is created again
+pub async fn grant_rank_thresholds(state: &MarsAPIState, player: &mut Player) -> Vec<Rank> {
+    let mut rank_ids : Vec<String> = Vec::new();
+    for threshold in player.reached_rank_thresholds(&state.config.data.rank_thresholds) {
+        if !rank_ids.contains(&threshold.rank_id) {
+            rank_ids.push(threshold.rank_id.clone());
+        };
+    };
+    let mut granted = Vec::new();
+    for rank_id in rank_ids.iter() {
+        match Database::find_by_id(&state.database.ranks, rank_id).await {
+            Some(rank) => {
+                player.auto_rank_ids.push(rank.id.clone());
+                player.rank_ids.push(rank.id.clone());
+                info!("Automatically granted rank {} to {}", rank.name, player.name);
+                granted.push(rank);
+            },
+            None => warn!("Rank threshold points at rank '{}', which does not exist", rank_id)
+        };
+    };
+    granted
+}
+
+pub fn announce_rank_grants(state: &MarsAPIState, player: &Player, granted: &[Rank]) {
+    for rank in granted.iter() {
+        record_audit(&state.database, AuditLogEntry::new(AuditAction::RankGrant)
+            .target(&player.id, &player.name)
+            .after(format!("{} (automatic)", rank.name)));
+        ServerBroadcast::send_from_api(&state.server_broadcast, &EventType::PlayerRankGrant, PlayerRankGrantData {
+            player_id: player.id.clone(),
+            rank_id: rank.id.clone(),
+            automatic: true
+        });
+    };
+}
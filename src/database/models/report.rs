@@ -0,0 +1,37 @@
+use mars_api_rs_derive::IdentifiableDocument;
+use mars_api_rs_macro::IdentifiableDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::{database::CollectionOwner, util::time::Timestamp};
+
+use super::player::SimplePlayer;
+
+// repeat reports from the same reporter against the same target are folded into one document
+#[derive(Deserialize, Serialize, IdentifiableDocument, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    #[id]
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub target: SimplePlayer,
+    pub reporter: SimplePlayer,
+    pub reasons: Vec<String>,
+    pub count: u32,
+    pub server_id: String,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    #[serde(default = "default_open")]
+    pub open: bool
+}
+
+fn default_open() -> bool { true }
+
+impl CollectionOwner<Report> for Report {
+    fn get_collection(database: &crate::database::Database) -> &mongodb::Collection<Report> {
+        &database.reports
+    }
+
+    fn get_collection_name() -> &'static str {
+        "report"
+    }
+}
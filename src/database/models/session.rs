@@ -1,9 +1,8 @@
-use log::warn;
 use mars_api_rs_derive::IdentifiableDocument;
 use mars_api_rs_macro::IdentifiableDocument;
 use serde::{Deserialize, Serialize};
 
-use crate::database::CollectionOwner;
+use crate::{database::CollectionOwner, util::time::Timestamp};
 
 use super::player::SimplePlayer;
 
@@ -16,25 +15,51 @@ pub struct Session {
     pub ip: String,
     pub player: SimplePlayer,
     pub server_id: String,
-    pub created_at: u64,
-    pub ended_at: Option<u64>
+    pub created_at: Timestamp,
+    pub ended_at: Option<Timestamp>,
+    // playtime credited so far, over every part of the session when it has been resumed
+    #[serde(default)]
+    pub playtime: u64,
+    #[serde(default)]
+    pub resumed_at: Option<Timestamp>,
+    // the realm of the server the session is on, `None` for the default realm
+    #[serde(default)]
+    pub realm: Option<String>
 } 
 
 impl Session {
+    // a server that has sent nothing for `stale_after` ms is treated as gone, along with every session still open on it
+    pub fn is_server_stale(last_alive_time: Option<u64>, now: u64, stale_after: u64) -> bool {
+        match last_alive_time {
+            Some(last_alive_time) => now.saturating_sub(last_alive_time) > stale_after,
+            None => true
+        }
+    }
+
     pub fn is_active(&self) -> bool {
         self.ended_at.is_none()
     }
 
-    pub fn length(&self) -> Option<u64> {
-        if self.ended_at.is_none() { None } else { 
-            let ended_at = self.ended_at.unwrap();
-            if ended_at < self.created_at {
-                warn!("Session length is negative");
-                None 
-            } else { 
-                Some(ended_at - self.created_at) 
-            }
-        } 
+    pub fn sanitized_copy(&self) -> Session {
+        let mut clone = self.clone();
+        clone.ip = String::new();
+        clone
+    }
+
+    // sessions that ended at or after this can be resumed, `None` when `sessions.resume-grace` is off
+    pub fn resume_cutoff(now: u64, grace: u64) -> Option<u64> {
+        if grace == 0 { None } else { Some(now.saturating_sub(grace)) }
+    }
+
+    // a login on `server_id` picks the session back up if it ended there at `cutoff` or later
+    pub fn is_resumable(&self, server_id: &str, cutoff: u64) -> bool {
+        self.server_id == server_id && self.ended_at.is_some_and(|ended_at| ended_at.millis() >= cutoff)
+    }
+
+    // how long the session ran since it was last opened or resumed, what has not been credited yet
+    pub fn part_length(&self) -> Option<u64> {
+        let ended_at = self.ended_at?;
+        Some(ended_at.millis_since(self.resumed_at.unwrap_or(self.created_at)))
     }
 }
 
@@ -47,3 +72,65 @@ impl CollectionOwner<Session> for Session {
         "session"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{database::models::player::{PlayerRecords, SimplePlayer}, util::time::Timestamp};
+
+    use super::Session;
+
+    fn session(created_at: u64) -> Session {
+        Session {
+            id: String::from("session"), ip: String::new(), player: SimplePlayer { name: String::from("Player"), id: String::from("id") },
+            server_id: String::from("server"), created_at: Timestamp::from_millis(created_at), ended_at: None, playtime: 0, resumed_at: None, realm: None
+        }
+    }
+
+    // what `Database::resume_session` does to a session it finds
+    fn resume(session: &mut Session, now: u64, grace: u64) -> bool {
+        match Session::resume_cutoff(now, grace) {
+            Some(cutoff) if session.is_resumable("server", cutoff) => {
+                session.ended_at = None;
+                session.resumed_at = Some(Timestamp::from_millis(now));
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn end(session: &mut Session, at: u64, records: &mut PlayerRecords) {
+        session.ended_at = Some(Timestamp::from_millis(at));
+        session.playtime += session.part_length().unwrap();
+        records.record_session(session);
+    }
+
+    #[test]
+    fn reconnects_within_the_grace_resume_the_session() {
+        let mut records = PlayerRecords::default();
+        let mut session = session(0);
+        end(&mut session, 60_000, &mut records);
+        assert!(resume(&mut session, 65_000, 10_000));
+        end(&mut session, 125_000, &mut records);
+        assert_eq!(session.playtime, 120_000);
+        assert_eq!(records.longest_session.map(|record| record.length), Some(120_000));
+    }
+
+    #[test]
+    fn reconnects_after_the_grace_start_a_new_session() {
+        let mut session = session(0);
+        end(&mut session, 60_000, &mut PlayerRecords::default());
+        assert!(!resume(&mut session, 75_000, 10_000));
+        assert!(!resume(&mut session, 61_000, 0));
+        assert_eq!(session.ended_at, Some(Timestamp::from_millis(60_000)));
+    }
+
+    #[test]
+    fn sessions_resume_only_once_ended_and_on_their_own_server() {
+        let mut session = session(0);
+        assert!(!session.is_resumable("server", 0));
+        session.ended_at = Some(Timestamp::from_millis(60_000));
+        assert!(session.is_resumable("server", 60_000));
+        assert!(!session.is_resumable("server", 60_001));
+        assert!(!session.is_resumable("other", 0));
+    }
+}
@@ -0,0 +1,41 @@
+use mars_api_rs_derive::IdentifiableDocument;
+use mars_api_rs_macro::IdentifiableDocument;
+use serde::{Deserialize, Serialize};
+
+use crate::{database::CollectionOwner, util::time::Timestamp};
+
+use super::{player::SimplePlayer, punishment::Punishment};
+
+// what an automatic rule in shadow mode would have issued, kept for staff to judge instead of being
+// inserted. `rule` names the rule and what it matched on, e.g. `auto-mute:spam`
+#[derive(Deserialize, Serialize, IdentifiableDocument, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowPunishment {
+    #[id]
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub rule: String,
+    pub punishment: Punishment,
+    pub created_at: Timestamp,
+    #[serde(default)]
+    pub review: Option<ShadowReview>
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowReview {
+    pub reviewer: SimplePlayer,
+    // whether the rule was right to want this punishment
+    pub correct: bool,
+    pub reviewed_at: Timestamp
+}
+
+impl CollectionOwner<ShadowPunishment> for ShadowPunishment {
+    fn get_collection(database: &crate::database::Database) -> &mongodb::Collection<ShadowPunishment> {
+        &database.shadow_punishments
+    }
+
+    fn get_collection_name() -> &'static str {
+        "shadow_punishment"
+    }
+}
@@ -1,8 +1,9 @@
 use mars_api_rs_macro::IdentifiableDocument;
 use mars_api_rs_derive::IdentifiableDocument;
 use serde::{Serialize, Deserialize};
+use strum_macros::{Display, EnumString};
 
-use crate::database::CollectionOwner;
+use crate::{database::{CollectionOwner, models::player::Player}, util::time::Timestamp};
 
 #[derive(Debug, Serialize, Deserialize, IdentifiableDocument)]
 pub struct Tag {
@@ -14,9 +15,25 @@ pub struct Tag {
     pub name_lower: String,
     pub display: String,
     #[serde(rename = "createdAt")]
-    pub created_at: f64
+    pub created_at: Timestamp,
+    // tags created before slots existed were all displayed as prefixes
+    #[serde(default = "default_tag_slots")]
+    pub slots: Vec<TagSlot>,
+    // owning the tag isn't enough to equip it below this level
+    #[serde(rename = "minLevel", default, skip_serializing_if = "Option::is_none")]
+    pub min_level: Option<u32>
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq, Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum TagSlot {
+    Prefix,
+    Suffix
+}
+
+pub fn default_tag_slots() -> Vec<TagSlot> { vec![TagSlot::Prefix] }
+
 impl CollectionOwner<Tag> for Tag {
     fn get_collection(database: &crate::database::Database) -> &mongodb::Collection<Tag> {
         &database.tags
@@ -26,3 +43,19 @@ impl CollectionOwner<Tag> for Tag {
         "tag"
     }
 }
+
+impl Tag {
+    // the before/after text the audit log keeps for tag edits
+    pub fn audit_summary(&self) -> String {
+        let slots : Vec<String> = self.slots.iter().map(|slot| slot.to_string()).collect();
+        match self.min_level {
+            Some(min_level) => format!("{} '{}' ({}, level {}+)", self.name, self.display, slots.join(", "), min_level),
+            None => format!("{} '{}' ({})", self.name, self.display, slots.join(", "))
+        }
+    }
+
+    // against the lifetime level, prestiging doesn't lock tags again
+    pub fn is_unlocked_by(&self, player: &Player) -> bool {
+        self.min_level.map(|min_level| player.get_lifetime_level() >= min_level).unwrap_or(true)
+    }
+}
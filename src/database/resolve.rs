@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
+use mongodb::{bson::{doc, Document}, options::FindOptions};
+
+use super::{Database, models::player::SimplePlayer};
+
+impl Database {
+    // every input tried as an id and as a name in one query. an input that is both one player's id and another's
+    // name resolves to the id, like `find_by_id_or_name`. `None` for inputs that match nothing
+    pub async fn resolve_players(&self, inputs: &[String]) -> anyhow::Result<HashMap<String, Option<SimplePlayer>>> {
+        let names : Vec<String> = inputs.iter().map(|input| input.to_lowercase()).collect();
+        let options = FindOptions::builder().projection(doc! { "name": 1, "nameLower": 1 }).build();
+        let mut cursor = self.players.clone_with_type::<Document>().find(doc! { "$or": [
+            { "_id": { "$in": inputs } },
+            { "nameLower": { "$in": &names } }
+        ] }, options).await?;
+        let mut by_id : HashMap<String, SimplePlayer> = HashMap::new();
+        let mut by_name : HashMap<String, SimplePlayer> = HashMap::new();
+        while let Some(document) = cursor.try_next().await? {
+            let (id, name) = match (document.get_str("_id"), document.get_str("name")) {
+                (Ok(id), Ok(name)) => (id.to_owned(), name.to_owned()),
+                _ => continue
+            };
+            let player = SimplePlayer { id: id.clone(), name };
+            by_name.insert(document.get_str("nameLower").map(|name| name.to_owned()).unwrap_or_else(|_| player.name.to_lowercase()), player.clone());
+            by_id.insert(id, player);
+        };
+        Ok(inputs.iter().zip(names.iter())
+            .map(|(input, name)| (input.clone(), by_id.get(input).or_else(|| by_name.get(name)).cloned()))
+            .collect())
+    }
+}
@@ -0,0 +1,63 @@
+use futures::TryStreamExt;
+use mongodb::{bson::{doc, Bson, Document}, error::ErrorKind, options::{FindOptions, InsertManyOptions}, IndexModel};
+use serde::Serialize;
+
+use super::{Database, DUPLICATE_KEY_ERROR_CODE};
+
+pub const SESSION_ARCHIVE_COLLECTION_NAME : &str = "session_archive";
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionRotation {
+    pub rotated: u64,
+    // whether the rotated sessions were copied to the archive before being removed
+    pub archived: bool,
+    pub batches: u32
+}
+
+impl Database {
+    pub async fn ensure_session_rotation_indexes(&self) -> anyhow::Result<()> {
+        self.sessions.create_index(IndexModel::builder().keys(doc! { "endedAt": 1 }).build(), None).await?;
+        self.session_archive.create_index(IndexModel::builder().keys(doc! { "player.id": 1 }).build(), None).await?;
+        Ok(())
+    }
+
+    // playtime is credited to the player when a session ends, so rotating ended sessions leaves every stat
+    // as it was. open sessions are never touched however old they are, the sweeper closes those
+    pub async fn rotate_sessions(&self, ended_before: u64, batch_size: i64, archive: bool) -> anyhow::Result<SessionRotation> {
+        let mut rotation = SessionRotation { archived: archive, ..Default::default() };
+        let sessions = self.sessions.clone_with_type::<Document>();
+        loop {
+            let filter = doc! { "endedAt": { "$ne": null, "$lt": ended_before as i64 } };
+            let batch : Vec<Document> = sessions.find(filter, FindOptions::builder().limit(batch_size).build()).await?.try_collect().await?;
+            if batch.is_empty() {
+                break;
+            };
+            let full = batch.len() as i64 == batch_size;
+            let ids : Vec<Bson> = batch.iter().filter_map(|session| session.get("_id").cloned()).collect();
+            if archive {
+                self.archive_sessions(batch).await?;
+            };
+            rotation.rotated += sessions.delete_many(doc! { "_id": { "$in": ids } }, None).await?.deleted_count;
+            rotation.batches += 1;
+            if !full {
+                break;
+            };
+        };
+        Ok(rotation)
+    }
+
+    // a batch that was archived but not deleted before a failure is archived again on the next run,
+    // the copies already there are skipped
+    async fn archive_sessions(&self, batch: Vec<Document>) -> anyhow::Result<()> {
+        let options = InsertManyOptions::builder().ordered(false).build();
+        match self.session_archive.clone_with_type::<Document>().insert_many(batch, options).await {
+            Ok(_) => Ok(()),
+            Err(e) => match e.kind.as_ref() {
+                ErrorKind::BulkWrite(failure) if failure.write_concern_error.is_none()
+                    && failure.write_errors.iter().flatten().all(|error| error.code == DUPLICATE_KEY_ERROR_CODE) => Ok(()),
+                _ => Err(e.into())
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use mongodb::{bson::{doc, Document, Regex}, options::FindOneOptions, IndexModel};
+use rocket::serde::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::util::string::escape_regex;
+
+use super::{Database, CollectionOwner};
+
+// declaration order is precedence, a search tries each requested strategy in this order
+// regardless of the order it was asked for in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE", ascii_case_insensitive)]
+pub enum SearchStrategy {
+    Id,
+    Name,
+    // only documents that keep a `nameHistory`, the most recent holder of the name wins
+    NameHistory,
+    // the alphabetically first name starting with the text
+    Prefix
+}
+
+impl SearchStrategy {
+    // what `find_by_id_or_name` matches
+    pub const ID_OR_NAME : [SearchStrategy; 2] = [SearchStrategy::Id, SearchStrategy::Name];
+
+    fn filter(&self, text: &str) -> Document {
+        match self {
+            SearchStrategy::Id => doc! { "_id": text },
+            SearchStrategy::Name => doc! { "nameLower": text.to_lowercase() },
+            SearchStrategy::NameHistory => doc! { "nameHistory.nameLower": text.to_lowercase() },
+            SearchStrategy::Prefix => doc! { "nameLower": Regex { pattern: format!("^{}", escape_regex(&text.to_lowercase())), options: String::new() } }
+        }
+    }
+
+    fn sort(&self) -> Option<Document> {
+        match self {
+            SearchStrategy::Id | SearchStrategy::Name => None,
+            SearchStrategy::NameHistory => Some(doc! { "lastJoinedAt": -1 }),
+            SearchStrategy::Prefix => Some(doc! { "nameLower": 1 })
+        }
+    }
+}
+
+pub struct SearchMatch<T> {
+    pub document: T,
+    pub matched_by: SearchStrategy
+}
+
+impl Database {
+    // what the name strategies and `find_by_id_or_name` look players up by
+    pub async fn ensure_player_name_indexes(&self) -> anyhow::Result<()> {
+        self.players.create_indexes(vec![
+            IndexModel::builder().keys(doc! { "nameLower": 1 }).build(),
+            IndexModel::builder().keys(doc! { "nameHistory.nameLower": 1 }).build()
+        ], None).await?;
+        Ok(())
+    }
+
+    pub async fn find_by_search<T>(&self, text: &str, strategies: &[SearchStrategy]) -> Option<SearchMatch<T>>
+        where T: DeserializeOwned + CollectionOwner<T> + Unpin + Send + Sync {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        };
+        let mut strategies = strategies.to_vec();
+        strategies.sort();
+        strategies.dedup();
+        for strategy in strategies {
+            let options = FindOneOptions::builder().sort(strategy.sort()).build();
+            match T::get_collection(self).find_one(strategy.filter(text), options).await {
+                Ok(Some(document)) => return Some(SearchMatch { document, matched_by: strategy }),
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Could not search '{}' by {}: {}", T::get_collection_name(), strategy, e);
+                    return None;
+                }
+            };
+        };
+        None
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+
+use super::{Database, models::{rank::Rank, tag::Tag}};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagUsage {
+    pub tag_id: String,
+    pub name: String,
+    // players who own the tag
+    pub assigned: u64,
+    // players with the tag in at least one slot
+    pub active: u64
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RankUsage {
+    pub rank_id: String,
+    pub name: String,
+    pub assigned: u64
+}
+
+impl Database {
+    // every tag is listed, unused ones with zero counts
+    pub async fn tag_usage_counts(&self) -> anyhow::Result<Vec<TagUsage>> {
+        let assigned = self.count_players_by(vec![
+            doc! { "$unwind": "$tagIds" },
+            doc! { "$group": { "_id": "$tagIds", "count": { "$sum": 1 } } }
+        ]).await?;
+        // a tag active in several slots of one player counts that player once
+        let active = self.count_players_by(vec![
+            doc! { "$project": { "active": { "$objectToArray": { "$ifNull": ["$activeTagIds", {}] } } } },
+            doc! { "$unwind": "$active" },
+            doc! { "$group": { "_id": { "player": "$_id", "tag": "$active.v" } } },
+            doc! { "$group": { "_id": "$_id.tag", "count": { "$sum": 1 } } }
+        ]).await?;
+        let tags = self.get_all_documents::<Tag>().await;
+        Ok(tags.into_iter().map(|tag| TagUsage {
+            assigned: assigned.get(&tag.id).copied().unwrap_or(0),
+            active: active.get(&tag.id).copied().unwrap_or(0),
+            tag_id: tag.id,
+            name: tag.name
+        }).collect())
+    }
+
+    // every rank is listed, unused ones with a zero count
+    pub async fn rank_usage_counts(&self) -> anyhow::Result<Vec<RankUsage>> {
+        let assigned = self.count_players_by(vec![
+            doc! { "$unwind": "$rankIds" },
+            doc! { "$group": { "_id": "$rankIds", "count": { "$sum": 1 } } }
+        ]).await?;
+        let ranks = self.get_all_documents::<Rank>().await;
+        Ok(ranks.into_iter().map(|rank| RankUsage {
+            assigned: assigned.get(&rank.id).copied().unwrap_or(0),
+            rank_id: rank.id,
+            name: rank.name
+        }).collect())
+    }
+
+    // runs a pipeline over players that ends in groups of `{ _id: <id>, count }`
+    async fn count_players_by(&self, pipeline: Vec<Document>) -> anyhow::Result<HashMap<String, u64>> {
+        let mut cursor = self.players.clone_with_type::<Document>().aggregate(pipeline, None).await?;
+        let mut counts = HashMap::new();
+        while let Some(group) = cursor.try_next().await? {
+            let id = match group.get_str("_id") {
+                Ok(id) => id.to_owned(),
+                Err(_) => continue
+            };
+            let count = group.get_i32("count").map(|count| count as u64).or_else(|_| group.get_i64("count").map(|count| count as u64)).unwrap_or(0);
+            counts.insert(id, count);
+        };
+        Ok(counts)
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use mongodb::{Collection, options::{Acknowledgment, CollectionOptions, WriteConcern}};
+
+// how many members acknowledge a write before it returns, by collection name (`punishment`, `damage_event`, ...).
+// applied to the collection handles, so `save`, `insert_one`, the batched `insert_many` of deaths and damage events and direct calls all follow it.
+// collections nobody configured use `default`, and without that the deployment's own default like before
+#[derive(Debug, Clone, Default)]
+pub struct WriteConcerns {
+    default: Option<Acknowledgment>,
+    collections: HashMap<String, Option<Acknowledgment>>
+}
+
+impl WriteConcerns {
+    // `collection` is a collection name or `default`. `concern` is `majority`, a number of members
+    // above 0, a custom write concern name, or `server` to leave it to the deployment
+    pub fn insert(&mut self, collection: &str, concern: &str) {
+        let concern = concern.trim();
+        let acknowledgment = if concern.eq_ignore_ascii_case("server") {
+            None
+        } else if concern.eq_ignore_ascii_case("majority") {
+            Some(Acknowledgment::Majority)
+        } else {
+            match concern.parse::<u32>() {
+                Ok(0) => {
+                    warn!("Unacknowledged writes are not supported, ignoring the write concern for {}", collection);
+                    return;
+                },
+                Ok(nodes) => Some(Acknowledgment::Nodes(nodes)),
+                Err(_) if concern.is_empty() => return,
+                Err(_) => Some(Acknowledgment::Custom(concern.to_owned()))
+            }
+        };
+        if collection.eq_ignore_ascii_case("default") {
+            self.default = acknowledgment;
+        } else {
+            self.collections.insert(collection.trim().to_lowercase(), acknowledgment);
+        };
+    }
+
+    pub fn for_collection(&self, name: &str) -> Option<WriteConcern> {
+        let acknowledgment = match self.collections.get(name) {
+            Some(acknowledgment) => acknowledgment.clone(),
+            None => self.default.clone()
+        };
+        acknowledgment.map(|w| WriteConcern::builder().w(w).build())
+    }
+
+    pub fn collection<T>(&self, db: &mongodb::Database, name: &str) -> Collection<T> {
+        db.collection_with_options::<T>(name, CollectionOptions::builder().write_concern(self.for_collection(name)).build())
+    }
+}
@@ -17,6 +17,18 @@ use crate::{util::auth::AuthorizationToken};
 
 mod payload;
 
+const ACHIEVEMENT_CATALOG_KEY : &str = "achievement-catalog";
+
+// every achievement, cached as one entry and dropped whenever an achievement is added or removed
+pub async fn get_achievement_catalog(state: &MarsAPIState) -> Vec<Achievement> {
+    if let Some(catalog) = state.redis.get_unchecked::<Vec<Achievement>>(ACHIEVEMENT_CATALOG_KEY).await {
+        return catalog;
+    };
+    let catalog = state.database.get_all_documents::<Achievement>().await;
+    state.redis.set_with_expiry(ACHIEVEMENT_CATALOG_KEY, &catalog, Some(state.achievement_cache.lifetime_ms as usize)).await;
+    catalog
+}
+
 #[post("/", format = "json", data = "<achievement_create_req>")]
 async fn add_achievement(
     state: &State<MarsAPIState>,
@@ -28,7 +40,7 @@ async fn add_achievement(
         None => {},
     };
 
-    let AchievementCreateRequest { name, description, metadata_preset, agent } = achievement_create_req.0;
+    let AchievementCreateRequest { name, description, metadata_preset, agent, rarity, broadcast } = achievement_create_req.0;
     let metadata = match metadata_preset {
         Some(preset) => Some(achievement_metadata_preset_to_metadata(preset)),
         None => None,
@@ -39,9 +51,13 @@ async fn add_achievement(
         name,
         description, 
         metadata, 
-        agent
+        agent,
+        rarity,
+        broadcast
     };
     state.database.save::<Achievement>(&new_achievement).await;
+    state.achievement_cache.store(&new_achievement).await;
+    state.redis.delete(ACHIEVEMENT_CATALOG_KEY).await;
     return Ok(JsonResponder::from(new_achievement, Status::Ok));
 }
 
@@ -57,6 +73,8 @@ async fn delete_achievement(
         },
         _ => {}
     };
+    state.achievement_cache.evict(achievement_id).await;
+    state.redis.delete(ACHIEVEMENT_CATALOG_KEY).await;
     let players_with_achievement_query = doc! {format!("stats.achievements.{}", achievement_id): {"$exists": true}};
     let mut players_with_achievement = Database::consume_cursor_into_owning_vec_option(
         state.database.players.find(players_with_achievement_query.clone(), None).await.ok()
@@ -84,7 +102,7 @@ async fn get_achievement_by_id(
 ) -> Result<JsonResponder<Achievement>, ApiErrorResponder> {
     Ok(JsonResponder::ok(
         unwrap_helper::return_default!(
-            state.database.find_by_id_or_name(achievement_id).await,
+            state.achievement_cache.get(&state.database, achievement_id).await,
             Err(ApiErrorResponder::tag_missing())
         )
     ))
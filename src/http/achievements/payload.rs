@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::database::models::achievement::{AchievementMetadataConstant, Agent};
+use crate::database::models::achievement::{AchievementMetadataConstant, Agent, AchievementRarity};
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -9,5 +9,9 @@ pub struct AchievementCreateRequest {
     pub description: String,
     #[serde(rename = "category")]
     pub metadata_preset: Option<AchievementMetadataConstant>,
-    pub agent: Agent
+    pub agent: Agent,
+    #[serde(default)]
+    pub rarity: AchievementRarity,
+    #[serde(default)]
+    pub broadcast: bool
 }
@@ -0,0 +1,59 @@
+use std::str::FromStr;
+
+use rocket::{Rocket, Build, State, http::Status, serde::json::Json};
+
+use crate::{MarsAPIState, database::{KeysetPage, audit::AuditLogQuery, models::{audit_log::{AuditAction, AuditLogEntry, AUDIT_LOG_PERMISSION}, player::Player, rank::Rank}}, util::{auth::AuthorizationToken, error::ApiErrorResponder, r#macro::unwrap_helper, responder::ListResponder}};
+
+#[derive(FromForm)]
+struct AuditLogParams {
+    viewer: Option<String>,
+    actor: Option<String>,
+    action: Option<String>,
+    target: Option<String>,
+    from: Option<u64>,
+    to: Option<u64>,
+    after: Option<String>,
+    limit: Option<i64>
+}
+
+// the server token is shared with every game server, so a viewer whose ranks grant `AUDIT_LOG_PERMISSION` is required.
+// `actor` and `target` take a name or an id, pass the previous page's `next` as `after`
+#[get("/?<params..>")]
+async fn get_audit_log(
+    state: &State<MarsAPIState>,
+    params: AuditLogParams,
+    _auth_guard: AuthorizationToken
+) -> Result<ListResponder<Json<KeysetPage<AuditLogEntry>>>, ApiErrorResponder> {
+    let AuditLogParams { viewer, actor, action, target, from, to, after, limit } = params;
+    let viewer = unwrap_helper::return_default!(viewer, Err(ApiErrorResponder::validation_error_with_message("The audit log needs a viewer")));
+    let viewer : Player = unwrap_helper::return_default!(state.player_cache.get(&state.database, &viewer).await, Err(ApiErrorResponder::missing_player()));
+    let allowed = Rank::find_for_player(&state.database, &viewer).await.iter()
+        .any(|rank| rank.permissions.iter().any(|permission| permission == AUDIT_LOG_PERMISSION));
+    if !allowed {
+        return Err(ApiErrorResponder::create_anonymous_error(Status::Forbidden, "You do not have permission to view the audit log"));
+    };
+    let action = match action {
+        Some(action) => Some(unwrap_helper::return_default!(AuditAction::from_str(&action).ok(), Err(ApiErrorResponder::validation_error_with_message("Unknown audit action")))),
+        None => None
+    };
+    // actors are stored by id, names are looked up so renamed staff still match
+    let actor_id = match actor {
+        Some(actor) => Some(state.player_cache.get(&state.database, &actor).await.map(|player| player.id).unwrap_or(actor)),
+        None => None
+    };
+    let query = AuditLogQuery { actor_id, action, target, from, to, after, limit };
+    match state.database.query_audit_log(query).await {
+        Ok((page, total)) => {
+            let returned = page.items.len();
+            Ok(ListResponder::new(Json(page), returned, total))
+        },
+        Err(e) => {
+            warn!("Could not query the audit log: {}", e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not query the audit log"))
+        }
+    }
+}
+
+pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
+    rocket_build.mount("/mc/audit", routes![get_audit_log])
+}
@@ -0,0 +1,106 @@
+use rocket::{Rocket, Build, State, http::Status, serde::json::Json};
+
+use crate::{MarsAPIState, util::{auth::AdminAuthorizationToken, error::ApiErrorResponder}};
+
+use self::payload::{CacheKeyResponse, CacheEvictResponse};
+
+mod payload;
+
+const DEFAULT_KEY_LIMIT : usize = 500;
+const MAX_KEY_LIMIT : usize = 5_000;
+
+// only the document caches can be listed or evicted, leaderboards, rebuild staging and confirmation
+// tokens live in the same redis and are never reachable from here
+fn cache_resource<'a>(state: &'a MarsAPIState, resource: &str) -> Result<&'a str, ApiErrorResponder> {
+    [
+        &state.player_cache.resource_name,
+        &state.match_cache.resource_name,
+        &state.rank_cache.resource_name,
+        &state.tag_cache.resource_name,
+        &state.achievement_cache.resource_name
+    ].into_iter()
+        .find(|name| name.as_str() == resource)
+        .map(|name| name.as_str())
+        .ok_or_else(|| ApiErrorResponder::validation_error_with_message("Unknown cache resource"))
+}
+
+// cache keys are lowercased like `Cache` writes them
+fn cache_key(resource: &str, key: &str) -> String {
+    format!("{}:{}", resource, key.to_lowercase())
+}
+
+#[get("/<resource>?<prefix>&<limit>")]
+async fn list_cache_keys(
+    state: &State<MarsAPIState>,
+    resource: &str,
+    prefix: Option<&str>,
+    limit: Option<usize>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Json<Vec<CacheKeyResponse>>, ApiErrorResponder> {
+    let resource = cache_resource(state, resource)?;
+    let limit = limit.unwrap_or(DEFAULT_KEY_LIMIT).clamp(1, MAX_KEY_LIMIT);
+    let listed = match state.redis.scan_prefix(&cache_key(resource, prefix.unwrap_or("")), limit).await {
+        Ok(mut keys) => {
+            keys.sort();
+            state.redis.ttls(&keys).await.map(|ttls| (keys, ttls))
+        },
+        Err(e) => Err(e)
+    };
+    match listed {
+        Ok((keys, ttls)) => Ok(Json(keys.into_iter().zip(ttls)
+            // -2 is a key that expired between the scan and the ttl lookup
+            .filter(|(_, ttl)| *ttl != -2)
+            .map(|(key, ttl)| CacheKeyResponse { key, ttl_ms: if ttl < 0 { None } else { Some(ttl) } })
+            .collect())),
+        Err(e) => {
+            warn!("Could not list cache keys: {}", e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not list cache keys"))
+        }
+    }
+}
+
+// the document is loaded again from mongo the next time it is asked for
+#[delete("/<resource>/<key>")]
+async fn evict_cache_key(
+    state: &State<MarsAPIState>,
+    resource: &str,
+    key: &str,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Json<CacheEvictResponse>, ApiErrorResponder> {
+    let key = cache_key(cache_resource(state, resource)?, key);
+    match state.redis.delete_key(&key).await {
+        Ok(deleted) => {
+            info!("Evicted cache key '{}'", key);
+            Ok(Json(CacheEvictResponse { evicted: if deleted { 1 } else { 0 } }))
+        },
+        Err(e) => {
+            warn!("Could not evict cache key '{}': {}", key, e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not evict the cache key"))
+        }
+    }
+}
+
+// without a prefix every cached document of the resource is evicted, they all load again from mongo
+#[delete("/<resource>?<prefix>")]
+async fn evict_cache_prefix(
+    state: &State<MarsAPIState>,
+    resource: &str,
+    prefix: Option<&str>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Json<CacheEvictResponse>, ApiErrorResponder> {
+    let prefix = cache_key(cache_resource(state, resource)?, prefix.unwrap_or(""));
+    match state.redis.delete_prefix(&prefix).await {
+        Ok(evicted) => {
+            info!("Evicted {} cache keys with prefix '{}'", evicted, prefix);
+            Ok(Json(CacheEvictResponse { evicted }))
+        },
+        Err(e) => {
+            warn!("Could not evict cache prefix '{}': {}", prefix, e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not evict the cache prefix"))
+        }
+    }
+}
+
+pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
+    rocket_build.mount("/mc/cache", routes![list_cache_keys, evict_cache_key, evict_cache_prefix])
+}
@@ -0,0 +1,16 @@
+use serde::{Serialize, Deserialize};
+
+// keys only, values are never returned since player entries carry ips
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheKeyResponse {
+    pub key: String,
+    // `None` when the key never expires
+    pub ttl_ms: Option<i64>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEvictResponse {
+    pub evicted: u64
+}
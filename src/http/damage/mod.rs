@@ -0,0 +1,27 @@
+use rocket::{Rocket, Build, State, http::Status, serde::json::Json};
+
+use crate::{MarsAPIState, database::damage_events::WeaponDamage, util::{auth::AdminAuthorizationToken, error::ApiErrorResponder}};
+
+// per-weapon damage totals over the persisted damage events, optionally since a time or for one match
+#[get("/weapons?<since>&<match_id>")]
+pub async fn get_weapon_damage(
+    state: &State<MarsAPIState>,
+    since: Option<u64>,
+    match_id: Option<&str>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Json<Vec<WeaponDamage>>, ApiErrorResponder> {
+    if state.damage_events.is_none() {
+        return Err(ApiErrorResponder::validation_error_with_message("Damage events are not being recorded"));
+    };
+    match state.database.weapon_damage_distribution(since, match_id).await {
+        Ok(distribution) => Ok(Json(distribution)),
+        Err(e) => {
+            warn!("Could not aggregate weapon damage: {}", e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not aggregate weapon damage"))
+        }
+    }
+}
+
+pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
+    rocket_build.mount("/mc/damage", routes![get_weapon_damage])
+}
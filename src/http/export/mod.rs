@@ -0,0 +1,46 @@
+use futures::{stream::BoxStream, Stream, StreamExt};
+use rocket::{Rocket, Build, State, http::ContentType, response::stream::TextStream, serde::json};
+use serde::Serialize;
+
+use crate::{MarsAPIState, database::{CollectionOwner, models::{player::Player, session::Session, punishment::Punishment, rank::Rank, r#match::Match, death::Death, level::Level, tag::Tag, achievement::Achievement, report::Report, leaderboard_snapshot::LeaderboardSnapshot}}, util::{auth::AdminAuthorizationToken, error::ApiErrorResponder}};
+
+type NdjsonStream<'r> = (ContentType, TextStream<BoxStream<'r, String>>);
+
+fn to_ndjson<'r, T: Serialize + Send + 'r>(documents: impl Stream<Item = T> + Send + 'r) -> BoxStream<'r, String> {
+    documents.filter_map(|document| futures::future::ready(match json::to_string(&document) {
+        Ok(line) => Some(format!("{}\n", line)),
+        Err(e) => {
+            warn!("Could not serialize exported document: {}", e);
+            None
+        }
+    })).boxed()
+}
+
+// one document per line, sent with chunked transfer encoding as the cursor advances
+#[get("/<collection>")]
+pub async fn export_collection<'r>(
+    state: &'r State<MarsAPIState>,
+    collection: &str,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<NdjsonStream<'r>, ApiErrorResponder> {
+    let database = &state.database;
+    let lines = match collection {
+        name if name == Player::get_collection_name() => to_ndjson(database.stream_collection::<Player>().await),
+        name if name == Session::get_collection_name() => to_ndjson(database.stream_collection::<Session>().await),
+        name if name == Punishment::get_collection_name() => to_ndjson(database.stream_collection::<Punishment>().await),
+        name if name == Rank::get_collection_name() => to_ndjson(database.stream_collection::<Rank>().await),
+        name if name == Match::get_collection_name() => to_ndjson(database.stream_collection::<Match>().await),
+        name if name == Death::get_collection_name() => to_ndjson(database.stream_collection::<Death>().await),
+        name if name == Level::get_collection_name() => to_ndjson(database.stream_collection::<Level>().await),
+        name if name == Tag::get_collection_name() => to_ndjson(database.stream_collection::<Tag>().await),
+        name if name == Achievement::get_collection_name() => to_ndjson(database.stream_collection::<Achievement>().await),
+        name if name == Report::get_collection_name() => to_ndjson(database.stream_collection::<Report>().await),
+        name if name == LeaderboardSnapshot::get_collection_name() => to_ndjson(database.stream_collection::<LeaderboardSnapshot>().await),
+        _ => return Err(ApiErrorResponder::validation_error_with_message("Unknown collection"))
+    };
+    Ok((ContentType::new("application", "x-ndjson"), TextStream(lines)))
+}
+
+pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
+    rocket_build.mount("/mc/export", routes![export_collection])
+}
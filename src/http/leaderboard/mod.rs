@@ -1,12 +1,23 @@
-use std::str::FromStr;
+use std::{collections::BTreeMap, str::FromStr, sync::Arc};
 
-use rocket::{Rocket, Build, State, serde::json::Json};
+use futures::{stream::BoxStream, StreamExt};
+use rocket::{Rocket, Build, State, serde::json::{self, Json}, http::{Status, ContentType}, response::stream::TextStream};
+use tokio::sync::mpsc;
+use strum::IntoEnumIterator;
 
-use crate::{MarsAPIState, socket::leaderboard::{ScoreType, LeaderboardEntry, LeaderboardPeriod}, util::{r#macro::unwrap_helper, error::ApiErrorResponder}};
+use crate::{MarsAPIState, database::{audit::record_audit, models::audit_log::{AuditLogEntry, AuditAction}}, socket::leaderboard::{Leaderboard, LeaderboardScore, MarsLeaderboards, ScoreType, LeaderboardPeriod, LeaderboardRebuildProgress, reconcile::ReconciliationMetrics}, util::{r#macro::unwrap_helper, error::ApiErrorResponder, auth::{AdminAuthorizationToken, RealmScope}, confirmation::{Confirmable, request_confirmation, consume_confirmation}, responder::ListResponder}};
+
+use self::payload::{LeaderboardAroundResponse, LeaderboardResetResponse, LeaderboardResponder, LeaderboardWithResetResponse, RankedLeaderboardEntry};
+
+mod payload;
+
+// not a `LeaderboardPeriod`, trending boards decay continuously instead of resetting
+const TRENDING_PERIOD : &str = "TRENDING";
 
 const PUBLIC_SCORE_TYPES : &[ScoreType] = &[
     ScoreType::Kills,
     ScoreType::Deaths,
+    ScoreType::Assists,
     ScoreType::FirstBloods,
     ScoreType::Wins,
     ScoreType::Losses,
@@ -29,23 +40,213 @@ const PUBLIC_SCORE_TYPES : &[ScoreType] = &[
     ScoreType::HighestKillstreak
 ];
 
-#[get("/<score_type>/<period>?<limit>")]
+// `include_reset` wraps the entries with when the current window ends, for client countdowns.
+// `TRENDING` reads the time-decayed board of score types listed in `leaderboards.decay.boards`
+#[get("/<score_type>/<period>?<limit>&<include_reset>&<realm>")]
 async fn get_leaderboard_entries(
     state: &State<MarsAPIState>, 
     score_type: &str, 
     period: &str, 
-    limit: Option<u32>
-) -> Result<Json<Vec<LeaderboardEntry>>, ApiErrorResponder> {
-    let score_type = unwrap_helper::return_default!(ScoreType::from_str(score_type).ok(), Err(ApiErrorResponder::validation_error()));
-    if !PUBLIC_SCORE_TYPES.contains(&score_type) {
-        return Err(ApiErrorResponder::unauthorized());
+    limit: Option<u32>,
+    include_reset: bool,
+    realm: Option<&str>,
+    realm_scope: RealmScope
+) -> Result<ListResponder<LeaderboardResponder>, ApiErrorResponder> {
+    let leaderboards = state.leaderboards_for(&realm_scope.resolve(&state.config, realm)?);
+    if !state.redis.is_available() {
+        return Err(ApiErrorResponder::cache_unavailable());
+    };
+    let leaderboard = find_public_leaderboard(leaderboards, score_type)?;
+    if period == TRENDING_PERIOD {
+        let entries = unwrap_helper::return_default!(leaderboard.fetch_trending(limit.unwrap_or(10).clamp(1, 50)).await, Err(ApiErrorResponder::validation_error()));
+        let (returned, total) = (entries.len(), leaderboard.count_trending().await);
+        if !include_reset {
+            return Ok(ListResponder::new(LeaderboardResponder::Entries(entries), returned, total));
+        };
+        return Ok(ListResponder::new(LeaderboardResponder::EntriesWithReset(LeaderboardWithResetResponse { entries, next_reset_at: None }), returned, total));
     };
     let period = unwrap_helper::return_default!(LeaderboardPeriod::from_str(period).ok(), Err(ApiErrorResponder::validation_error()));
     let limit = limit.unwrap_or(10);
-    let leaderboard = score_type.to_leaderboard(&state.leaderboards).fetch_top(&period, if limit > 50 { 50 } else { limit }).await;
-    Ok(Json(leaderboard))
+    let total = leaderboard.count(&period).await;
+    let leaderboard = leaderboard.fetch_top(&period, if limit > 50 { 50 } else { limit }).await;
+    let returned = leaderboard.len();
+    if !include_reset {
+        return Ok(ListResponder::new(LeaderboardResponder::Entries(leaderboard), returned, total));
+    };
+    Ok(ListResponder::new(LeaderboardResponder::EntriesWithReset(LeaderboardWithResetResponse {
+        entries: leaderboard,
+        next_reset_at: period.get_next_reset(&leaderboards.boundaries)
+    }), returned, total))
+}
+
+#[derive(FromForm)]
+struct AroundParams<'r> {
+    range: Option<u32>,
+    bottom: bool,
+    realm: Option<&'r str>
+}
+
+// `range` places either side of the player, the trending board has no fixed positions to go around.
+// `bottom` returns the last `range` entries instead when the player is not on the board
+#[get("/<score_type>/<period>/around/<player_id>?<params..>")]
+async fn get_leaderboard_around(
+    state: &State<MarsAPIState>,
+    score_type: &str,
+    period: &str,
+    player_id: &str,
+    params: AroundParams<'_>,
+    realm_scope: RealmScope
+) -> Result<Json<LeaderboardAroundResponse>, ApiErrorResponder> {
+    let AroundParams { range, bottom, realm } = params;
+    let leaderboards = state.leaderboards_for(&realm_scope.resolve(&state.config, realm)?);
+    if !state.redis.is_available() {
+        return Err(ApiErrorResponder::cache_unavailable());
+    };
+    let leaderboard = find_public_leaderboard(leaderboards, score_type)?;
+    let period = unwrap_helper::return_default!(LeaderboardPeriod::from_str(period).ok(), Err(ApiErrorResponder::validation_error()));
+    let player = unwrap_helper::return_default!(state.player_cache.get(&state.database, player_id).await, Err(ApiErrorResponder::missing_player()));
+    let range = range.unwrap_or(5).clamp(1, 25);
+    let total = leaderboard.count(&period).await;
+    if let Some((start, entries)) = leaderboard.fetch_around(&period, &player.id_name(), range).await {
+        let rank = start + entries.iter().position(|entry| entry.id == player.id).unwrap_or(0) as u64 + 1;
+        return Ok(Json(LeaderboardAroundResponse { on_board: true, rank: Some(rank), entries: RankedLeaderboardEntry::from_position(start, entries), total }));
+    };
+    let entries = if bottom {
+        let (start, entries) = leaderboard.fetch_bottom(&period, range).await;
+        RankedLeaderboardEntry::from_position(start, entries)
+    } else {
+        Vec::new()
+    };
+    Ok(Json(LeaderboardAroundResponse { on_board: false, rank: None, entries, total }))
+}
+
+// dynamic boards are only public when their definition says so
+pub fn is_public_leaderboard(leaderboard: &Leaderboard) -> bool {
+    match &leaderboard.score {
+        LeaderboardScore::Builtin(score_type) => PUBLIC_SCORE_TYPES.contains(score_type),
+        LeaderboardScore::Dynamic(score_type) => score_type.public
+    }
+}
+
+fn find_public_leaderboard<'a>(leaderboards: &'a MarsLeaderboards, score_type: &str) -> Result<&'a Leaderboard, ApiErrorResponder> {
+    let leaderboard = match ScoreType::from_str(score_type) {
+        Ok(score_type) => leaderboards.from_score_type(score_type),
+        Err(_) => leaderboards.find_dynamic(score_type).ok_or_else(ApiErrorResponder::validation_error)?
+    };
+    if !is_public_leaderboard(leaderboard) {
+        return Err(ApiErrorResponder::unauthorized());
+    };
+    Ok(leaderboard)
+}
+
+// resets the current key of every period unless one is given, in the default realm unless one is given
+#[post("/<score_type>/reset?<period>&<confirm>&<realm>")]
+async fn reset_leaderboard(
+    state: &State<MarsAPIState>, 
+    score_type: &str, 
+    period: Option<&str>,
+    confirm: Option<&str>,
+    realm: Option<&str>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Confirmable<Vec<LeaderboardResetResponse>>, ApiErrorResponder> {
+    if !state.redis.is_available() {
+        return Err(ApiErrorResponder::cache_unavailable());
+    };
+    let realm = admin_realm(state, realm)?;
+    let leaderboard = unwrap_helper::return_default!(state.leaderboards_for(&realm).find(score_type), Err(ApiErrorResponder::validation_error()));
+    let score_type = match &realm {
+        Some(realm) => format!("{}@{}", leaderboard.score.name(), realm),
+        None => leaderboard.score.name()
+    };
+    let periods = match period {
+        Some(period) => vec![unwrap_helper::return_default!(LeaderboardPeriod::from_str(period).ok(), Err(ApiErrorResponder::validation_error()))],
+        None => LeaderboardPeriod::iter().collect()
+    };
+    if state.config.options.confirmation_required {
+        let binding = format!("leaderboard-reset:{}:{}", score_type, period.map(|_| periods[0].to_string()).unwrap_or_else(|| String::from("all")));
+        match confirm {
+            Some(token) => consume_confirmation(state, &binding, token).await?,
+            None => {
+                let mut effect = BTreeMap::new();
+                for period in periods.iter() {
+                    effect.insert(period.to_string(), leaderboard.count(period).await);
+                };
+                return Ok(Confirmable::Pending(request_confirmation(state, &binding, effect).await));
+            }
+        };
+    };
+    let mut responses : Vec<LeaderboardResetResponse> = Vec::new();
+    for period in periods {
+        let snapshot = match leaderboard.reset(&period).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                warn!("Could not reset leaderboard {} ({}): {}", score_type, period, e);
+                return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not reset the leaderboard"));
+            }
+        };
+        responses.push(LeaderboardResetResponse { 
+            period: period.to_string(), 
+            snapshot_id: snapshot.as_ref().map(|snapshot| snapshot.id.clone()), 
+            archived_entries: snapshot.map(|snapshot| snapshot.entries.len()).unwrap_or(0) 
+        });
+    };
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::LeaderboardReset)
+        .target(&score_type, &score_type)
+        .after(responses.iter().map(|response| format!("{} ({} archived)", response.period, response.archived_entries)).collect::<Vec<String>>().join(", ")));
+    Ok(Confirmable::Done(responses))
+}
+
+// the realm an admin operation is for, the default realm unless one is given
+fn admin_realm(state: &MarsAPIState, realm: Option<&str>) -> Result<Option<String>, ApiErrorResponder> {
+    match realm {
+        Some(realm) if state.config.is_realm(realm) => Ok(Some(realm.to_owned())),
+        Some(_) => Err(ApiErrorResponder::validation_error_with_message("Unknown realm")),
+        None => Ok(None)
+    }
+}
+
+// the rebuild runs in its own task so it finishes even if the client disconnects, progress is streamed as NDJSON.
+// a realm's boards are rebuilt from the stats its servers credited, the default realm's unless one is given
+#[post("/rebuild?<realm>")]
+async fn rebuild_leaderboards(
+    state: &State<MarsAPIState>,
+    realm: Option<&str>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<(ContentType, TextStream<BoxStream<'static, String>>), ApiErrorResponder> {
+    let realm = admin_realm(state, realm)?;
+    let (progress_sender, progress_receiver) = mpsc::unbounded_channel::<LeaderboardRebuildProgress>();
+    let leaderboards = Arc::clone(state.leaderboards_for(&realm));
+    let database = Arc::clone(&state.database);
+    let min_playtime = state.config.options.leaderboard_min_playtime;
+    let batch_size = state.config.options.document_batch_size;
+    let mut audit = AuditLogEntry::new(AuditAction::LeaderboardRebuild);
+    if let Some(realm) = &realm {
+        audit = audit.after(format!("realm {}", realm));
+    };
+    record_audit(&state.database, audit);
+    tokio::spawn(async move {
+        info!("Rebuilding leaderboards from player documents...");
+        let processed = leaderboards.rebuild(&database, batch_size, min_playtime, progress_sender).await;
+        info!("Rebuilt leaderboards from {} players", processed);
+    });
+    let lines = futures::stream::unfold(progress_receiver, |mut receiver| async move {
+        let progress = receiver.recv().await?;
+        Some((format!("{}\n", json::to_string(&progress).unwrap_or_default()), receiver))
+    }).boxed();
+    Ok((ContentType::new("application", "x-ndjson"), TextStream(lines)))
+}
+
+// drift found by the periodic reconciliation since the API started
+#[get("/reconciliation?<realm>")]
+async fn get_reconciliation_metrics(
+    state: &State<MarsAPIState>,
+    realm: Option<&str>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Json<ReconciliationMetrics>, ApiErrorResponder> {
+    let realm = admin_realm(state, realm)?;
+    Ok(Json(state.leaderboards_for(&realm).reconciliation_metrics()))
 }
 
 pub fn mount(rocket: Rocket<Build>) -> Rocket<Build> {
-    rocket.mount("/mc/leaderboards", routes![get_leaderboard_entries])
+    rocket.mount("/mc/leaderboards", routes![get_leaderboard_entries, get_leaderboard_around, reset_leaderboard, rebuild_leaderboards, get_reconciliation_metrics])
 }
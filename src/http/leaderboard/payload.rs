@@ -0,0 +1,68 @@
+use rocket::{Request, response::{self, Responder, Response}, serde::json::Json, http::{ContentType, Status}};
+use serde::{Serialize, Deserialize};
+
+use crate::socket::leaderboard::LeaderboardEntry;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardResetResponse {
+    pub period: String,
+    pub snapshot_id: Option<String>,
+    pub archived_entries: usize
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardWithResetResponse {
+    pub entries: Vec<LeaderboardEntry>,
+    // epoch millis, `None` for the all-time board
+    pub next_reset_at: Option<u64>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RankedLeaderboardEntry {
+    // 1-based
+    pub rank: u64,
+    pub id: String,
+    pub name: String,
+    pub score: u64
+}
+
+impl RankedLeaderboardEntry {
+    // `start` is the 0-based position of the first entry
+    pub fn from_position(start: u64, entries: Vec<LeaderboardEntry>) -> Vec<Self> {
+        entries.into_iter().enumerate()
+            .map(|(i, entry)| RankedLeaderboardEntry { rank: start + i as u64 + 1, id: entry.id, name: entry.name, score: entry.score })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardAroundResponse {
+    pub on_board: bool,
+    // the player's own rank, `None` when they are not on the board
+    pub rank: Option<u64>,
+    // around the player, or the bottom of the board when they are not on it and it was asked for
+    pub entries: Vec<RankedLeaderboardEntry>,
+    pub total: u64
+}
+
+pub enum LeaderboardResponder {
+    Entries(Vec<LeaderboardEntry>),
+    EntriesWithReset(LeaderboardWithResetResponse)
+}
+
+impl <'r> Responder<'r, 'static> for LeaderboardResponder {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let response = match &self {
+            LeaderboardResponder::Entries(entries) => Json(entries).respond_to(req)?,
+            LeaderboardResponder::EntriesWithReset(wrapped) => Json(wrapped).respond_to(req)?
+        };
+        Response::build_from(response)
+            .header(ContentType::JSON)
+            .status(Status::Ok)
+            .ok()
+    }
+}
@@ -2,17 +2,17 @@ use futures::future::join_all;
 use mongodb::bson::doc;
 use rocket::{Rocket, Build, State, serde::json::Json};
 
-use crate::{MarsAPIState, http::map::payload::MapLoadOneRequest, util::{auth::AuthorizationToken, time::get_u64_time_millis, r#macro::unwrap_helper, error::ApiErrorResponder}, database::{models::level::{Level, LevelRecords}, Database}};
+use crate::{MarsAPIState, http::map::payload::MapLoadOneRequest, util::{auth::AuthorizationToken, body_limit::{LimitedJson, ImportLimit}, time::{get_u64_time_millis, Timestamp}, r#macro::unwrap_helper, error::ApiErrorResponder, responder::ListResponder}, database::{models::level::{Level, LevelRecords}, Database}};
 
 mod payload;
 
 #[post("/", format = "json", data = "<maps>")]
 async fn add_maps(
     state: &State<MarsAPIState>,
-    maps: Json<Vec<MapLoadOneRequest>>,
+    maps: LimitedJson<Vec<MapLoadOneRequest>, ImportLimit>,
     _auth_guard: AuthorizationToken
 ) -> Json<Vec<Level>> {
-    let map_list = maps.0;
+    let map_list = maps.into_inner();
     let map_list_length = map_list.len();
     let time_millis = get_u64_time_millis();
     let mut maps_to_save : Vec<Level> = Vec::new();
@@ -28,7 +28,7 @@ async fn add_maps(
             existing_map.version = map.version;
             existing_map.gamemodes = map.gamemodes;
             existing_map.authors = map.authors;
-            existing_map.updated_at = time_millis;
+            existing_map.updated_at = Timestamp::from_millis(time_millis);
             existing_map.contributors = map.contributors;
             existing_map
         } else {
@@ -40,8 +40,8 @@ async fn add_maps(
                 name_lower: lowercase_map_name,
                 version: map.version,
                 gamemodes: map.gamemodes,
-                loaded_at: time_millis,
-                updated_at: time_millis,
+                loaded_at: Timestamp::from_millis(time_millis),
+                updated_at: Timestamp::from_millis(time_millis),
                 authors: map.authors,
                 contributors: map.contributors,
                 records: LevelRecords::default(),
@@ -59,8 +59,10 @@ async fn add_maps(
 }
 
 #[get("/")]
-async fn get_all_maps(state: &State<MarsAPIState>) -> Json<Vec<Level>> {
-    Json(state.database.get_all_documents::<Level>().await)
+async fn get_all_maps(state: &State<MarsAPIState>) -> Result<ListResponder<Json<Vec<Level>>>, ApiErrorResponder> {
+    state.database.load_all_documents::<Level>(state.config.options.strict_decoding).await
+        .map(ListResponder::loaded)
+        .map_err(|e| ApiErrorResponder::load_failed(&e))
 }
 
 #[get("/<map_id>")]
@@ -1,5 +1,5 @@
 use rocket::{State, Build, Rocket};
-use crate::{database::models::r#match::Match, MarsAPIState, util::{responder::JsonResponder, error::ApiErrorResponder, r#macro::unwrap_helper}};
+use crate::{database::models::{r#match::Match, participant::MatchContribution}, MarsAPIState, util::{responder::{JsonResponder, ListResponder}, error::ApiErrorResponder, r#macro::unwrap_helper}};
 
 #[get("/<match_id>")]
 pub async fn matches(
@@ -15,6 +15,23 @@ pub async fn matches(
     Ok(JsonResponder::ok(cached_match))
 }
 
+// per-player contributions for this match alone, players who left before the end are flagged rather than dropped
+#[get("/<match_id>/scoreboard")]
+pub async fn match_scoreboard(
+    state: &State<MarsAPIState>,
+    match_id: &str
+) -> Result<ListResponder<JsonResponder<Vec<MatchContribution>>>, ApiErrorResponder> {
+    let match_id = match_id.to_lowercase();
+    let cached_match = 
+        unwrap_helper::return_default!(
+            state.match_cache.get(&state.database, &match_id).await,
+            Err(ApiErrorResponder::validation_error())
+        );
+    let scoreboard = cached_match.get_scoreboard();
+    let returned = scoreboard.len();
+    Ok(ListResponder::new(JsonResponder::ok(scoreboard), returned, returned as u64))
+}
+
 pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
-    rocket_build.mount("/mc/matches", routes![matches])
+    rocket_build.mount("/mc/matches", routes![matches, match_scoreboard])
 }
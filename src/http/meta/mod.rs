@@ -0,0 +1,38 @@
+use rocket::{Rocket, Build, State, serde::json::Json};
+use strum::{EnumProperty, IntoEnumIterator};
+
+use crate::{MarsAPIState, database::models::{level::LevelGamemode, punishment::PunishmentKind}, socket::leaderboard::{LeaderboardPeriod, ScoreType}, util::responder::CachedResponder};
+
+use self::payload::{GamemodeResponse, MetaEnumsResponse};
+
+mod payload;
+
+// everything here comes from the build or from config read at startup, so clients can hold on to it
+const META_MAX_AGE_SECONDS : u64 = 300;
+
+#[get("/enums")]
+fn get_enums(state: &State<MarsAPIState>) -> CachedResponder<Json<MetaEnumsResponse>> {
+    let score_types = ScoreType::iter().map(|score_type| score_type.to_string())
+        .chain(state.config.data.dynamic_score_types.iter().map(|score_type| score_type.name.clone()))
+        .collect();
+    let gamemodes = LevelGamemode::iter()
+        .filter(|gamemode| !matches!(gamemode, LevelGamemode::Unknown(_)))
+        .map(|gamemode| GamemodeResponse { key: gamemode.key().to_owned(), name: gamemode.get_str("fancy").unwrap_or(gamemode.key()).to_owned() })
+        .collect();
+    let mut punishment_types : Vec<_> = state.config.data.punishment_types.iter().collect();
+    punishment_types.sort_by_key(|punishment_type| punishment_type.position);
+    let mut report_categories : Vec<String> = state.config.options.auto_mute.thresholds.keys().cloned().collect();
+    report_categories.sort();
+    CachedResponder::new(Json(MetaEnumsResponse {
+        score_types,
+        leaderboard_periods: LeaderboardPeriod::iter().map(|period| period.to_string()).collect(),
+        gamemodes,
+        punishment_actions: PunishmentKind::iter().map(|kind| kind.to_string()).collect(),
+        punishment_types: punishment_types.into_iter().map(|punishment_type| punishment_type.name.clone()).collect(),
+        report_categories
+    }), META_MAX_AGE_SECONDS)
+}
+
+pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
+    rocket_build.mount("/meta", routes![get_enums])
+}
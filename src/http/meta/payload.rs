@@ -0,0 +1,22 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GamemodeResponse {
+    // what the gamemode's stats are stored under
+    pub key: String,
+    pub name: String
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetaEnumsResponse {
+    // built-in score types first, then the ones from `dynamic_leaderboards.yml`
+    pub score_types: Vec<String>,
+    pub leaderboard_periods: Vec<String>,
+    pub gamemodes: Vec<GamemodeResponse>,
+    pub punishment_actions: Vec<String>,
+    pub punishment_types: Vec<String>,
+    // reasons are free text, these are the categories auto-mute has thresholds for
+    pub report_categories: Vec<String>
+}
@@ -0,0 +1,13 @@
+use rocket::{Rocket, Build, State, http::ContentType};
+
+use crate::MarsAPIState;
+
+// left open like `/status` so a scraper needs no token, none of the counters name players or servers
+#[get("/")]
+fn get_metrics(state: &State<MarsAPIState>) -> (ContentType, String) {
+    (ContentType::new("text", "plain").with_params(("version", "0.0.4")), state.metrics.render())
+}
+
+pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
+    rocket_build.mount("/metrics", routes![get_metrics])
+}
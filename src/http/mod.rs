@@ -12,3 +12,9 @@ pub mod tag;
 pub mod perks;
 pub mod r#match;
 pub mod achievements;
+pub mod export;
+pub mod damage;
+pub mod cache;
+pub mod audit;
+pub mod meta;
+pub mod metrics;
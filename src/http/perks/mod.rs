@@ -1,8 +1,9 @@
-use rocket::{Rocket, State, Build, serde::json::Json};
+use futures::future::join_all;
+use rocket::{Rocket, State, Build, serde::json::Json, http::Status};
 
-use crate::{MarsAPIState, database::models::{join_sound::JoinSound, player::Player}, util::{auth::AuthorizationToken, responder::JsonResponder, error::ApiErrorResponder}};
+use crate::{MarsAPIState, database::models::{join_sound::JoinSound, join_message::JoinMessage, player::Player}, util::{auth::AuthorizationToken, responder::JsonResponder, error::ApiErrorResponder}};
 
-use self::payload::JoinSoundSetRequest;
+use self::payload::{JoinSoundSetRequest, JoinMessageSetRequest, TagPerkResponse};
 
 mod payload;
 
@@ -24,11 +25,11 @@ async fn update_join_sound(
         Some(mut p) => {
             let current_sound = set_join_req.0.active_join_sound_id;
             if p.active_join_sound_id == current_sound {
-                return Ok(JsonResponder::ok(p));
+                return Ok(JsonResponder::ok(p.without_senior_notes()));
             };
             p.active_join_sound_id = current_sound;
             state.player_cache.set(&state.database, &p.name, &p, true).await;
-            Ok(JsonResponder::ok(p))
+            Ok(JsonResponder::ok(p.without_senior_notes()))
         },
         None => {
             Err(ApiErrorResponder::missing_player())
@@ -36,9 +37,68 @@ async fn update_join_sound(
     }
 }
 
+#[get("/join_messages")]
+fn get_join_messages(
+    state: &State<MarsAPIState>
+) -> Json<&Vec<JoinMessage>> {
+    Json(&state.config.data.join_messages)
+}
+
+#[post("/join_messages/<player_id>/message", format = "json", data = "<set_message_req>")]
+async fn update_join_message(
+    state: &State<MarsAPIState>,
+    player_id: &str,
+    set_message_req: Json<JoinMessageSetRequest>,
+    _auth_guard: AuthorizationToken
+) -> Result<JsonResponder<Player>, ApiErrorResponder> {
+    let mut player = match state.player_cache.get(&state.database, player_id).await {
+        Some(player) => player,
+        None => return Err(ApiErrorResponder::missing_player())
+    };
+    let message_id = set_message_req.0.active_join_message_id;
+    if player.active_join_message_id == message_id {
+        return Ok(JsonResponder::ok(player.without_senior_notes()));
+    };
+    // clearing the message is always allowed
+    if let Some(message_id) = &message_id {
+        let join_message = match state.config.data.join_messages.iter().find(|join_message| &join_message.id == message_id) {
+            Some(join_message) => join_message,
+            None => return Err(ApiErrorResponder::validation_error_with_message(&format!("Unknown join message '{}'", message_id)))
+        };
+        if !join_message.is_unlocked_by(&player) {
+            warn!("{} tried to select join message {} without a rank that unlocks it", player.name, join_message.id);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::Forbidden, "You do not have a rank that unlocks this join message"));
+        };
+    };
+    player.active_join_message_id = message_id;
+    state.player_cache.set(&state.database, &player.name, &player, true).await;
+    Ok(JsonResponder::ok(player.without_senior_notes()))
+}
+
+// the player's tags, level-gated ones they can't equip yet are marked locked with the level they need
+#[get("/tags/<player_id>")]
+async fn get_tag_perks(
+    state: &State<MarsAPIState>,
+    player_id: &str
+) -> Result<Json<Vec<TagPerkResponse>>, ApiErrorResponder> {
+    let player = match state.player_cache.get(&state.database, player_id).await {
+        Some(player) => player,
+        None => return Err(ApiErrorResponder::missing_player())
+    };
+    let tags = join_all(player.tag_ids.iter().map(|tag_id| state.tag_cache.get(&state.database, tag_id))).await;
+    Ok(Json(tags.into_iter().flatten().map(|tag| TagPerkResponse {
+        locked: !tag.is_unlocked_by(&player),
+        required_level: tag.min_level,
+        tag
+    }).collect()))
+}
+
 pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
     rocket_build.mount("/mc/perks", routes![
         get_join_sounds,
-        update_join_sound
+        update_join_sound,
+        get_join_messages,
+        update_join_message,
+        get_tag_perks
     ])
 }
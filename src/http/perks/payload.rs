@@ -1,7 +1,22 @@
 use serde::{Serialize, Deserialize};
 
+use crate::database::models::tag::Tag;
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JoinSoundSetRequest {
     pub active_join_sound_id: Option<String>
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinMessageSetRequest {
+    pub active_join_message_id: Option<String>
+}
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagPerkResponse {
+    pub tag: Tag,
+    pub locked: bool,
+    pub required_level: Option<u32>
+}
@@ -3,22 +3,55 @@ mod payloads;
 use futures::future::join_all;
 use mongodb::bson::doc;
 use payloads::PlayerPreLoginRequest;
-use rocket::{serde::json::Json, Build, Rocket, State, http::Status};
+use rocket::{serde::json::Json, Build, Rocket, State, http::Status, response::Redirect};
 use uuid::Uuid;
-use crate::{util::{auth::AuthorizationToken, error::{ApiError, ApiErrorResponder}, string::to_utf8_byte_array, responder::{JsonResponder, EmptyResponse}, time::get_u64_time_millis, r#macro::unwrap_helper}, MarsAPIState, database::{Database, models::{punishment::{Punishment, PunishmentKind, StaffNote}, player::{Player, PlayerStats, SessionRecord}, session::Session, rank::Rank, tag::Tag}}, http::player::payloads::{PlayerLoginRequest, PlayerLookupResponse, PlayerAddNoteRequest, PlayerSetActiveTagRequest}, socket::leaderboard::{Leaderboard, ScoreType, LeaderboardPeriod}};
+use crate::{util::{auth::{AuthorizationToken, AdminAuthorizationToken, RealmAuthorizationToken, RealmScope}, body_limit::{LimitedJson, BatchLimit}, error::ApiErrorResponder, string::to_utf8_byte_array, responder::{JsonResponder, EmptyResponse, ListResponder}, time::Timestamp, r#macro::unwrap_helper, confirmation::{Confirmable, request_confirmation, consume_confirmation}}, MarsAPIState, database::{Database, InsertError, KeysetPage, aggregate::AggregateStats, evasion::{name_similar_banned, RECENT_BANNED_CACHE_KEY, RECENT_BANNED_CACHE_MS}, audit::record_audit, corrections::{StatCorrectionResult, StatCorrectionError, STAT_CORRECTION_PERMISSION, correctable_field}, erasure::{ErasureMode, ErasureReport}, head_to_head::{HeadToHead, HeadToHeadError}, search::{SearchStrategy, SearchMatch}, models::{punishment::{Punishment, PunishmentKind, StaffNote, NoteType, NoteVisibility, SENIOR_NOTE_PERMISSION}, player::{Player, PlayerIp, PlayerStats, SimplePlayer, NameHistoryEntry, PrestigeError}, session::Session, rank::Rank, tag::{Tag, TagSlot}, audit_log::{AuditLogEntry, AuditAction}, rank_threshold::{grant_rank_thresholds, announce_rank_grants}, milestone::grant_milestones}}, http::{leaderboard::is_public_leaderboard, player::payloads::{PlayerLoginRequest, PlayerLookupResponse, PlayerExportResponse, PlayerSearchResponse, PlayerLevelProgressResponse, PlayerRankProgressResponse, PlayerMatchHistoryEntry, RankThresholdProgress, PlayerAggregateRequest, PlayerResolveRequest, PlayerResolveResponse, StatCorrectionRequest, PlayerAddNoteRequest, PlayerSetActiveTagRequest, PlayerFreezeRequest}}, socket::{leaderboard::{Leaderboard, LeaderboardPeriod, ScoreType, percentile::BoardPercentile}, server::server_context::ServerBroadcast, event_type::EventType}};
 use sha2::{Sha256, Digest};
 
-use self::payloads::{PlayerPreLoginResponse, PlayerPreLoginResponder, PlayerLoginResponse, PlayerLogoutRequest, PlayerProfileResponder, PlayerProfileResponse, PlayerAltResponse};
-use std::{time::{SystemTime, UNIX_EPOCH}, collections::HashMap};
+use self::payloads::{PlayerPreLoginResponse, PlayerPreLoginResponder, PlayerLoginResponse, PlayerLogoutRequest, PlayerProfileResponder, PlayerProfileResponse, PlayerAltResponse, PlayerPresenceResponse, PlayerAchievementResponse, PlayerWeaponStatsResponse, OnlinePlayersResponse, OnlineStaffResponse, ExportedPunishments};
+use std::{time::{SystemTime, UNIX_EPOCH}, collections::{BTreeMap, HashMap}, str::FromStr};
 
-use super::punishment::payloads::PunishmentIssueRequest;
+use super::{punishment::{announce_superseded, payloads::{PunishmentIssueRequest, PunishmentPublicView}, resolution::resolve_punishment}, achievements::get_achievement_catalog};
+
+// only new accounts and renamed ones are compared, the name of everyone else was already checked when they took it.
+// matches are reported to staff and returned to the server, the join itself goes ahead as usual
+async fn flag_similar_banned(state: &State<MarsAPIState>, server_id: &str, player: &SimplePlayer) -> Vec<SimplePlayer> {
+    let max_distance = state.config.options.evasion_name_distance;
+    if max_distance == 0 {
+        return Vec::new();
+    };
+    let banned = match state.redis.get_unchecked::<Vec<SimplePlayer>>(RECENT_BANNED_CACHE_KEY).await {
+        Some(banned) => banned,
+        None => match state.database.find_recent_banned().await {
+            Ok(banned) => {
+                state.redis.set_with_expiry(RECENT_BANNED_CACHE_KEY, &banned, Some(RECENT_BANNED_CACHE_MS)).await;
+                banned
+            },
+            Err(e) => {
+                warn!("Could not load recent bans to compare with {}: {}", player.name, e);
+                return Vec::new();
+            }
+        }
+    };
+    let mut similar_banned = name_similar_banned(&player.name, &banned, max_distance);
+    similar_banned.retain(|banned| banned.id != player.id);
+    if !similar_banned.is_empty() {
+        info!("{} joined {} with a name similar to banned {}", player.name, server_id,
+            similar_banned.iter().map(|banned| banned.name.as_str()).collect::<Vec<&str>>().join(", "));
+        let (config, server_id, player, similar) = (state.config.clone(), server_id.to_owned(), player.clone(), similar_banned.clone());
+        tokio::spawn(async move {
+            config.webhooks.send_evasion_suspect_webhook(&server_id, &player, &similar).await;
+        });
+    };
+    similar_banned
+}
 
 #[post("/<player_id>/prelogin", format = "json", data = "<prelogin_req>")]
 pub async fn prelogin(
     state: &State<MarsAPIState>, 
     prelogin_req: Json<PlayerPreLoginRequest>, 
     player_id: &str, 
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<PlayerPreLoginResponder, ApiErrorResponder> {
     let data = prelogin_req.0;
 
@@ -30,11 +63,15 @@ pub async fn prelogin(
     let player_optional = Database::find_by_id(&state.database.players, &data.player.id).await;
     if let Some(mut returning_player) = player_optional {
         println!("the player was found!");
-        returning_player.name = data.player.name.clone();
-        returning_player.name_lower = returning_player.name.to_lowercase();
-        if !returning_player.ips.contains(&ip) {
-            returning_player.ips.push(ip.clone());
+        let renamed = match returning_player.record_name(&data.player.name, state.config.options.name_history_limit) {
+            Some(previous_name) => {
+                state.player_cache.evict(&previous_name).await;
+                true
+            },
+            None => false
         };
+        returning_player.record_ip(&ip, state.config.options.player_ip_limit);
+        returning_player.repair_presence_stats();
 
         let mut puns : Vec<Punishment> = state.database.get_active_player_punishments(&returning_player).await;
         let ban_pun_optional = puns.iter().find(|pun| pun.action.is_ban());
@@ -54,44 +91,58 @@ pub async fn prelogin(
 
         state.player_cache.set(&state.database, &returning_player.name, &returning_player, true).await;
         state.database.ensure_player_name_uniqueness(&data.player.name, &data.player.id).await;
+        let similar_banned = if renamed && !banned { flag_similar_banned(state, &auth_guard.server_id, &returning_player.to_simple()).await } else { Vec::new() };
 
         Ok(PlayerPreLoginResponder { 
             response: PlayerPreLoginResponse {
                 new: false, 
                 allowed: !banned, 
-                player: returning_player, 
-                active_punishments: puns 
+                player: returning_player.without_senior_notes(), 
+                active_punishments: puns,
+                similar_banned
             }
         })
     } else {
         println!("Could not find player in database!");
-        let time_millis : f64 = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as f64;
+        let time_millis = Timestamp::now();
         let player = Player {
             id: data.player.id.clone(),
             name: data.player.name.clone(),
             name_lower: data.player.name.to_lowercase(),
-            ips: vec![ip],
+            ips: vec![PlayerIp { ip: ip.clone(), last_seen_at: Timestamp::now() }],
             first_joined_at: time_millis,
             last_joined_at: time_millis,
             rank_ids: Vec::new(),
+            auto_rank_ids: Vec::new(),
             tag_ids: Vec::new(),
+            milestone_tag_ids: Vec::new(),
             active_tag_id: None,
+            active_tag_ids: HashMap::new(),
             stats: PlayerStats::default(),
             gamemode_stats: HashMap::new(),
             notes: Vec::new(),
             last_session_id: None,
-            active_join_sound_id: None
+            active_join_sound_id: None,
+            active_join_message_id: None,
+            frozen: false,
+            name_history: vec![NameHistoryEntry::new(&data.player.name, time_millis)],
+            prestige: 0,
+            prestige_xp: 0,
+            reset_xp: 0,
+            realm_stats: HashMap::new()
         };
 
         state.player_cache.set(&state.database, &player.name, &player, true).await;
         state.database.ensure_player_name_uniqueness(&data.player.name, &data.player.id).await;
+        let similar_banned = flag_similar_banned(state, &auth_guard.server_id, &player.to_simple()).await;
 
         Ok(PlayerPreLoginResponder {
             response: PlayerPreLoginResponse {
                 new: true,
                 allowed: true,
-                player,
-                active_punishments: Vec::new()
+                player: player.without_senior_notes(),
+                active_punishments: Vec::new(),
+                similar_banned
             }
         })
     }
@@ -117,7 +168,7 @@ pub async fn login(
     state: &State<MarsAPIState>, 
     login_req: Json<PlayerLoginRequest>, 
     player_id: &str, 
-    auth_guard: AuthorizationToken
+    auth_guard: RealmAuthorizationToken
 ) -> Result<JsonResponder<PlayerLoginResponse>, ApiErrorResponder> {
     let data = login_req.0;
     let mut player : Player = async_extract_player_from_url_v2!(&data.player.name, state);
@@ -132,16 +183,31 @@ pub async fn login(
 
     let time_millis : u64 = u64::try_from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()).unwrap_or(u64::MAX);
     let ip = hash_ip(&state, &data.ip);
-    let active_session = Session {
-        id: Uuid::new_v4().to_string(),
-        player: player.to_simple(),
-        ip: ip.clone(),
-        server_id: auth_guard.server_id,
-        created_at: time_millis,
-        ended_at: None
+    let resume_grace = state.config.options.session_resume_grace;
+    let resumed_session = match (&player.last_session_id, Session::resume_cutoff(time_millis, resume_grace)) {
+        (Some(last_session_id), Some(resume_cutoff)) => {
+            state.database.resume_session(last_session_id, &auth_guard.server_id, resume_cutoff, &ip, time_millis).await
+        },
+        _ => None
+    };
+    let active_session = match resumed_session {
+        Some(session) => session,
+        None => {
+            let session = Session {
+                id: Uuid::new_v4().to_string(),
+                player: player.to_simple(),
+                ip: ip.clone(),
+                server_id: auth_guard.server_id,
+                created_at: Timestamp::from_millis(time_millis),
+                ended_at: None,
+                playtime: 0,
+                resumed_at: None,
+                realm: auth_guard.realm
+            };
+            state.database.save(&session).await;
+            session
+        }
     };
-
-    state.database.save(&active_session).await;
     let mut player_ranks = player.rank_ids.clone();
     let mut default_ranks : Vec<String> = Rank::find_default(&state.database).await
         .iter()
@@ -150,7 +216,15 @@ pub async fn login(
     player_ranks.append(&mut default_ranks);
     player_ranks.dedup();
 
-    player.last_joined_at = time_millis as f64;
+    // inactivity goes by the last join anywhere, so the player was evicted from every realm's boards
+    let inactive_after = state.config.options.leaderboard_inactive_after;
+    if inactive_after > 0 && Timestamp::from_millis(time_millis).millis_since(player.last_joined_at) > inactive_after {
+        let min_playtime = state.config.options.leaderboard_min_playtime;
+        join_all(state.every_realm().into_iter()
+            .filter(|leaderboards| player.is_leaderboard_eligible_in(&leaderboards.realm, min_playtime))
+            .map(|leaderboards| leaderboards.restore_player(&player))).await;
+    };
+    player.last_joined_at = Timestamp::from_millis(time_millis);
     player.last_session_id = Some(active_session.id.clone());
 
     state.player_cache.set(&state.database, &player.name, &player, true).await;
@@ -163,7 +237,7 @@ pub async fn login(
 pub async fn logout(
     state: &State<MarsAPIState>, 
     logout_req: Json<PlayerLogoutRequest>, 
-    _auth_guard: AuthorizationToken
+    _auth_guard: RealmAuthorizationToken
 ) -> Result<JsonResponder<EmptyResponse>, ApiErrorResponder> {
     let data = logout_req.0;
     let mut player : Player = async_extract_player_from_url_v2!(&data.player.name, state);
@@ -177,72 +251,263 @@ pub async fn logout(
     };
 
     let time_millis : u64 = u64::try_from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()).unwrap_or(u64::MAX);
-    session.ended_at = Some(time_millis);
+    session.ended_at = Some(Timestamp::from_millis(time_millis));
+    session.playtime += data.playtime;
+    // the session's realm is swapped in while its playtime and record are credited
+    player.swap_realm_stats(&session.realm);
     player.stats.server_playtime += data.playtime;
 
-    state.leaderboards.server_playtime.increment(&player.id_name(), Some(u32::try_from(data.playtime).unwrap_or(u32::MAX))).await; // Will break in 2106
+    state.leaderboards_for(&session.realm).server_playtime.increment(&player.id_name(), Some(data.playtime)).await;
 
-    let record_session = if let Some(session_record) = &player.stats.records.longest_session {
-        Some(session_record.length.clone())
-    } else {
-        None
-    };
-    if record_session.is_none() || data.playtime > record_session.unwrap() {
-        player.stats.records.longest_session = Some(SessionRecord { session_id: session.id.clone(), length: data.playtime.clone() });
-    };
+    player.stats.records.record_session(&session);
+    player.swap_realm_stats(&session.realm);
+
+    let granted_ranks = grant_rank_thresholds(state, &mut player).await;
+    grant_milestones(state, &mut player).await;
 
     state.database.save(&session).await;
     state.player_cache.set(&state.database, &player.name, &player, true).await;
+    announce_rank_grants(state, &player, &granted_ranks);
 
     Ok(JsonResponder::ok(EmptyResponse {}))
 }
 
 
-#[get("/<player_id>?<include_leaderboard_positions>")]
+// the whole catalog, including achievements the player has not started
+#[get("/<player_id>/achievements")]
+pub async fn get_player_achievements(
+    state: &State<MarsAPIState>,
+    player_id: &str
+) -> Result<ListResponder<Json<Vec<PlayerAchievementResponse>>>, ApiErrorResponder> {
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let catalog = get_achievement_catalog(state).await;
+    let achievements : Vec<PlayerAchievementResponse> = catalog.into_iter().map(|achievement| PlayerAchievementResponse::new(achievement, &player)).collect();
+    let returned = achievements.len();
+    Ok(ListResponder::new(Json(achievements), returned, returned as u64))
+}
+
+// sessions on servers that have gone quiet are left out, they will be closed when the server starts up again
+#[get("/online?<realm>")]
+pub async fn get_online_players(state: &State<MarsAPIState>, realm: Option<&str>, realm_scope: RealmScope) -> Result<Json<OnlinePlayersResponse>, ApiErrorResponder> {
+    let realm = realm_scope.resolve(&state.config, realm)?;
+    let players = state.database.get_online_players(&state.live_server_ids().await, &realm).await;
+    Ok(Json(OnlinePlayersResponse { count: players.len(), players }))
+}
+
+// for servers to check before something that needs staff around, `satisfied` is `count >= required`
+#[get("/online/staff?<realm>")]
+pub async fn get_online_staff(state: &State<MarsAPIState>, realm: Option<&str>, realm_scope: RealmScope) -> Result<Json<OnlineStaffResponse>, ApiErrorResponder> {
+    let realm = realm_scope.resolve(&state.config, realm)?;
+    let count = state.database.count_online_staff(&state.live_server_ids().await, &realm).await;
+    let required = state.config.options.staff_presence_required;
+    Ok(Json(OnlineStaffResponse { count, required, satisfied: count >= required }))
+}
+
+#[get("/<player_id>/weapons")]
+pub async fn get_weapon_stats(
+    state: &State<MarsAPIState>,
+    player_id: &str
+) -> Result<Json<PlayerWeaponStatsResponse>, ApiErrorResponder> {
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let weapon_names = &state.config.options.weapon_names;
+    Ok(Json(PlayerWeaponStatsResponse {
+        weapon_kills: weapon_names.collapse(&player.stats.weapon_kills),
+        weapon_deaths: weapon_names.collapse(&player.stats.weapon_deaths)
+    }))
+}
+
+// never having met is not an error, both counts are zero
+#[get("/<player_id>/vs/<opponent_id>")]
+pub async fn get_head_to_head(
+    state: &State<MarsAPIState>,
+    player_id: &str,
+    opponent_id: &str
+) -> Result<Json<HeadToHead>, ApiErrorResponder> {
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let opponent : Player = async_extract_player_from_url_v2!(&opponent_id.to_lowercase(), state);
+    if player.id == opponent.id {
+        return Err(ApiErrorResponder::validation_error_with_message("A player has no head-to-head record with themselves"));
+    };
+    match state.database.head_to_head(&player.to_simple(), &opponent.to_simple()).await {
+        Ok(head_to_head) => Ok(Json(head_to_head)),
+        Err(HeadToHeadError::MissingPlayerIds(untracked)) => Err(ApiErrorResponder::create_anonymous_error(
+            Status::UnprocessableEntity,
+            &format!("{} deaths between these players were recorded without player ids, so a head-to-head record cannot be built", untracked)
+        )),
+        Err(HeadToHeadError::Other(e)) => {
+            warn!("Could not build the head-to-head record of {} and {}: {}", player.id_name(), opponent.id_name(), e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not build the head-to-head record"))
+        }
+    }
+}
+
+const MAX_AGGREGATE_PLAYERS : usize = 100;
+
+// combined stats of a roster, ids that match no player come back in `unknownIds` instead of being dropped
+#[post("/aggregate", format = "json", data = "<aggregate_req>")]
+pub async fn aggregate_players(
+    state: &State<MarsAPIState>,
+    aggregate_req: LimitedJson<PlayerAggregateRequest, BatchLimit>
+) -> Result<Json<AggregateStats>, ApiErrorResponder> {
+    let mut player_ids = aggregate_req.into_inner().player_ids;
+    player_ids.sort();
+    player_ids.dedup();
+    if player_ids.is_empty() || player_ids.len() > MAX_AGGREGATE_PLAYERS {
+        return Err(ApiErrorResponder::validation_error_with_message(&format!("Send between 1 and {} player ids", MAX_AGGREGATE_PLAYERS)));
+    };
+    match state.database.aggregate_player_stats(&player_ids).await {
+        Ok(stats) => Ok(Json(stats)),
+        Err(e) => {
+            warn!("Could not aggregate the stats of {} players: {}", player_ids.len(), e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not aggregate player stats"))
+        }
+    }
+}
+
+const MAX_RESOLVE_PLAYERS : usize = 200;
+
+// names and ids to their players in one query, for lists that only need to show who someone is
+#[post("/resolve", format = "json", data = "<resolve_req>")]
+pub async fn resolve_players(
+    state: &State<MarsAPIState>,
+    resolve_req: LimitedJson<PlayerResolveRequest, BatchLimit>
+) -> Result<Json<PlayerResolveResponse>, ApiErrorResponder> {
+    let mut inputs = resolve_req.into_inner().players;
+    inputs.sort();
+    inputs.dedup();
+    if inputs.is_empty() || inputs.len() > MAX_RESOLVE_PLAYERS {
+        return Err(ApiErrorResponder::validation_error_with_message(&format!("Send between 1 and {} ids or names", MAX_RESOLVE_PLAYERS)));
+    };
+    let resolutions = match state.database.resolve_players(&inputs).await {
+        Ok(resolutions) => resolutions,
+        Err(e) => {
+            warn!("Could not resolve {} players: {}", inputs.len(), e);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not resolve players"));
+        }
+    };
+    let mut response = PlayerResolveResponse { resolved: HashMap::new(), unresolved: Vec::new() };
+    for input in inputs.into_iter() {
+        match resolutions.get(&input).cloned().flatten() {
+            Some(player) => { response.resolved.insert(input, player); },
+            None => response.unresolved.push(input)
+        };
+    };
+    Ok(Json(response))
+}
+
+const PLAYER_POSITIONS_LIFETIME_MS : usize = 30_000;
+
+// positions on every board and period, cached briefly since boards move with every match
+#[get("/<player_id>/ranks?<realm>")]
+pub async fn get_leaderboard_positions(
+    state: &State<MarsAPIState>,
+    player_id: &str,
+    realm: Option<&str>,
+    realm_scope: RealmScope
+) -> Result<Json<HashMap<String, HashMap<String, Option<u64>>>>, ApiErrorResponder> {
+    let realm = realm_scope.resolve(&state.config, realm)?;
+    if !state.redis.is_available() {
+        return Err(ApiErrorResponder::cache_unavailable());
+    };
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let leaderboards = state.leaderboards_for(&realm);
+    let cache_key = match &realm {
+        Some(realm) => format!("player-positions:{}:{}", realm, player.id),
+        None => format!("player-positions:{}", player.id)
+    };
+    if let Some(positions) = state.redis.get_unchecked::<HashMap<String, HashMap<String, Option<u64>>>>(&cache_key).await {
+        return Ok(Json(positions));
+    };
+    let id_name = player.id_name();
+    let positions : HashMap<String, HashMap<String, Option<u64>>> = join_all(leaderboards.all().into_iter().map(|leaderboard| {
+        let id_name = &id_name;
+        async move { (leaderboard.score.name(), leaderboard.get_positions(id_name).await) }
+    })).await.into_iter().collect();
+    state.redis.set_with_expiry(&cache_key, &positions, Some(PLAYER_POSITIONS_LIFETIME_MS)).await;
+    Ok(Json(positions))
+}
+
+const PLAYER_PERCENTILES_LIFETIME_MS : usize = 60_000;
+
+// board sizes move slowly, so these are cached a little longer than positions
+#[get("/<player_id>/percentiles?<period>&<realm>")]
+pub async fn get_leaderboard_percentiles(
+    state: &State<MarsAPIState>,
+    player_id: &str,
+    period: Option<&str>,
+    realm: Option<&str>,
+    realm_scope: RealmScope
+) -> Result<Json<BTreeMap<String, Option<BoardPercentile>>>, ApiErrorResponder> {
+    let realm = realm_scope.resolve(&state.config, realm)?;
+    let period = match period {
+        Some(period) => unwrap_helper::return_default!(LeaderboardPeriod::from_str(period).ok(), Err(ApiErrorResponder::validation_error())),
+        None => LeaderboardPeriod::AllTime
+    };
+    if !state.redis.is_available() {
+        return Err(ApiErrorResponder::cache_unavailable());
+    };
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let cache_key = format!("player-percentiles:{}:{}:{}", realm.as_deref().unwrap_or(""), period, player.id);
+    if let Some(percentiles) = state.redis.get_unchecked::<BTreeMap<String, Option<BoardPercentile>>>(&cache_key).await {
+        return Ok(Json(percentiles));
+    };
+    let percentiles = state.leaderboards_for(&realm).get_percentiles(&player.id_name(), &period).await;
+    state.redis.set_with_expiry(&cache_key, &percentiles, Some(PLAYER_PERCENTILES_LIFETIME_MS)).await;
+    Ok(Json(percentiles))
+}
+
+#[get("/<player_id>?<include_leaderboard_positions>&<realm>")]
 pub async fn profile(
     state: &State<MarsAPIState>, 
     player_id: &str,
-    include_leaderboard_positions: bool
+    include_leaderboard_positions: bool,
+    realm: Option<&str>,
+    realm_scope: RealmScope
 ) -> Result<PlayerProfileResponder, ApiErrorResponder> {
+    let realm = realm_scope.resolve(&state.config, realm)?;
     let player_id = player_id.to_lowercase();
     let player : Player = async_extract_player_from_url_v2!(&player_id, state);
-    let profile = player.sanitized_copy();
+    let active_session = state.database.get_active_player_session(&player).await;
+    let profile = PlayerPresenceResponse::new(player.sanitized_copy(), active_session.as_ref(), &state.config.options);
     if !include_leaderboard_positions {
         return Ok(PlayerProfileResponder::RawProfile(profile))
     };
+    let leaderboards = state.leaderboards_for(&realm);
     // omitted: messages sent, server + game playtime
-    let included_lbs : Vec<&Leaderboard> = vec![
-        &state.leaderboards.kills, 
-        &state.leaderboards.deaths,
-        &state.leaderboards.first_bloods,
-        &state.leaderboards.wins,
-        &state.leaderboards.losses,
-        &state.leaderboards.ties,
-        &state.leaderboards.xp,
-        &state.leaderboards.matches_played,
-        &state.leaderboards.core_leaks,
-        &state.leaderboards.core_block_destroys,
-        &state.leaderboards.destroyable_destroys,
-        &state.leaderboards.destroyable_block_destroys,
-        &state.leaderboards.flag_captures,
-        &state.leaderboards.flag_pickups,
-        &state.leaderboards.flag_drops,
-        &state.leaderboards.flag_defends,
-        &state.leaderboards.flag_hold_time,
-        &state.leaderboards.wool_captures,
-        &state.leaderboards.wool_pickups,
-        &state.leaderboards.wool_drops,
-        &state.leaderboards.wool_defends,
-        &state.leaderboards.control_point_captures,
-        &state.leaderboards.highest_killstreak
+    let mut included_lbs : Vec<&Leaderboard> = vec![
+        &leaderboards.kills, 
+        &leaderboards.deaths,
+        &leaderboards.assists,
+        &leaderboards.first_bloods,
+        &leaderboards.wins,
+        &leaderboards.losses,
+        &leaderboards.ties,
+        &leaderboards.xp,
+        &leaderboards.matches_played,
+        &leaderboards.core_leaks,
+        &leaderboards.core_block_destroys,
+        &leaderboards.destroyable_destroys,
+        &leaderboards.destroyable_block_destroys,
+        &leaderboards.flag_captures,
+        &leaderboards.flag_pickups,
+        &leaderboards.flag_drops,
+        &leaderboards.flag_defends,
+        &leaderboards.flag_hold_time,
+        &leaderboards.wool_captures,
+        &leaderboards.wool_pickups,
+        &leaderboards.wool_drops,
+        &leaderboards.wool_defends,
+        &leaderboards.control_point_captures,
+        &leaderboards.highest_killstreak
     ];
-    let mut positions : HashMap<ScoreType, u64> = HashMap::new();
+    included_lbs.extend(leaderboards.dynamic.iter().map(|leaderboard| leaderboard.as_ref()).filter(|leaderboard| is_public_leaderboard(leaderboard)));
+    let mut positions : HashMap<String, u64> = HashMap::new();
     let mut lb_position_tasks : Vec<_> = Vec::new();
     for lb in included_lbs.iter() {
 
         // move owned id string into closure
         let wrapper = |player_id: String| async move {
-            (lb.score_type.clone(), lb.get_position(&player_id, &LeaderboardPeriod::AllTime).await)
+            (lb.score.name(), lb.get_position(&player_id, &LeaderboardPeriod::AllTime).await)
         };
 
         lb_position_tasks.push(wrapper(player.id_name()));
@@ -251,13 +516,68 @@ pub async fn profile(
     join_all(lb_position_tasks).await.into_iter().filter(|pos_opt| pos_opt.1.is_some()).for_each(|pos| {
         positions.insert(pos.0, pos.1.unwrap());
     });
+    let win_rates = player.gamemode_stats.iter()
+        .map(|(gamemode, stats)| (gamemode.clone(), stats.win_rate(&gamemode.win_outcomes())))
+        .collect();
     Ok(PlayerProfileResponder::ProfileWithLeaderboardPositions(PlayerProfileResponse {
         player: profile,
-        leaderboard_positions: positions
+        leaderboard_positions: positions,
+        win_rates
     }))
 }
 
 
+// `viewer` is whoever asked for the export. a player exporting themselves gets their own copy with the fields
+// the profile hides stripped, anyone else gets the staff copy with senior-only notes dropped for non-senior viewers
+#[get("/<player_id>/export?<viewer>")]
+pub async fn export_player(
+    state: &State<MarsAPIState>, 
+    player_id: &str,
+    viewer: Option<&str>,
+    _auth_guard: AuthorizationToken
+) -> Result<Json<PlayerExportResponse>, ApiErrorResponder> {
+    let mut player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let own = viewer.map(|viewer| viewer.eq_ignore_ascii_case(&player.id) || viewer.eq_ignore_ascii_case(&player.name)).unwrap_or(false);
+    if !own && !sees_senior_notes(state, viewer).await {
+        player.hide_senior_notes();
+    };
+    let options = &state.config.options;
+    let (active_session, punishments, sessions, catalog) = futures::join!(
+        state.database.get_active_player_session(&player),
+        state.database.load_player_punishments(&player, options.strict_decoding),
+        state.database.get_recent_player_sessions(&player, options.list_max_results as i64),
+        get_achievement_catalog(state)
+    );
+    let mut punishments = punishments.map_err(|e| ApiErrorResponder::load_failed(&e))?.documents;
+    punishments.sort_by_key(|punishment| punishment.issued_at);
+    let achievements = catalog.into_iter().map(|achievement| PlayerAchievementResponse::new(achievement, &player)).collect();
+    let level_progress = PlayerLevelProgressResponse::new(&player, options.prestige_level_cap);
+    let win_rates = player.gamemode_stats.iter()
+        .map(|(gamemode, stats)| (gamemode.clone(), stats.win_rate(&gamemode.win_outcomes())))
+        .collect();
+    let (exported, punishments, sessions) = if own {
+        (
+            player.sanitized_copy(),
+            ExportedPunishments::Own(punishments.iter().map(|punishment| {
+                PunishmentPublicView::new(punishment, punishment.code.clone().unwrap_or_default(), options.punishment_appeal_url.clone())
+            }).collect()),
+            sessions.iter().map(|session| session.sanitized_copy()).collect()
+        )
+    } else {
+        (player, ExportedPunishments::Staff(punishments), sessions)
+    };
+    Ok(Json(PlayerExportResponse {
+        player: PlayerPresenceResponse::new(exported, active_session.as_ref(), options),
+        punishments,
+        sessions,
+        achievements,
+        level_progress,
+        win_rates,
+        includes_ips: !own,
+        exported_at: Timestamp::now()
+    }))
+}
+
 // why isn't the url parameter used?
 #[post("/<_player_id>/punishments", format = "json", data = "<pun_issue_req>")]
 pub async fn issue_punishment(
@@ -266,25 +586,26 @@ pub async fn issue_punishment(
     _player_id: &str,
     auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<Punishment>, ApiErrorResponder> {
-    let data = pun_issue_req.0;
-    let punishment_id = Uuid::new_v4().to_string();
-    let time_millis : u64 = u64::try_from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()).unwrap_or(u64::MAX);
-    let target_player : Player = async_extract_player_from_url_v2!(&data.target_name, state);
-    let punishment = Punishment { 
-        id: punishment_id, 
-        reason: data.reason, 
-        issued_at: time_millis as f64, 
-        silent: data.silent, 
-        offence: data.offence, 
-        action: data.action, 
-        note: data.note, 
-        punisher: data.punisher, 
-        target: target_player.to_simple(), 
-        target_ips: data.target_ips, 
-        reversion: None, 
-        server_id: Some(auth_guard.server_id)
-    };
-    state.database.insert_one(&punishment).await;
+    let mut punishment = resolve_punishment(state, pun_issue_req.0, auth_guard.server_id.clone()).await?.punishment;
+    match state.database.insert_punishment(&mut punishment).await {
+        Ok(()) => {},
+        // the id or every code tried was taken, issuing again gets fresh ones
+        Err(InsertError::DuplicateKey) => return Err(ApiErrorResponder::create_anonymous_error(Status::Conflict, "The punishment conflicts with an existing one, issue it again")),
+        Err(e) => {
+            warn!("Could not insert punishment {}: {}", punishment.id, e);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not save the punishment"));
+        }
+    };
+    announce_superseded(state, &punishment).await;
+    let mut summary = format!("{} {} for {}, length {}ms", punishment.id, punishment.action.kind, punishment.reason.name, punishment.action.length());
+    if !punishment.supersedes.is_empty() {
+        summary.push_str(&format!(", superseding {}", punishment.supersedes.join(", ")));
+    };
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::PunishmentIssue)
+        .actor(punishment.punisher.clone())
+        .server(&auth_guard.server_id)
+        .target(&punishment.target.id, &punishment.target.name)
+        .after(summary));
     {
         // take ownership for the spawned task
         let pun_clone = punishment.clone();
@@ -296,15 +617,18 @@ pub async fn issue_punishment(
     Ok(JsonResponder::from(punishment, Status::Created))
 }
 
-
 #[get("/<player_id>/punishments")]
 pub async fn get_punishments(
     state: &State<MarsAPIState>, 
     player_id: &str,
     _auth_guard: AuthorizationToken
-) -> Result<JsonResponder<Vec<Punishment>>, ApiErrorResponder> {
+) -> Result<ListResponder<JsonResponder<Vec<Punishment>>>, ApiErrorResponder> {
     let player : Player = async_extract_player_from_url_v2!(&player_id, state);
-    Ok(JsonResponder::created(state.database.get_player_punishments(&player).await))
+    let options = &state.config.options;
+    let (punishments, total) = state.database.get_player_punishments_limited(&player, options.list_max_results as i64, options.strict_decoding).await
+        .map_err(|e| ApiErrorResponder::load_failed(&e))?;
+    let returned = punishments.documents.len();
+    Ok(ListResponder::new(JsonResponder::created(punishments.documents), returned, total).skipping(punishments.failures))
 }
 
 pub fn hash_ip(state: &MarsAPIState, digest: &String) -> String {
@@ -322,28 +646,63 @@ pub fn sha256_hash_formatted(digest: &String) -> String {
     })
 }
 
-#[get("/<player_id>/lookup?<include_alts>")]
+// `strategies` is a comma separated list of search strategies, id, name and name history when not given
+#[get("/search?<query>&<strategies>")]
+pub async fn search_player(
+    state: &State<MarsAPIState>,
+    query: &str,
+    strategies: Option<&str>,
+    _auth_guard: AuthorizationToken
+) -> Result<Json<PlayerSearchResponse>, ApiErrorResponder> {
+    let strategies : Vec<SearchStrategy> = match strategies {
+        Some(strategies) => unwrap_helper::return_default!(
+            strategies.split(',').map(|strategy| SearchStrategy::from_str(strategy.trim()).ok()).collect::<Option<Vec<SearchStrategy>>>(),
+            Err(ApiErrorResponder::validation_error_with_message("Unknown search strategy"))
+        ),
+        None => vec![SearchStrategy::Id, SearchStrategy::Name, SearchStrategy::NameHistory]
+    };
+    let found : SearchMatch<Player> = unwrap_helper::return_default!(state.database.find_by_search(query, &strategies).await, Err(ApiErrorResponder::missing_player()));
+    Ok(Json(PlayerSearchResponse { player: found.document.to_simple(), matched_by: found.matched_by }))
+}
+
+#[get("/<player_id>/lookup?<include_alts>&<include_name_history>&<viewer>")]
 pub async fn lookup_player(
     state: &State<MarsAPIState>, 
     player_id: &str,
     include_alts: bool,
+    include_name_history: Option<bool>,
+    viewer: Option<&str>,
     _auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<PlayerLookupResponse>, ApiErrorResponder> {
-    let player : Player = async_extract_player_from_url_v2!(&player_id, state);
+    // current names and ids take precedence over names from the history
+    let player : Player = match state.player_cache.get(&state.database, player_id).await {
+        Some(player) => player,
+        None if include_name_history.unwrap_or(false) => unwrap_helper::return_default!(
+            state.database.find_player_by_past_name(player_id).await, Err(ApiErrorResponder::missing_player())
+        ),
+        None => return Err(ApiErrorResponder::missing_player())
+    };
+    let senior = sees_senior_notes(state, viewer).await;
+    let fetched_alts = if include_alts { state.database.get_alts_for_player(&player).await } else { Vec::new() };
+    let player_ids : Vec<String> = std::iter::once(&player).chain(fetched_alts.iter()).map(|p| p.id.clone()).collect();
+    let active_sessions = state.database.get_active_sessions_for_players(&player_ids).await;
     let alts : Vec<PlayerAltResponse> = {
         let mut alts : Vec<PlayerAltResponse> = Vec::new();
-        if include_alts {
-            let fetched_alts = state.database.get_alts_for_player(&player).await;
-            let pun_tasks : Vec<_> = fetched_alts.iter().map(|alt| {
-                state.database.get_player_punishments(alt)
-            }).collect();
-            let alt_puns = join_all(pun_tasks).await;
-            for (alt, puns) in fetched_alts.into_iter().zip(alt_puns) {
-                alts.push(PlayerAltResponse { player: alt, punishments: puns });
-            }
-        };
+        let pun_tasks : Vec<_> = fetched_alts.iter().map(|alt| {
+            state.database.get_player_punishments(alt)
+        }).collect();
+        let alt_puns = join_all(pun_tasks).await;
+        for (mut alt, puns) in fetched_alts.into_iter().zip(alt_puns) {
+            if !senior {
+                alt.hide_senior_notes();
+            };
+            let session = active_sessions.get(&alt.id);
+            alts.push(PlayerAltResponse { player: PlayerPresenceResponse::new(alt, session, &state.config.options), punishments: puns });
+        }
         alts
     };
+    let session = active_sessions.get(&player.id);
+    let player = PlayerPresenceResponse::new(if senior { player } else { player.without_senior_notes() }, session, &state.config.options);
     Ok(JsonResponder::created(PlayerLookupResponse { player, alts }))
 }
 
@@ -352,15 +711,31 @@ pub async fn add_player_note(
     state: &State<MarsAPIState>, 
     player_id: &str,
     add_note_req: Json<PlayerAddNoteRequest>,
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<Player>, ApiErrorResponder> {
     let data = add_note_req.0;
+    if data.note_type == NoteType::System {
+        return Err(ApiErrorResponder::validation_error_with_message("System notes cannot be added by staff"));
+    };
     let mut player : Player = async_extract_player_from_url_v2!(&player_id, state);
     let id = player.notes.iter().max_by_key(|note| note.id).map(|note| note.id).unwrap_or(0) + 1;
-    let note = StaffNote { id, author: data.author, content: data.content, created_at: get_u64_time_millis() };
+    let note = StaffNote { 
+        id, 
+        author: data.author, 
+        content: data.content, 
+        created_at: Timestamp::now(), 
+        note_type: data.note_type, 
+        visibility: data.visibility 
+    };
     let note_clone = note.clone();
     player.notes.push(note);
     state.player_cache.set(&state.database, player_id, &player, true).await;
+    let viewer = note_clone.author.id.clone();
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::NoteAdd)
+        .actor(Some(note_clone.author.clone()))
+        .server(&auth_guard.server_id)
+        .target(&player.id, &player.name)
+        .after(format!("#{} {}: {}", note_clone.id, note_clone.note_type, note_clone.content)));
     {
         // take ownership for the spawned task
         let state_clone = state.config.clone();
@@ -369,21 +744,69 @@ pub async fn add_player_note(
             state_clone.webhooks.send_new_note_webhook(&player_simple, &note_clone).await;
         });
     }
-    Ok(JsonResponder::created(player))
+    Ok(JsonResponder::created(visible_player(state, Some(&viewer), player).await))
 }
 
-#[delete("/<player_id>/notes/<note_id>")]
+// whether the viewer's ranks grant `SENIOR_NOTE_PERMISSION`, a missing or unknown viewer is not senior
+pub async fn sees_senior_notes(state: &MarsAPIState, viewer: Option<&str>) -> bool {
+    let viewer = match viewer {
+        Some(viewer) => viewer,
+        None => return false
+    };
+    match state.player_cache.get(&state.database, viewer).await {
+        Some(viewer) => Rank::find_for_player(&state.database, &viewer).await.iter()
+            .any(|rank| rank.permissions.iter().any(|permission| permission == SENIOR_NOTE_PERMISSION)),
+        None => false
+    }
+}
+
+// every response that carries a full player goes through this or `Player::without_senior_notes`
+pub async fn visible_player(state: &MarsAPIState, viewer: Option<&str>, player: Player) -> Player {
+    if sees_senior_notes(state, viewer).await { player } else { player.without_senior_notes() }
+}
+
+// senior-only notes are dropped unless the viewer's ranks grant `SENIOR_NOTE_PERMISSION`
+#[get("/<player_id>/notes?<viewer>&<note_type>")]
+pub async fn get_player_notes(
+    state: &State<MarsAPIState>, 
+    player_id: &str,
+    viewer: Option<&str>,
+    note_type: Option<&str>,
+    _auth_guard: AuthorizationToken
+) -> Result<ListResponder<Json<Vec<StaffNote>>>, ApiErrorResponder> {
+    let note_type = match note_type {
+        Some(note_type) => Some(unwrap_helper::return_default!(NoteType::from_str(note_type).ok(), Err(ApiErrorResponder::validation_error_with_message("Unknown note type")))),
+        None => None
+    };
+    let player : Player = async_extract_player_from_url_v2!(&player_id, state);
+    let senior = sees_senior_notes(state, viewer).await;
+    let mut notes : Vec<StaffNote> = player.notes.into_iter()
+        .filter(|note| senior || !note.is_visible_to_senior_only())
+        .filter(|note| note_type.map(|note_type| note.note_type == note_type).unwrap_or(true))
+        .collect();
+    let total = notes.len() as u64;
+    notes.truncate(state.config.options.list_max_results as usize);
+    let returned = notes.len();
+    Ok(ListResponder::new(Json(notes), returned, total))
+}
+
+#[delete("/<player_id>/notes/<note_id>?<viewer>")]
 pub async fn delete_player_note(
     state: &State<MarsAPIState>, 
     player_id: &str,
     note_id: u32,
-    _auth_guard: AuthorizationToken
+    viewer: Option<&str>,
+    auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<Player>, ApiErrorResponder> {
     let mut player : Player = async_extract_player_from_url_v2!(&player_id, state);
     let note_index = unwrap_helper::return_default!(player.notes.iter().position(|note| { note.id == note_id }), Err(ApiErrorResponder::note_missing()));
     let note_clone = player.notes[note_index].clone();
     player.notes.remove(note_index);
     state.player_cache.set(&state.database, player_id, &player, true).await;
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::NoteDelete)
+        .server(&auth_guard.server_id)
+        .target(&player.id, &player.name)
+        .before(format!("#{} by {}: {}", note_clone.id, note_clone.author.name, note_clone.content)));
     {
         // take ownership for the spawned task
         let state_clone = state.config.clone();
@@ -392,34 +815,354 @@ pub async fn delete_player_note(
             state_clone.webhooks.send_deleted_note_webhook(&player_simple, &note_clone).await;
         });
     }
-    Ok(JsonResponder::created(player))
+    Ok(JsonResponder::created(visible_player(state, viewer, player).await))
 }
 
-#[put("/<player_id>/active_tag", format = "json", data = "<tag_set_req>")]
-async fn set_active_tag(
+// crediting resumes from the moment of unfreezing, stats from the frozen period are not applied
+async fn set_player_frozen(
+    state: &State<MarsAPIState>, 
+    player_id: &str,
+    data: PlayerFreezeRequest,
+    frozen: bool
+) -> Result<JsonResponder<Player>, ApiErrorResponder> {
+    let mut player : Player = async_extract_player_from_url_v2!(&player_id, state);
+    if player.frozen == frozen {
+        return Ok(JsonResponder::ok(visible_player(state, Some(&data.author.id), player).await));
+    };
+    player.frozen = frozen;
+    let id = player.notes.iter().max_by_key(|note| note.id).map(|note| note.id).unwrap_or(0) + 1;
+    let content = format!("{}: {}", if frozen { "Froze stats" } else { "Unfroze stats" }, data.reason);
+    let note = StaffNote { 
+        id, 
+        author: data.author, 
+        content, 
+        created_at: Timestamp::now(), 
+        note_type: NoteType::System, 
+        visibility: NoteVisibility::Staff 
+    };
+    let note_clone = note.clone();
+    player.notes.push(note);
+    state.player_cache.set(&state.database, player_id, &player, true).await;
+    record_audit(&state.database, AuditLogEntry::new(if frozen { AuditAction::StatsFreeze } else { AuditAction::StatsUnfreeze })
+        .actor(Some(note_clone.author.clone()))
+        .target(&player.id, &player.name)
+        .reason(data.reason));
+    let viewer = note_clone.author.id.clone();
+    {
+        // take ownership for the spawned task
+        let state_clone = state.config.clone();
+        let player_simple = player.to_simple();
+        tokio::spawn(async move {
+            state_clone.webhooks.send_new_note_webhook(&player_simple, &note_clone).await;
+        });
+    }
+    Ok(JsonResponder::ok(visible_player(state, Some(&viewer), player).await))
+}
+
+#[post("/<player_id>/freeze", format = "json", data = "<freeze_req>")]
+pub async fn freeze_player(
+    state: &State<MarsAPIState>, 
+    player_id: &str,
+    freeze_req: Json<PlayerFreezeRequest>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<JsonResponder<Player>, ApiErrorResponder> {
+    set_player_frozen(state, player_id, freeze_req.0, true).await
+}
+
+#[post("/<player_id>/unfreeze", format = "json", data = "<unfreeze_req>")]
+pub async fn unfreeze_player(
+    state: &State<MarsAPIState>, 
+    player_id: &str,
+    unfreeze_req: Json<PlayerFreezeRequest>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<JsonResponder<Player>, ApiErrorResponder> {
+    set_player_frozen(state, player_id, unfreeze_req.0, false).await
+}
+
+const MAX_STAT_CORRECTIONS : usize = 1_000;
+
+// each row is applied on its own, a row that fails leaves the rest alone. the author's ranks must grant
+// `STAT_CORRECTION_PERMISSION`. a realm's token corrects that realm's stats. all-time leaderboard entries
+// follow, periodic boards are left as they are
+#[post("/stats/corrections", format = "json", data = "<correction_req>")]
+pub async fn correct_player_stats(
+    state: &State<MarsAPIState>,
+    correction_req: LimitedJson<StatCorrectionRequest, BatchLimit>,
+    auth_guard: RealmAuthorizationToken
+) -> Result<Json<Vec<StatCorrectionResult>>, ApiErrorResponder> {
+    let data = correction_req.into_inner();
+    let reason = data.reason.trim();
+    if reason.is_empty() {
+        return Err(ApiErrorResponder::validation_error_with_message("Stat corrections need a reason"));
+    };
+    if data.corrections.is_empty() || data.corrections.len() > MAX_STAT_CORRECTIONS {
+        return Err(ApiErrorResponder::validation_error_with_message(&format!("Send between 1 and {} corrections", MAX_STAT_CORRECTIONS)));
+    };
+    let author : Player = unwrap_helper::return_default!(state.player_cache.get(&state.database, &data.author.name).await, Err(ApiErrorResponder::missing_player()));
+    let allowed = Rank::find_for_player(&state.database, &author).await.iter()
+        .any(|rank| rank.permissions.iter().any(|permission| permission == STAT_CORRECTION_PERMISSION));
+    if !allowed {
+        return Err(ApiErrorResponder::create_anonymous_error(Status::Forbidden, "You do not have permission to correct stats"));
+    };
+
+    let mut results : Vec<StatCorrectionResult> = Vec::new();
+    for correction in data.corrections.iter() {
+        let score_type = match ScoreType::from_str(&correction.stat.to_uppercase()) {
+            Ok(score_type) => score_type,
+            Err(_) => { results.push(StatCorrectionResult::failed(correction, "Unknown stat")); continue; }
+        };
+        let (field, max) = match correctable_field(&score_type, &auth_guard.realm) {
+            Some(field) => field,
+            None => { results.push(StatCorrectionResult::failed(correction, format!("{} cannot be corrected", score_type))); continue; }
+        };
+        if correction.delta == 0 {
+            results.push(StatCorrectionResult::failed(correction, "The delta cannot be 0"));
+            continue;
+        };
+        // the cached copy can hold stats that were never saved, they go to mongo before the `$inc` so the
+        // eviction below only drops values that are already stored
+        if let Some(target) = Database::find_by_id(&state.database.players, &correction.player_id).await {
+            state.player_cache.persist_cached_value(&state.database, &target.name).await;
+        };
+        let applied = match state.database.correct_stat(&correction.player_id, &field, max, correction.delta).await {
+            Ok(applied) => applied,
+            Err(StatCorrectionError::MissingPlayer) => { results.push(StatCorrectionResult::failed(correction, "Player not found")); continue; },
+            Err(StatCorrectionError::OutOfRange(current)) => {
+                results.push(StatCorrectionResult::failed(correction, format!("{} is {}, {:+} would take it out of range", score_type, current, correction.delta)));
+                continue;
+            },
+            Err(StatCorrectionError::Other(e)) => {
+                warn!("Could not correct {} of {}: {}", score_type, correction.player_id, e);
+                results.push(StatCorrectionResult::failed(correction, "Could not apply the correction"));
+                continue;
+            }
+        };
+        // the cached copy still has the old value and would write it back on the next save
+        state.player_cache.evict(&applied.player.name).await;
+        state.leaderboards_for(&auth_guard.realm).from_score_type(score_type.clone())
+            .correct_all_time(&format!("{}/{}", applied.player.id, applied.player.name), correction.delta).await;
+        record_audit(&state.database, AuditLogEntry::new(AuditAction::StatsCorrect)
+            .actor(Some(data.author.clone()))
+            .server(&auth_guard.server_id)
+            .target(&applied.player.id, &applied.player.name)
+            .before(format!("{} {}", score_type, applied.before))
+            .after(format!("{} {} ({:+})", score_type, applied.after, correction.delta))
+            .reason(reason));
+        results.push(StatCorrectionResult {
+            player_id: applied.player.id,
+            stat: score_type.to_string(),
+            delta: correction.delta,
+            success: true,
+            error: None,
+            before: Some(applied.before),
+            after: Some(applied.after)
+        });
+    };
+    let applied = results.iter().filter(|result| result.success).count();
+    info!("{} applied {} of {} stat corrections: {}", data.author.name, applied, results.len(), reason);
+    Ok(Json(results))
+}
+
+#[post("/<player_id>/prestige")]
+pub async fn prestige_player(
+    state: &State<MarsAPIState>, 
+    player_id: &str,
+    auth_guard: AuthorizationToken
+) -> Result<JsonResponder<Player>, ApiErrorResponder> {
+    let mut player : Player = async_extract_player_from_url_v2!(player_id, state);
+    let before = format!("prestige {}, {} xp", player.prestige, player.stats.xp);
+    let xp_before = player.stats.xp;
+    let prestige = match player.prestige(&state.config.options) {
+        Ok(prestige) => prestige,
+        Err(PrestigeError::BelowCap { level, cap }) => return Err(ApiErrorResponder::prestige_below_cap(level, cap))
+    };
+    state.player_cache.set(&state.database, &player.name, &player, true).await;
+    // the all-time xp board follows the xp that's left, what was earned in the current periods still counts there
+    let reset_xp = xp_before.saturating_sub(player.stats.xp);
+    if reset_xp > 0 {
+        state.leaderboards.xp.correct_all_time(&player.id_name(), -(reset_xp as i64)).await;
+    };
+    ServerBroadcast::send_from_api(&state.server_broadcast, &EventType::PlayerPrestige, prestige);
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::PlayerPrestige)
+        .server(&auth_guard.server_id)
+        .target(&player.id, &player.name)
+        .before(before)
+        .after(format!("prestige {}, {} xp", player.prestige, player.stats.xp)));
+    Ok(JsonResponder::ok(player.without_senior_notes()))
+}
+
+// sends clients to the configured avatar source, so they keep working when it changes.
+// `variant` is `face` (the default) or `body`
+#[get("/<player_id>/avatar?<variant>&<size>")]
+pub async fn get_player_avatar(
+    state: &State<MarsAPIState>,
+    player_id: &str,
+    variant: Option<&str>,
+    size: Option<u32>
+) -> Result<Redirect, ApiErrorResponder> {
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let avatars = &state.config.options.avatars;
+    let size = size.unwrap_or(64).clamp(8, 512);
+    let url = match variant.unwrap_or("face") {
+        "face" => player.to_simple().get_face_url(avatars, size),
+        "body" => player.to_simple().get_body_url(avatars, size),
+        _ => return Err(ApiErrorResponder::validation_error_with_message("Expected face or body"))
+    };
+    Ok(Redirect::temporary(url))
+}
+
+#[get("/<player_id>/level-progress")]
+pub async fn get_level_progress(
+    state: &State<MarsAPIState>,
+    player_id: &str
+) -> Result<Json<PlayerLevelProgressResponse>, ApiErrorResponder> {
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    Ok(Json(PlayerLevelProgressResponse::new(&player, state.config.options.prestige_level_cap)))
+}
+
+#[get("/<player_id>/rank-progress")]
+pub async fn get_rank_progress(
+    state: &State<MarsAPIState>,
+    player_id: &str
+) -> Result<Json<PlayerRankProgressResponse>, ApiErrorResponder> {
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let thresholds = &state.config.data.rank_thresholds;
+    let rank_names = join_all(thresholds.iter().map(|threshold| Database::find_by_id(&state.database.ranks, &threshold.rank_id))).await;
+    let thresholds : Vec<RankThresholdProgress> = thresholds.iter().zip(rank_names)
+        .map(|(threshold, rank)| RankThresholdProgress::new(threshold, &player, rank.map(|rank| rank.name)))
+        .collect();
+    let next = thresholds.iter()
+        .filter(|threshold| !threshold.granted && threshold.rank_name.is_some())
+        .max_by(|a, b| a.progress.total_cmp(&b.progress))
+        .cloned();
+    Ok(Json(PlayerRankProgressResponse { next, thresholds }))
+}
+
+// newest first, pass `next` back as `after` for the following page
+#[get("/<player_id>/matches?<after>&<limit>&<realm>")]
+pub async fn get_player_matches(
+    state: &State<MarsAPIState>,
+    player_id: &str,
+    after: Option<u64>,
+    limit: Option<i64>,
+    realm: Option<&str>,
+    realm_scope: RealmScope
+) -> Result<Json<KeysetPage<PlayerMatchHistoryEntry>>, ApiErrorResponder> {
+    let realm = realm_scope.resolve(&state.config, realm)?;
+    let player : Player = async_extract_player_from_url_v2!(&player_id.to_lowercase(), state);
+    let limit = limit.unwrap_or(20).clamp(1, 50);
+    let page = match state.database.get_player_matches(&player.id, &realm, after, limit).await {
+        Ok(page) => page,
+        Err(e) => {
+            warn!("Could not load matches of {}: {}", player.name, e);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not load matches"));
+        }
+    };
+    let items = page.items.iter().map(|current_match| PlayerMatchHistoryEntry::new(current_match, &player.id)).collect();
+    Ok(Json(KeysetPage { items, next: page.next }))
+}
+
+#[delete("/<player_id>/data?<mode>&<confirm>")]
+pub async fn erase_player_data(
+    state: &State<MarsAPIState>, 
+    player_id: &str,
+    mode: &str,
+    confirm: Option<&str>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Confirmable<ErasureReport>, ApiErrorResponder> {
+    let mode = unwrap_helper::return_default!(ErasureMode::from_str(mode).ok(), Err(ApiErrorResponder::validation_error()));
+    let player : Player = async_extract_player_from_url_v2!(player_id, state);
+    if state.config.options.confirmation_required {
+        let binding = format!("erase:{}:{}", player.id, mode);
+        match confirm {
+            Some(token) => consume_confirmation(state, &binding, token).await?,
+            None => {
+                let effect = match state.database.erasure_effect(&player.id).await {
+                    Ok(effect) => effect,
+                    Err(e) => {
+                        warn!("Could not count data to erase for {}: {}", player.id_name(), e);
+                        return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not count player data"));
+                    }
+                };
+                return Ok(Confirmable::Pending(request_confirmation(state, &binding, effect).await));
+            }
+        };
+    };
+    // deaths still buffered would otherwise be written with the player in them after the erasure
+    state.death_writer.flush_all().await;
+    let report = match state.database.erase_player_data(&player.id, mode).await {
+        Ok(report) => report,
+        Err(e) => {
+            warn!("Could not erase data for {}: {}", player.id_name(), e);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not erase player data"));
+        }
+    };
+    // redis is outside the transaction, so it is only cleaned up once the database side has committed
+    state.player_cache.evict(&player.name).await;
+    let id_name = player.id_name();
+    for leaderboards in state.every_realm() {
+        for leaderboard in leaderboards.all() {
+            leaderboard.remove(&id_name).await;
+        };
+    };
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::PlayerDataDelete)
+        .target(&player.id, &player.name)
+        .after(mode.to_string()));
+    info!("Erased data for {} ({})", player.id, mode);
+    Ok(Confirmable::Done(report))
+}
+
+async fn set_player_active_tag(
     state: &State<MarsAPIState>, 
     player_id: &str, 
-    tag_set_req: Json<PlayerSetActiveTagRequest>,
-    _auth_guard: AuthorizationToken
+    slot: TagSlot,
+    tag_id: Option<String>
 ) -> Result<JsonResponder<Player>, ApiErrorResponder> {
-    let tag_id = tag_set_req.active_tag_id.clone();
     let mut player = async_extract_player_from_url_v2!(player_id, state);
 
-    if tag_id == player.active_tag_id {
-        return Ok(JsonResponder::from(player, Status::Ok));
+    if tag_id.as_ref() == player.active_tag_ids.get(&slot) {
+        return Ok(JsonResponder::from(player.without_senior_notes(), Status::Ok));
     }
 
-    if tag_id.is_none() {
-        player.active_tag_id = Option::None;
-    } else {
-        if !player.tag_ids.contains(tag_id.as_ref().unwrap()) {
+    if let Some(tag_id) = tag_id.as_ref() {
+        if !player.tag_ids.contains(tag_id) {
             return Err(ApiErrorResponder::tag_missing_from_player());
         }
-        player.active_tag_id = tag_id;
+        let tag = unwrap_helper::return_default!(Database::find_by_id(&state.database.tags, tag_id).await, Err(ApiErrorResponder::tag_missing()));
+        if !tag.slots.contains(&slot) {
+            return Err(ApiErrorResponder::tag_slot_incompatible());
+        }
+        if let (Some(min_level), false) = (tag.min_level, tag.is_unlocked_by(&player)) {
+            return Err(ApiErrorResponder::tag_level_locked(min_level, player.get_lifetime_level()));
+        }
     }
+    player.set_active_tag(slot, tag_id);
 
     state.player_cache.set(&state.database, &player.name, &player, true).await;
-    return Ok(JsonResponder::from(player, Status::Ok));
+    Ok(JsonResponder::from(player.without_senior_notes(), Status::Ok))
+}
+
+// legacy single tag endpoint, sets the prefix slot
+#[put("/<player_id>/active_tag", format = "json", data = "<tag_set_req>")]
+async fn set_active_tag(
+    state: &State<MarsAPIState>, 
+    player_id: &str, 
+    tag_set_req: Json<PlayerSetActiveTagRequest>,
+    _auth_guard: AuthorizationToken
+) -> Result<JsonResponder<Player>, ApiErrorResponder> {
+    set_player_active_tag(state, player_id, TagSlot::Prefix, tag_set_req.0.active_tag_id).await
+}
+
+#[put("/<player_id>/active_tags/<slot>", format = "json", data = "<tag_set_req>")]
+async fn set_active_tag_slot(
+    state: &State<MarsAPIState>, 
+    player_id: &str, 
+    slot: &str,
+    tag_set_req: Json<PlayerSetActiveTagRequest>,
+    _auth_guard: AuthorizationToken
+) -> Result<JsonResponder<Player>, ApiErrorResponder> {
+    let slot = unwrap_helper::return_default!(TagSlot::from_str(slot).ok(), Err(ApiErrorResponder::validation_error_with_message("Unknown tag slot")));
+    set_player_active_tag(state, player_id, slot, tag_set_req.0.active_tag_id).await
 }
 
 #[put("/<player_id>/tags/<tag_id>")]
@@ -427,7 +1170,7 @@ async fn add_tag_to_player(
     state: &State<MarsAPIState>, 
     player_id: &str, 
     tag_id: &str, 
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<Player>, ApiErrorResponder> {
     let mut player = async_extract_player_from_url_v2!(player_id, state);
 
@@ -442,7 +1185,11 @@ async fn add_tag_to_player(
 
     player.tag_ids.push(tag.id.clone());
     state.player_cache.set(&state.database, &player.name, &player, true).await;
-    return Ok(JsonResponder::from(player, Status::Ok));
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::TagGrant)
+        .server(&auth_guard.server_id)
+        .target(&player.id, &player.name)
+        .after(tag.name));
+    Ok(JsonResponder::from(player.without_senior_notes(), Status::Ok))
 }
 
 
@@ -451,7 +1198,7 @@ async fn delete_player_tag(
     state: &State<MarsAPIState>,
     player_id: &str,
     tag_id: &str,
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<Player>, ApiErrorResponder> {
     let mut player = async_extract_player_from_url_v2!(player_id, state);
     let tag = match state.database.find_by_id_or_name::<Tag>(tag_id).await {
@@ -462,12 +1209,13 @@ async fn delete_player_tag(
         Some(tag_index) => player.tag_ids.swap_remove(tag_index),
         None => return Err(ApiErrorResponder::tag_missing_from_player())
     };
-    if player.active_tag_id.is_some() && player.active_tag_id.as_ref().unwrap() == &tag.id {
-        player.active_tag_id = Option::None;
-    }
+    player.clear_active_tag(&tag.id);
     state.player_cache.set(&state.database, &player.name, &player, true).await;
-    return Ok(JsonResponder::from(player, Status::Ok));
-
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::TagRevoke)
+        .server(&auth_guard.server_id)
+        .target(&player.id, &player.name)
+        .before(tag.name));
+    Ok(JsonResponder::from(player.without_senior_notes(), Status::Ok))
 }
 
 #[put("/<player_id>/ranks/<rank_id>")]
@@ -475,7 +1223,7 @@ async fn add_player_rank(
     state: &State<MarsAPIState>, 
     player_id: &str, 
     rank_id: &str, 
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<Json<Player>, ApiErrorResponder> {
     let mut player = unwrap_helper::return_default!(state.player_cache.get(&state.database, player_id).await, Err(ApiErrorResponder::missing_player()));
     let rank = unwrap_helper::return_default!(state.database.find_by_id_or_name::<Rank>(rank_id).await, Err(ApiErrorResponder::missing_rank()));
@@ -484,24 +1232,37 @@ async fn add_player_rank(
     player.rank_ids.push(rank.id);
 
     state.player_cache.set(&state.database, &player.name, &player, true).await;
-    Ok(Json(player))
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::RankGrant)
+        .server(&auth_guard.server_id)
+        .target(&player.id, &player.name)
+        .after(rank.name));
+    Ok(Json(player.without_senior_notes()))
 }
 
-#[delete("/<player_id>/ranks/<rank_id>")]
+// ranks granted by a rank threshold are only revoked with `force`, they are not granted again afterwards
+#[delete("/<player_id>/ranks/<rank_id>?<force>")]
 async fn delete_player_rank(
     state: &State<MarsAPIState>, 
     player_id: &str, 
     rank_id: &str, 
-    _auth_guard: AuthorizationToken
+    force: bool,
+    auth_guard: AuthorizationToken
 ) -> Result<Json<Player>, ApiErrorResponder> {
     let mut player = unwrap_helper::return_default!(state.player_cache.get(&state.database, player_id).await, Err(ApiErrorResponder::missing_player()));
     let rank = unwrap_helper::return_default!(state.database.find_by_id_or_name::<Rank>(rank_id).await, Err(ApiErrorResponder::missing_rank()));
 
     if !player.rank_ids.contains(&rank.id) { return Err(ApiErrorResponder::rank_not_present()); };
+    if player.auto_rank_ids.contains(&rank.id) && !force {
+        return Err(ApiErrorResponder::create_anonymous_error(Status::Conflict, "The rank was earned through a rank threshold, pass force to revoke it anyway"));
+    };
     player.rank_ids.retain(|rank_id| { rank_id != rank.id.as_str() });
 
     state.player_cache.set(&state.database, &player.name, &player, true).await;
-    Ok(Json(player))
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::RankRevoke)
+        .server(&auth_guard.server_id)
+        .target(&player.id, &player.name)
+        .before(rank.name));
+    Ok(Json(player.without_senior_notes()))
 }
 
 pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
@@ -512,10 +1273,32 @@ pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
         profile, 
         issue_punishment, 
         get_punishments,
+        export_player,
         lookup_player,
         add_player_note,
+        get_player_notes,
         delete_player_note,
+        freeze_player,
+        unfreeze_player,
+        erase_player_data,
+        prestige_player,
+        correct_player_stats,
+        aggregate_players,
+        resolve_players,
+        get_level_progress,
+        get_player_avatar,
+        get_rank_progress,
+        get_player_matches,
+        get_player_achievements,
+        get_leaderboard_positions,
+        get_leaderboard_percentiles,
+        get_weapon_stats,
+        get_head_to_head,
+        search_player,
+        get_online_players,
+        get_online_staff,
         set_active_tag,
+        set_active_tag_slot,
         add_tag_to_player,
         delete_player_tag,
         add_player_rank,
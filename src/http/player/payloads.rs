@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use rocket::{response::{self, Response, Responder}, Request, http::{Status, ContentType}, serde::json::Json};
 
-use crate::{database::models::{player::{SimplePlayer, Player}, punishment::Punishment, session::Session}, socket::leaderboard::ScoreType};
+use crate::{config::MarsConfigOptions, http::punishment::payloads::PunishmentPublicView, database::{search::SearchStrategy, corrections::StatCorrection}, database::models::{achievement::Achievement, level::LevelGamemode, r#match::{Match, MatchAbandonment}, participant::MatchContribution, player::{SimplePlayer, Player, PlayerStats}, punishment::{Punishment, NoteType, NoteVisibility}, session::Session, rank_threshold::{RankMetric, RankThreshold}}, util::time::{Timestamp, PlaytimeRounding, format_playtime}, socket::{player::player_xp_listener::XP_PER_LEVEL, participant::participant_context::PlayerMatchResult}};
 
 #[derive(Deserialize, Serialize)]
 pub struct PlayerPreLoginRequest {
@@ -23,7 +23,9 @@ pub struct PlayerPreLoginResponse {
     pub new: bool,
     pub allowed: bool,
     pub player: Player,
-    pub active_punishments: Vec<Punishment>
+    pub active_punishments: Vec<Punishment>,
+    // recently banned players with a similar name, for staff to look at. never acted on by the API
+    pub similar_banned: Vec<SimplePlayer>
 }
 
 impl<'r> Responder<'r, 'static> for PlayerPreLoginResponder {
@@ -65,17 +67,248 @@ pub struct PlayerLogoutRequest {
     pub playtime: u64
 }
 
+// `stats.serverPlaytime` and `stats.gamePlaytime` stay in milliseconds, these are derived for display
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PlayerLookupResponse {
+pub struct PlaytimeSummary {
+    pub server_playtime_seconds: u64,
+    pub game_playtime_seconds: u64,
+    pub server_playtime_formatted: String,
+    pub game_playtime_formatted: String
+}
+
+impl PlaytimeSummary {
+    pub fn new(stats: &PlayerStats, rounding: PlaytimeRounding) -> Self {
+        Self {
+            server_playtime_seconds: stats.server_playtime / 1_000,
+            game_playtime_seconds: stats.game_playtime / 1_000,
+            server_playtime_formatted: format_playtime(stats.server_playtime, rounding),
+            game_playtime_formatted: format_playtime(stats.game_playtime, rounding)
+        }
+    }
+}
+
+// `xp_for_next_level` is what the whole level takes, `xp_remaining` what is left of it.
+// both are `None` and `progress` is 1 once the player reaches the prestige cap,
+// xp still counts from there but the next step is prestiging rather than levelling
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerLevelProgressResponse {
+    pub level: u32,
+    pub prestige: u32,
+    // prestige and level together, e.g. `P2-37`, just the level before the first prestige
+    pub display: String,
+    pub xp_into_level: u32,
+    pub xp_for_next_level: Option<u32>,
+    pub xp_remaining: Option<u32>,
+    pub progress: f64,
+    pub maxed: bool
+}
+
+impl PlayerLevelProgressResponse {
+    pub fn new(player: &Player, level_cap: u32) -> Self {
+        let level = player.get_prestige_level();
+        let xp_into_level = player.get_xp_into_level();
+        let maxed = level >= level_cap;
+        Self {
+            level,
+            prestige: player.prestige,
+            display: player.get_progression_display(),
+            xp_into_level,
+            xp_for_next_level: if maxed { None } else { Some(XP_PER_LEVEL) },
+            xp_remaining: if maxed { None } else { Some(XP_PER_LEVEL - xp_into_level) },
+            progress: if maxed { 1.0 } else { xp_into_level as f64 / XP_PER_LEVEL as f64 },
+            maxed
+        }
+    }
+}
+
+// `result` is absent for matches that were abandoned or ended before results were kept
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerMatchHistoryEntry {
+    pub id: String,
+    pub map_name: String,
+    pub server_id: String,
+    pub loaded_at: Timestamp,
+    pub started_at: Option<Timestamp>,
+    pub ended_at: Option<Timestamp>,
+    pub result: Option<PlayerMatchResult>,
+    pub contribution: Option<MatchContribution>,
+    pub abandoned: Option<MatchAbandonment>
+}
+
+impl PlayerMatchHistoryEntry {
+    pub fn new(current_match: &Match, player_id: &str) -> Self {
+        Self {
+            id: current_match.id.clone(),
+            map_name: current_match.level.name.clone(),
+            server_id: current_match.server_id.clone(),
+            loaded_at: current_match.loaded_at,
+            started_at: current_match.started_at,
+            ended_at: current_match.ended_at,
+            result: current_match.results.get(player_id).cloned(),
+            contribution: current_match.get_contribution_of(player_id),
+            abandoned: current_match.abandoned.clone()
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RankThresholdProgress {
+    pub rank_id: String,
+    // `None` when the rank no longer exists
+    pub rank_name: Option<String>,
+    pub metric: RankMetric,
+    pub threshold: u64,
+    pub current: u64,
+    pub progress: f64,
+    pub granted: bool
+}
+
+impl RankThresholdProgress {
+    pub fn new(threshold: &RankThreshold, player: &Player, rank_name: Option<String>) -> Self {
+        let current = threshold.metric.of(player);
+        Self {
+            rank_id: threshold.rank_id.clone(),
+            rank_name,
+            metric: threshold.metric,
+            threshold: threshold.threshold,
+            current,
+            progress: (current as f64 / threshold.threshold as f64).min(1.0),
+            granted: player.auto_rank_ids.contains(&threshold.rank_id)
+        }
+    }
+}
+
+// `next` is the closest threshold not granted yet
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerRankProgressResponse {
+    pub next: Option<RankThresholdProgress>,
+    pub thresholds: Vec<RankThresholdProgress>
+}
+
+pub const TOP_WEAPONS_COUNT : usize = 5;
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopWeapon {
+    pub name: String,
+    pub kills: u32
+}
+
+// presence is derived from sessions when the response is built and never stored on the player
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerPresenceResponse {
+    #[serde(flatten)]
     pub player: Player,
+    pub online: bool,
+    pub last_seen: Timestamp,
+    pub playtime: PlaytimeSummary,
+    // by display name, the full maps are under `/weapons`
+    pub top_weapons: Vec<TopWeapon>,
+    // the level within the current prestige, see `PlayerLevelProgressResponse`
+    pub level: u32,
+    pub level_display: String
+}
+
+impl PlayerPresenceResponse {
+    pub fn new(player: Player, active_session: Option<&Session>, options: &MarsConfigOptions) -> Self {
+        let online = active_session.is_some();
+        let last_seen = if online { Timestamp::now() } else { player.last_joined_at };
+        let playtime = PlaytimeSummary::new(&player.stats, options.playtime_rounding);
+        let top_weapons = options.weapon_names.top(&player.stats.weapon_kills, TOP_WEAPONS_COUNT).into_iter()
+            .map(|(name, kills)| TopWeapon { name, kills })
+            .collect();
+        let level = player.get_prestige_level();
+        let level_display = player.get_progression_display();
+        Self { player, online, last_seen, playtime, top_weapons, level, level_display }
+    }
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AchievementStatus {
+    Completed,
+    InProgress,
+    Locked
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AchievementProgress {
+    pub current: u64,
+    pub target: u64
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerAchievementResponse {
+    #[serde(flatten)]
+    pub achievement: Achievement,
+    pub status: AchievementStatus,
+    pub completed_at: Option<u64>,
+    pub progress: Option<AchievementProgress>
+}
+
+impl PlayerAchievementResponse {
+    pub fn new(achievement: Achievement, player: &Player) -> Self {
+        let completed_at = player.stats.achievements.get(&achievement.id).map(|data| data.completion_time);
+        let progress = achievement.agent.progress(&player.stats).map(|(current, target)| AchievementProgress { current, target });
+        let status = match (&completed_at, &progress) {
+            (Some(_), _) => AchievementStatus::Completed,
+            (None, Some(progress)) if progress.current > 0 => AchievementStatus::InProgress,
+            _ => AchievementStatus::Locked
+        };
+        Self { achievement, status, completed_at, progress }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerSearchResponse {
+    pub player: SimplePlayer,
+    pub matched_by: SearchStrategy
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerLookupResponse {
+    pub player: PlayerPresenceResponse,
     pub alts: Vec<PlayerAltResponse>
 }
 
+// everything about one player in a single document, for support and data requests
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerExportResponse {
+    pub player: PlayerPresenceResponse,
+    pub punishments: ExportedPunishments,
+    // the most recent, up to `list.max-results`
+    pub sessions: Vec<Session>,
+    pub achievements: Vec<PlayerAchievementResponse>,
+    pub level_progress: PlayerLevelProgressResponse,
+    pub win_rates: HashMap<LevelGamemode, f64>,
+    // false for a player's own copy, which has ips and staff notes stripped
+    pub includes_ips: bool,
+    pub exported_at: Timestamp
+}
+
+// a player's own copy only carries what the ban screen shows
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ExportedPunishments {
+    Staff(Vec<Punishment>),
+    Own(Vec<PunishmentPublicView>)
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerAltResponse {
-    pub player: Player,
+    pub player: PlayerPresenceResponse,
     pub punishments: Vec<Punishment>
 }
 
@@ -83,12 +316,37 @@ pub struct PlayerAltResponse {
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PlayerProfileResponse {
-    pub player: Player,
-    pub leaderboard_positions: HashMap<ScoreType, u64>
+    pub player: PlayerPresenceResponse,
+    pub leaderboard_positions: HashMap<String, u64>,
+    // per gamemode, by what that gamemode counts as a win
+    pub win_rates: HashMap<LevelGamemode, f64>
+}
+
+// weapon stats keyed by display name, raw keys sharing a display name are summed
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerWeaponStatsResponse {
+    pub weapon_kills: HashMap<String, u32>,
+    pub weapon_deaths: HashMap<String, u32>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OnlinePlayersResponse {
+    pub count: usize,
+    pub players: Vec<SimplePlayer>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OnlineStaffResponse {
+    pub count: usize,
+    pub required: usize,
+    pub satisfied: bool
 }
 
 pub enum PlayerProfileResponder {
-    RawProfile(Player),
+    RawProfile(PlayerPresenceResponse),
     ProfileWithLeaderboardPositions(PlayerProfileResponse)
 }
 
@@ -116,7 +374,45 @@ impl <'r> Responder<'r, 'static> for PlayerProfileResponder {
 #[derive(Serialize, Deserialize)]
 pub struct PlayerAddNoteRequest {
     pub author: SimplePlayer,
-    pub content: String
+    pub content: String,
+    #[serde(default, rename = "type")]
+    pub note_type: NoteType,
+    #[serde(default)]
+    pub visibility: NoteVisibility
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerFreezeRequest {
+    pub author: SimplePlayer,
+    pub reason: String
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerResolveRequest {
+    // ids and names, mixed
+    pub players: Vec<String>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerResolveResponse {
+    // keyed by the input as it was sent
+    pub resolved: HashMap<String, SimplePlayer>,
+    pub unresolved: Vec<String>
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerAggregateRequest {
+    pub player_ids: Vec<String>
+}
+
+#[derive(Deserialize)]
+pub struct StatCorrectionRequest {
+    pub author: SimplePlayer,
+    pub reason: String,
+    pub corrections: Vec<StatCorrection>
 }
 
 #[derive(Deserialize, Serialize)]
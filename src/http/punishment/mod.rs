@@ -1,16 +1,31 @@
-use rocket::{Rocket, Build, serde::json::Json, State};
+use std::collections::BTreeMap;
 
-use crate::{database::{models::punishment::{PunishmentType, Punishment, PunishmentReversion}, Database}, MarsAPIState, util::{error::ApiErrorResponder, auth::AuthorizationToken, r#macro::unwrap_helper, time::get_u64_time_millis}};
+use mongodb::{bson::{doc, Bson}, options::FindOptions};
+use rocket::{Rocket, Build, http::Status, serde::json::Json, State};
 
-use self::payloads::PunishmentRevertRequest;
+use crate::{database::{models::{punishment::{PunishmentType, Punishment, PunishmentKind, PunishmentReversion}, shadow_punishment::{ShadowPunishment, ShadowReview}, audit_log::{AuditLogEntry, AuditAction}}, audit::record_audit, Database, InsertError}, MarsAPIState, util::{error::ApiErrorResponder, auth::AuthorizationToken, r#macro::unwrap_helper, time::Timestamp, confirmation::{Confirmable, request_confirmation, consume_confirmation}}, socket::{event_type::EventType, player::player_events::PlayerPunishmentData, server::server_context::ServerBroadcast}};
+
+use self::{payloads::{PunishmentApplyLinkedRequest, PunishmentRevertRequest, ShadowReviewRequest, PunishmentIssueRequest, PunishmentPublicView}, resolution::{PunishmentResolution, resolve_punishment}};
 
 pub mod payloads;
+pub mod resolution;
+pub mod shadow;
 
 #[get("/types")]
 fn get_pun_types(state: &State<MarsAPIState>, _auth_guard: AuthorizationToken) -> Json<&Vec<PunishmentType>> {
     Json(&state.config.data.punishment_types)
 }
 
+// nothing is written, the result is exactly what issuing the same request would produce
+#[post("/preview", format = "json", data = "<pun_issue_req>")]
+async fn preview_pun(
+    state: &State<MarsAPIState>, 
+    pun_issue_req: Json<PunishmentIssueRequest>, 
+    auth_guard: AuthorizationToken
+) -> Result<Json<PunishmentResolution>, ApiErrorResponder> {
+    Ok(Json(resolve_punishment(state, pun_issue_req.0, auth_guard.server_id).await?))
+}
+
 #[get("/<punishment_id>")]
 async fn get_pun(
     state: &State<MarsAPIState>, 
@@ -20,17 +35,34 @@ async fn get_pun(
     Ok(Json(unwrap_helper::return_default!(Database::find_by_id(&state.database.punishments, punishment_id).await, Err(ApiErrorResponder::missing_punishment()))))
 }
 
+// public, for the ban screen. punishments from before codes existed cannot be found this way
+#[get("/code/<code>")]
+async fn get_pun_by_code(state: &State<MarsAPIState>, code: &str) -> Result<Json<PunishmentPublicView>, ApiErrorResponder> {
+    let punishment = unwrap_helper::return_default!(state.database.find_punishment_by_code(code).await, Err(ApiErrorResponder::missing_punishment()));
+    let code = punishment.code.clone().unwrap_or_default();
+    Ok(Json(PunishmentPublicView::new(&punishment, code, state.config.options.punishment_appeal_url.clone())))
+}
+
 #[post("/<punishment_id>/revert", format = "json", data = "<revert_req>")]
 async fn revert_pun(
     state: &State<MarsAPIState>, 
     punishment_id: &str, 
     revert_req: Json<PunishmentRevertRequest>, 
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<Json<Punishment>, ApiErrorResponder> {
     let data = revert_req.0;
     let mut punishment = unwrap_helper::return_default!(Database::find_by_id(&state.database.punishments, punishment_id).await, Err(ApiErrorResponder::missing_punishment()));
-    punishment.reversion = Some(PunishmentReversion { reverted_at: get_u64_time_millis(), reverter: data.reverter, reason: data.reason });
+    punishment.reversion = Some(PunishmentReversion { reverted_at: Timestamp::now(), reverter: data.reverter.clone(), reason: data.reason.clone() });
     state.database.save(&punishment).await;
+    for restored in state.database.restore_superseded(&punishment).await.into_iter().filter(|restored| restored.is_active()) {
+        ServerBroadcast::send_from_api(&state.server_broadcast, &EventType::PlayerPunishment, PlayerPunishmentData { punishment: restored });
+    };
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::PunishmentRevert)
+        .actor(Some(data.reverter))
+        .server(&auth_guard.server_id)
+        .target(&punishment.target.id, &punishment.target.name)
+        .before(punishment.id.clone())
+        .reason(data.reason));
     {
         // take ownership for the spawned task
         let pun_clone = punishment.clone();
@@ -42,6 +74,178 @@ async fn revert_pun(
     Ok(Json(punishment))
 }
 
+// servers only hear of the punishments they issue, so a mute that a new punishment ended is sent to all of them
+pub async fn announce_superseded(state: &MarsAPIState, punishment: &Punishment) {
+    if punishment.supersedes.is_empty() {
+        return;
+    };
+    let ended_mutes = Database::consume_cursor_into_owning_vec_option(state.database.punishments.find(doc! {
+        "supersededBy": &punishment.id,
+        "action.kind": PunishmentKind::Mute.to_string()
+    }, None).await.ok()).await;
+    for ended in ended_mutes.into_iter() {
+        ServerBroadcast::send_from_api(&state.server_broadcast, &EventType::PlayerPunishmentEnd, PlayerPunishmentData { punishment: ended });
+    };
+}
+
+// issuing only records the linked accounts, this is the one way they get punished. it always asks for a
+// confirmation, whatever `confirmation.required` is, since it punishes accounts nobody named. each account
+// goes through the same checks as issuing to it directly and counts its own offences, accounts whose
+// punishment could not be issued are left for the next request
+#[post("/<punishment_id>/apply-linked?<confirm>", format = "json", data = "<apply_req>")]
+async fn apply_pun_to_linked(
+    state: &State<MarsAPIState>, 
+    punishment_id: &str, 
+    confirm: Option<&str>,
+    apply_req: Json<PunishmentApplyLinkedRequest>, 
+    auth_guard: AuthorizationToken
+) -> Result<Confirmable<Vec<Punishment>>, ApiErrorResponder> {
+    let mut punishment = unwrap_helper::return_default!(Database::find_by_id(&state.database.punishments, punishment_id).await, Err(ApiErrorResponder::missing_punishment()));
+    if punishment.reversion.is_some() {
+        return Err(ApiErrorResponder::validation_error_with_message("The punishment has been reverted"));
+    };
+    if punishment.linked_accounts.is_empty() {
+        return Err(ApiErrorResponder::validation_error_with_message("The punishment has no linked accounts"));
+    };
+    let pending = punishment.linked_accounts.iter().filter(|linked| linked.applied_punishment_id.is_none()).count();
+    if pending == 0 {
+        return Err(ApiErrorResponder::validation_error_with_message("The punishment has already been applied to its linked accounts"));
+    };
+    let binding = format!("apply-linked:{}", punishment.id);
+    match confirm {
+        Some(token) => consume_confirmation(state, &binding, token).await?,
+        None => {
+            let effect = BTreeMap::from([(String::from("punishments"), pending as u64)]);
+            return Ok(Confirmable::Pending(request_confirmation(state, &binding, effect).await));
+        }
+    };
+
+    let data = apply_req.0;
+    let reference = punishment.code.clone().unwrap_or_else(|| punishment.id.clone());
+    let mut applied : Vec<Punishment> = Vec::new();
+    let mut last_error : Option<ApiErrorResponder> = None;
+    for index in 0..punishment.linked_accounts.len() {
+        let linked = punishment.linked_accounts[index].clone();
+        if linked.applied_punishment_id.is_some() {
+            continue;
+        };
+        let target_ips = state.player_cache.get(&state.database, &linked.player.id).await
+            .map(|player| player.ip_addresses())
+            .unwrap_or_default();
+        let request = PunishmentIssueRequest {
+            reason: punishment.reason.clone(),
+            offence: 1,
+            action: punishment.action.clone(),
+            note: Some(format!("Linked to {} of {} ({:.0}% of their ips)", reference, punishment.target.name, linked.confidence * 100.0)),
+            punisher: Some(data.punisher.clone()),
+            target_name: linked.player.id.clone(),
+            target_ips,
+            silent: punishment.silent,
+            override_reason_rules: data.override_reason_rules
+        };
+        let resolution = match resolve_punishment(state, request, auth_guard.server_id.clone()).await {
+            Ok(resolution) => resolution,
+            Err(e) => {
+                warn!("Could not apply punishment {} to linked account {}", punishment.id, linked.player.name);
+                last_error = Some(e);
+                continue;
+            }
+        };
+        let mut linked_punishment = resolution.punishment;
+        linked_punishment.offence = resolution.escalation.previous_offences + 1;
+        // an alt's own alts are not followed
+        linked_punishment.linked_accounts = Vec::new();
+        linked_punishment.link_confidence = None;
+        match state.database.insert_punishment(&mut linked_punishment).await {
+            Ok(()) => {},
+            Err(InsertError::DuplicateKey) => {
+                warn!("Punishment for linked account {} of {} conflicts with an existing one", linked.player.name, punishment.id);
+                last_error = Some(ApiErrorResponder::create_anonymous_error(Status::Conflict, "A punishment conflicts with an existing one, apply them again"));
+                continue;
+            },
+            Err(e) => {
+                warn!("Could not insert punishment for linked account {} of {}: {}", linked.player.name, punishment.id, e);
+                last_error = Some(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not save the punishments"));
+                continue;
+            }
+        };
+        punishment.linked_accounts[index].applied_punishment_id = Some(linked_punishment.id.clone());
+        announce_superseded(state, &linked_punishment).await;
+        let mut summary = format!("{} {} for {}, linked to {}", linked_punishment.id, linked_punishment.action.kind, linked_punishment.reason.name, punishment.id);
+        if !linked_punishment.supersedes.is_empty() {
+            summary.push_str(&format!(", superseding {}", linked_punishment.supersedes.join(", ")));
+        };
+        record_audit(&state.database, AuditLogEntry::new(AuditAction::PunishmentIssue)
+            .actor(Some(data.punisher.clone()))
+            .server(&auth_guard.server_id)
+            .target(&linked_punishment.target.id, &linked_punishment.target.name)
+            .after(summary));
+        {
+            // take ownership for the spawned task
+            let pun_clone = linked_punishment.clone();
+            let state_clone = state.config.clone();
+            tokio::spawn(async move {
+                state_clone.webhooks.send_punishment_webhook(&pun_clone).await;
+            });
+        }
+        applied.push(linked_punishment);
+    };
+    if applied.is_empty() {
+        return Err(last_error.unwrap_or_else(|| ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not save the punishments")));
+    };
+    if punishment.linked_accounts.iter().all(|linked| linked.applied_punishment_id.is_some()) {
+        punishment.linked_applied_at = Some(Timestamp::now());
+    };
+    state.database.save(&punishment).await;
+    info!("Applied punishment {} to {} of {} pending linked accounts", punishment.id, applied.len(), pending);
+    Ok(Confirmable::Done(applied))
+}
+
+// the review queue of rules in shadow mode, newest first. `rule` is the full rule, e.g. `auto-mute:spam`
+#[get("/shadow?<rule>&<reviewed>")]
+async fn get_shadow_puns(
+    state: &State<MarsAPIState>,
+    rule: Option<String>,
+    reviewed: Option<bool>,
+    _auth_guard: AuthorizationToken
+) -> Json<Vec<ShadowPunishment>> {
+    let mut filter = doc! {};
+    if let Some(rule) = rule {
+        filter.insert("rule", rule);
+    };
+    match reviewed {
+        Some(true) => { filter.insert("review", doc! { "$ne": null }); },
+        Some(false) => { filter.insert("review", Bson::Null); },
+        None => {}
+    };
+    let options = FindOptions::builder()
+        .sort(doc! { "createdAt": -1 })
+        .limit(state.config.options.list_max_results as i64)
+        .build();
+    Json(Database::consume_cursor_into_owning_vec_option(state.database.shadow_punishments.find(filter, options).await.ok()).await)
+}
+
+// reviewing only records the verdict, whether the rule goes live is still up to its config
+#[post("/shadow/<shadow_id>/review", format = "json", data = "<review_req>")]
+async fn review_shadow_pun(
+    state: &State<MarsAPIState>,
+    shadow_id: &str,
+    review_req: Json<ShadowReviewRequest>,
+    auth_guard: AuthorizationToken
+) -> Result<Json<ShadowPunishment>, ApiErrorResponder> {
+    let data = review_req.0;
+    let mut shadow = unwrap_helper::return_default!(Database::find_by_id(&state.database.shadow_punishments, shadow_id).await, Err(ApiErrorResponder::missing_punishment()));
+    shadow.review = Some(ShadowReview { reviewer: data.reviewer.clone(), correct: data.correct, reviewed_at: Timestamp::now() });
+    state.database.save(&shadow).await;
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::ShadowReview)
+        .actor(Some(data.reviewer))
+        .server(&auth_guard.server_id)
+        .target(&shadow.punishment.target.id, &shadow.punishment.target.name)
+        .before(format!("{} ({})", shadow.id, shadow.rule))
+        .after(if data.correct { "correct" } else { "incorrect" }.to_owned()));
+    Ok(Json(shadow))
+}
+
 pub fn mount(rocket: Rocket<Build>) -> Rocket<Build> {
-    rocket.mount("/mc/punishments", routes![get_pun_types, get_pun, revert_pun])
+    rocket.mount("/mc/punishments", routes![get_pun_types, preview_pun, get_pun, get_pun_by_code, revert_pun, apply_pun_to_linked, get_shadow_puns, review_shadow_pun])
 }
@@ -1,6 +1,8 @@
 use serde::{Serialize, Deserialize};
 
-use crate::database::models::{punishment::{PunishmentReason, PunishmentAction}, player::SimplePlayer};
+use crate::{database::models::{punishment::{Punishment, PunishmentReason, PunishmentAction, PunishmentKind}, player::SimplePlayer}, util::{error::ApiErrorResponder, validation::Validator, time::Timestamp}};
+
+pub const MAX_PUNISHMENT_NOTE_LENGTH : usize = 256;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -14,7 +16,25 @@ pub struct PunishmentIssueRequest {
     pub punisher: Option<SimplePlayer>,
     pub target_name: String,
     pub target_ips: Vec<String>,
-    pub silent: bool
+    pub silent: bool,
+    // needs `REASON_OVERRIDE_PERMISSION`, for the rare punishment no rule fits
+    #[serde(default)]
+    pub override_reason_rules: bool
+}
+
+impl PunishmentIssueRequest {
+    pub fn validate(&self) -> Result<(), ApiErrorResponder> {
+        let mut validator = Validator::new();
+        validator.not_blank("targetName", &self.target_name);
+        if self.action.is_timed() {
+            let length = self.action.length();
+            validator.check("action.length", length == -1 || length > 0, "must be positive, or -1 for a permanent punishment");
+        };
+        if let Some(note) = &self.note {
+            validator.max_length("note", note, MAX_PUNISHMENT_NOTE_LENGTH);
+        };
+        validator.finish()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -23,3 +43,51 @@ pub struct PunishmentRevertRequest {
     pub reason: String,
     pub reverter: SimplePlayer
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PunishmentApplyLinkedRequest {
+    pub punisher: SimplePlayer,
+    // like the issue request, the punisher needs `REASON_OVERRIDE_PERMISSION`
+    #[serde(default)]
+    pub override_reason_rules: bool
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowReviewRequest {
+    pub reviewer: SimplePlayer,
+    pub correct: bool
+}
+
+// what the target is shown on the ban screen, nothing about staff, notes or ips
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PunishmentPublicView {
+    pub code: String,
+    pub kind: PunishmentKind,
+    pub reason: String,
+    pub message: String,
+    pub issued_at: Timestamp,
+    // -1 for permanent punishments
+    pub expires_at: i64,
+    pub active: bool,
+    pub reverted: bool,
+    pub appeal_url: Option<String>
+}
+
+impl PunishmentPublicView {
+    pub fn new(punishment: &Punishment, code: String, appeal_url: Option<String>) -> Self {
+        Self {
+            code,
+            kind: punishment.action.kind.clone(),
+            reason: punishment.reason.name.clone(),
+            message: punishment.reason.message().to_owned(),
+            issued_at: punishment.issued_at,
+            expires_at: punishment.expires_at(),
+            active: punishment.is_active(),
+            reverted: punishment.reversion.is_some(),
+            appeal_url
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+
+use rocket::http::Status;
+
+use crate::{database::{decode::DecodeFailure, models::{punishment::{Punishment, PunishmentAction, PunishmentConflictPolicy, PunishmentKind}, player::{Player, SimplePlayer}, rank::{Rank, PunishmentLengthLimits, PunishmentLengthViolation, PERMANENT_PUNISHMENT_PERMISSION, REASON_OVERRIDE_PERMISSION}}}, util::{error::ApiErrorResponder, time::Timestamp, validation::Validator, string::strip_color_codes}, MarsAPIState};
+
+use super::payloads::PunishmentIssueRequest;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PunishmentEscalation {
+    // unreverted punishments the target already has for the same reason
+    pub previous_offences: u32,
+    // the step of the reason's ladder for the requested offence, none for reasons that are not configured
+    pub ladder_action: Option<PunishmentAction>,
+    pub matches_ladder: bool,
+    // punishments of the target that could not be loaded and were left out of `previous_offences`,
+    // staff should check them before trusting the count
+    pub skipped: Vec<DecodeFailure>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PunishmentResolution {
+    pub punishment: Punishment,
+    pub expires_at: i64,
+    // other accounts on the target's ips, only filled for ip bans
+    pub covered_accounts: Vec<SimplePlayer>,
+    pub escalation: PunishmentEscalation
+}
+
+// everything issuing a punishment decides before it is written, previews return this as is so they
+// can never disagree with what would actually be issued
+pub async fn resolve_punishment(state: &MarsAPIState, data: PunishmentIssueRequest, server_id: String) -> Result<PunishmentResolution, ApiErrorResponder> {
+    data.validate()?;
+    let target_player = match state.player_cache.get(&state.database, &data.target_name).await {
+        Some(player) => player,
+        None => return Err(ApiErrorResponder::missing_player())
+    };
+    if let Some(punisher) = &data.punisher {
+        let issuer : Player = match state.player_cache.get(&state.database, &punisher.id).await {
+            Some(player) => player,
+            None => return Err(ApiErrorResponder::missing_player())
+        };
+        let issuer_ranks = Rank::find_for_player(&state.database, &issuer).await;
+        if data.action.is_timed() {
+            validate_punishment_length(&issuer_ranks, data.action.length())?;
+        };
+        validate_punishment_reason(state, &data, &issuer, &issuer_ranks)?;
+    };
+
+    // a punishment that doesn't load would quietly put the target on a lower step of the ladder, so the ones
+    // that were skipped are reported with the escalation instead of blocking the punishment
+    let loaded = state.database.load_player_punishments(&target_player, false).await
+        .map_err(|e| ApiErrorResponder::load_failed(&e))?;
+    let existing = loaded.documents;
+    if !loaded.failures.is_empty() {
+        warn!("{} punishments of {} could not be loaded and are not counted as offences", loaded.failures.len(), target_player.name);
+    };
+    let previous_offences = existing.iter()
+        .filter(|punishment| punishment.reason.name == data.reason.name && punishment.reversion.is_none())
+        .count() as u32;
+    let supersedes = resolve_conflicts(state.config.options.punishment_conflict_policy, &data.action.kind, &existing)?;
+    let ladder_action = state.config.data.punishment_types.iter()
+        .find(|punishment_type| punishment_type.name == data.reason.name)
+        .and_then(|punishment_type| {
+            let step = (data.offence.max(1) - 1) as usize;
+            punishment_type.actions.get(step.min(punishment_type.actions.len().saturating_sub(1))).cloned()
+        });
+    let matches_ladder = ladder_action.as_ref().map(|action| action.kind == data.action.kind && action.length() == data.action.length()).unwrap_or(true);
+
+    let covered_accounts = if data.action.kind == PunishmentKind::IpBan {
+        state.database.get_players_on_ips(&data.target_ips, &target_player.id).await.iter().map(|player| player.to_simple()).collect()
+    } else {
+        Vec::new()
+    };
+
+    let link_confidence = Some(state.config.options.punishment_alt_link_confidence).filter(|confidence| *confidence > 0.0);
+    let linked_accounts = match link_confidence {
+        Some(confidence) => state.database.get_linked_accounts(&target_player, confidence).await,
+        None => Vec::new()
+    };
+
+    let punishment = Punishment {
+        id: Uuid::new_v4().to_string(),
+        reason: data.reason,
+        issued_at: Timestamp::now(),
+        silent: data.silent,
+        offence: data.offence,
+        action: data.action,
+        note: data.note,
+        punisher: data.punisher,
+        target: target_player.to_simple(),
+        target_ips: data.target_ips,
+        reversion: None,
+        server_id: Some(server_id),
+        automated: false,
+        code: None,
+        linked_accounts,
+        link_confidence,
+        linked_applied_at: None,
+        supersedes,
+        superseded_by: None
+    };
+    Ok(PunishmentResolution {
+        expires_at: punishment.expires_at(),
+        punishment,
+        covered_accounts,
+        escalation: PunishmentEscalation { previous_offences, ladder_action, matches_ladder, skipped: loaded.failures }
+    })
+}
+
+// the ids of the active punishments a punishment of `kind` supersedes, or why it can't be issued
+fn resolve_conflicts(policy: PunishmentConflictPolicy, kind: &PunishmentKind, existing: &[Punishment]) -> Result<Vec<String>, ApiErrorResponder> {
+    let severity = match kind.severity() {
+        Some(severity) => severity,
+        None => return Ok(Vec::new())
+    };
+    let active = existing.iter()
+        .filter(|punishment| punishment.is_active())
+        .filter_map(|punishment| punishment.action.kind.severity().map(|other| (punishment, other)));
+    match policy {
+        PunishmentConflictPolicy::Independent => Ok(Vec::new()),
+        PunishmentConflictPolicy::Supersede => Ok(active.filter(|(_, other)| *other < severity).map(|(punishment, _)| punishment.id.clone()).collect()),
+        PunishmentConflictPolicy::Reject => match active.filter(|(_, other)| *other > severity).max_by_key(|(_, other)| *other) {
+            Some((greater, _)) => Err(ApiErrorResponder::punishment_conflict(
+                &format!("The player already has an active {} ({}), a {} would be redundant", greater.action.kind, greater.id, kind)
+            )),
+            None => Ok(Vec::new())
+        }
+    }
+}
+
+// punishments without a punisher come from the console and are not limited
+fn validate_punishment_length(issuer_ranks: &[Rank], length: i64) -> Result<(), ApiErrorResponder> {
+    let limits = PunishmentLengthLimits::from_ranks(issuer_ranks);
+    match limits.check(length) {
+        Ok(_) => Ok(()),
+        Err(PunishmentLengthViolation::Permanent) => Err(ApiErrorResponder::punishment_length_not_allowed(
+            &format!("Your rank cannot issue permanent punishments (requires {})", PERMANENT_PUNISHMENT_PERMISSION)
+        )),
+        Err(PunishmentLengthViolation::TooShort(min)) => Err(ApiErrorResponder::punishment_length_not_allowed(
+            &format!("The minimum punishment length for your rank is {}ms", min)
+        )),
+        Err(PunishmentLengthViolation::TooLong(max)) => Err(ApiErrorResponder::punishment_length_not_allowed(
+            &format!("The maximum punishment length for your rank is {}ms", max)
+        ))
+    }
+}
+
+// like lengths, only punishments issued by staff are checked
+fn validate_punishment_reason(state: &MarsAPIState, data: &PunishmentIssueRequest, issuer: &Player, issuer_ranks: &[Rank]) -> Result<(), ApiErrorResponder> {
+    let rules = &state.config.options.punishment_reasons;
+    if !rules.enabled {
+        return Ok(());
+    };
+    if data.override_reason_rules {
+        if !issuer_ranks.iter().any(|rank| rank.permissions.iter().any(|permission| permission == REASON_OVERRIDE_PERMISSION)) {
+            return Err(ApiErrorResponder::create_anonymous_error(
+                Status::Forbidden,
+                &format!("Your rank cannot override the reason rules (requires {})", REASON_OVERRIDE_PERMISSION)
+            ));
+        };
+        info!("{} overrode the reason rules for a {} with reason '{}'", issuer.name, data.action.kind, data.reason.message());
+        return Ok(());
+    };
+    let from_template = state.config.data.punishment_types.iter()
+        .any(|punishment_type| punishment_type.name == data.reason.name && punishment_type.message == data.reason.message());
+    if from_template {
+        return Ok(());
+    };
+    if rules.template_required.contains(&data.action.kind) {
+        return Err(ApiErrorResponder::validation_error_with_message(
+            &format!("A {} has to use one of the configured punishment reasons", data.action.kind)
+        ));
+    };
+    let message = strip_color_codes(data.reason.message());
+    let message = message.trim();
+    let normalized = message.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+    Validator::new()
+        .check("reason.message", message.chars().count() >= rules.min_length, &format!("must be at least {} characters", rules.min_length))
+        .check("reason.message", message.is_empty() || message.chars().any(|c| c.is_alphanumeric()), "must contain letters or numbers")
+        .check("reason.message", normalized.is_empty() || !rules.blocklist.contains(&normalized), "is too vague to explain the punishment, describe what happened")
+        .finish()
+}
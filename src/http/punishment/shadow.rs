@@ -0,0 +1,31 @@
+use mongodb::bson::doc;
+use uuid::Uuid;
+
+use crate::{MarsAPIState, database::{InsertError, models::{punishment::Punishment, shadow_punishment::ShadowPunishment}}, util::time::{get_u64_time_millis, Timestamp}};
+
+// stands in for issuing when `rule` is in shadow mode. a target the rule already matched within `window`
+// and that nobody reviewed yet is not queued again, like a live rule leaving an existing punishment alone
+pub async fn record_shadow_punishment(state: &MarsAPIState, rule: &str, punishment: Punishment, window: u64) {
+    let now = get_u64_time_millis();
+    let queued = state.database.shadow_punishments.find_one(doc! {
+        "rule": rule,
+        "punishment.target.id": &punishment.target.id,
+        "review": null,
+        "createdAt": { "$gte": now.saturating_sub(window) as i64 }
+    }, None).await;
+    if let Ok(Some(_)) = queued {
+        return;
+    };
+    let mut shadow = ShadowPunishment { id: Uuid::new_v4().to_string(), rule: rule.to_owned(), punishment, created_at: Timestamp::from_millis(now), review: None };
+    let mut inserted = state.database.insert_one(&shadow).await;
+    // the id is random, a taken one only needs another
+    if let Err(InsertError::DuplicateKey) = inserted {
+        shadow.id = Uuid::new_v4().to_string();
+        inserted = state.database.insert_one(&shadow).await;
+    };
+    if let Err(e) = inserted {
+        warn!("Could not record shadow punishment for {} ({}): {}", shadow.punishment.target.name, rule, e);
+        return;
+    };
+    info!("[shadow] {} would have issued a {} to {}", rule, shadow.punishment.action.kind, shadow.punishment.target.name);
+}
@@ -1,11 +1,13 @@
+use std::collections::BTreeMap;
+
 use futures::future::join_all;
 use mongodb::bson::doc;
-use rocket::{Rocket, Build, State, serde::json::Json};
+use rocket::{Rocket, Build, State, http::Status, serde::json::Json};
 use uuid::Uuid;
 
-use crate::{MarsAPIState, http::rank::payload::RankCreateRequest, database::{models::{rank::Rank, player::Player}, Database}, util::{error::ApiErrorResponder, time::get_u64_time_millis, auth::AuthorizationToken, r#macro::unwrap_helper}};
+use crate::{MarsAPIState, http::rank::payload::RankCreateRequest, database::{models::{rank::Rank, player::Player, audit_log::{AuditLogEntry, AuditAction}}, audit::record_audit, usage::RankUsage, grants::{PlayerSelector, PlayerGrant, BulkGrantResponse}, Database}, util::{error::ApiErrorResponder, time::Timestamp, auth::{AuthorizationToken, AdminAuthorizationToken}, r#macro::unwrap_helper, confirmation::{Confirmable, request_confirmation, consume_confirmation}, responder::ListResponder}};
 
-use self::payload::RankUpdateRequest;
+use self::payload::{RankUpdateRequest, RankReassignResponse};
 
 mod payload;
 
@@ -13,9 +15,10 @@ mod payload;
 async fn create_rank(
     state: &State<MarsAPIState>, 
     create_req: Json<RankCreateRequest>,
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<Json<Rank>, ApiErrorResponder> {
     let data = create_req.0;
+    payload::validate_rank_fields(&data.name, &data.display_name, &data.prefix, data.min_punishment_length, data.max_punishment_length)?;
     let conflict = state.database.find_by_name::<Rank>(&data.name).await;
     if let Some(_) = conflict {
         return Err(ApiErrorResponder::rank_confict());
@@ -34,28 +37,134 @@ async fn create_rank(
         permissions: perms, 
         staff: data.staff, 
         apply_on_join: data.apply_on_join, 
-        created_at: get_u64_time_millis() as f64 
+        min_punishment_length: data.min_punishment_length,
+        max_punishment_length: data.max_punishment_length,
+        created_at: Timestamp::now() 
     };
 
     state.database.save(&rank).await;
+    state.rank_cache.store(&rank).await;
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::RankCreate)
+        .server(&auth_guard.server_id)
+        .target(&rank.id, &rank.name)
+        .after(rank.audit_summary()));
 
     Ok(Json(rank))
 }
 
 #[get("/")]
-async fn get_ranks(state: &State<MarsAPIState>) -> Json<Vec<Rank>> {
-    Json(state.database.get_all_documents::<Rank>().await)
+async fn get_ranks(state: &State<MarsAPIState>) -> Result<ListResponder<Json<Vec<Rank>>>, ApiErrorResponder> {
+    state.database.load_all_documents::<Rank>(state.config.options.strict_decoding).await
+        .map(ListResponder::loaded)
+        .map_err(|e| ApiErrorResponder::load_failed(&e))
+}
+
+#[get("/usage")]
+async fn get_rank_usage(state: &State<MarsAPIState>, _auth_guard: AdminAuthorizationToken) -> Result<Json<Vec<RankUsage>>, ApiErrorResponder> {
+    match state.database.rank_usage_counts().await {
+        Ok(usage) => Ok(Json(usage)),
+        Err(e) => {
+            warn!("Could not count rank usage: {}", e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not count rank usage"))
+        }
+    }
+}
+
+// the players are read before the update so their cached copies can be dropped afterwards. players who
+// earned the rank through a rank threshold keep it unless `force` is set
+#[post("/<rank_id>/reassign?<to>&<force>&<confirm>")]
+async fn reassign_rank(
+    state: &State<MarsAPIState>,
+    rank_id: &str,
+    to: Option<&str>,
+    force: bool,
+    confirm: Option<&str>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Confirmable<RankReassignResponse>, ApiErrorResponder> {
+    if let Some(to) = to {
+        if to == rank_id {
+            return Err(ApiErrorResponder::validation_error_with_message("Cannot reassign a rank to itself"));
+        };
+        if Database::find_by_id(&state.database.ranks, to).await.is_none() {
+            return Err(ApiErrorResponder::missing_rank());
+        };
+    };
+    let (affected_players, protected_players) : (Vec<Player>, Vec<Player>) = state.database.get_players_by_rank(rank_id).await.into_iter()
+        .partition(|player| force || !player.auto_rank_ids.iter().any(|auto_rank_id| auto_rank_id == rank_id));
+    if state.config.options.confirmation_required {
+        let binding = format!("rank-reassign:{}:{}:{}", rank_id, to.unwrap_or("none"), force);
+        match confirm {
+            Some(token) => consume_confirmation(state, &binding, token).await?,
+            None => {
+                let effect = BTreeMap::from([
+                    (String::from("players"), affected_players.len() as u64),
+                    (String::from("protected"), protected_players.len() as u64)
+                ]);
+                return Ok(Confirmable::Pending(request_confirmation(state, &binding, effect).await));
+            }
+        };
+    };
+    let modified = match state.database.reassign_rank(rank_id, to, force).await {
+        Ok(modified) => modified,
+        Err(e) => {
+            warn!("Could not reassign rank {}: {}", rank_id, e);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not reassign rank"));
+        }
+    };
+    join_all(affected_players.iter().map(|player| state.player_cache.evict(&player.name))).await;
+    if modified > 0 {
+        record_audit(&state.database, AuditLogEntry::new(AuditAction::RankReassign)
+            .target(rank_id, rank_id)
+            .after(format!("moved {} players to {}", modified, to.unwrap_or("no rank"))));
+    };
+    info!("Reassigned rank '{}' to {:?} for {} players", rank_id, to, modified);
+    Ok(Confirmable::Done(RankReassignResponse { modified }))
+}
+
+// grants or revokes `rank_id` for every selected player at once, players already in the target state are skipped.
+// players who earned the rank through a rank threshold keep it on a revoke without `force`
+#[post("/<rank_id>/<action>?<force>", format = "json", data = "<selector>")]
+async fn bulk_rank_grant(
+    state: &State<MarsAPIState>,
+    rank_id: &str,
+    action: &str,
+    force: bool,
+    selector: Json<PlayerSelector>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Json<BulkGrantResponse>, ApiErrorResponder> {
+    let revoke = match action {
+        "grant" => false,
+        "revoke" => true,
+        _ => return Err(ApiErrorResponder::validation_error_with_message("Expected grant or revoke"))
+    };
+    let rank = unwrap_helper::return_default!(state.rank_cache.get(&state.database, rank_id).await, Err(ApiErrorResponder::missing_rank()));
+    let result = match state.database.bulk_grant(PlayerGrant::Rank, &rank.id, &selector, revoke, force).await {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Could not {} rank {} in bulk: {}", action, rank.id, e);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not apply bulk rank grant"));
+        }
+    };
+    join_all(result.player_names.iter().map(|name| state.player_cache.evict(name))).await;
+    if result.modified > 0 {
+        record_audit(&state.database, AuditLogEntry::new(if revoke { AuditAction::RankRevoke } else { AuditAction::RankGrant })
+            .target(&rank.id, &rank.name)
+            .after(format!("bulk {} for {} players", action, result.modified)));
+    };
+    info!("Bulk {} of rank '{}' modified {} players", action, rank.id, result.modified);
+    Ok(Json(result))
 }
 
 #[get("/<rank_id>")]
 async fn get_rank_by_id(state: &State<MarsAPIState>, rank_id: &str) -> Result<Json<Rank>, ApiErrorResponder> {
-    let rank = unwrap_helper::return_default!(Database::find_by_id(&state.database.ranks, rank_id).await, Err(ApiErrorResponder::missing_rank()));
+    let rank = unwrap_helper::return_default!(state.rank_cache.get(&state.database, rank_id).await, Err(ApiErrorResponder::missing_rank()));
     Ok(Json(rank))
 }
 
 
 #[delete("/<rank_id>")]
-async fn delete_rank(state: &State<MarsAPIState>, rank_id: &str, _auth_guard: AuthorizationToken) -> Result<(), ApiErrorResponder> {
+async fn delete_rank(state: &State<MarsAPIState>, rank_id: &str, auth_guard: AuthorizationToken) -> Result<(), ApiErrorResponder> {
+    let existing_rank = Database::find_by_id(&state.database.ranks, rank_id).await;
     let delete_count = match state.database.delete_by_id::<Rank>(rank_id).await {
         Some(delete_result) => delete_result.deleted_count,
         None => 0
@@ -63,6 +172,7 @@ async fn delete_rank(state: &State<MarsAPIState>, rank_id: &str, _auth_guard: Au
     if delete_count == 0 {
         return Err(ApiErrorResponder::missing_rank());
     };
+    state.rank_cache.evict(rank_id).await;
 
     // we love loading every player into memory
     let mut players_with_rank = Database::consume_cursor_into_owning_vec_option(state.database.players.find(doc! {"rankIds": rank_id}, None).await.ok()).await;
@@ -81,6 +191,12 @@ async fn delete_rank(state: &State<MarsAPIState>, rank_id: &str, _auth_guard: Au
     }
     join_all(cache_updates).await;
 
+    let mut entry = AuditLogEntry::new(AuditAction::RankDelete).server(&auth_guard.server_id);
+    entry = match existing_rank {
+        Some(rank) => entry.target(&rank.id, &rank.name).before(rank.audit_summary()),
+        None => entry.target(rank_id, rank_id)
+    };
+    record_audit(&state.database, entry);
     info!("Rank '{}' was deleted. Affected players: {}", rank_id, formatted_player_names);
     Ok(())
 }
@@ -90,15 +206,17 @@ async fn update_rank(
     state: &State<MarsAPIState>, 
     rank_update_req: Json<RankUpdateRequest>, 
     rank_id: &str, 
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<Json<Rank>, ApiErrorResponder> {
     let data = rank_update_req.0;
+    payload::validate_rank_fields(&data.name, &data.display_name, &data.prefix, data.min_punishment_length, data.max_punishment_length)?;
     let existing_rank = unwrap_helper::return_default!(Database::find_by_id(&state.database.ranks, rank_id).await, Err(ApiErrorResponder::missing_rank()));
     let conflict_rank = state.database.ranks.find_one(doc! {"_id": {"$ne": &existing_rank.id}, "nameLower": data.name.to_lowercase()}, None).await.ok().unwrap_or(None);
     if conflict_rank.is_some() {
         return Err(ApiErrorResponder::rank_confict());
     };
 
+    let before = existing_rank.audit_summary();
     let rank_lower_name = data.name.to_lowercase();
     let mut perms = data.permissions;
     perms.dedup();
@@ -112,13 +230,21 @@ async fn update_rank(
         permissions: perms, 
         staff: data.staff, 
         apply_on_join: data.apply_on_join, 
+        min_punishment_length: data.min_punishment_length,
+        max_punishment_length: data.max_punishment_length,
         created_at: existing_rank.created_at
     };
 
     state.database.save(&updated_rank).await;
+    state.rank_cache.store(&updated_rank).await;
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::RankUpdate)
+        .server(&auth_guard.server_id)
+        .target(&updated_rank.id, &updated_rank.name)
+        .before(before)
+        .after(updated_rank.audit_summary()));
     Ok(Json(updated_rank))
 }
 
 pub fn mount(rocket: Rocket<Build>) -> Rocket<Build>  {
-    rocket.mount("/mc/ranks", routes![create_rank, get_ranks, get_rank_by_id, delete_rank, update_rank])
+    rocket.mount("/mc/ranks", routes![create_rank, get_ranks, get_rank_by_id, get_rank_usage, reassign_rank, bulk_rank_grant, delete_rank, update_rank])
 }
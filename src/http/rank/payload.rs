@@ -1,5 +1,30 @@
 use serde::{Serialize, Deserialize};
 
+use crate::util::{error::ApiErrorResponder, validation::Validator};
+
+pub const MAX_RANK_NAME_LENGTH : usize = 32;
+
+pub fn validate_rank_fields(name: &str, display_name: &Option<String>, prefix: &Option<String>, min_punishment_length: Option<i64>, max_punishment_length: Option<i64>) -> Result<(), ApiErrorResponder> {
+    let mut validator = Validator::new();
+    validator.not_blank("name", name).max_length("name", name, MAX_RANK_NAME_LENGTH);
+    if let Some(display_name) = display_name {
+        validator.color_codes("displayName", display_name);
+    };
+    if let Some(prefix) = prefix {
+        validator.color_codes("prefix", prefix);
+    };
+    if let Some(min) = min_punishment_length {
+        validator.check("minPunishmentLength", min >= 0, "must not be negative");
+    };
+    if let Some(max) = max_punishment_length {
+        validator.check("maxPunishmentLength", max == -1 || max > 0, "must be positive, or -1 for no cap");
+    };
+    if let (Some(min), Some(max)) = (min_punishment_length, max_punishment_length) {
+        validator.check("minPunishmentLength", max == -1 || min <= max, "must not be greater than maxPunishmentLength");
+    };
+    validator.finish()
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RankCreateRequest {
@@ -12,7 +37,11 @@ pub struct RankCreateRequest {
     #[serde(default)]
     pub staff: bool,
     #[serde(default)]
-    pub apply_on_join: bool
+    pub apply_on_join: bool,
+    #[serde(default)]
+    pub min_punishment_length: Option<i64>,
+    #[serde(default)]
+    pub max_punishment_length: Option<i64>
 }
 
 #[derive(Serialize, Deserialize)]
@@ -26,5 +55,15 @@ pub struct RankUpdateRequest {
     pub prefix: Option<String>,
     pub permissions: Vec<String>,
     pub staff: bool,
-    pub apply_on_join: bool
+    pub apply_on_join: bool,
+    #[serde(default)]
+    pub min_punishment_length: Option<i64>,
+    #[serde(default)]
+    pub max_punishment_length: Option<i64>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RankReassignResponse {
+    pub modified: u64
 }
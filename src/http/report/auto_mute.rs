@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+
+use mongodb::bson::doc;
+
+use crate::{MarsAPIState, database::{Database, InsertError, audit::record_audit, models::{audit_log::{AuditLogEntry, AuditAction}, player::{Player, SimplePlayer}, punishment::{Punishment, PunishmentAction, PunishmentKind, PunishmentReason}}}, util::time::{get_u64_time_millis, Timestamp}, http::punishment::shadow::record_shadow_punishment, socket::{event_type::EventType, player::player_events::PlayerPunishmentData, server::server_context::ServerBroadcast}};
+
+pub fn report_category(reason: &str) -> String {
+    reason.trim().to_lowercase()
+}
+
+// mutes the target once enough distinct reporters agree on a category within the window. the
+// mute is flagged as automated and reverts like any other punishment, a target already muted is left alone.
+// the offence counts earlier unreverted punishments for the same category like staff issued ones do
+// a shadowed category only queues the mute for review
+pub async fn check_auto_mute(state: &MarsAPIState, target: &SimplePlayer, reason: &str, server_id: &str) -> Option<Punishment> {
+    let options = &state.config.options.auto_mute;
+    if !options.enabled {
+        return None;
+    };
+    let category = report_category(reason);
+    let threshold = *options.thresholds.get(&category)?;
+    let now = get_u64_time_millis();
+
+    let reports = Database::consume_cursor_into_owning_vec_option(state.database.reports.find(doc! {
+        "target.id": &target.id,
+        "open": true,
+        "updatedAt": { "$gte": now.saturating_sub(options.window) as i64 }
+    }, None).await.ok()).await;
+    let reporters : HashSet<String> = reports.into_iter()
+        .filter(|report| report.reasons.iter().any(|reason| report_category(reason) == category))
+        .map(|report| report.reporter.id)
+        .collect();
+    if (reporters.len() as u32) < threshold {
+        return None;
+    };
+
+    let target_player : Player = state.player_cache.get(&state.database, &target.id).await?;
+    let existing = state.database.load_player_punishments(&target_player, false).await.ok()?.documents;
+    if existing.iter().any(|punishment| punishment.action.kind == PunishmentKind::Mute && punishment.is_active()) {
+        return None;
+    };
+    let reason = PunishmentReason::new(&format!("Reported for {}", category), "You have been muted while staff review reports against you", &category);
+    let offence = existing.iter()
+        .filter(|punishment| punishment.reason.name == reason.name && punishment.reversion.is_none())
+        .count() as u32 + 1;
+    // numbered by the automatic mutes the target ever had, so two reports racing past the check
+    // build the same id and only one of the inserts gets in
+    let previous_automatic = existing.iter().filter(|punishment| punishment.automated).count();
+    let mut punishment = Punishment {
+        id: format!("auto-mute:{}:{}", target_player.id, previous_automatic + 1),
+        reason,
+        issued_at: Timestamp::from_millis(now),
+        silent: false,
+        offence,
+        action: PunishmentAction::new(PunishmentKind::Mute, options.length),
+        note: Some(format!("Automatic mute after {} players reported '{}' within {}ms", reporters.len(), category, options.window)),
+        punisher: None,
+        target: target_player.to_simple(),
+        target_ips: target_player.ip_addresses(),
+        reversion: None,
+        server_id: Some(server_id.to_owned()),
+        automated: true,
+        code: None,
+        linked_accounts: Vec::new(),
+        link_confidence: None,
+        linked_applied_at: None,
+        supersedes: Vec::new(),
+        superseded_by: None
+    };
+    if options.is_shadowed(&category) {
+        record_shadow_punishment(state, &format!("auto-mute:{}", category), punishment, options.window).await;
+        return None;
+    };
+    match state.database.insert_punishment(&mut punishment).await {
+        Ok(()) => {},
+        Err(InsertError::DuplicateKey) => return None,
+        Err(e) => {
+            warn!("Could not insert automatic mute for {}: {}", target.name, e);
+            return None;
+        }
+    };
+    info!("Automatically muted {} after {} reports for '{}'", target.name, reporters.len(), category);
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::PunishmentIssue)
+        .server(server_id)
+        .target(&punishment.target.id, &punishment.target.name)
+        .after(format!("{} {} for {}, length {}ms", punishment.id, punishment.action.kind, punishment.reason.name, punishment.action.length())));
+    {
+        // take ownership for the spawned task
+        let pun_clone = punishment.clone();
+        let state_clone = state.config.clone();
+        tokio::spawn(async move {
+            state_clone.webhooks.send_punishment_webhook(&pun_clone).await;
+        });
+    }
+    // no server issued it, so every server is told and the one the target is on applies it
+    ServerBroadcast::send_from_api(&state.server_broadcast, &EventType::PlayerPunishment, PlayerPunishmentData { punishment: punishment.clone() });
+    Some(punishment)
+}
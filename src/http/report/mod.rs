@@ -1,10 +1,15 @@
 mod payload;
+mod auto_mute;
 
-use rocket::{serde::json::Json, State, Build, Rocket};
+use std::collections::HashMap;
 
-use crate::{util::{auth::AuthorizationToken, error::ApiErrorResponder}, MarsAPIState};
+use mongodb::bson::doc;
+use rocket::{serde::json::Json, State, Build, Rocket, http::Status};
+use uuid::Uuid;
 
-use self::payload::ReportCreateRequest;
+use crate::{util::{auth::AuthorizationToken, error::ApiErrorResponder, responder::JsonResponder, time::{get_u64_time_millis, Timestamp}, r#macro::unwrap_helper}, MarsAPIState, database::{Database, InsertError, audit::record_audit, models::{report::Report, audit_log::{AuditLogEntry, AuditAction}}}};
+
+use self::{payload::{ReportCreateRequest, ReportCreateResponse, ReportTargetSummary}, auto_mute::check_auto_mute};
 
 
 #[post("/", format = "json", data = "<report>")]
@@ -12,8 +17,56 @@ pub async fn new_report(
     state: &State<MarsAPIState>,
     report: Json<ReportCreateRequest>,
     auth_guard: AuthorizationToken,
-) -> Result<(), ApiErrorResponder> {
+) -> Result<JsonResponder<ReportCreateResponse>, ApiErrorResponder> {
     let data = report.0;
+    let options = &state.config.options;
+
+    // fails open if redis is unavailable, reports are more important than the limit
+    let rate_limit_key = format!("report_rate_limit:{}", data.reporter.id);
+    if let Ok(submitted) = state.redis.increment_with_expiry(&rate_limit_key, options.report_rate_limit_window).await {
+        if submitted > options.report_rate_limit {
+            return Err(ApiErrorResponder::report_rate_limited());
+        };
+    };
+
+    let now = get_u64_time_millis();
+    let existing = state.database.reports.find_one(doc! {
+        "reporter.id": &data.reporter.id,
+        "target.id": &data.target.id,
+        "open": true,
+        "updatedAt": { "$gte": now.saturating_sub(options.report_cooldown) as i64 }
+    }, None).await.unwrap_or(None);
+
+    if let Some(mut existing) = existing {
+        existing.count += 1;
+        existing.reasons.push(data.reason);
+        existing.updated_at = Timestamp::from_millis(now);
+        state.database.save(&existing).await;
+        let mute = check_auto_mute(state, &existing.target, existing.reasons.last().unwrap_or(&String::new()), &auth_guard.server_id).await;
+        return Ok(JsonResponder::ok(ReportCreateResponse { report: existing, updated: true, mute }));
+    };
+
+    let mut report = Report {
+        id: Uuid::new_v4().to_string(),
+        target: data.target.clone(),
+        reporter: data.reporter.clone(),
+        reasons: vec![data.reason.clone()],
+        count: 1,
+        server_id: auth_guard.server_id.clone(),
+        created_at: Timestamp::from_millis(now),
+        updated_at: Timestamp::from_millis(now),
+        open: true
+    };
+    let mut inserted = state.database.insert_one(&report).await;
+    // the id is random, a taken one only needs another
+    if let Err(InsertError::DuplicateKey) = inserted {
+        report.id = Uuid::new_v4().to_string();
+        inserted = state.database.insert_one(&report).await;
+    };
+    if let Err(e) = inserted {
+        warn!("Could not insert report {}: {}", report.id, e);
+        return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not save the report"));
+    };
     state.config.webhooks.send_report_webhook(
         &auth_guard.server_id,
         &data.reporter, 
@@ -21,9 +74,60 @@ pub async fn new_report(
         &data.reason, 
         &data.online_staff
     ).await;
-    Ok(())
+    let mute = check_auto_mute(state, &report.target, &data.reason, &auth_guard.server_id).await;
+    Ok(JsonResponder::created(ReportCreateResponse { report, updated: false, mute }))
+}
+
+// open reports grouped by target, most reported first
+#[get("/")]
+pub async fn get_open_reports(
+    state: &State<MarsAPIState>,
+    _auth_guard: AuthorizationToken
+) -> Json<Vec<ReportTargetSummary>> {
+    let reports = Database::consume_cursor_into_owning_vec_option(
+        state.database.reports.find(doc! { "open": true }, None).await.ok()
+    ).await;
+    let mut summaries : HashMap<String, ReportTargetSummary> = HashMap::new();
+    let mut reporters : HashMap<String, Vec<String>> = HashMap::new();
+    for report in reports.into_iter() {
+        let summary = summaries.entry(report.target.id.clone()).or_insert_with(|| ReportTargetSummary { 
+            target: report.target.clone(), 
+            report_count: 0, 
+            reporter_count: 0, 
+            last_reported_at: Timestamp::default(), 
+            report_ids: Vec::new() 
+        });
+        summary.report_count += report.count;
+        summary.last_reported_at = summary.last_reported_at.max(report.updated_at);
+        summary.report_ids.push(report.id.clone());
+
+        let target_reporters = reporters.entry(report.target.id.clone()).or_default();
+        if !target_reporters.contains(&report.reporter.id) {
+            target_reporters.push(report.reporter.id.clone());
+            summary.reporter_count += 1;
+        };
+    };
+    let mut summaries : Vec<ReportTargetSummary> = summaries.into_values().collect();
+    summaries.sort_by_key(|summary| std::cmp::Reverse(summary.report_count));
+    Json(summaries)
+}
+
+#[post("/<report_id>/close")]
+pub async fn close_report(
+    state: &State<MarsAPIState>,
+    report_id: &str,
+    auth_guard: AuthorizationToken
+) -> Result<JsonResponder<Report>, ApiErrorResponder> {
+    let mut report = unwrap_helper::return_default!(Database::find_by_id(&state.database.reports, report_id).await, Err(ApiErrorResponder::report_missing()));
+    report.open = false;
+    state.database.save(&report).await;
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::ReportClose)
+        .server(&auth_guard.server_id)
+        .target(&report.target.id, &report.target.name)
+        .before(format!("{} from {}: {}", report.id, report.reporter.name, report.reasons.join(", "))));
+    Ok(JsonResponder::ok(report))
 }
 
 pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
-    rocket_build.mount("/mc/reports", routes![new_report])
+    rocket_build.mount("/mc/reports", routes![new_report, get_open_reports, close_report])
 }
@@ -1,6 +1,6 @@
 use serde::{Serialize, Deserialize};
 
-use crate::database::models::player::SimplePlayer;
+use crate::{database::models::{player::SimplePlayer, report::Report, punishment::Punishment}, util::time::Timestamp};
 
 #[derive(Serialize, Deserialize)]
 pub struct ReportCreateRequest {
@@ -10,3 +10,23 @@ pub struct ReportCreateRequest {
     #[serde(rename = "onlineStaff")]
     pub online_staff: Vec<SimplePlayer>,
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportCreateResponse {
+    pub report: Report,
+    // true when an existing open report was bumped instead of a new one being created
+    pub updated: bool,
+    // the automatic mute this report tipped the target into, for the reporting server to apply
+    pub mute: Option<Punishment>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportTargetSummary {
+    pub target: SimplePlayer,
+    pub report_count: u32,
+    pub reporter_count: u32,
+    pub last_reported_at: Timestamp,
+    pub report_ids: Vec<String>
+}
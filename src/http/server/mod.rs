@@ -1,8 +1,7 @@
-use futures::future::join_all;
 use mongodb::bson::doc;
 use rocket::{Rocket, Build, State, http::Status, serde::json::Json};
 
-use crate::{MarsAPIState, util::{auth::AuthorizationToken, error::ApiErrorResponder, time::get_u64_time_millis, r#macro::unwrap_helper, responder::JsonResponder}, database::{models::{r#match::Match, session::Session, player::Player, server::ServerEvents}, Database}, http::server::payloads::{ServerStatusResponse, XPMultiplierRequest}};
+use crate::{MarsAPIState, util::{auth::AuthorizationToken, error::ApiErrorResponder, time::get_u64_time_millis, r#macro::unwrap_helper, responder::JsonResponder, tracking::StatCategory}, database::models::{r#match::{Match, AbandonReason}, server::ServerEvents}, socket::{r#match::abandonment::abandon_match, server::sweeper::close_hanging_sessions}, http::server::payloads::{ServerStatusResponse, XPMultiplierRequest}};
 
 pub mod payloads;
 
@@ -29,47 +28,15 @@ async fn server_startup(
         Ok(())
     );
     let current_match = state.redis.get_unchecked::<Match>(&format!("match:{}", last_match_id)).await;
-    if current_match.is_some() {
-        let mut current_match = current_match.unwrap();
-        current_match.ended_at = Some(last_alive_time.unwrap());
-        state.match_cache.set_with_expiry(&state.database, &current_match.id, &current_match, true, Some(3600000)).await;
+    if let Some(mut current_match) = current_match {
+        abandon_match(state, &mut current_match, AbandonReason::ServerRestart, last_alive_time.unwrap()).await;
     };
 
-    let mut hanging_sessions = Database::consume_cursor_into_owning_vec_option(state.database.sessions.find(doc! {
-        "serverId": server_id,
-        "endedAt": null
-    }, None).await.ok()).await;
-    let mut sessions_to_write : Vec<Session> = Vec::new();
-    let mut players_to_write : Vec<Player> = Vec::new();
-
-    for hanging_session in hanging_sessions.iter_mut() {
-        hanging_session.ended_at = Some(last_alive_time.unwrap());
-        sessions_to_write.push(hanging_session.to_owned());
-
-        let mut cached_player = unwrap_helper::continue_default!(state.player_cache.get(&state.database, &hanging_session.player.name).await);
-        cached_player.stats.server_playtime += hanging_session.length().unwrap_or(0);
-        players_to_write.push(cached_player);
-    }
-
-    // unfortunately rust's mongo driver doesn't support bulk writes yet so that's sad
-    { 
-        let player_tasks : Vec<_> = players_to_write.iter().map(|player| {
-            state.database.players.replace_one(doc! {
-                "_id": &player.id
-            }, player, None)
-        }).collect();
-        join_all(player_tasks).await;
-        let session_tasks : Vec<_> = sessions_to_write.iter().map(|session| {
-            state.database.sessions.replace_one(doc! {
-                "_id": &session.id
-            }, session, None)
-        }).collect();
-        join_all(session_tasks).await;
-    }
+    let (players_written, sessions_written) = close_hanging_sessions(state, server_id, last_alive_time.unwrap()).await;
 
     state.redis.set(&format!("server:{}:last_alive_time", server_id), &get_u64_time_millis()).await;
 
-    info!("Saved {} players, {} sessions on startup '{}'", players_to_write.len(), sessions_to_write.len(), server_id);
+    info!("Saved {} players, {} sessions on startup '{}'", players_written, sessions_written, server_id);
     Ok(())
 }
 
@@ -92,7 +59,8 @@ async fn server_status(
         state.redis.get_unchecked::<Match>(&format!("match:{}", current_match_id)).await, 
         Err(ApiErrorResponder::create_anonymous_error(Status::NotFound, "No current match"))
     );
-    let tracking_stats = current_match.is_tracking_stats();
+    // the full breakdown is in `trackedStats` on the match
+    let tracking_stats = current_match.is_tracking(StatCategory::Kills);
     Ok(JsonResponder::created(ServerStatusResponse { last_alive_time, current_match, stats_tracking: tracking_stats }))
 }
 
@@ -1,18 +1,43 @@
-use rocket::{Rocket, Build};
+use rocket::{Rocket, Build, State};
 use rocket::serde::{Serialize, json::Json};
 use rocket::http::Status;
 
+use crate::{MarsAPIState, util::auth::AdminAuthorizationToken};
+
 #[derive(Serialize)]
 #[serde(crate = "rocket::serde")]
 pub struct StatusResponse {
     status: &'static str
 }
 
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde", rename_all = "camelCase")]
+pub struct CacheStatusResponse {
+    available: bool,
+    unavailable_since: Option<u64>,
+    // redis operations that failed fast while it was down
+    skipped_operations: u64,
+    pending_increments: usize,
+    dropped_increments: u64
+}
+
 #[get("/")]
 pub fn status() -> Json<StatusResponse> {
    Json(StatusResponse { status: Status::Ok.reason().unwrap_or("OK") }) 
 }
 
+#[get("/cache")]
+pub fn cache_status(state: &State<MarsAPIState>, _auth_guard: AdminAuthorizationToken) -> Json<CacheStatusResponse> {
+    let write_buffer = state.leaderboards.write_buffer.as_ref();
+    Json(CacheStatusResponse {
+        available: state.redis.is_available(),
+        unavailable_since: state.redis.unavailable_since(),
+        skipped_operations: state.redis.skipped_operations(),
+        pending_increments: write_buffer.map(|buffer| buffer.pending_entries()).unwrap_or(0),
+        dropped_increments: write_buffer.map(|buffer| buffer.dropped()).unwrap_or(0)
+    })
+}
+
 pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
-    rocket_build.mount("/status", routes![status])
+    rocket_build.mount("/status", routes![status, cache_status])
 }
@@ -1,9 +1,10 @@
+use futures::future::join_all;
 use log::info;
 use mongodb::{results::DeleteResult, bson::doc};
 use rocket::{State, Rocket, Build, http::Status, serde::json::Json};
 use uuid::Uuid;
 
-use crate::{util::{auth::AuthorizationToken, responder::JsonResponder, error::{ApiErrorResponder}, time::get_u64_time_millis, r#macro::unwrap_helper}, MarsAPIState, database::{models::tag::Tag, Database}};
+use crate::{util::{auth::{AuthorizationToken, AdminAuthorizationToken}, responder::{JsonResponder, ListResponder}, error::{ApiErrorResponder}, time::Timestamp, r#macro::unwrap_helper}, MarsAPIState, database::{models::{tag::Tag, audit_log::{AuditLogEntry, AuditAction}}, audit::record_audit, usage::TagUsage, grants::{PlayerSelector, PlayerGrant, BulkGrantResponse}, Database}};
 
 use self::payload::TagCreateRequest;
 
@@ -13,30 +14,84 @@ mod payload;
 async fn create_tag(
     state: &State<MarsAPIState>,
     tag_create_req: Json<TagCreateRequest>,
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<Tag>, ApiErrorResponder> {
+    tag_create_req.validate()?;
     match state.database.find_by_id_or_name::<Tag>(&tag_create_req.name).await {
         Some(_tag) => return Err(ApiErrorResponder::tag_conflict()),
         None => {},
     };
 
-    let TagCreateRequest { name, display } = tag_create_req.0;
+    let TagCreateRequest { name, display, slots, min_level } = tag_create_req.0;
 
     let tag = Tag {
         id: Uuid::new_v4().to_string(),
         name: name.clone(),
         name_lower: name.clone().to_lowercase(),
         display: display.clone(),
-        created_at: get_u64_time_millis() as f64,
+        created_at: Timestamp::now(),
+        slots,
+        min_level
     };
 
     state.database.save::<Tag>(&tag).await;
+    state.tag_cache.store(&tag).await;
+    record_audit(&state.database, AuditLogEntry::new(AuditAction::TagCreate)
+        .server(&auth_guard.server_id)
+        .target(&tag.id, &tag.name)
+        .after(tag.audit_summary()));
     return Ok(JsonResponder::from(tag, Status::Ok));
 }
 
 #[get("/")]
-async fn get_tags(state: &State<MarsAPIState>) -> Json<Vec<Tag>> {
-    Json(state.database.get_all_documents::<Tag>().await)
+async fn get_tags(state: &State<MarsAPIState>) -> Result<ListResponder<Json<Vec<Tag>>>, ApiErrorResponder> {
+    state.database.load_all_documents::<Tag>(state.config.options.strict_decoding).await
+        .map(ListResponder::loaded)
+        .map_err(|e| ApiErrorResponder::load_failed(&e))
+}
+
+#[get("/usage")]
+async fn get_tag_usage(
+    state: &State<MarsAPIState>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<JsonResponder<Vec<TagUsage>>, ApiErrorResponder> {
+    match state.database.tag_usage_counts().await {
+        Ok(usage) => Ok(JsonResponder::ok(usage)),
+        Err(e) => {
+            warn!("Could not count tag usage: {}", e);
+            Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not count tag usage"))
+        }
+    }
+}
+
+// grants or revokes `tag_id` for every selected player at once, players already in the target state are skipped
+#[post("/<tag_id>/<action>", format = "json", data = "<selector>")]
+async fn bulk_tag_grant(
+    state: &State<MarsAPIState>,
+    tag_id: &str,
+    action: &str,
+    selector: Json<PlayerSelector>,
+    _auth_guard: AdminAuthorizationToken
+) -> Result<Json<BulkGrantResponse>, ApiErrorResponder> {
+    let revoke = match action {
+        "grant" => false,
+        "revoke" => true,
+        _ => return Err(ApiErrorResponder::validation_error_with_message("Expected grant or revoke"))
+    };
+    let tag = unwrap_helper::return_default!(state.tag_cache.get(&state.database, tag_id).await, Err(ApiErrorResponder::tag_missing()));
+    let result = match state.database.bulk_grant(PlayerGrant::Tag, &tag.id, &selector, revoke, false).await {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Could not {} tag {} in bulk: {}", action, tag.id, e);
+            return Err(ApiErrorResponder::create_anonymous_error(Status::InternalServerError, "Could not apply bulk tag grant"));
+        }
+    };
+    join_all(result.player_names.iter().map(|name| state.player_cache.evict(name))).await;
+    record_audit(&state.database, AuditLogEntry::new(if revoke { AuditAction::TagRevoke } else { AuditAction::TagGrant })
+        .target(&tag.id, &tag.name)
+        .after(format!("bulk {} for {} players", action, result.modified)));
+    info!("Bulk {} of tag '{}' modified {} players", action, tag.id, result.modified);
+    Ok(Json(result))
 }
 
 #[get("/<tag_id>")]
@@ -46,7 +101,7 @@ async fn get_tag_by_id(
 ) -> Result<JsonResponder<Tag>, ApiErrorResponder> {
     Ok(JsonResponder::ok(
         unwrap_helper::return_default!(
-            state.database.find_by_id_or_name(tag_id).await,
+            state.tag_cache.get(&state.database, tag_id).await,
             Err(ApiErrorResponder::tag_missing())
         )
     ))
@@ -77,14 +132,16 @@ async fn get_tag_by_id(
 async fn delete_tag(
     state: &State<MarsAPIState>,
     tag_id: &str,
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<(), ApiErrorResponder> {
+    let existing_tag = Database::find_by_id(&state.database.tags, tag_id).await;
     match state.database.delete_by_id::<Tag>(tag_id).await {
         Some(DeleteResult { deleted_count: 0, .. }) | None => {
             return Err(ApiErrorResponder::tag_missing());
         },
         _ => {}
     };
+    state.tag_cache.evict(tag_id).await;
     let mut players_with_tag = Database::consume_cursor_into_owning_vec_option(
         state.database.players.find(doc! {"tagIds": tag_id}, None).await.ok()
     ).await;
@@ -93,11 +150,15 @@ async fn delete_tag(
             Some(tag_idx) => { player.tag_ids.swap_remove(tag_idx); },
             None => {},
         };
-        if player.active_tag_id.is_some() && player.active_tag_id.as_ref().unwrap() == &tag_id.to_string() {
-            player.active_tag_id = Option::None;
-        }
+        player.clear_active_tag(&tag_id.to_string());
         state.player_cache.set(&state.database, &player.name, &player, true).await;
     };
+    let mut entry = AuditLogEntry::new(AuditAction::TagDelete).server(&auth_guard.server_id);
+    entry = match existing_tag {
+        Some(tag) => entry.target(&tag.id, &tag.name).before(tag.audit_summary()),
+        None => entry.target(tag_id, tag_id)
+    };
+    record_audit(&state.database, entry);
     info!(
         "Tag {} was deleted. Affected players: {}", 
         tag_id, 
@@ -114,8 +175,9 @@ async fn update_tag(
     state: &State<MarsAPIState>,
     tag_update_req: Json<TagCreateRequest>,
     tag_id: &str,
-    _auth_guard: AuthorizationToken
+    auth_guard: AuthorizationToken
 ) -> Result<JsonResponder<Tag>, ApiErrorResponder> {
+    tag_update_req.validate()?;
     match state.database.find_by_id_or_name::<Tag>(tag_id).await {
         Some(tag) => {
             let updated_tag = Tag {
@@ -123,7 +185,9 @@ async fn update_tag(
                 name: tag_update_req.name.clone(),
                 name_lower: tag_update_req.name.clone().to_lowercase(),
                 display: tag_update_req.display.clone(),
-                created_at: tag.created_at
+                created_at: tag.created_at,
+                slots: tag_update_req.slots.clone(),
+                min_level: tag_update_req.min_level
             };
             if let Ok(bson_tag) = mongodb::bson::to_bson(&updated_tag) {
                 if let Some(tag_document) = bson_tag.as_document() {
@@ -134,6 +198,12 @@ async fn update_tag(
                     ).await;
                 }
             };
+            state.tag_cache.store(&updated_tag).await;
+            record_audit(&state.database, AuditLogEntry::new(AuditAction::TagUpdate)
+                .server(&auth_guard.server_id)
+                .target(&updated_tag.id, &updated_tag.name)
+                .before(tag.audit_summary())
+                .after(updated_tag.audit_summary()));
             Ok(JsonResponder::ok(updated_tag))
         }
         None => {
@@ -147,6 +217,8 @@ pub fn mount(rocket_build: Rocket<Build>) -> Rocket<Build> {
         create_tag,
         get_tags,
         get_tag_by_id,
+        get_tag_usage,
+        bulk_tag_grant,
         delete_tag,
         update_tag
     ])
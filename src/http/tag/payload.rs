@@ -1,7 +1,30 @@
 use serde::{Deserialize, Serialize};
 
+use crate::{database::models::tag::{TagSlot, default_tag_slots}, util::{error::ApiErrorResponder, validation::Validator}};
+
+pub const MAX_TAG_NAME_LENGTH : usize = 32;
+pub const MAX_TAG_DISPLAY_LENGTH : usize = 64;
+
 #[derive(Serialize, Deserialize)]
 pub struct TagCreateRequest {
     pub name: String,
-    pub display: String
-}
\ No newline at end of file
+    pub display: String,
+    #[serde(default = "default_tag_slots")]
+    pub slots: Vec<TagSlot>,
+    #[serde(rename = "minLevel", default)]
+    pub min_level: Option<u32>
+}
+
+impl TagCreateRequest {
+    pub fn validate(&self) -> Result<(), ApiErrorResponder> {
+        Validator::new()
+            .not_blank("name", &self.name)
+            .max_length("name", &self.name, MAX_TAG_NAME_LENGTH)
+            .not_blank("display", &self.display)
+            .max_length("display", &self.display, MAX_TAG_DISPLAY_LENGTH)
+            .color_codes("display", &self.display)
+            .check("slots", !self.slots.is_empty(), "must contain at least one slot")
+            .check("minLevel", self.min_level != Some(0), "must be at least 1")
+            .finish()
+    }
+}
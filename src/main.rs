@@ -1,14 +1,17 @@
 #[macro_use] extern crate rocket;
 
-use std::{marker::PhantomData, sync::Arc, env, net::{Ipv4Addr, IpAddr}};
+use std::{marker::PhantomData, sync::Arc, env, net::{Ipv4Addr, IpAddr}, time::Duration, collections::HashMap};
 
 use anyhow::anyhow;
+use futures::future::join_all;
 use config::{deserialize_mars_config, MarsConfig};
-use database::{Database, cache::{Cache, get_redis_pool, RedisAdapter}, models::{player::Player, r#match::Match}};
+use database::{Database, cache::{Cache, get_redis_pool, RedisAdapter}, models::{player::Player, r#match::Match, rank::Rank, session::Session, tag::Tag, achievement::Achievement, damage_event::DamageEvent}, damage_events::spawn_damage_event_writer, death_writer::DeathWriter};
 use rocket::{Build, Rocket, Shutdown, Config, figment::Figment};
 use socket::leaderboard::MarsLeaderboards;
 
-use crate::socket::socket_handler::{SocketState, setup_socket};
+use crate::socket::{socket_handler::{SocketState, setup_socket}, server::{server_context::ServerBroadcast, sweeper}};
+use tokio::sync::{broadcast, mpsc::Sender, Semaphore};
+use util::{body_limit::{limits, payload_too_large}, compression::CompressionFairing, metrics::Metrics, time::{get_u64_time_millis, PlaytimeSecondsMigration}};
 
 mod util;
 mod config;
@@ -43,7 +46,47 @@ pub struct MarsAPIState {
     pub redis: Arc<RedisAdapter>,
     pub player_cache: Arc<Cache<Player>>,
     pub match_cache: Arc<Cache<Match>>,
+    pub rank_cache: Arc<Cache<Rank>>,
+    pub tag_cache: Arc<Cache<Tag>>,
+    pub achievement_cache: Arc<Cache<Achievement>>,
+    // boards of the default realm
     pub leaderboards: Arc<MarsLeaderboards>,
+    pub realm_leaderboards: HashMap<String, Arc<MarsLeaderboards>>,
+    pub server_broadcast: broadcast::Sender<ServerBroadcast>,
+    // only set when damage events are persisted
+    pub damage_events: Option<Sender<DamageEvent>>,
+    pub death_writer: DeathWriter,
+    // bounds the player saves of every match ending at once so they cannot take the whole pool
+    pub match_end_writes: Arc<Semaphore>,
+    pub metrics: Arc<Metrics>,
+}
+
+impl MarsAPIState {
+    // a realm without boards of its own, which is never configured, falls back to the default boards
+    pub fn leaderboards_for(&self, realm: &Option<String>) -> &Arc<MarsLeaderboards> {
+        match realm {
+            Some(realm) => self.realm_leaderboards.get(realm).unwrap_or(&self.leaderboards),
+            None => &self.leaderboards
+        }
+    }
+
+    pub fn every_realm(&self) -> Vec<&Arc<MarsLeaderboards>> {
+        std::iter::once(&self.leaderboards).chain(self.realm_leaderboards.values()).collect()
+    }
+
+    // servers with open sessions that are not stale, what counts as online everywhere
+    pub async fn live_server_ids(&self) -> Vec<String> {
+        let now = get_u64_time_millis();
+        let stale_after = self.config.options.session_stale_after;
+        let mut live_server_ids : Vec<String> = Vec::new();
+        for server_id in self.database.get_servers_with_active_sessions().await {
+            let last_alive_time = self.redis.get_unchecked::<u64>(&format!("server:{}:last_alive_time", server_id)).await;
+            if !Session::is_server_stale(last_alive_time, now, stale_after) {
+                live_server_ids.push(server_id);
+            };
+        };
+        live_server_ids
+    }
 }
 
 fn rocket(state: MarsAPIState) -> Rocket<Build> {
@@ -61,7 +104,13 @@ fn rocket(state: MarsAPIState) -> Rocket<Build> {
         &http::leaderboard::mount,
         &http::report::mount,
         &http::r#match::mount,
-        &http::achievements::mount
+        &http::achievements::mount,
+        &http::export::mount,
+        &http::damage::mount,
+        &http::cache::mount,
+        &http::audit::mount,
+        &http::meta::mount,
+        &http::metrics::mount
     ];
     let is_debug = env::var("MARS_DEBUG").unwrap_or("false".to_owned()).parse::<bool>().unwrap_or(false);
     let http_port = env::var("MARS_HTTP_PORT").unwrap_or("8000".to_owned()).parse::<u32>().unwrap_or(8000);
@@ -70,8 +119,16 @@ fn rocket(state: MarsAPIState) -> Rocket<Build> {
     )
         .merge::<(&str, IpAddr)>(("address", Ipv4Addr::new(0, 0, 0, 0).into()))
         .merge(("port", http_port))
+        .merge(("limits", limits(&state.config.options)))
         .extract().unwrap();
-    let mut rocket_build = rocket::custom(config).manage(state);
+    let compression = if state.config.options.compression_enabled { Some(CompressionFairing {
+        threshold: state.config.options.compression_threshold,
+        preferred: state.config.options.compression_algorithm
+    }) } else { None };
+    let mut rocket_build = rocket::custom(config).manage(state).register("/", catchers![payload_too_large]);
+    if let Some(compression) = compression {
+        rocket_build = rocket_build.attach(compression);
+    };
 
     rocket_build = mounts.iter().fold(rocket_build, |mut build, mount_fn| {
         build = (mount_fn)(build);
@@ -126,14 +183,40 @@ async fn main() -> Result<(), String> {
         Err(e) => return Err(format!("Logger Setup Error: {}", e)),
     }
 
+    let metrics = Arc::new(Metrics::default());
+
     // setup db pool
-    let database = Arc::new(match database::connect(&mars_config.options.mongo_url, Some(2), Some(8)).await {
+    let database = Arc::new(match database::connect(&mars_config.options.mongo_url, Some(2), Some(mars_config.options.mongo_max_pool_size), &mars_config.options.write_concerns, Arc::clone(&metrics)).await {
         Ok(db) => db,
         Err(db_err) => return Err(format!("Mongo Error: {}", db_err))
     });
+    database.migrate_active_tag_slots().await;
+    database.migrate_name_history().await;
+    database.migrate_match_participant_ids().await;
+    if let Err(e) = database.ensure_punishment_code_index().await {
+        warn!("Could not create the punishment code index, codes are not checked for collisions: {}", e);
+    };
+    if let Err(e) = database.ensure_head_to_head_indexes().await {
+        warn!("Could not create the head-to-head death indexes, lookups will scan the deaths collection: {}", e);
+    };
+    if let Err(e) = database.ensure_match_history_indexes().await {
+        warn!("Could not create the match history index, player match lookups will scan the matches collection: {}", e);
+    };
+    if let Err(e) = database.ensure_session_rotation_indexes().await {
+        warn!("Could not create the session rotation indexes: {}", e);
+    };
+    if let Err(e) = database.ensure_recent_ban_index().await {
+        warn!("Could not create the recent ban index, name similarity checks will scan the punishments collection: {}", e);
+    };
+    if let Err(e) = database.ensure_player_name_indexes().await {
+        warn!("Could not create the player name indexes, name lookups will scan the players collection: {}", e);
+    };
+    if let Err(e) = database.ensure_player_ip_index().await {
+        warn!("Could not create the player ip index, alt lookups will scan the players collection: {}", e);
+    };
 
     // setup redis pool
-    let redis_adapter = Arc::new(match get_redis_pool(&mars_config.options.redis_host).await {
+    let redis_adapter = Arc::new(match get_redis_pool(&mars_config.options.redis_host, Arc::clone(&metrics)).await {
         Ok(adapter) => adapter,
         Err(redis_error) => return Err(format!("Redis Error: {}", redis_error))
     });
@@ -146,6 +229,69 @@ async fn main() -> Result<(), String> {
         resource_type: PhantomData
     });
 
+    if !database.has_migrated("player-ip-entries").await {
+        match database.migrate_player_ips(mars_config.options.player_ip_limit).await {
+            Ok(migrated) => {
+                if migrated > 0 {
+                    info!("Migrated ips for {} players", migrated);
+                    if let Err(e) = player_cache.evict_all().await {
+                        warn!("Could not evict cached players after migrating ips: {}", e);
+                    };
+                };
+                database.mark_migrated("player-ip-entries").await;
+            },
+            Err(e) => warn!("Could not migrate player ips: {}", e)
+        };
+    };
+    // cached players need no eviction here, the health points their json carries still read as the same totals
+    if !database.has_migrated("damage-fixed-point").await {
+        match database.migrate_damage_fixed_point().await {
+            Ok(migrated) => {
+                if migrated > 0 {
+                    info!("Converted damage totals of {} players to fixed point", migrated);
+                };
+                database.mark_migrated("damage-fixed-point").await;
+            },
+            Err(e) => warn!("Could not convert damage totals to fixed point: {}", e)
+        };
+    };
+    // players with doubles or negative playtimes could not be read at all, the cache is cleared so no copy keeps an old total
+    if !database.has_migrated("playtime-units").await {
+        match database.migrate_playtime_units().await {
+            Ok(migrated) => {
+                if migrated > 0 {
+                    info!("Normalized playtime totals for {} players", migrated);
+                    if let Err(e) = player_cache.evict_all().await {
+                        warn!("Could not evict cached players after migrating playtime units: {}", e);
+                    };
+                };
+                database.mark_migrated("playtime-units").await;
+            },
+            Err(e) => warn!("Could not migrate playtime units: {}", e)
+        };
+    };
+    match mars_config.options.playtime_seconds_migration {
+        PlaytimeSecondsMigration::Off => {},
+        PlaytimeSecondsMigration::DryRun => match database.migrate_playtime_seconds(true).await {
+            Ok(found) => info!("Dry run: {} players have playtimes that would be scaled up from seconds", found),
+            Err(e) => warn!("Could not check playtimes for seconds: {}", e)
+        },
+        PlaytimeSecondsMigration::Apply => if !database.has_migrated("playtime-seconds").await {
+            match database.migrate_playtime_seconds(false).await {
+                Ok(migrated) => {
+                    info!("Scaled up playtimes from seconds for {} players", migrated);
+                    if migrated > 0 {
+                        if let Err(e) = player_cache.evict_all().await {
+                            warn!("Could not evict cached players after scaling up playtimes: {}", e);
+                        };
+                    };
+                    database.mark_migrated("playtime-seconds").await;
+                },
+                Err(e) => warn!("Could not scale up playtimes from seconds: {}", e)
+            };
+        }
+    };
+
     // redis match cache
     let match_cache = Arc::new(Cache {
         redis: Arc::clone(&redis_adapter),
@@ -154,8 +300,172 @@ async fn main() -> Result<(), String> {
         resource_type: PhantomData
     });
 
+    // redis caches for documents the panel reads on every load
+    let rank_cache = Arc::new(Cache {
+        redis: Arc::clone(&redis_adapter),
+        resource_name: String::from("rank"),
+        lifetime_ms: 3_600_000,
+        resource_type: PhantomData
+    });
+    let tag_cache = Arc::new(Cache {
+        redis: Arc::clone(&redis_adapter),
+        resource_name: String::from("tag"),
+        lifetime_ms: 3_600_000,
+        resource_type: PhantomData
+    });
+    let achievement_cache = Arc::new(Cache {
+        redis: Arc::clone(&redis_adapter),
+        resource_name: String::from("achievement"),
+        lifetime_ms: 3_600_000,
+        resource_type: PhantomData
+    });
+
+    if mars_config.options.cache_warm_on_startup {
+        let (ranks, tags, achievements) = tokio::join!(
+            rank_cache.warm(&database),
+            tag_cache.warm(&database),
+            achievement_cache.warm(&database)
+        );
+        info!("Warmed cache with {} ranks, {} tags and {} achievements", ranks, tags, achievements);
+    };
+
     // leaderboards
-    let leaderboards = Arc::new(MarsLeaderboards::new(Arc::clone(&redis_adapter), Arc::clone(&database)));
+    let leaderboard_flush_interval = mars_config.options.leaderboard_flush_interval;
+    let build_leaderboards = |realm: Option<String>| Arc::new(MarsLeaderboards::new(
+        Arc::clone(&redis_adapter),
+        Arc::clone(&database),
+        if leaderboard_flush_interval > 0 { Some(mars_config.options.leaderboard_max_pending) } else { None },
+        mars_config.options.leaderboard_periods.clone(),
+        &mars_config.data.dynamic_score_types,
+        &mars_config.options.leaderboard_decay,
+        realm
+    ));
+    let leaderboards = build_leaderboards(None);
+    let realm_leaderboards : HashMap<String, Arc<MarsLeaderboards>> = mars_config.realm_tokens.keys()
+        .map(|realm| (realm.clone(), build_leaderboards(Some(realm.clone()))))
+        .collect();
+    if !realm_leaderboards.is_empty() {
+        info!("Keeping separate leaderboards for realms {}", realm_leaderboards.keys().cloned().collect::<Vec<String>>().join(", "));
+    };
+    let every_realm : Vec<Arc<MarsLeaderboards>> = std::iter::once(Arc::clone(&leaderboards)).chain(realm_leaderboards.values().cloned()).collect();
+    for leaderboards in every_realm.iter() {
+        if leaderboard_flush_interval > 0 {
+            let leaderboards = Arc::clone(leaderboards);
+            tokio::spawn(async move {
+                let mut flush = tokio::time::interval(Duration::from_millis(leaderboard_flush_interval));
+                loop {
+                    flush.tick().await;
+                    leaderboards.flush_pending().await;
+                }
+            });
+        };
+
+        // stored trending scores grow exponentially until they are rebased onto the current time
+        if mars_config.options.leaderboard_decay.is_enabled() {
+            let leaderboards = Arc::clone(leaderboards);
+            let rebase_interval = mars_config.options.leaderboard_decay.interval;
+            tokio::spawn(async move {
+                let mut rebase = tokio::time::interval(Duration::from_millis(rebase_interval));
+                loop {
+                    rebase.tick().await;
+                    leaderboards.rebase_trending().await;
+                }
+            });
+        };
+    };
+
+    let leaderboard_inactive_after = mars_config.options.leaderboard_inactive_after;
+    let leaderboard_reconcile_interval = mars_config.options.leaderboard_reconcile_interval;
+
+    // playtime entries left at the old u32 cap by earlier rebuilds, in the background since it reads players one by one.
+    // each realm's boards are repaired once, a realm whose boards could not be read is tried again on the next start
+    {
+        let every_realm = every_realm.clone();
+        let database = Arc::clone(&database);
+        tokio::spawn(async move {
+            for leaderboards in every_realm.iter() {
+                let marker = format!("repair-capped-scores:{}", leaderboards.realm.as_deref().unwrap_or("default"));
+                if database.has_migrated(&marker).await {
+                    continue;
+                };
+                match leaderboards.repair_capped_scores().await {
+                    Ok(repaired) => {
+                        if repaired > 0 {
+                            info!("Restored {} capped playtime leaderboard entries from player stats", repaired);
+                        };
+                        database.mark_migrated(&marker).await;
+                    },
+                    Err(e) => warn!("Could not repair capped playtime leaderboard entries: {}", e)
+                };
+            };
+        });
+    };
+
+    // inactive players drop off the all-time boards, each sweep only looks at who crossed the cutoff since the last one
+    if leaderboard_inactive_after > 0 {
+        let every_realm = every_realm.clone();
+        let database = Arc::clone(&database);
+        let sweep_interval = mars_config.options.leaderboard_inactive_sweep_interval;
+        tokio::spawn(async move {
+            let mut sweep = tokio::time::interval(Duration::from_millis(sweep_interval));
+            let mut last_cutoff = 0u64;
+            loop {
+                sweep.tick().await;
+                let cutoff = get_u64_time_millis().saturating_sub(leaderboard_inactive_after);
+                let inactive = database.get_players_last_joined_between(last_cutoff, cutoff).await;
+                join_all(every_realm.iter().map(|leaderboards| leaderboards.evict_players(&inactive))).await;
+                if !inactive.is_empty() {
+                    info!("Removed {} inactive players from the all-time leaderboards", inactive.len());
+                };
+                last_cutoff = cutoff;
+            }
+        });
+    };
+
+    // small random samples of the all-time boards are checked against player stats, catching drift long before a rebuild would
+    if leaderboard_reconcile_interval > 0 {
+        let every_realm = every_realm.clone();
+        let database = Arc::clone(&database);
+        let sample_size = mars_config.options.leaderboard_reconcile_sample_size;
+        let min_playtime = mars_config.options.leaderboard_min_playtime;
+        tokio::spawn(async move {
+            let mut reconcile = tokio::time::interval(Duration::from_millis(leaderboard_reconcile_interval));
+            // the first tick completes right away, there's nothing to compare that early
+            reconcile.tick().await;
+            loop {
+                reconcile.tick().await;
+                for leaderboards in every_realm.iter() {
+                    match leaderboards.reconcile_sample(&database, sample_size, min_playtime, leaderboard_inactive_after).await {
+                        Ok(run) if run.mismatches > 0 => warn!(
+                            "Corrected {} leaderboard entries on {} boards after sampling {} players ({})",
+                            run.mismatches,
+                            run.boards.len(),
+                            run.players_checked,
+                            run.boards.iter().map(|(board, drift)| format!("{} {:+}", board, drift.net_drift)).collect::<Vec<String>>().join(", ")
+                        ),
+                        Ok(_) => {},
+                        Err(e) => warn!("Could not reconcile the leaderboards: {}", e)
+                    };
+                };
+            }
+        });
+    };
+
+    // raw damage events, opt-in
+    let damage_events = if mars_config.options.damage_events_enabled {
+        if let Err(e) = database.ensure_damage_event_collection(mars_config.options.damage_events_max_bytes).await {
+            return Err(format!("Mongo Error: {}", e));
+        };
+        Some(spawn_damage_event_writer(Arc::clone(&database)))
+    } else {
+        None
+    };
+
+    let match_end_writes = Arc::new(Semaphore::new(mars_config.options.get_match_end_write_concurrency()));
+    let death_writer = DeathWriter::spawn(Arc::clone(&database), mars_config.options.death_flush_interval, mars_config.options.death_max_buffer);
+
+    // events fanned out to every connected server
+    let (server_broadcast, _) = broadcast::channel::<ServerBroadcast>(64);
 
     // immutable state for rocket to manage
     let state = MarsAPIState { 
@@ -164,7 +474,46 @@ async fn main() -> Result<(), String> {
         redis: Arc::clone(&redis_adapter), 
         player_cache, 
         match_cache,
-        leaderboards
+        rank_cache,
+        tag_cache,
+        achievement_cache,
+        leaderboards,
+        realm_leaderboards,
+        server_broadcast,
+        damage_events,
+        death_writer,
+        match_end_writes,
+        metrics
+    };
+
+    // closes sessions and matches left behind by servers that went away without saying so
+    let sweep_interval = mars_config.options.abandonment.sweep_interval;
+    if sweep_interval > 0 {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut sweep = tokio::time::interval(Duration::from_millis(sweep_interval));
+            loop {
+                sweep.tick().await;
+                sweeper::sweep(&state).await;
+            }
+        });
+    };
+
+    let rotation = mars_config.options.session_rotation.clone();
+    if rotation.retention > 0 && rotation.interval > 0 {
+        let database = Arc::clone(&database);
+        tokio::spawn(async move {
+            let mut rotate = tokio::time::interval(Duration::from_millis(rotation.interval));
+            loop {
+                rotate.tick().await;
+                let ended_before = get_u64_time_millis().saturating_sub(rotation.retention);
+                match database.rotate_sessions(ended_before, rotation.batch_size, rotation.archive).await {
+                    Ok(run) if run.rotated > 0 => info!("Rotated {} sessions in {} batches ({})", run.rotated, run.batches, if run.archived { "archived" } else { "deleted" }),
+                    Ok(_) => {},
+                    Err(e) => warn!("Could not rotate sessions: {}", e)
+                };
+            }
+        });
     };
 
     let ws_port = env::var("MARS_WS_PORT").unwrap_or("7000".to_owned()).parse::<u32>().unwrap_or(7000);
@@ -181,5 +530,15 @@ async fn main() -> Result<(), String> {
         warn!("{}", e);
     };
 
+    let deaths = state.death_writer.flush_all().await;
+    if deaths > 0 {
+        info!("Flushed {} buffered deaths", deaths);
+    };
+
+    let flushed : usize = join_all(state.every_realm().into_iter().map(|leaderboards| leaderboards.flush_pending())).await.into_iter().sum();
+    if flushed > 0 {
+        info!("Flushed {} pending leaderboard increments", flushed);
+    };
+
     Ok(())
 }
@@ -0,0 +1,77 @@
+use strum_macros::{Display, EnumString};
+
+// servers may send `t`, the time in ms an event happened at, next to `e` and `d`. their clocks are not
+// trusted further than `events.clock-skew-tolerance` from the API's, past that the event is either
+// clamped to the edge of the tolerance or dropped. phase events are always clamped, a dropped match end or
+// party leave would leave the match in the wrong state. events without `t` happen at the time they arrive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ClockSkewPolicy {
+    Clamp,
+    Reject
+}
+
+#[derive(Debug, Clone)]
+pub struct ClockSkewOptions {
+    pub tolerance: u64,
+    pub policy: ClockSkewPolicy
+}
+
+impl Default for ClockSkewOptions {
+    fn default() -> Self {
+        ClockSkewOptions { tolerance: 5_000, policy: ClockSkewPolicy::Clamp }
+    }
+}
+
+pub enum EventTime {
+    Accepted(u64),
+    // the time sent and the time it was moved to
+    Clamped(u64, u64),
+    Rejected(u64)
+}
+
+impl ClockSkewOptions {
+    pub fn validate(&self, sent_at: Option<u64>, now: u64, phase_event: bool) -> EventTime {
+        let sent_at = match sent_at {
+            Some(sent_at) => sent_at,
+            None => return EventTime::Accepted(now)
+        };
+        let (earliest, latest) = (now.saturating_sub(self.tolerance), now.saturating_add(self.tolerance));
+        if (earliest..=latest).contains(&sent_at) {
+            return EventTime::Accepted(sent_at);
+        };
+        match self.policy {
+            ClockSkewPolicy::Reject if !phase_event => EventTime::Rejected(sent_at),
+            _ => EventTime::Clamped(sent_at, sent_at.clamp(earliest, latest))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClockSkewOptions, ClockSkewPolicy, EventTime};
+
+    const NOW : u64 = 1_700_000_000_000;
+
+    fn options(policy: ClockSkewPolicy) -> ClockSkewOptions {
+        ClockSkewOptions { tolerance: 5_000, policy }
+    }
+
+    #[test]
+    fn far_off_times_are_clamped_to_the_tolerance() {
+        let clamp = options(ClockSkewPolicy::Clamp);
+        assert!(matches!(clamp.validate(Some(u64::MAX), NOW, false), EventTime::Clamped(u64::MAX, at) if at == NOW + 5_000));
+        assert!(matches!(clamp.validate(Some(0), NOW, false), EventTime::Clamped(0, at) if at == NOW - 5_000));
+        assert!(matches!(clamp.validate(Some(NOW - 4_000), NOW, false), EventTime::Accepted(at) if at == NOW - 4_000));
+        assert!(matches!(clamp.validate(None, NOW, false), EventTime::Accepted(NOW)));
+    }
+
+    #[test]
+    fn reject_drops_far_off_events_but_clamps_phase_events() {
+        let reject = options(ClockSkewPolicy::Reject);
+        assert!(matches!(reject.validate(Some(u64::MAX), NOW, false), EventTime::Rejected(u64::MAX)));
+        assert!(matches!(reject.validate(Some(0), NOW, false), EventTime::Rejected(0)));
+        assert!(matches!(reject.validate(Some(u64::MAX), NOW, true), EventTime::Clamped(u64::MAX, at) if at == NOW + 5_000));
+        assert!(matches!(reject.validate(Some(0), NOW, true), EventTime::Clamped(0, at) if at == NOW - 5_000));
+    }
+}
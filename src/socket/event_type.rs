@@ -11,6 +11,7 @@ pub enum EventType {
     MatchStart,
     MatchEnd,
     PlayerDeath,
+    PlayerDamage,
     Killstreak,
     PartyJoin,
     PartyLeave,
@@ -36,5 +37,18 @@ pub enum EventType {
     ForceMatchEnd,
     Message,
     DisconnectPlayer,
-    PlayerUpdate
+    PlayerUpdate,
+    AchievementUnlock,
+    PlayerPrestige,
+    PlayerRankGrant,
+    PlayerMilestone,
+    PlayerPunishment,
+    PlayerPunishmentEnd
+}
+
+impl EventType {
+    // events that move a match or a party along, dropping one leaves the match in the wrong state
+    pub fn is_phase_event(&self) -> bool {
+        matches!(self, EventType::MatchLoad | EventType::MatchStart | EventType::MatchEnd | EventType::PartyJoin | EventType::PartyLeave)
+    }
 }
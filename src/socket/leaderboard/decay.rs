@@ -0,0 +1,201 @@
+use redis::{aio::Connection, Script};
+
+use crate::util::time::get_u64_time_millis;
+
+use super::{Leaderboard, LeaderboardEntry};
+
+// trending boards weigh every increment by how recent it is. with a half-life h, an increment d made at
+// time t is worth d * 2^(-(now - t) / h) at `now`, so a score is the sum of those over everything the
+// player did. rewriting every entry as time passes would be far too many writes, so entries are stored
+// relative to an epoch e instead: an increment at t is added as d * 2^((t - e) / h), and the real score
+// is the stored one times 2^(-(now - e) / h). that factor is the same for every entry, ordering never
+// needs it and only the scores handed out are multiplied by it. stored scores grow exponentially the
+// further t gets from e, so every `leaderboards.decay.interval` the whole board is multiplied by the
+// factor, which makes stored and real scores equal again, and e moves to now. entries that have decayed
+// below one half, which would show as 0, are dropped at that point
+#[derive(Debug, Clone)]
+pub struct DecayOptions {
+    // ms for an increment to lose half its weight, 0 turns trending boards off
+    pub half_life: u64,
+    pub interval: u64,
+    // built-in score types or dynamic board names that get a trending board
+    pub boards: Vec<String>
+}
+
+impl Default for DecayOptions {
+    fn default() -> Self {
+        DecayOptions { half_life: 604_800_000, interval: 3_600_000, boards: Vec::new() }
+    }
+}
+
+impl DecayOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.half_life > 0 && !self.boards.is_empty()
+    }
+
+    pub fn decays(&self, name: &str) -> bool {
+        self.half_life > 0 && self.boards.iter().any(|board| board == name)
+    }
+}
+
+// what `delta` made at `at` is worth relative to `reference`, both in epoch millis. increments the write
+// buffer collects are summed relative to the first one it saw, the increment script then weighs that sum
+// from there to the board's epoch, which gives the same score as weighing each increment on its own
+pub(super) fn weigh(delta: f64, at: u64, reference: u64, half_life: u64) -> f64 {
+    delta * 2f64.powf((at as f64 - reference as f64) / half_life as f64)
+}
+
+// both scripts read the epoch in redis itself, so an increment can never be weighed against an epoch
+// that a rebase running at the same time already moved
+pub(super) const INCREMENT_SCRIPT : &str = r"
+local now = tonumber(ARGV[2])
+local epoch = tonumber(redis.call('GET', KEYS[2]))
+if not epoch then
+    epoch = now
+    redis.call('SET', KEYS[2], ARGV[2])
+end
+return redis.call('ZINCRBY', KEYS[1], tonumber(ARGV[1]) * 2 ^ ((now - epoch) / tonumber(ARGV[3])), ARGV[4])
+";
+
+const REBASE_SCRIPT : &str = r"
+local now = tonumber(ARGV[1])
+local epoch = tonumber(redis.call('GET', KEYS[2]))
+if not epoch or now <= epoch then
+    return 0
+end
+redis.call('ZUNIONSTORE', KEYS[1], 1, KEYS[1], 'WEIGHTS', 2 ^ (-(now - epoch) / tonumber(ARGV[2])))
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '(-0.5', '(0.5')
+redis.call('SET', KEYS[2], ARGV[1])
+return redis.call('ZCARD', KEYS[1])
+";
+
+pub struct LeaderboardDecay {
+    pub half_life: u64,
+    increment: Script,
+    rebase: Script
+}
+
+impl LeaderboardDecay {
+    pub fn new(half_life: u64) -> Self {
+        LeaderboardDecay { half_life, increment: Script::new(INCREMENT_SCRIPT), rebase: Script::new(REBASE_SCRIPT) }
+    }
+
+    // what a stored score is worth `elapsed` ms after the epoch
+    fn factor(&self, elapsed: u64) -> f64 {
+        2f64.powf(-(elapsed as f64) / self.half_life as f64)
+    }
+}
+
+impl Leaderboard {
+    fn get_trending_id(&self) -> String {
+        format!("{}:trending", self.get_base_id())
+    }
+
+    fn get_trending_epoch_id(&self) -> String {
+        format!("{}:epoch", self.get_trending_id())
+    }
+
+    pub fn is_trending(&self) -> bool {
+        self.decay.is_some()
+    }
+
+    // weighted as of now, through the write buffer like the other boards when there is one
+    pub(super) async fn add_trending(&self, id: &str, delta: i64) {
+        let decay = match &self.decay {
+            Some(decay) => decay,
+            None => return
+        };
+        let (key, epoch_key, now) = (self.get_trending_id(), self.get_trending_epoch_id(), get_u64_time_millis());
+        if let Some(write_buffer) = &self.write_buffer {
+            write_buffer.add_trending(key, epoch_key, decay.half_life, id, delta as f64, now);
+            return;
+        };
+        let _ = self.cache.submit(|mut conn| async move {
+            let _ = decay.increment.key(key).key(epoch_key)
+                .arg(delta).arg(now).arg(decay.half_life).arg(id)
+                .invoke_async::<Connection, ()>(&mut conn).await;
+        }).await;
+    }
+
+    pub(super) async fn remove_trending(&self, id: &str) {
+        if self.decay.is_none() {
+            return;
+        };
+        if let Some(write_buffer) = &self.write_buffer {
+            write_buffer.discard(&[self.get_trending_id()], &[id.to_owned()]);
+        };
+        let _ = self.cache.submit(|mut conn| async move {
+            let _ = redis::cmd("ZREM").arg(self.get_trending_id()).arg(id).query_async::<Connection, ()>(&mut conn).await;
+        }).await;
+    }
+
+    // `None` when this board has no trending board
+    pub async fn fetch_trending(&self, limit: u32) -> Option<Vec<LeaderboardEntry>> {
+        let decay = self.decay.as_ref()?;
+        let (key, epoch_key) = (self.get_trending_id(), self.get_trending_epoch_id());
+        let (epoch, raw) = self.cache.submit(|mut conn| async move {
+            redis::pipe()
+                .cmd("GET").arg(epoch_key)
+                .cmd("ZRANGE").arg(key).arg(0u32).arg(limit.max(1) - 1).arg("REV").arg("WITHSCORES")
+                .query_async::<Connection, (Option<u64>, Vec<String>)>(&mut conn).await.ok()
+        }).await.unwrap_or(None).unwrap_or_default();
+        let factor = match epoch {
+            Some(epoch) => decay.factor(get_u64_time_millis().saturating_sub(epoch)),
+            None => return Some(Vec::new())
+        };
+        Some(Self::strings_as_leaderboard_entries(raw, factor))
+    }
+
+    pub async fn count_trending(&self) -> u64 {
+        if self.decay.is_none() {
+            return 0;
+        };
+        self.cache.submit(|mut conn| async move {
+            redis::cmd("ZCARD").arg(self.get_trending_id()).query_async::<Connection, u64>(&mut conn).await.unwrap_or(0)
+        }).await.unwrap_or(0)
+    }
+
+    // returns how many entries are left on the board
+    pub(super) async fn rebase_trending(&self, now: u64) -> u64 {
+        let decay = match &self.decay {
+            Some(decay) => decay,
+            None => return 0
+        };
+        let (key, epoch_key) = (self.get_trending_id(), self.get_trending_epoch_id());
+        self.cache.submit(|mut conn| async move {
+            decay.rebase.key(key).key(epoch_key).arg(now).arg(decay.half_life)
+                .invoke_async::<Connection, u64>(&mut conn).await.unwrap_or(0)
+        }).await.unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR : u64 = 3_600_000;
+
+    #[test]
+    fn increments_lose_half_their_weight_every_half_life() {
+        let decay = LeaderboardDecay::new(HOUR);
+        assert_eq!(decay.factor(0), 1.0);
+        assert_eq!(decay.factor(HOUR), 0.5);
+        assert_eq!(decay.factor(3 * HOUR), 0.125);
+    }
+
+    #[test]
+    fn summing_from_a_reference_matches_weighing_each_increment() {
+        let (epoch, half_life) = (1_000 * HOUR, HOUR);
+        let increments = [(4.0, epoch + HOUR / 2), (2.0, epoch + HOUR), (-1.0, epoch + 2 * HOUR)];
+
+        let each : f64 = increments.iter().map(|(delta, at)| weigh(*delta, *at, epoch, half_life)).sum();
+        let reference = increments[0].1;
+        let summed : f64 = increments.iter().map(|(delta, at)| weigh(*delta, *at, reference, half_life)).sum();
+        assert!((weigh(summed, reference, epoch, half_life) - each).abs() < 1e-9);
+
+        // what the board hands out two half-lives after the last increment
+        let now = epoch + 4 * HOUR;
+        let real : f64 = increments.iter().map(|(delta, at)| delta * LeaderboardDecay::new(half_life).factor(now - at)).sum();
+        assert!((each * LeaderboardDecay::new(half_life).factor(now - epoch) - real).abs() < 1e-9);
+    }
+}
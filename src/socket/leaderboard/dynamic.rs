@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::database::models::player::PlayerStats;
+
+use super::ScoreType;
+
+// one entry of `dynamic_leaderboards.yml`, e.g. `{ name: OBJECTIVES, formula: "WOOL_CAPTURES * 3 + FLAG_CAPTURES * 3 + CORE_LEAKS", public: true }`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicScoreDefinition {
+    pub name: String,
+    pub formula: String,
+    // readable without a token and shown on public profiles, like the public built-in boards
+    #[serde(default)]
+    pub public: bool
+}
+
+// a board scored as a weighted sum of built-in scores. weights are whole numbers so scores stay integral
+// like every other board, negative weights are allowed and can take a score below zero
+#[derive(Debug, Clone)]
+pub struct DynamicScoreType {
+    pub name: String,
+    pub terms: Vec<(ScoreType, i64)>,
+    pub public: bool
+}
+
+enum FormulaToken {
+    Number(i64),
+    Stat(String),
+    Plus,
+    Minus,
+    Times
+}
+
+fn tokenize(formula: &str) -> Result<Vec<FormulaToken>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = formula.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            ' ' | '\t' => { chars.next(); },
+            '+' => { chars.next(); tokens.push(FormulaToken::Plus); },
+            '-' => { chars.next(); tokens.push(FormulaToken::Minus); },
+            '*' => { chars.next(); tokens.push(FormulaToken::Times); },
+            '0'..='9' => {
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                    chars.next();
+                };
+                tokens.push(FormulaToken::Number(digits.parse::<i64>().map_err(|_| format!("weight {} is too large", digits))?));
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                    name.push(c);
+                    chars.next();
+                };
+                tokens.push(FormulaToken::Stat(name));
+            },
+            c => return Err(format!("unexpected '{}'", c))
+        };
+    };
+    Ok(tokens)
+}
+
+impl DynamicScoreType {
+    // formula := ['-'] term (('+' | '-') term)*, term := factor ('*' factor)*, factor := integer | SCORE_TYPE.
+    // every term names exactly one score type, terms on the same score type are added together
+    pub fn parse(definition: &DynamicScoreDefinition) -> Result<Self, String> {
+        let name = definition.name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') {
+            return Err(format!("Dynamic leaderboard name '{}' must be SCREAMING_SNAKE_CASE", definition.name));
+        };
+        if ScoreType::from_str(name).is_ok() {
+            return Err(format!("Dynamic leaderboard '{}' has the name of a built-in leaderboard", name));
+        };
+        let invalid = |reason: String| format!("Dynamic leaderboard '{}' has an invalid formula: {}", name, reason);
+
+        let mut terms : Vec<(ScoreType, i64)> = Vec::new();
+        let mut tokens = tokenize(&definition.formula).map_err(invalid)?.into_iter().peekable();
+        let mut sign = match tokens.peek() {
+            Some(FormulaToken::Minus) => { tokens.next(); -1i64 },
+            Some(_) => 1,
+            None => return Err(invalid(String::from("it is empty")))
+        };
+        loop {
+            let mut weight = sign;
+            let mut score_type : Option<ScoreType> = None;
+            loop {
+                match tokens.next() {
+                    Some(FormulaToken::Number(number)) => {
+                        weight = weight.checked_mul(number).ok_or_else(|| invalid(String::from("a weight is too large")))?;
+                    },
+                    Some(FormulaToken::Stat(stat)) => {
+                        let parsed = ScoreType::from_str(&stat.to_ascii_uppercase()).map_err(|_| invalid(format!("unknown score type '{}'", stat)))?;
+                        // killstreaks are kept as a maximum, there is no delta to weigh
+                        if parsed == ScoreType::HighestKillstreak {
+                            return Err(invalid(format!("{} cannot be used in a formula", parsed)));
+                        };
+                        if score_type.replace(parsed).is_some() {
+                            return Err(invalid(String::from("score types cannot be multiplied together")));
+                        };
+                    },
+                    _ => return Err(invalid(String::from("expected a number or a score type")))
+                };
+                match tokens.peek() {
+                    Some(FormulaToken::Times) => { tokens.next(); },
+                    _ => break
+                };
+            };
+            let score_type = score_type.ok_or_else(|| invalid(String::from("every term needs a score type")))?;
+            match terms.iter_mut().find(|(existing, _)| *existing == score_type) {
+                Some((_, existing)) => *existing = existing.checked_add(weight).ok_or_else(|| invalid(String::from("a weight is too large")))?,
+                None => terms.push((score_type, weight))
+            };
+            sign = match tokens.next() {
+                Some(FormulaToken::Plus) => 1,
+                Some(FormulaToken::Minus) => -1,
+                None => break,
+                Some(_) => return Err(invalid(String::from("expected '+' or '-' between terms")))
+            };
+        };
+        terms.retain(|(_, weight)| *weight != 0);
+        if terms.is_empty() {
+            return Err(invalid(String::from("every weight cancels out")));
+        };
+        Ok(DynamicScoreType { name: name.to_owned(), terms, public: definition.public })
+    }
+
+    pub fn weight_of(&self, score_type: &ScoreType) -> Option<i64> {
+        self.terms.iter().find(|(term, _)| term == score_type).map(|(_, weight)| *weight)
+    }
+
+    pub fn evaluate(&self, stats: &PlayerStats) -> i64 {
+        self.terms.iter().fold(0i64, |score, (score_type, weight)| score.saturating_add(weight.saturating_mul(i64::try_from(stats.get_score(score_type)).unwrap_or(i64::MAX))))
+    }
+}
+
+pub fn parse_dynamic_score_types(definitions: &[DynamicScoreDefinition]) -> Result<Vec<DynamicScoreType>, String> {
+    let mut score_types : Vec<DynamicScoreType> = Vec::new();
+    for definition in definitions.iter() {
+        let score_type = DynamicScoreType::parse(definition)?;
+        if score_types.iter().any(|existing| existing.name == score_type.name) {
+            return Err(format!("Dynamic leaderboard '{}' is defined more than once", score_type.name));
+        };
+        score_types.push(score_type);
+    };
+    Ok(score_types)
+}
@@ -1,7 +1,28 @@
-use crate::{socket::{player::{player_listener::PlayerListener, player_events::PlayerDeathData}, participant::participant_context::{PlayerMatchResult}, r#match::match_events::{MatchEndData}, server::server_context::ServerContext}, database::models::{participant::Participant, r#match::Match}};
+use crate::{socket::{player::{player_listener::PlayerListener, player_events::PlayerDeathData}, participant::participant_context::{PlayerMatchResult}, r#match::match_events::{MatchEndData}, server::server_context::ServerContext}, database::models::{participant::{Participant, KillCounts}, r#match::Match}, util::tracking::StatCategory};
 
 pub struct LeaderboardListener {}
 
+async fn is_credited(server_context: &ServerContext, current_match: &Match, context: &Participant, category: StatCategory) -> bool {
+    if !current_match.is_tracking(category) {
+        return false;
+    };
+    if let Some(eligible) = context.leaderboard_eligible {
+        return eligible;
+    };
+    let state = &server_context.api_state;
+    match state.player_cache.get(&state.database, &context.get_name_lower()).await {
+        Some(player) => !player.frozen && player.is_leaderboard_eligible_in(&server_context.realm, state.config.options.leaderboard_min_playtime),
+        None => true
+    }
+}
+
+// kept on the participant so an abandoned match takes back exactly these, see `abandonment::abandon_match`
+fn credit_boards(server_context: &ServerContext, context: &mut Participant, credit: impl Fn(&mut KillCounts)) {
+    let boundaries = &server_context.leaderboards().boundaries;
+    let today = boundaries.today();
+    context.credited.credit_boards(|at| boundaries.date_of(at) == today, credit);
+}
+
 #[async_trait]
 impl PlayerListener for LeaderboardListener {
     type Context = Participant;
@@ -14,33 +35,34 @@ impl PlayerListener for LeaderboardListener {
         end_data: &mut MatchEndData
     ) { 
         {
-            if !current_match.is_tracking_stats() {
+            if !is_credited(server_context, current_match, context, StatCategory::Activity).await {
                 return;
             }
 
-            let match_result = current_match.get_participant_match_result(&context, end_data);
-
-            match match_result {
-                PlayerMatchResult::Win => {
-                    server_context.api_state.leaderboards.wins.increment(&context.get_id_name(), Some(1)).await;
-                },
-                PlayerMatchResult::Lose => {
-                    server_context.api_state.leaderboards.losses.increment(&context.get_id_name(), Some(1)).await;
-                },
-                PlayerMatchResult::Tie => {
-                    server_context.api_state.leaderboards.ties.increment(&context.get_id_name(), Some(1)).await;
-                },
-                _ => {} 
-            }
+            // like the player's own stats, the result of a match someone barely played in is left off the boards
+            if context.get_presence(current_match, &server_context.api_state.config.options.match_participation).participated {
+                match current_match.get_participant_match_result(context, end_data) {
+                    PlayerMatchResult::Win => {
+                        server_context.leaderboards().wins.increment(&context.get_id_name(), Some(1)).await;
+                    },
+                    PlayerMatchResult::Lose => {
+                        server_context.leaderboards().losses.increment(&context.get_id_name(), Some(1)).await;
+                    },
+                    PlayerMatchResult::Tie => {
+                        server_context.leaderboards().ties.increment(&context.get_id_name(), Some(1)).await;
+                    },
+                    _ => {} 
+                }
+                server_context.leaderboards().matches_played.increment(&context.get_id_name(), Some(1)).await;
+            };
 
-            server_context.api_state.leaderboards.matches_played.increment(&context.get_id_name(), Some(1)).await;
-            server_context.api_state.leaderboards.messages_sent.increment(
+            server_context.leaderboards().messages_sent.increment(
                 &context.get_id_name(), 
-                Some(context.stats.messages.total())
+                Some(context.stats.messages.total() as u64)
             ).await;
-            server_context.api_state.leaderboards.game_playtime.increment(
+            server_context.leaderboards().game_playtime.increment(
                 &context.get_id_name(), 
-                Some(u32::try_from(context.stats.game_playtime).unwrap_or(0))
+                Some(context.stats.game_playtime)
             ).await;
         };
     }
@@ -55,17 +77,32 @@ impl PlayerListener for LeaderboardListener {
         first_blood: bool
     ) { 
         {
-            if !current_match.is_tracking_stats() {
+            if !is_credited(server_context, current_match, context, StatCategory::Kills).await {
                 return;
             };
 
-            server_context.api_state.leaderboards.kills.increment(&context.get_id_name(), Some(1)).await;
+            server_context.leaderboards().kills.increment(&context.get_id_name(), Some(1)).await;
+            credit_boards(server_context, context, |counts| counts.kills += 1);
             if first_blood {
-                server_context.api_state.leaderboards.first_bloods.increment(&context.get_id_name(), Some(1)).await;
+                server_context.leaderboards().first_bloods.increment(&context.get_id_name(), Some(1)).await;
             };
         }
     }
 
+    async fn on_assist(
+        &self,
+        server_context: &mut ServerContext, 
+        current_match: &mut Match, 
+        context: &mut Self::Context, 
+        _data: &mut PlayerDeathData
+    ) {
+        if !is_credited(server_context, current_match, context, StatCategory::Kills).await {
+            return;
+        };
+        server_context.leaderboards().assists.increment(&context.get_id_name(), Some(1)).await;
+        credit_boards(server_context, context, |counts| counts.assists += 1);
+    }
+
     async fn on_death(
         &self,
         server_context: &mut ServerContext, 
@@ -75,11 +112,12 @@ impl PlayerListener for LeaderboardListener {
         _first_blood: bool
     ) { 
         {
-            if !current_match.is_tracking_stats() {
+            if !is_credited(server_context, current_match, context, StatCategory::Kills).await {
                 return;
             };
 
-            server_context.api_state.leaderboards.deaths.increment(&context.get_id_name(), Some(1)).await;
+            server_context.leaderboards().deaths.increment(&context.get_id_name(), Some(1)).await;
+            credit_boards(server_context, context, |counts| counts.deaths += 1);
         };
     }
 
@@ -91,10 +129,10 @@ impl PlayerListener for LeaderboardListener {
         amount: u32
     ) {
         {
-            if !current_match.is_tracking_stats() {
+            if !is_credited(server_context, current_match, context, StatCategory::Kills).await {
                 return;
             };
-            server_context.api_state.leaderboards.highest_killstreak.set_if_higher(&context.get_id_name(), amount).await;
+            server_context.leaderboards().highest_killstreak.set_if_higher(&context.get_id_name(), amount).await;
         };
     }
 
@@ -106,11 +144,11 @@ impl PlayerListener for LeaderboardListener {
         _percentage: f32, 
         block_count: u32
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.destroyable_destroys.increment(&context.get_id_name(), Some(1)).await;
-        server_context.api_state.leaderboards.destroyable_block_destroys.increment(&context.get_id_name(), Some(block_count)).await;
+        server_context.leaderboards().destroyable_destroys.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().destroyable_block_destroys.increment(&context.get_id_name(), Some(block_count as u64)).await;
     }
 
     async fn on_core_leak(
@@ -121,11 +159,11 @@ impl PlayerListener for LeaderboardListener {
         _percentage: f32, 
         _block_count: u32
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.core_leaks.increment(&context.get_id_name(), Some(1)).await;
-        server_context.api_state.leaderboards.core_block_destroys.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().core_leaks.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().core_block_destroys.increment(&context.get_id_name(), Some(1)).await;
     }
 
     async fn on_flag_place(
@@ -135,11 +173,11 @@ impl PlayerListener for LeaderboardListener {
         context: &mut Self::Context, 
         held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.flag_captures.increment(&context.get_id_name(), Some(1)).await;
-        server_context.api_state.leaderboards.flag_hold_time.increment(&context.get_id_name(), Some(u32::try_from(held_time).unwrap())).await;
+        server_context.leaderboards().flag_captures.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().flag_hold_time.increment(&context.get_id_name(), Some(held_time)).await;
     }
 
     async fn on_flag_pickup(
@@ -148,10 +186,10 @@ impl PlayerListener for LeaderboardListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.flag_pickups.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().flag_pickups.increment(&context.get_id_name(), Some(1)).await;
     }
 
     async fn on_flag_drop(
@@ -161,11 +199,11 @@ impl PlayerListener for LeaderboardListener {
         context: &mut Self::Context, 
         held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.flag_drops.increment(&context.get_id_name(), Some(1)).await;
-        server_context.api_state.leaderboards.flag_hold_time.increment(&context.get_id_name(), Some(u32::try_from(held_time).unwrap())).await;
+        server_context.leaderboards().flag_drops.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().flag_hold_time.increment(&context.get_id_name(), Some(held_time)).await;
     }
 
     async fn on_flag_defend(
@@ -174,10 +212,10 @@ impl PlayerListener for LeaderboardListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.flag_defends.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().flag_defends.increment(&context.get_id_name(), Some(1)).await;
     }
 
     async fn on_wool_place(
@@ -187,10 +225,10 @@ impl PlayerListener for LeaderboardListener {
         context: &mut Self::Context, 
         _held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.wool_captures.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().wool_captures.increment(&context.get_id_name(), Some(1)).await;
     }
 
     async fn on_wool_pickup(
@@ -199,10 +237,10 @@ impl PlayerListener for LeaderboardListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.wool_pickups.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().wool_pickups.increment(&context.get_id_name(), Some(1)).await;
     }
 
     async fn on_wool_drop(
@@ -212,10 +250,10 @@ impl PlayerListener for LeaderboardListener {
         context: &mut Self::Context, 
         _held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.wool_drops.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().wool_drops.increment(&context.get_id_name(), Some(1)).await;
     }
 
     async fn on_wool_defend(
@@ -224,10 +262,10 @@ impl PlayerListener for LeaderboardListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
-        server_context.api_state.leaderboards.wool_defends.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().wool_defends.increment(&context.get_id_name(), Some(1)).await;
     }
 
     async fn on_control_point_capture(
@@ -237,10 +275,10 @@ impl PlayerListener for LeaderboardListener {
         context: &mut Self::Context, 
         _contributors: u32, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !is_credited(server_context, current_match, context, StatCategory::Objectives).await {
             return;
         };
 
-        server_context.api_state.leaderboards.control_point_captures.increment(&context.get_id_name(), Some(1)).await;
+        server_context.leaderboards().control_point_captures.increment(&context.get_id_name(), Some(1)).await;
     }
 }
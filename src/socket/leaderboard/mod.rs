@@ -1,22 +1,26 @@
-use std::sync::Arc;
-use mongodb::{bson::doc, Cursor};
+use std::{sync::{Arc, Mutex}, collections::HashMap, str::FromStr, time::Instant};
+use mongodb::bson::doc;
 use num_traits::cast::FromPrimitive;
 use redis::{aio::Connection, ToRedisArgs};
 use serde::{Serialize, Deserialize};
 use strum_macros::{Display, EnumIter, EnumString};
 use strum::IntoEnumIterator;
 
-use chrono::{Month, DateTime, Utc, TimeZone, FixedOffset, Datelike};
+use chrono::{Month, Datelike, NaiveDate};
+use futures::future::join_all;
+use tokio::sync::mpsc::UnboundedSender;
 
-use crate::{database::{cache::RedisAdapter, Database, models::player::Player}, util::r#macro::unwrap_helper};
+use self::{write_buffer::LeaderboardWriteBuffer, period::PeriodBoundaries, dynamic::DynamicScoreType, reconcile::ReconciliationMetrics, decay::{DecayOptions, LeaderboardDecay}};
 
-pub mod leaderboard_listener;
+use crate::{database::{cache::RedisAdapter, Database, CollectionOwner, InsertError, models::{player::{Player, PlayerStats}, leaderboard_snapshot::LeaderboardSnapshot}}, util::{r#macro::unwrap_helper, time::{get_u64_time_millis, Timestamp}}};
 
-fn get_est_datetime() -> DateTime<FixedOffset> {
-    let naive_utc_time = Utc::now().naive_utc();
-    let fixed_offset = FixedOffset::west(4 * 3600); // UTC-4 for EST
-    fixed_offset.from_utc_datetime(&naive_utc_time)
-}
+pub mod leaderboard_listener;
+pub mod write_buffer;
+pub mod period;
+pub mod dynamic;
+pub mod reconcile;
+pub mod decay;
+pub mod percentile;
 
 pub enum Season {
     Spring,
@@ -45,7 +49,7 @@ impl Season {
     }
 }
 
-#[derive(EnumIter, EnumString)]
+#[derive(EnumIter, EnumString, Display)]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum LeaderboardPeriod {
     Daily,
@@ -57,8 +61,12 @@ pub enum LeaderboardPeriod {
 }
 
 impl LeaderboardPeriod {
-    pub fn get_today_id(&self) -> String {
-        let date = get_est_datetime();
+    pub fn get_today_id(&self, boundaries: &PeriodBoundaries) -> String {
+        self.get_id_on(boundaries.today(), boundaries)
+    }
+
+    // the key that was written to on `date`
+    pub fn get_id_on(&self, date: NaiveDate, boundaries: &PeriodBoundaries) -> String {
         match &self {
             Self::Daily => {
                 let day = date.day();
@@ -67,9 +75,9 @@ impl LeaderboardPeriod {
                 format!("{}:d:{}:{}", year, month, day)
             },
             Self::Weekly => {
-                let week = date.iso_week().week();
-                let year = date.year();
-                format!("{}:w:{}", year, week)
+                // the iso year, the days of a week that crosses new year share one key
+                let week = boundaries.week_start_of(date).iso_week();
+                format!("{}:w:{}", week.year(), week.week())
             },
             Self::Monthly => {
                 let month = date.month() - 1;
@@ -89,14 +97,64 @@ impl LeaderboardPeriod {
             Self::AllTime => String::from("all"),
         }
     }
+
+    // seasons follow the key, so january and february count from the start of the year rather than from november
+    fn season_start_month(month: u32) -> u32 {
+        match month {
+            1 | 2 => 1,
+            3 | 4 => 3,
+            5..=8 => 5,
+            9 | 10 => 9,
+            _ => 11
+        }
+    }
+
+    fn get_window_start_date(&self, today: NaiveDate, boundaries: &PeriodBoundaries) -> Option<NaiveDate> {
+        match &self {
+            Self::Daily => Some(today),
+            Self::Weekly => Some(boundaries.week_start_of(today)),
+            Self::Monthly => NaiveDate::from_ymd_opt(today.year(), today.month(), 1),
+            Self::Seasonally => NaiveDate::from_ymd_opt(today.year(), Self::season_start_month(today.month()), 1),
+            Self::Yearly => NaiveDate::from_ymd_opt(today.year(), 1, 1),
+            Self::AllTime => None
+        }
+    }
+
+    // start of the window the current key covers, in epoch millis
+    pub fn get_window_start(&self, boundaries: &PeriodBoundaries) -> Option<u64> {
+        let start = self.get_window_start_date(boundaries.today(), boundaries)?;
+        boundaries.start_of(start)
+    }
+
+    // when the current key stops being written to, in epoch millis
+    pub fn get_next_reset(&self, boundaries: &PeriodBoundaries) -> Option<u64> {
+        let today = boundaries.today();
+        let start = self.get_window_start_date(today, boundaries)?;
+        let next = match &self {
+            Self::Daily => today.succ_opt(),
+            Self::Weekly => start.checked_add_signed(chrono::Duration::days(7)),
+            Self::Monthly => if start.month() == 12 { NaiveDate::from_ymd_opt(start.year() + 1, 1, 1) } else { NaiveDate::from_ymd_opt(start.year(), start.month() + 1, 1) },
+            Self::Seasonally => match start.month() {
+                1 => NaiveDate::from_ymd_opt(start.year(), 3, 1),
+                3 => NaiveDate::from_ymd_opt(start.year(), 5, 1),
+                5 => NaiveDate::from_ymd_opt(start.year(), 9, 1),
+                9 => NaiveDate::from_ymd_opt(start.year(), 11, 1),
+                _ => NaiveDate::from_ymd_opt(start.year() + 1, 1, 1)
+            },
+            Self::Yearly => NaiveDate::from_ymd_opt(start.year() + 1, 1, 1),
+            Self::AllTime => None
+        }?;
+        boundaries.start_of(next)
+    }
 }
 
-#[derive(Display, EnumString, Serialize, Deserialize, Clone, Eq, Hash, PartialEq)]
+#[derive(Debug, Display, EnumString, EnumIter, Serialize, Deserialize, Clone, Eq, Hash, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
 pub enum ScoreType {
     Kills,
     Deaths,
+    Assists,
     FirstBloods,
     Wins,
     Losses,
@@ -128,6 +186,7 @@ impl ScoreType {
         match self {
             ScoreType::Kills => &lbs.kills,
             ScoreType::Deaths => &lbs.deaths,
+            ScoreType::Assists => &lbs.assists,
             ScoreType::FirstBloods => &lbs.first_bloods,
             ScoreType::Wins => &lbs.wins,
             ScoreType::Losses => &lbs.losses,
@@ -156,10 +215,41 @@ impl ScoreType {
     }
 }
 
+#[derive(Clone)]
+pub enum LeaderboardScore {
+    Builtin(ScoreType),
+    Dynamic(Arc<DynamicScoreType>)
+}
+
+impl LeaderboardScore {
+    pub fn name(&self) -> String {
+        match self {
+            LeaderboardScore::Builtin(score_type) => score_type.to_string(),
+            LeaderboardScore::Dynamic(score_type) => score_type.name.clone()
+        }
+    }
+
+    pub fn of(&self, stats: &PlayerStats) -> i64 {
+        match self {
+            LeaderboardScore::Builtin(score_type) => i64::try_from(stats.get_score(score_type)).unwrap_or(i64::MAX),
+            LeaderboardScore::Dynamic(score_type) => score_type.evaluate(stats)
+        }
+    }
+}
+
 pub struct Leaderboard {
-    pub score_type: ScoreType,
+    pub score: LeaderboardScore,
     pub database: Arc<Database>,
-    pub cache: Arc<RedisAdapter>
+    pub cache: Arc<RedisAdapter>,
+    // increments go straight to redis when unset
+    pub write_buffer: Option<Arc<LeaderboardWriteBuffer>>,
+    pub boundaries: Arc<PeriodBoundaries>,
+    // dynamic boards whose formula uses this board's score, with the weight it has there
+    pub derived: Vec<(i64, Arc<Leaderboard>)>,
+    // set when the board also keeps a time-decayed trending board
+    pub decay: Option<Arc<LeaderboardDecay>>,
+    // `None` for the default realm, whose keys have no prefix
+    pub realm: Option<String>
 }
 
 
@@ -173,26 +263,17 @@ impl Leaderboard {
         }).await;
     }
 
-    pub async fn populate_all_time(&self) {
-        let cursor : Cursor<Player> = match self.database.players.find(doc! {}, None).await {
-            Ok(player_cursor) => player_cursor,
-            Err(_) => return
-        };
-        let players = {
-            let mut players = Database::consume_cursor_into_owning_vec(cursor).await;
-            players.sort_by(|a, b| {
-                b.stats.get_score(&self.score_type).cmp(&a.stats.get_score(&self.score_type))
-            });
-            players
-        };
-        let members = {
-            let mut members : Vec<(String, u64)> = Vec::new();
-            for player in players.iter() {
-                members.push((player.id_name(), player.stats.get_score(&self.score_type) as u64));
+    pub async fn populate_all_time(&self, batch_size: usize) {
+        let key = self.get_id(&LeaderboardPeriod::AllTime);
+        let key = &key;
+        self.database.for_each_document::<Player, _, _>(batch_size, |players, _| async move {
+            let members : Vec<(i64, String)> = players.iter()
+                .filter_map(|player| self.expected_score(player).map(|score| (score, player.id_name())))
+                .collect();
+            if !members.is_empty() {
+                self.zadd_entries(key, &members).await;
             };
-            members
-        };
-        self.zadd_entries(&self.get_id(&LeaderboardPeriod::AllTime), &members).await;
+        }).await;
     }
 
     pub async fn set(&self, id: &String, score: u32) {
@@ -204,23 +285,131 @@ impl Leaderboard {
         }).await;
     }
 
-    pub async fn increment(&self, id: &String, incr: Option<u32>) {
-        let u64_incr = incr.unwrap_or(1) as u64;
-        let _ = self.cache.submit(|mut conn| async move {
+    pub async fn increment(&self, id: &String, incr: Option<u64>) {
+        let u64_incr = incr.unwrap_or(1);
+        join_all(self.derived.iter().map(|(weight, derived)| derived.add(id, weight.saturating_mul(u64_incr as i64)))).await;
+        self.add_trending(id, u64_incr as i64).await;
+        if let Some(write_buffer) = &self.write_buffer {
+            for period in LeaderboardPeriod::iter() {
+                write_buffer.add(self.get_id(&period), id, u64_incr);
+            };
+            return;
+        };
+        let started = Instant::now();
+        let written = self.cache.submit(|mut conn| async move {
             for period in LeaderboardPeriod::iter() {
                 let _ = redis::cmd("ZINCRBY").arg(&self.get_id(&period)).arg(u64_incr).arg(id).query_async::<Connection, ()>(&mut conn).await;
             };
         }).await;
+        if written.is_ok() {
+            self.cache.metrics.record_leaderboard_write(started.elapsed());
+        };
+    }
+
+    // signed so a negative weight can lower a dynamic board, only positive amounts fit the write buffer
+    async fn add(&self, id: &String, delta: i64) {
+        if delta == 0 {
+            return;
+        };
+        self.add_trending(id, delta).await;
+        if let (Some(write_buffer), true) = (&self.write_buffer, delta > 0) {
+            for period in LeaderboardPeriod::iter() {
+                write_buffer.add(self.get_id(&period), id, delta as u64);
+            };
+            return;
+        };
+        let _ = self.cache.submit(|mut conn| async move {
+            for period in LeaderboardPeriod::iter() {
+                let _ = redis::cmd("ZINCRBY").arg(self.get_id(&period)).arg(delta).arg(id).query_async::<Connection, ()>(&mut conn).await;
+            };
+        }).await;
+    }
+
+    // takes back an increment made at `at` (epoch millis) from the keys it went to, periods that rolled over
+    // since are not touched by the current ones. goes straight to redis, the write buffer only holds increments
+    pub async fn retract(&self, id: &String, decr: u32, at: u64) {
+        if decr == 0 {
+            return;
+        };
+        let date = self.boundaries.date_of(at);
+        let i64_decr = -(decr as i64);
+        join_all(self.derived.iter().map(|(weight, derived)| derived.retract_signed(id, weight.saturating_mul(i64_decr), date))).await;
+        self.add_trending(id, i64_decr).await;
+        self.retract_signed(id, i64_decr, date).await;
     }
 
-    fn strings_as_leaderboard_entries(raw: Vec<String>) -> Vec<LeaderboardEntry> {
+    async fn retract_signed(&self, id: &String, delta: i64, date: NaiveDate) {
+        if delta == 0 {
+            return;
+        };
+        let _ = self.cache.submit(|mut conn| async move {
+            for period in LeaderboardPeriod::iter() {
+                let key = format!("{}:{}", self.get_base_id(), period.get_id_on(date, &self.boundaries));
+                let _ = redis::cmd("ZINCRBY").arg(key).arg(delta).arg(id).query_async::<Connection, ()>(&mut conn).await;
+            };
+        }).await;
+    }
+
+    // moves an all-time entry that is already on the board, a player kept off it by the playtime gate or
+    // for inactivity stays off. periodic boards are left alone, stats don't say which period a correction is for
+    pub async fn correct_all_time(&self, id: &String, delta: i64) {
+        if delta == 0 {
+            return;
+        };
+        let corrections : Vec<(String, i64)> = std::iter::once((self.get_id(&LeaderboardPeriod::AllTime), delta))
+            .chain(self.derived.iter().map(|(weight, derived)| (derived.get_id(&LeaderboardPeriod::AllTime), weight.saturating_mul(delta))))
+            .filter(|(_, delta)| *delta != 0)
+            .collect();
+        let _ = self.cache.submit(|mut conn| async move {
+            let mut pipe = redis::pipe();
+            for (key, delta) in corrections.iter() {
+                pipe.cmd("ZADD").arg(key).arg("XX").arg("INCR").arg(*delta).arg(id).ignore();
+            };
+            let _ = pipe.query_async::<Connection, ()>(&mut conn).await;
+        }).await;
+    }
+
+    pub async fn remove_all_time(&self, ids: &[String]) {
+        if ids.is_empty() {
+            return;
+        };
+        if let Some(write_buffer) = &self.write_buffer {
+            write_buffer.discard(&[self.get_id(&LeaderboardPeriod::AllTime)], ids);
+        };
+        let _ = self.cache.submit(|mut conn| async move {
+            let _ = redis::cmd("ZREM").arg(self.get_id(&LeaderboardPeriod::AllTime)).arg(ids).query_async::<Connection, ()>(&mut conn).await;
+        }).await;
+    }
+
+    pub async fn remove(&self, id: &String) {
+        self.remove_trending(id).await;
+        if let Some(write_buffer) = &self.write_buffer {
+            let keys : Vec<String> = LeaderboardPeriod::iter().map(|period| self.get_id(&period)).collect();
+            write_buffer.discard(&keys, std::slice::from_ref(id));
+        };
+        let _ = self.cache.submit(|mut conn| async move {
+            for period in LeaderboardPeriod::iter() {
+                let _ = redis::cmd("ZREM").arg(self.get_id(&period)).arg(id).query_async::<Connection, ()>(&mut conn).await;
+            };
+        }).await;
+    }
+
+    pub async fn count(&self, period: &LeaderboardPeriod) -> u64 {
+        self.cache.submit(|mut conn| async move {
+            redis::cmd("ZCARD").arg(self.get_id(period)).query_async::<Connection, u64>(&mut conn).await.unwrap_or(0)
+        }).await.unwrap_or(0)
+    }
+
+    // scores are multiplied by `scale` first, trending boards store theirs relative to an epoch
+    fn strings_as_leaderboard_entries(raw: Vec<String>, scale: f64) -> Vec<LeaderboardEntry> {
         let mut entries : Vec<LeaderboardEntry> = Vec::new();
         if raw.len() <= 1 || raw.len() % 2 == 1 {
             return entries;
         };
         for i in (0..=(raw.len() - 2)).step_by(2) {
             let id_name = raw[i].clone();
-            let score = raw[i + 1].parse::<u32>().unwrap_or(0);
+            // dynamic boards can go below zero, those entries read as 0
+            let score = raw[i + 1].parse::<f64>().map(|score| (score * scale).round().max(0.0) as u64).unwrap_or(0);
             let (id, name) = {
                 let mut parts = id_name.split("/");
                 let id = unwrap_helper::continue_default!(parts.next());
@@ -240,7 +429,7 @@ impl Leaderboard {
             };
             top.unwrap_or(Vec::new())
         }).await.unwrap_or(Vec::new());
-        Self::strings_as_leaderboard_entries(lb_top)
+        Self::strings_as_leaderboard_entries(lb_top, 1.0)
     }
 
     pub async fn set_if_higher(&self, id: &String, new: u32) {
@@ -267,14 +456,155 @@ impl Leaderboard {
         }).await.unwrap_or(None) // this unwrap occurs if a connection can't be obtained
     }
 
+    // the entries `range` places either side of `id`, with the 0-based position of the first one. `None`
+    // when the player has no entry, the board can move between the two commands so the window is approximate
+    pub async fn fetch_around(&self, period: &LeaderboardPeriod, id: &String, range: u32) -> Option<(u64, Vec<LeaderboardEntry>)> {
+        let key = self.get_id(period);
+        let (start, raw) = self.cache.submit(|mut conn| async move {
+            let rank = redis::cmd("ZREVRANK").arg(&key).arg(id).query_async::<Connection, Option<u64>>(&mut conn).await.ok()??;
+            let start = rank.saturating_sub(range as u64);
+            let raw = redis::cmd("ZRANGE").arg(&key).arg(start).arg(rank + range as u64).arg("REV").arg("WITHSCORES")
+                .query_async::<Connection, Vec<String>>(&mut conn).await.ok()?;
+            Some((start, raw))
+        }).await.ok()??;
+        Some((start, Self::strings_as_leaderboard_entries(raw, 1.0)))
+    }
+
+    // the lowest `limit` entries, highest first, with the 0-based position of the first one
+    pub async fn fetch_bottom(&self, period: &LeaderboardPeriod, limit: u32) -> (u64, Vec<LeaderboardEntry>) {
+        let key = self.get_id(period);
+        let (total, raw) = self.cache.submit(|mut conn| async move {
+            redis::pipe()
+                .cmd("ZCARD").arg(&key)
+                .cmd("ZRANGE").arg(&key).arg(-(limit as i64)).arg(-1i64).arg("REV").arg("WITHSCORES")
+                .query_async::<Connection, (u64, Vec<String>)>(&mut conn).await.unwrap_or_default()
+        }).await.unwrap_or_default();
+        let entries = Self::strings_as_leaderboard_entries(raw, 1.0);
+        (total.saturating_sub(entries.len() as u64), entries)
+    }
+
+    // 1-based position on every period of this board in one round trip, `None` where the player has no entry
+    pub async fn get_positions(&self, id: &String) -> HashMap<String, Option<u64>> {
+        let periods : Vec<LeaderboardPeriod> = LeaderboardPeriod::iter().collect();
+        let ranks = self.cache.submit(|mut conn| async move {
+            let mut pipe = redis::pipe();
+            for period in periods.iter() {
+                pipe.cmd("ZREVRANK").arg(self.get_id(period)).arg(id);
+            };
+            pipe.query_async::<Connection, Vec<Option<u64>>>(&mut conn).await.ok()
+        }).await.unwrap_or(None).unwrap_or_default();
+        LeaderboardPeriod::iter().zip(ranks.into_iter().chain(std::iter::repeat(None)))
+            .map(|(period, rank)| (period.to_string(), rank.map(|rank| rank + 1)))
+            .collect()
+    }
+
+    pub async fn fetch_all(&self, period: &LeaderboardPeriod) -> Vec<LeaderboardEntry> {
+        let lb_all = self.cache.submit(|mut conn| async move {
+            redis::cmd("ZRANGE").arg(self.get_id(period)).arg(0i32).arg(-1i32).arg("REV").arg("WITHSCORES")
+                .query_async::<Connection, Vec<String>>(&mut conn).await.unwrap_or_default()
+        }).await.unwrap_or(Vec::new());
+        Self::strings_as_leaderboard_entries(lb_all, 1.0)
+    }
+
+    // archives the current standings before clearing the key, player documents are left alone so
+    // the board can be rebuilt from them afterwards. resetting an empty board is a no-op.
+    pub async fn reset(&self, period: &LeaderboardPeriod) -> anyhow::Result<Option<LeaderboardSnapshot>> {
+        let key = self.get_id(period);
+        let entries = self.fetch_all(period).await;
+        if entries.is_empty() {
+            return Ok(None);
+        };
+
+        let mut snapshot = LeaderboardSnapshot {
+            id: uuid::Uuid::new_v4().to_string(),
+            score_type: self.score.name(),
+            period: period.to_string(),
+            key: key.clone(),
+            entries,
+            created_at: Timestamp::now()
+        };
+        // the id is random, a taken one only needs another. the board is only cleared once it is archived
+        match self.database.insert_one(&snapshot).await {
+            Err(InsertError::DuplicateKey) => {
+                snapshot.id = uuid::Uuid::new_v4().to_string();
+                self.database.insert_one(&snapshot).await?;
+            },
+            result => result?
+        };
+        info!("Archived leaderboard '{}' ({} entries) to {}/{}", key, snapshot.entries.len(), LeaderboardSnapshot::get_collection_name(), snapshot.id);
+
+        self.cache.submit(|mut conn| async move {
+            redis::cmd("DEL").arg(&key).query_async::<Connection, ()>(&mut conn).await
+        }).await??;
+        Ok(Some(snapshot))
+    }
+
+    // what the player's stats in the board's realm add up to, `None` if they never played there
+    fn expected_score(&self, player: &Player) -> Option<i64> {
+        player.stats_in(&self.realm).map(|stats| self.score.of(stats))
+    }
+
+    // every key of the board starts with this
+    fn get_base_id(&self) -> String {
+        match &self.realm {
+            Some(realm) => format!("realm:{}:lb:{}", realm, self.score.name()),
+            None => format!("lb:{}", self.score.name())
+        }
+    }
+
     fn get_id(&self, period: &LeaderboardPeriod) -> String {
-        format!("lb:{}:{}", self.score_type, period.get_today_id())
+        format!("{}:{}", self.get_base_id(), period.get_today_id(&self.boundaries))
+    }
+
+    fn get_rebuild_id(&self) -> String {
+        format!("{}:rebuild", self.get_id(&LeaderboardPeriod::AllTime))
+    }
+
+    // all-time scores go to a staging key that replaces the live one once every batch is in. period boards are
+    // only given players whose whole history falls inside the window, everything else on them is left as is
+    async fn seed_rebuild_batch(&self, players: &[Player], window_starts: &[(LeaderboardPeriod, u64)]) {
+        let all_time : Vec<(i64, String)> = players.iter()
+            .filter_map(|player| self.expected_score(player).map(|score| (score, player.id_name())))
+            .collect();
+        if !all_time.is_empty() {
+            self.zadd_entries(&self.get_rebuild_id(), &all_time).await;
+        };
+        for (period, window_start) in window_starts.iter() {
+            let in_window : Vec<(i64, String)> = players.iter()
+                .filter(|player| player.first_joined_at.millis() >= *window_start)
+                .filter_map(|player| self.expected_score(player).map(|score| (score, player.id_name())))
+                .collect();
+            if !in_window.is_empty() {
+                self.zadd_entries(&self.get_id(period), &in_window).await;
+            };
+        };
+    }
+
+    async fn finish_rebuild(&self) {
+        let live_key = self.get_id(&LeaderboardPeriod::AllTime);
+        let rebuild_key = self.get_rebuild_id();
+        let _ = self.cache.submit(|mut conn| async move {
+            // the staging key only exists if there was at least one player
+            if redis::cmd("RENAME").arg(&rebuild_key).arg(&live_key).query_async::<Connection, ()>(&mut conn).await.is_err() {
+                let _ = redis::cmd("DEL").arg(&live_key).query_async::<Connection, ()>(&mut conn).await;
+            };
+        }).await;
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeaderboardRebuildProgress {
+    pub players_processed: u64,
+    // estimated when the rebuild started
+    pub players_total: u64,
+    pub done: bool
+}
+
 pub struct MarsLeaderboards {
     pub kills: Leaderboard,
     pub deaths: Leaderboard,
+    pub assists: Leaderboard,
     pub first_bloods: Leaderboard,
     pub wins: Leaderboard,
     pub losses: Leaderboard,
@@ -298,38 +628,191 @@ pub struct MarsLeaderboards {
     pub wool_pickups: Leaderboard,
     pub wool_defends: Leaderboard,
     pub control_point_captures: Leaderboard,
-    pub highest_killstreak: Leaderboard
+    pub highest_killstreak: Leaderboard,
+    // boards defined in `dynamic_leaderboards.yml`, in file order
+    pub dynamic: Vec<Arc<Leaderboard>>,
+    pub reconciliation: Mutex<ReconciliationMetrics>,
+    pub write_buffer: Option<Arc<LeaderboardWriteBuffer>>,
+    pub boundaries: Arc<PeriodBoundaries>,
+    // a realm's boards are built from the player stats of that realm, see `Player::stats_in`
+    pub realm: Option<String>
 }
 
 impl MarsLeaderboards {
-    pub fn new(redis: Arc<RedisAdapter>, database: Arc<Database>) -> Self {
+    // writes are buffered when `max_pending` is set, which then bounds the buffer
+    pub fn new(redis: Arc<RedisAdapter>, database: Arc<Database>, max_pending: Option<usize>, boundaries: PeriodBoundaries, dynamic: &[DynamicScoreType], decay: &DecayOptions, realm: Option<String>) -> Self {
+        let boundaries = Arc::new(boundaries);
+        let write_buffer = max_pending.map(|max_pending| Arc::new(LeaderboardWriteBuffer::new(Arc::clone(&redis), max_pending)));
+        // realms share the options, the default realm already warned about them
+        for name in decay.boards.iter().filter(|_| realm.is_none()) {
+            if ScoreType::from_str(name).is_err() && !dynamic.iter().any(|score_type| &score_type.name == name) {
+                warn!("'{}' in leaderboards.decay.boards is not a leaderboard, it gets no trending board", name);
+            };
+        };
+        let board = |score: LeaderboardScore, derived: Vec<(i64, Arc<Leaderboard>)>| {
+            // killstreaks are set rather than incremented, there is nothing to decay
+            let decays = decay.decays(&score.name()) && !matches!(score, LeaderboardScore::Builtin(ScoreType::HighestKillstreak));
+            Leaderboard {
+                decay: if decays { Some(Arc::new(LeaderboardDecay::new(decay.half_life))) } else { None },
+                score, cache: Arc::clone(&redis), database: Arc::clone(&database), write_buffer: write_buffer.clone(), boundaries: Arc::clone(&boundaries), derived,
+                realm: realm.clone()
+            }
+        };
+        let dynamic : Vec<Arc<Leaderboard>> = dynamic.iter()
+            .map(|score_type| Arc::new(board(LeaderboardScore::Dynamic(Arc::new(score_type.clone())), Vec::new())))
+            .collect();
+        let builtin = |score_type: ScoreType| {
+            let derived = dynamic.iter().filter_map(|leaderboard| match &leaderboard.score {
+                LeaderboardScore::Dynamic(dynamic_type) => dynamic_type.weight_of(&score_type).map(|weight| (weight, Arc::clone(leaderboard))),
+                LeaderboardScore::Builtin(_) => None
+            }).collect();
+            board(LeaderboardScore::Builtin(score_type), derived)
+        };
         MarsLeaderboards {
-            kills: Leaderboard { score_type: ScoreType::Kills, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            deaths: Leaderboard { score_type: ScoreType::Deaths, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            first_bloods: Leaderboard { score_type: ScoreType::FirstBloods, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            wins: Leaderboard { score_type: ScoreType::Wins, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            losses: Leaderboard { score_type: ScoreType::Losses, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            ties: Leaderboard { score_type: ScoreType::Ties, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            xp: Leaderboard { score_type: ScoreType::Xp, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            messages_sent: Leaderboard { score_type: ScoreType::MessagesSent, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            matches_played: Leaderboard { score_type: ScoreType::MatchesPlayed, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            server_playtime: Leaderboard { score_type: ScoreType::ServerPlaytime, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            game_playtime: Leaderboard { score_type: ScoreType::GamePlaytime, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            core_leaks: Leaderboard { score_type: ScoreType::CoreLeaks, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            core_block_destroys: Leaderboard { score_type: ScoreType::CoreBlockDestroys, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            destroyable_destroys: Leaderboard { score_type: ScoreType::DestroyableDestroys, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            destroyable_block_destroys: Leaderboard { score_type: ScoreType::DestroyableBlockDestroys, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            flag_captures: Leaderboard { score_type: ScoreType::FlagCaptures, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            flag_drops: Leaderboard { score_type: ScoreType::FlagDrops, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            flag_pickups: Leaderboard { score_type: ScoreType::FlagPickups, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            flag_defends: Leaderboard { score_type: ScoreType::FlagDefends, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            flag_hold_time: Leaderboard { score_type: ScoreType::FlagHoldTime, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            wool_captures: Leaderboard { score_type: ScoreType::WoolCaptures, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            wool_drops: Leaderboard { score_type: ScoreType::WoolDrops, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            wool_pickups: Leaderboard { score_type: ScoreType::WoolPickups, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            wool_defends: Leaderboard { score_type: ScoreType::WoolDefends, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            control_point_captures: Leaderboard { score_type: ScoreType::ControlPointCaptures, cache: Arc::clone(&redis), database: Arc::clone(&database) },
-            highest_killstreak: Leaderboard { score_type: ScoreType::HighestKillstreak, cache: Arc::clone(&redis), database: Arc::clone(&database) }
+            kills: builtin(ScoreType::Kills),
+            deaths: builtin(ScoreType::Deaths),
+            assists: builtin(ScoreType::Assists),
+            first_bloods: builtin(ScoreType::FirstBloods),
+            wins: builtin(ScoreType::Wins),
+            losses: builtin(ScoreType::Losses),
+            ties: builtin(ScoreType::Ties),
+            xp: builtin(ScoreType::Xp),
+            messages_sent: builtin(ScoreType::MessagesSent),
+            matches_played: builtin(ScoreType::MatchesPlayed),
+            server_playtime: builtin(ScoreType::ServerPlaytime),
+            game_playtime: builtin(ScoreType::GamePlaytime),
+            core_leaks: builtin(ScoreType::CoreLeaks),
+            core_block_destroys: builtin(ScoreType::CoreBlockDestroys),
+            destroyable_destroys: builtin(ScoreType::DestroyableDestroys),
+            destroyable_block_destroys: builtin(ScoreType::DestroyableBlockDestroys),
+            flag_captures: builtin(ScoreType::FlagCaptures),
+            flag_drops: builtin(ScoreType::FlagDrops),
+            flag_pickups: builtin(ScoreType::FlagPickups),
+            flag_defends: builtin(ScoreType::FlagDefends),
+            flag_hold_time: builtin(ScoreType::FlagHoldTime),
+            wool_captures: builtin(ScoreType::WoolCaptures),
+            wool_drops: builtin(ScoreType::WoolDrops),
+            wool_pickups: builtin(ScoreType::WoolPickups),
+            wool_defends: builtin(ScoreType::WoolDefends),
+            control_point_captures: builtin(ScoreType::ControlPointCaptures),
+            highest_killstreak: builtin(ScoreType::HighestKillstreak),
+            dynamic: dynamic.clone(),
+            reconciliation: Mutex::new(ReconciliationMetrics::default()),
+            write_buffer: write_buffer.clone(),
+            boundaries: Arc::clone(&boundaries),
+            realm
+        }
+    }
+
+    // moves every trending board onto the current time, see `decay` for why this is needed
+    pub async fn rebase_trending(&self) {
+        let now = get_u64_time_millis();
+        join_all(self.all().into_iter().filter(|leaderboard| leaderboard.is_trending()).map(|leaderboard| leaderboard.rebase_trending(now))).await;
+    }
+
+    pub async fn flush_pending(&self) -> usize {
+        match &self.write_buffer {
+            Some(write_buffer) => write_buffer.flush().await,
+            None => 0
+        }
+    }
+
+    // repopulates every board from player documents, sending progress after each batch
+    pub async fn rebuild(&self, database: &Database, batch_size: usize, min_playtime: u64, progress: UnboundedSender<LeaderboardRebuildProgress>) -> u64 {
+        let window_starts : Vec<(LeaderboardPeriod, u64)> = LeaderboardPeriod::iter()
+            .filter_map(|period| period.get_window_start(&self.boundaries).map(|start| (period, start)))
+            .collect();
+        let leaderboards = self.all();
+        let (leaderboards_ref, window_starts, progress_ref) = (&leaderboards, &window_starts, &progress);
+        let processed = database.for_each_document::<Player, _, _>(batch_size, |mut batch, batch_progress| async move {
+            batch.retain(|player| player.is_leaderboard_eligible_in(&self.realm, min_playtime));
+            join_all(leaderboards_ref.iter().map(|leaderboard| leaderboard.seed_rebuild_batch(&batch, window_starts))).await;
+            let _ = progress_ref.send(LeaderboardRebuildProgress { players_processed: batch_progress.processed, players_total: batch_progress.total, done: false });
+        }).await;
+        // pending increments land on the boards being replaced, the rebuilt ones already count them from the player documents
+        self.flush_pending().await;
+        join_all(leaderboards.iter().map(|leaderboard| leaderboard.finish_rebuild())).await;
+        let _ = progress.send(LeaderboardRebuildProgress { players_processed: processed, players_total: processed, done: true });
+        processed
+    }
+
+    // puts a player who just reached the playtime gate on the all-time boards with everything they did before,
+    // which the boards skipped at the time. periodic and trending boards only count what happens from here on.
+    // called from the socket, where `stats` already are the ones of this realm
+    pub async fn include_player(&self, player: &Player) {
+        self.set_all_time(&player.id_name(), &player.stats).await;
+        info!("{} reached the leaderboard playtime gate", player.id_name());
+    }
+
+    // only the all-time boards, the periodic ones roll over long before anyone counts as inactive
+    pub async fn evict_players(&self, id_names: &[String]) {
+        join_all(self.all().into_iter().map(|leaderboard| leaderboard.remove_all_time(id_names))).await;
+    }
+
+    // scores were capped at u32::MAX before, about 49 days of millis, so all-time playtime entries written from stats by
+    // rebuilds, restores and the playtime gate stopped there. entries at or past the old cap that are still short of
+    // the player's stats are set to them, entries built from increments alone already match and are left alone.
+    // dynamic boards weighing playtime only come right with a rebuild
+    // fails without repairing anything when the entries can't be read, so the caller can try again later
+    pub async fn repair_capped_scores(&self) -> anyhow::Result<u64> {
+        let cap = u32::MAX as u64;
+        let mut repaired = 0u64;
+        for score_type in [ScoreType::ServerPlaytime, ScoreType::GamePlaytime, ScoreType::FlagHoldTime] {
+            let leaderboard = self.from_score_type(score_type.clone());
+            let key = leaderboard.get_id(&LeaderboardPeriod::AllTime);
+            let key = &key;
+            let raw = leaderboard.cache.submit(|mut conn| async move {
+                redis::cmd("ZRANGEBYSCORE").arg(key).arg(cap).arg("+inf").arg("WITHSCORES")
+                    .query_async::<Connection, Vec<String>>(&mut conn).await
+            }).await??;
+            for entry in Leaderboard::strings_as_leaderboard_entries(raw, 1.0) {
+                let player = unwrap_helper::continue_default!(Database::find_by_id(&leaderboard.database.players, &entry.id).await);
+                let score = unwrap_helper::continue_default!(player.stats_in(&self.realm)).get_score(&score_type);
+                if score > entry.score {
+                    leaderboard.zadd_entries(key, &vec![(score, player.id_name())]).await;
+                    repaired += 1;
+                };
+            };
+        };
+        Ok(repaired)
+    }
+
+    // puts an evicted player back exactly as their stats stand, their periodic entries were never touched
+    pub async fn restore_player(&self, player: &Player) {
+        if let Some(stats) = player.stats_in(&self.realm) {
+            self.set_all_time(&player.id_name(), stats).await;
+        };
+    }
+
+    // every all-time entry set to what `stats` add up to, boards the stats give nothing on are left alone
+    async fn set_all_time(&self, id_name: &str, stats: &PlayerStats) {
+        join_all(self.all().into_iter().map(|leaderboard| {
+            let score = leaderboard.score.of(stats);
+            let entries = vec![(score, id_name.to_owned())];
+            async move {
+                if score != 0 {
+                    leaderboard.zadd_entries(&leaderboard.get_id(&LeaderboardPeriod::AllTime), &entries).await;
+                };
+            }
+        })).await;
+    }
+
+    // every built-in board followed by the dynamic ones
+    pub fn all(&self) -> Vec<&Leaderboard> {
+        ScoreType::iter().map(|score_type| self.from_score_type(score_type))
+            .chain(self.dynamic.iter().map(|leaderboard| leaderboard.as_ref()))
+            .collect()
+    }
+
+    pub fn find_dynamic(&self, name: &str) -> Option<&Leaderboard> {
+        self.dynamic.iter().find(|leaderboard| leaderboard.score.name() == name).map(|leaderboard| leaderboard.as_ref())
+    }
+
+    // a built-in score type or the name of a dynamic board
+    pub fn find(&self, name: &str) -> Option<&Leaderboard> {
+        match ScoreType::from_str(name) {
+            Ok(score_type) => Some(self.from_score_type(score_type)),
+            Err(_) => self.find_dynamic(name)
         }
     }
 
@@ -337,6 +820,7 @@ impl MarsLeaderboards {
         match score_type {
             ScoreType::Kills => &self.kills,
             ScoreType::Deaths => &self.deaths,
+            ScoreType::Assists => &self.assists,
             ScoreType::FirstBloods => &self.first_bloods,
             ScoreType::Wins => &self.wins,
             ScoreType::Losses => &self.losses,
@@ -370,5 +854,42 @@ impl MarsLeaderboards {
 pub struct LeaderboardEntry {
     pub id: String,
     pub name: String,
-    pub score: u32
+    pub score: u64
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::database::models::player::PlayerStats;
+
+    use super::{Leaderboard, LeaderboardPeriod, LeaderboardScore, ScoreType, period::PeriodBoundaries};
+
+    #[test]
+    fn weeks_across_new_year_share_a_key() {
+        let boundaries = PeriodBoundaries::default();
+        let december = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let january = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        assert_eq!(LeaderboardPeriod::Weekly.get_id_on(december, &boundaries), "2025:w:1");
+        assert_eq!(LeaderboardPeriod::Weekly.get_id_on(january, &boundaries), "2025:w:1");
+    }
+
+    #[test]
+    fn playtime_past_u32_seconds_keeps_its_order() {
+        // more than u32::MAX seconds, in millis
+        let longer = u32::MAX as u64 * 1000 + 2;
+        let shorter = u32::MAX as u64 * 1000 + 1;
+        let stats = |playtime: u64| PlayerStats { server_playtime: playtime, ..Default::default() };
+        let score = LeaderboardScore::Builtin(ScoreType::ServerPlaytime);
+        assert_eq!(stats(longer).get_score(&ScoreType::ServerPlaytime), longer);
+        assert!(score.of(&stats(longer)) > score.of(&stats(shorter)));
+
+        // as redis hands them back, highest first
+        let raw = vec![
+            String::from("a/Longer"), score.of(&stats(longer)).to_string(),
+            String::from("b/Shorter"), score.of(&stats(shorter)).to_string()
+        ];
+        let entries = Leaderboard::strings_as_leaderboard_entries(raw, 1.0);
+        assert_eq!(entries.iter().map(|entry| entry.score).collect::<Vec<u64>>(), vec![longer, shorter]);
+    }
 }
@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+
+use redis::aio::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::{LeaderboardPeriod, MarsLeaderboards};
+
+// below this many entries "top 50%" says more about the board than the player, only the position is given
+pub const MIN_PERCENTILE_POPULATION : u64 = 20;
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardPercentile {
+    // 1-based
+    pub position: u64,
+    pub population: u64,
+    // the share of the board at or above the player, rounded up to a tenth so the leader of a big board isn't "top 0%".
+    // `None` on boards smaller than `MIN_PERCENTILE_POPULATION`
+    pub top_percent: Option<f64>
+}
+
+impl BoardPercentile {
+    fn new(rank: u64, population: u64) -> Self {
+        let position = rank + 1;
+        // the board may have grown between the two reads, a position can't be past its end
+        let population = population.max(position);
+        let top_percent = if population < MIN_PERCENTILE_POPULATION {
+            None
+        } else {
+            Some((position as f64 * 1000.0 / population as f64).ceil() / 10.0)
+        };
+        BoardPercentile { position, population, top_percent }
+    }
+}
+
+impl MarsLeaderboards {
+    // every board in one pipeline, `None` for boards the player has no entry on
+    pub async fn get_percentiles(&self, id_name: &str, period: &LeaderboardPeriod) -> BTreeMap<String, Option<BoardPercentile>> {
+        let leaderboards = self.all();
+        let keys : Vec<String> = leaderboards.iter().map(|leaderboard| leaderboard.get_id(period)).collect();
+        let replies = self.kills.cache.submit(|mut conn| async move {
+            let mut pipe = redis::pipe();
+            for key in keys.iter() {
+                pipe.cmd("ZREVRANK").arg(key).arg(id_name).cmd("ZCARD").arg(key);
+            };
+            pipe.query_async::<Connection, Vec<(Option<u64>, u64)>>(&mut conn).await.ok()
+        }).await.unwrap_or(None).unwrap_or_default();
+        leaderboards.iter().zip(replies.into_iter().map(Some).chain(std::iter::repeat(None)))
+            .map(|(leaderboard, reply)| {
+                let percentile = match reply {
+                    Some((Some(rank), population)) => Some(BoardPercentile::new(rank, population)),
+                    _ => None
+                };
+                (leaderboard.score.name(), percentile)
+            })
+            .collect()
+    }
+}
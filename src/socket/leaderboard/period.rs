@@ -0,0 +1,48 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+// where the time-windowed boards roll over. windows start at local midnight in `timezone`, so
+// daily boards reset at the same wall-clock time all year even as the UTC offset changes
+#[derive(Debug, Clone)]
+pub struct PeriodBoundaries {
+    pub timezone: Tz,
+    pub week_start: Weekday
+}
+
+impl Default for PeriodBoundaries {
+    fn default() -> Self {
+        // a fixed UTC-4, which is what the boards used before the timezone was configurable
+        PeriodBoundaries { timezone: Tz::Etc__GMTPlus4, week_start: Weekday::Mon }
+    }
+}
+
+impl PeriodBoundaries {
+    pub fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.timezone)
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+
+    pub fn date_of(&self, millis: u64) -> NaiveDate {
+        match Utc.timestamp_millis_opt(millis as i64).single() {
+            Some(at) => at.with_timezone(&self.timezone).date_naive(),
+            None => self.today()
+        }
+    }
+
+    pub fn week_start_of(&self, date: NaiveDate) -> NaiveDate {
+        let days_since_start = (7 + date.weekday().num_days_from_monday() - self.week_start.num_days_from_monday()) % 7;
+        date - Duration::days(days_since_start as i64)
+    }
+
+    // the first instant of `date` in epoch millis. on a DST change that skips midnight this is the
+    // first hour that exists, and on one that repeats it the earlier of the two
+    pub fn start_of(&self, date: NaiveDate) -> Option<u64> {
+        (0..=3).find_map(|hour| {
+            let local = date.and_hms_opt(hour, 0, 0)?;
+            self.timezone.from_local_datetime(&local).earliest()
+        }).map(|start| start.timestamp_millis() as u64)
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use futures::future::join_all;
+use redis::aio::Connection;
+use serde::Serialize;
+
+use crate::{database::{Database, models::player::Player}, util::time::get_u64_time_millis};
+
+use super::{Leaderboard, LeaderboardPeriod, MarsLeaderboards};
+
+#[derive(Serialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardDrift {
+    pub mismatches: u64,
+    // board minus stats, summed. consistently positive or negative points at a write path that is off
+    pub net_drift: i64,
+    pub absolute_drift: u64
+}
+
+impl BoardDrift {
+    fn add(&mut self, other: &BoardDrift) {
+        self.mismatches += other.mismatches;
+        self.net_drift = self.net_drift.saturating_add(other.net_drift);
+        self.absolute_drift = self.absolute_drift.saturating_add(other.absolute_drift);
+    }
+}
+
+#[derive(Serialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciliationRun {
+    pub at: u64,
+    pub players_checked: u64,
+    pub mismatches: u64,
+    // only boards that had a mismatch
+    pub boards: BTreeMap<String, BoardDrift>
+}
+
+// totals since the API started
+#[derive(Serialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciliationMetrics {
+    pub runs: u64,
+    pub players_checked: u64,
+    pub mismatches: u64,
+    pub boards: BTreeMap<String, BoardDrift>,
+    pub last_run: Option<ReconciliationRun>
+}
+
+impl Leaderboard {
+    async fn all_time_scores(&self, id_names: &[String]) -> Vec<Option<f64>> {
+        let key = self.get_id(&LeaderboardPeriod::AllTime);
+        self.cache.submit(|mut conn| async move {
+            let mut pipe = redis::pipe();
+            for id_name in id_names.iter() {
+                pipe.cmd("ZSCORE").arg(&key).arg(id_name);
+            };
+            pipe.query_async::<Connection, Vec<Option<f64>>>(&mut conn).await.ok()
+        }).await.unwrap_or(None).unwrap_or_else(|| vec![None; id_names.len()])
+    }
+
+    // puts the players' all-time entries back at what their stats say, `None` when nothing needed fixing
+    async fn reconcile(&self, players: &[Player], id_names: &[String]) -> Option<BoardDrift> {
+        let scores = self.all_time_scores(id_names).await;
+        let mut drift = BoardDrift::default();
+        let mut corrections : Vec<(i64, String)> = Vec::new();
+        for ((player, id_name), score) in players.iter().zip(id_names.iter()).zip(scores) {
+            let expected = self.expected_score(player).unwrap_or(0);
+            let actual = score.map(|score| score as i64).unwrap_or(0);
+            if actual == expected {
+                continue;
+            };
+            let difference = actual.saturating_sub(expected);
+            info!("Leaderboard {} had {} at {} instead of {} ({:+})", self.score.name(), id_name, actual, expected, difference);
+            drift.mismatches += 1;
+            drift.net_drift = drift.net_drift.saturating_add(difference);
+            drift.absolute_drift = drift.absolute_drift.saturating_add(difference.unsigned_abs());
+            corrections.push((expected, id_name.clone()));
+        };
+        if corrections.is_empty() {
+            return None;
+        };
+        self.zadd_entries(&self.get_id(&LeaderboardPeriod::AllTime), &corrections).await;
+        Some(drift)
+    }
+}
+
+impl MarsLeaderboards {
+    // compares the all-time boards with the stats of a random sample of players and corrects them. the periodic
+    // boards can't be checked, stats aren't kept per period. players who are online, below the playtime gate or
+    // evicted for inactivity are left out since their boards differ from their stats on purpose
+    pub async fn reconcile_sample(&self, database: &Database, sample_size: u32, min_playtime: u64, inactive_after: u64) -> anyhow::Result<ReconciliationRun> {
+        let now = get_u64_time_millis();
+        let mut players = database.sample_players(sample_size).await?;
+        let player_ids : Vec<String> = players.iter().map(|player| player.id.clone()).collect();
+        let online = database.get_active_sessions_for_players(&player_ids).await;
+        players.retain(|player| {
+            !online.contains_key(&player.id)
+                && player.is_leaderboard_eligible_in(&self.realm, min_playtime)
+                && (inactive_after == 0 || now.saturating_sub(player.last_joined_at.millis()) <= inactive_after)
+        });
+        // buffered increments are already in the stats, they have to reach the boards before comparing
+        self.flush_pending().await;
+
+        let id_names : Vec<String> = players.iter().map(|player| player.id_name()).collect();
+        let leaderboards = self.all();
+        let drifts = join_all(leaderboards.iter().map(|leaderboard| leaderboard.reconcile(&players, &id_names))).await;
+        let mut run = ReconciliationRun { at: now, players_checked: players.len() as u64, mismatches: 0, boards: BTreeMap::new() };
+        for (leaderboard, drift) in leaderboards.iter().zip(drifts) {
+            if let Some(drift) = drift {
+                run.mismatches += drift.mismatches;
+                run.boards.insert(leaderboard.score.name(), drift);
+            };
+        };
+
+        let mut metrics = match self.reconciliation.lock() {
+            Ok(metrics) => metrics,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        metrics.runs += 1;
+        metrics.players_checked += run.players_checked;
+        metrics.mismatches += run.mismatches;
+        for (board, drift) in run.boards.iter() {
+            metrics.boards.entry(board.clone()).or_default().add(drift);
+        };
+        metrics.last_run = Some(run.clone());
+        Ok(run)
+    }
+
+    pub fn reconciliation_metrics(&self) -> ReconciliationMetrics {
+        match self.reconciliation.lock() {
+            Ok(metrics) => metrics.clone(),
+            Err(poisoned) => poisoned.into_inner().clone()
+        }
+    }
+}
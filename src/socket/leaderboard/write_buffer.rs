@@ -0,0 +1,188 @@
+use std::{collections::HashMap, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, time::Instant};
+
+use redis::aio::Connection;
+
+use crate::database::cache::RedisAdapter;
+
+use super::decay::{weigh, INCREMENT_SCRIPT};
+
+#[derive(Default)]
+struct PendingIncrements {
+    // sorted set key -> member -> pending increment
+    by_key: HashMap<String, HashMap<String, u64>>,
+    // trending board key -> pending weighted increments, see `decay`
+    trending: HashMap<String, PendingTrending>,
+    // members across every key, what `max_entries` bounds
+    entries: usize
+}
+
+struct PendingTrending {
+    epoch_key: String,
+    half_life: u64,
+    // the weights are relative to this, the time of the first increment since the last flush
+    reference: u64,
+    members: HashMap<String, f64>
+}
+
+// leaderboard increments collected in memory and applied in one pipeline per flush.
+// reads do not see pending deltas, so boards may lag behind by up to one flush interval.
+// a failed flush keeps its increments for the next one, so they survive redis being down for a while
+pub struct LeaderboardWriteBuffer {
+    redis: Arc<RedisAdapter>,
+    pending: Mutex<PendingIncrements>,
+    max_entries: usize,
+    dropped: AtomicU64
+}
+
+impl LeaderboardWriteBuffer {
+    pub fn new(redis: Arc<RedisAdapter>, max_entries: usize) -> Self {
+        Self { redis, pending: Mutex::new(PendingIncrements::default()), max_entries, dropped: AtomicU64::new(0) }
+    }
+
+    // increments for members that aren't pending yet are dropped once the buffer is full
+    pub fn add(&self, key: String, member: &str, delta: u64) {
+        let mut pending = match self.pending.lock() {
+            Ok(pending) => pending,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let PendingIncrements { by_key, entries, .. } = &mut *pending;
+        let members = by_key.entry(key).or_default();
+        match members.get_mut(member) {
+            Some(pending_delta) => *pending_delta += delta,
+            None if *entries >= self.max_entries => { self.dropped.fetch_add(1, Ordering::Relaxed); },
+            None => {
+                members.insert(member.to_owned(), delta);
+                *entries += 1;
+            }
+        };
+    }
+
+    // `delta` as made at `at`, weighed against the board's epoch only when it is flushed
+    pub fn add_trending(&self, key: String, epoch_key: String, half_life: u64, member: &str, delta: f64, at: u64) {
+        let mut pending = match self.pending.lock() {
+            Ok(pending) => pending,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let PendingIncrements { trending, entries, .. } = &mut *pending;
+        let board = trending.entry(key).or_insert_with(|| PendingTrending { epoch_key, half_life, reference: at, members: HashMap::new() });
+        let weighted = weigh(delta, at, board.reference, board.half_life);
+        match board.members.get_mut(member) {
+            Some(pending_delta) => *pending_delta += weighted,
+            None if *entries >= self.max_entries => { self.dropped.fetch_add(1, Ordering::Relaxed); },
+            None => {
+                board.members.insert(member.to_owned(), weighted);
+                *entries += 1;
+            }
+        };
+    }
+
+    // drops what is pending for `members` on `keys`, so a flush can't put removed entries back
+    pub fn discard(&self, keys: &[String], members: &[String]) {
+        let mut pending = match self.pending.lock() {
+            Ok(pending) => pending,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        let PendingIncrements { by_key, trending, entries } = &mut *pending;
+        for key in keys {
+            if let Some(board) = trending.get_mut(key) {
+                for member in members {
+                    if board.members.remove(member).is_some() {
+                        *entries -= 1;
+                    };
+                };
+                if board.members.is_empty() {
+                    trending.remove(key);
+                };
+            };
+            let key_members = match by_key.get_mut(key) {
+                Some(key_members) => key_members,
+                None => continue
+            };
+            for member in members {
+                if key_members.remove(member).is_some() {
+                    *entries -= 1;
+                };
+            };
+            if key_members.is_empty() {
+                by_key.remove(key);
+            };
+        };
+    }
+
+    pub fn pending_entries(&self) -> usize {
+        match self.pending.lock() {
+            Ok(pending) => pending.entries,
+            Err(poisoned) => poisoned.into_inner().entries
+        }
+    }
+
+    // increments lost to a full buffer since the API started
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn requeue(&self, increments: PendingIncrements) {
+        for (key, members) in increments.by_key.into_iter() {
+            for (member, delta) in members.into_iter() {
+                self.add(key.clone(), &member, delta);
+            };
+        };
+        for (key, board) in increments.trending.into_iter() {
+            for (member, delta) in board.members.into_iter() {
+                self.add_trending(key.clone(), board.epoch_key.clone(), board.half_life, &member, delta, board.reference);
+            };
+        };
+    }
+
+    // returns how many increments were written, a failed flush puts them back
+    pub async fn flush(&self) -> usize {
+        let pending = {
+            let mut pending = match self.pending.lock() {
+                Ok(pending) => pending,
+                Err(poisoned) => poisoned.into_inner()
+            };
+            std::mem::take(&mut *pending)
+        };
+        if pending.by_key.is_empty() && pending.trending.is_empty() {
+            return 0;
+        };
+        // atomic, so a failed flush applied none of its increments and requeueing them can't count any twice
+        let mut pipeline = redis::pipe();
+        pipeline.atomic();
+        let mut increments = 0usize;
+        for (key, members) in pending.by_key.iter() {
+            for (member, delta) in members.iter() {
+                pipeline.cmd("ZINCRBY").arg(key).arg(*delta).arg(member).ignore();
+                increments += 1;
+            };
+        };
+        for (key, board) in pending.trending.iter() {
+            for (member, delta) in board.members.iter() {
+                pipeline.cmd("EVAL").arg(INCREMENT_SCRIPT).arg(2).arg(key).arg(&board.epoch_key)
+                    .arg(*delta).arg(board.reference).arg(board.half_life).arg(member).ignore();
+                increments += 1;
+            };
+        };
+        let started = Instant::now();
+        let result = self.redis.submit(|mut conn| async move {
+            pipeline.query_async::<Connection, ()>(&mut conn).await
+        }).await;
+        let error = match result {
+            Ok(Ok(())) => {
+                self.redis.metrics.record_leaderboard_write(started.elapsed());
+                return increments;
+            },
+            Ok(Err(e)) => {
+                self.redis.metrics.record_redis_error();
+                e.to_string()
+            },
+            Err(e) => e.to_string()
+        };
+        // redis being down is logged once by the adapter, not on every flush
+        if self.redis.is_available() {
+            warn!("Could not flush {} leaderboard increments, keeping them for the next flush: {}", increments, error);
+        };
+        self.requeue(pending);
+        0
+    }
+}
@@ -1,6 +1,6 @@
 
 
-use crate::{socket::{player::{player_listener::PlayerListener, player_events::PlayerDeathData}, r#match::match_events::MatchEndData, server::server_context::ServerContext}, database::models::{player::{PlayerRecord, ProjectileRecord, FirstBloodRecord}, death::DamageCause, participant::Participant, r#match::Match}, util::time::get_u64_time_millis};
+use crate::{socket::{player::{player_listener::PlayerListener, player_events::PlayerDeathData}, r#match::match_events::MatchEndData, server::server_context::ServerContext}, database::models::{player::{PlayerRecord, ProjectileRecord, FirstBloodRecord}, death::DamageCause, participant::Participant, r#match::Match}, util::tracking::StatCategory};
 use async_trait::async_trait;
 
 pub struct MapRecordListener {}
@@ -17,9 +17,12 @@ impl PlayerListener for MapRecordListener {
         data: &mut PlayerDeathData, 
         first_blood: bool
     ) { 
+        if !current_match.is_tracking(StatCategory::Records) || context.is_frozen(&server_context.api_state).await {
+            return;
+        };
         {
             if first_blood {
-                let time = get_u64_time_millis() - current_match.started_at.unwrap();
+                let time = current_match.elapsed_at(server_context.event_time);
                 let record_beat = match current_match.level.records.fastest_first_blood.as_ref() {
                     Some(first_blood_record) => {
                         time < first_blood_record.time
@@ -66,6 +69,9 @@ impl PlayerListener for MapRecordListener {
         context: &mut Self::Context, 
         amount: u32
     ) {
+        if !current_match.is_tracking(StatCategory::Records) || context.is_frozen(&server_context.api_state).await {
+            return;
+        };
         {
             let current_record = match &current_match.level.records.highest_killstreak {
                 Some(ks_record) => { ks_record.value },
@@ -91,6 +97,9 @@ impl PlayerListener for MapRecordListener {
         context: &mut Self::Context, 
         held_time: u64, 
     ) {
+        if !current_match.is_tracking(StatCategory::Records) || context.is_frozen(&server_context.api_state).await {
+            return;
+        };
         let record_time = &current_match.level.records.fastest_wool_capture;
         if record_time.is_none() || held_time < record_time.as_ref().unwrap().value {
             current_match.level.records.fastest_wool_capture = Some(PlayerRecord { 
@@ -112,6 +121,9 @@ impl PlayerListener for MapRecordListener {
         context: &mut Self::Context, 
         held_time: u64, 
     ) {
+        if !current_match.is_tracking(StatCategory::Records) || context.is_frozen(&server_context.api_state).await {
+            return;
+        };
         let record_time = &current_match.level.records.fastest_flag_capture;
         if record_time.is_none() || held_time < record_time.as_ref().unwrap().value {
             current_match.level.records.fastest_flag_capture = Some(PlayerRecord { 
@@ -126,6 +138,57 @@ impl PlayerListener for MapRecordListener {
         ).await;
     }
 
+    async fn on_core_leak(
+        &self, 
+        server_context: &mut ServerContext, 
+        current_match: &mut Match, 
+        context: &mut Self::Context, 
+        _percentage: f32, 
+        _block_count: u32
+    ) {
+        if !current_match.is_tracking(StatCategory::Records) || context.is_frozen(&server_context.api_state).await {
+            return;
+        };
+        let time = current_match.elapsed_at(server_context.event_time);
+        let record_time = &current_match.level.records.fastest_core_leak;
+        if PlayerRecord::is_beaten(record_time, time) {
+            current_match.level.records.fastest_core_leak = Some(PlayerRecord { 
+                match_id: current_match.id.clone(), 
+                player: context.get_simple_player(), 
+                value: time 
+            });
+        }
+
+        server_context.api_state.match_cache.set(
+            &server_context.api_state.database, &current_match.id, current_match, false
+        ).await;
+    }
+
+    async fn on_control_point_capture(
+        &self, 
+        server_context: &mut ServerContext, 
+        current_match: &mut Match, 
+        context: &mut Self::Context, 
+        _contributors: u32, 
+    ) {
+        if !current_match.is_tracking(StatCategory::Records) || context.is_frozen(&server_context.api_state).await {
+            return;
+        };
+        let time = current_match.elapsed_at(server_context.event_time);
+        let record_time = &current_match.level.records.fastest_control_point_capture;
+        if PlayerRecord::is_beaten(record_time, time) {
+            current_match.level.records.fastest_control_point_capture = Some(PlayerRecord { 
+                match_id: current_match.id.clone(), 
+                player: context.get_simple_player(), 
+                value: time 
+            });
+        }
+
+        server_context.api_state.match_cache.set(
+            &server_context.api_state.database, &current_match.id, current_match, false
+        ).await;
+    }
+
     async fn on_match_end_v2(
         &self,
         server_context: &mut ServerContext, 
@@ -133,6 +196,9 @@ impl PlayerListener for MapRecordListener {
         context: &mut Self::Context, 
         _end_data: &mut MatchEndData
     ) {
+        if !current_match.is_tracking(StatCategory::Records) || context.is_frozen(&server_context.api_state).await {
+            return;
+        };
         {
             let kills = context.stats.kills;
             let record_kills = 
@@ -0,0 +1,63 @@
+use futures::future::join_all;
+
+use crate::{database::models::{r#match::{AbandonReason, Match, MatchState}, player::PlayerStats, participant::KillCounts}, MarsAPIState, util::tracking::StatCategory};
+
+// wins, losses, ties and matches played are only credited when a match ends, so an abandoned match never
+// counts towards them. kills, deaths and assists were credited as they happened and are only taken back
+// when `abandonment.count-partial-kills` is off, or when the match was given a result that can't be right
+pub async fn abandon_match(state: &MarsAPIState, current_match: &mut Match, reason: AbandonReason, at: u64) {
+    if matches!(current_match.get_state(), MatchState::Post | MatchState::Abandoned) {
+        return;
+    };
+    let was_in_progress = current_match.get_state() == MatchState::InProgress;
+    current_match.abandon(reason, at);
+
+    if was_in_progress && (reason == AbandonReason::InvalidResult || !state.config.options.abandonment.count_partial_kills) {
+        revert_partial_kills(state, current_match).await;
+    };
+
+    state.match_cache.set_with_expiry(&state.database, &current_match.id, current_match, true, Some(3_600_000)).await;
+    info!("Match {} on '{}' abandoned: {}", current_match.id, current_match.server_id, reason);
+}
+
+// only what was credited comes off, see `KillCredits`. per-gamemode counters are taken back from the
+// gamemodes the match credits, board credits from the periods they were made in
+async fn revert_partial_kills(state: &MarsAPIState, current_match: &Match) {
+    let mut players = Vec::new();
+    let gamemodes = current_match.get_credited_gamemodes(StatCategory::Kills);
+    let leaderboards = state.leaderboards_for(&current_match.realm);
+    for participant in current_match.participants.values() {
+        let credited = &participant.credited;
+        if credited.is_empty() {
+            continue;
+        };
+        let id_name = participant.get_id_name();
+        for credit in credited.boards.iter() {
+            leaderboards.kills.retract(&id_name, credit.counts.kills, credit.at).await;
+            leaderboards.deaths.retract(&id_name, credit.counts.deaths, credit.at).await;
+            leaderboards.assists.retract(&id_name, credit.counts.assists, credit.at).await;
+        };
+        if credited.profile.is_empty() && credited.gamemodes.is_empty() {
+            continue;
+        };
+        let mut player = match state.player_cache.get(&state.database, &participant.get_name_lower()).await {
+            Some(player) => player,
+            None => continue
+        };
+        let take_back = |stats: &mut PlayerStats, counts: &KillCounts| {
+            stats.kills = stats.kills.saturating_sub(counts.kills);
+            stats.deaths = stats.deaths.saturating_sub(counts.deaths);
+            stats.assists = stats.assists.saturating_sub(counts.assists);
+        };
+        player.swap_realm_stats(&current_match.realm);
+        take_back(&mut player.stats, &credited.profile);
+        for gamemode in gamemodes.iter() {
+            if let Some(stats) = player.gamemode_stats.get_mut(gamemode) {
+                take_back(stats, &credited.gamemodes);
+            };
+        };
+        player.swap_realm_stats(&current_match.realm);
+        players.push(player);
+    };
+    join_all(players.iter().map(|player| state.player_cache.set(&state.database, &player.name, player, true))).await;
+}
@@ -2,7 +2,7 @@ use std::collections::{HashSet, HashMap};
 
 use serde::{Serialize, Deserialize};
 
-use crate::database::models::{participant::SimpleParticipant, r#match::Match};
+use crate::{database::models::{participant::SimpleParticipant, r#match::Match, match_outcome::MatchOutcome, damage::Damage}, socket::participant::participant_context::PlayerMatchResult};
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -14,7 +14,10 @@ pub struct MatchStartData {
 #[serde(rename_all = "camelCase")]
 pub struct MatchEndData {
     pub winning_parties: Vec<String>,
-    pub big_stats: HashMap<String, BigStats>
+    pub big_stats: HashMap<String, BigStats>,
+    // per player id, for gamemodes whose outcomes are not just win/loss/tie
+    #[serde(default)]
+    pub outcomes: HashMap<String, MatchOutcome>
 }
 
 impl MatchEndData {
@@ -22,6 +25,17 @@ impl MatchEndData {
         self.winning_parties.is_empty() || self.winning_parties.len() == i_match.parties.len()
     }
 
+    // the outcome derived from the winning parties, followed by the one the server reported if it differs
+    pub fn get_outcomes(&self, id: &String, result: &PlayerMatchResult) -> Vec<MatchOutcome> {
+        let mut outcomes : Vec<MatchOutcome> = MatchOutcome::from_result(result).into_iter().collect();
+        if let Some(custom) = self.outcomes.get(id).map(|outcome| MatchOutcome::new(outcome.name())) {
+            if !outcomes.contains(&custom) {
+                outcomes.push(custom);
+            };
+        };
+        outcomes
+    }
+
     pub fn get_stats_for_participant(&mut self, id: &String) -> &mut BigStats {
         if !self.big_stats.contains_key(id) {
             self.big_stats.insert(id.to_owned(), BigStats::default());
@@ -36,9 +50,9 @@ pub struct BigStats {
     pub blocks: PlayerBlocksData,
     pub bow_shots_taken: u32,
     pub bow_shots_hit: u32,
-    pub damage_given: f64,
-    pub damage_taken: f64,
-    pub damage_given_bow: f64
+    pub damage_given: Damage,
+    pub damage_taken: Damage,
+    pub damage_given_bow: Damage
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -2,7 +2,7 @@ use std::collections::HashMap;
 
 use uuid::Uuid;
 
-use crate::{socket::{server::{server_context::ServerContext, server_events::MatchLoadData}, socket_router::SocketError}, database::{Database, models::{r#match::{Match, Party, MatchState}, participant::Participant}}, util::{r#macro::unwrap_helper, time::get_u64_time_millis}};
+use crate::{socket::{server::{server_context::ServerContext, server_events::MatchLoadData}, socket_router::SocketError, objective::objective_validation::ObjectiveState}, database::{Database, models::{r#match::{Match, Party, MatchState}, participant::Participant}}, util::{r#macro::unwrap_helper, time::Timestamp}};
 
 use super::match_events::{MatchStartData, MatchEndData};
 
@@ -13,7 +13,7 @@ pub struct MatchPhaseListener<'a> {
 impl MatchPhaseListener<'_> {
     pub async fn on_load(&mut self, data: MatchLoadData) -> Result<(), SocketError> {
         let mut level = unwrap_helper::return_default!(Database::find_by_id(&self.server.api_state.database.levels, &data.map_id).await, Err(SocketError::InvalidMatchState));
-        let time_millis = get_u64_time_millis();
+        let time_millis = self.server.event_time;
         let match_id = Uuid::new_v4().to_string();
         level.goals = Some(data.goals);
         level.last_match_id = Some(match_id.clone());
@@ -23,16 +23,24 @@ impl MatchPhaseListener<'_> {
             parties.insert(party.name.clone(), Party { name: party.name, alias: party.alias, color: party.color, min: party.min, max: party.max });
         }
 
+        let tracked_stats = self.server.api_state.config.options.stat_tracking.tracked(&level.gamemodes);
         let new_match = Match {
             id: match_id,
-            loaded_at: time_millis,
+            loaded_at: Timestamp::from_millis(time_millis),
             started_at: None,
             ended_at: None,
             level,
             parties,
             participants: HashMap::new(),
+            participant_ids: Vec::new(),
             server_id: self.server.id.clone(),
-            first_blood: None
+            first_blood: None,
+            scoreboard: Vec::new(),
+            abandoned: None,
+            results: HashMap::new(),
+            objectives: ObjectiveState::default(),
+            realm: self.server.realm.clone(),
+            tracked_stats: Some(tracked_stats)
         };
 
 
@@ -47,7 +55,7 @@ impl MatchPhaseListener<'_> {
             return Err(SocketError::InvalidMatchState)
         };
 
-        current_match.started_at = Some(get_u64_time_millis());
+        current_match.started_at = Some(Timestamp::from_millis(self.server.event_time));
 
         let participants : Vec<Participant> = data.participants.into_iter().map(|p| { Participant::from_simple(p) }).collect();
         current_match.save_participants(participants);
@@ -60,7 +68,7 @@ impl MatchPhaseListener<'_> {
         if MatchState::InProgress != current_match.get_state() {
             return Err(SocketError::InvalidMatchState)
         };
-        current_match.ended_at = Some(get_u64_time_millis());
+        current_match.ended_at = Some(Timestamp::from_millis(self.server.event_time));
         info!("({}) Match ended: {}", self.server.id, current_match.id);
         Ok(current_match)
     }
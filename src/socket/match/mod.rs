@@ -1,2 +1,4 @@
 pub mod match_phase_listener;
 pub mod match_events;
+pub mod abandonment;
+pub mod result_verification;
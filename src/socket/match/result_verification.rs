@@ -0,0 +1,101 @@
+use std::{collections::HashMap, str::FromStr};
+
+use strum_macros::{Display, EnumString};
+
+use crate::database::models::{level::LevelGamemode, r#match::Match};
+
+use super::match_events::MatchEndData;
+
+// what a reported match result has to satisfy before anything is credited, ordered from least to most strict.
+// `ffa` checks that winners and players are on parties the match has and that no party has more players than
+// it can hold, `team` also needs every player of a party to end up with the same outcome
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ResultRules {
+    Off,
+    Ffa,
+    Team
+}
+
+// per gamemode, the ones that are not configured use `default`
+#[derive(Debug, Clone)]
+pub struct ResultVerification {
+    default: ResultRules,
+    gamemodes: HashMap<LevelGamemode, ResultRules>
+}
+
+impl Default for ResultVerification {
+    fn default() -> Self {
+        ResultVerification { default: ResultRules::Off, gamemodes: HashMap::new() }
+    }
+}
+
+impl ResultVerification {
+    // `gamemode_key` is a gamemode key or `default`
+    pub fn insert(&mut self, gamemode_key: &str, rules: &str) {
+        let rules = match ResultRules::from_str(rules.trim()) {
+            Ok(rules) => rules,
+            Err(_) => {
+                warn!("Unknown result verification '{}' for {}", rules, gamemode_key);
+                return;
+            }
+        };
+        if gamemode_key.eq_ignore_ascii_case("default") {
+            self.default = rules;
+        } else {
+            self.gamemodes.insert(LevelGamemode::from_key(&gamemode_key.trim().to_uppercase()), rules);
+        };
+    }
+
+    // a level with several gamemodes is held to the strictest of them
+    pub fn rules_for(&self, gamemodes: &[LevelGamemode]) -> ResultRules {
+        gamemodes.iter()
+            .map(|gamemode| self.gamemodes.get(gamemode).copied().unwrap_or(self.default))
+            .max()
+            .unwrap_or(self.default)
+    }
+
+    // `Err` with why the result can't be right
+    pub fn verify(&self, current_match: &Match, end: &MatchEndData) -> Result<(), String> {
+        let rules = self.rules_for(&current_match.level.gamemodes);
+        if rules == ResultRules::Off {
+            return Ok(());
+        };
+        if let Some(party) = end.winning_parties.iter().find(|party| !current_match.parties.contains_key(*party)) {
+            return Err(format!("winning party '{}' is not in the match", party));
+        };
+        if let Some(id) = end.outcomes.keys().find(|id| !current_match.participants.contains_key(*id)) {
+            return Err(format!("outcome reported for {}, who is not in the match", id));
+        };
+
+        let mut members : HashMap<&str, Vec<&String>> = HashMap::new();
+        for participant in current_match.participants.values() {
+            if let Some(party_name) = &participant.party_name {
+                members.entry(party_name.as_str()).or_default().push(&participant.id);
+            };
+        };
+        for (party_name, ids) in members.iter() {
+            let party = match current_match.parties.get(*party_name) {
+                Some(party) => party,
+                None => return Err(format!("players are on party '{}', which is not in the match", party_name))
+            };
+            if party.max > 0 && ids.len() > party.max as usize {
+                return Err(format!("party '{}' has {} players but holds {}", party_name, ids.len(), party.max));
+            };
+        };
+
+        if rules == ResultRules::Team {
+            for (party_name, ids) in members.iter() {
+                let mut outcomes = ids.iter().map(|id| {
+                    let result = current_match.get_participant_match_result(&current_match.participants[id.as_str()], end);
+                    end.get_outcomes(id, &result)
+                });
+                let first = outcomes.next().unwrap_or_default();
+                if outcomes.any(|outcome| outcome != first) {
+                    return Err(format!("players of party '{}' were given different outcomes", party_name));
+                };
+            };
+        };
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 pub mod leaderboard;
 pub mod socket_handler;
 pub mod socket_router;
+pub mod socket_encoding;
+pub mod event_time;
 pub mod event_type;
 pub mod server;
 pub mod r#match;
@@ -1 +1,2 @@
 pub mod objective_events;
+pub mod objective_validation;
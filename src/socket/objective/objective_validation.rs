@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::database::models::r#match::{Match, MatchState};
+
+// `lenient` logs events that don't fit the match but still credits them, `strict` drops them.
+// events naming players who aren't in the match are always dropped, there is no one to credit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ObjectiveValidation {
+    Off,
+    Lenient,
+    Strict
+}
+
+// what the match has seen happen to its objectives, kept on the match so it survives a restart
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectiveState {
+    pub destroyed: HashSet<String>,
+    pub leaked_cores: HashSet<String>,
+    pub placed_wools: HashSet<String>,
+    // objective id -> id of the player carrying it
+    pub flag_carriers: HashMap<String, String>,
+    // several players of a team can each carry a copy of the same wool
+    #[serde(default, deserialize_with = "deserialize_wool_carriers")]
+    pub wool_carriers: HashMap<String, HashSet<String>>,
+    // control point id -> party holding it
+    pub point_owners: HashMap<String, String>
+}
+
+// matches stored before wools could have several carriers kept one per wool
+fn deserialize_wool_carriers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, HashSet<String>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Carriers {
+        Many(HashSet<String>),
+        One(String)
+    }
+    let carriers : HashMap<String, Carriers> = HashMap::deserialize(deserializer)?;
+    Ok(carriers.into_iter().map(|(wool_id, carriers)| match carriers {
+        Carriers::Many(carriers) => (wool_id, carriers),
+        Carriers::One(carrier) => (wool_id, HashSet::from([carrier]))
+    }).collect())
+}
+
+impl ObjectiveState {
+    pub fn carries_wool(&self, wool_id: &str, player_id: &str) -> bool {
+        self.wool_carriers.get(wool_id).map(|carriers| carriers.contains(player_id)).unwrap_or(false)
+    }
+
+    // a player who dies or leaves their party drops whatever they were carrying
+    pub fn release_carrier(&mut self, player_id: &str) {
+        self.flag_carriers.retain(|_, carrier| carrier != player_id);
+        self.wool_carriers.values_mut().for_each(|carriers| { carriers.remove(player_id); });
+        self.wool_carriers.retain(|_, carriers| !carriers.is_empty());
+    }
+}
+
+pub enum ObjectiveEvent<'a> {
+    DestroyableDamage { destroyable_id: &'a str, player_id: &'a str },
+    DestroyableDestroy { destroyable_id: &'a str, player_ids: Vec<&'a str> },
+    CoreLeak { core_id: &'a str, player_ids: Vec<&'a str> },
+    FlagPickup { flag_id: &'a str, player_id: &'a str },
+    FlagDrop { flag_id: &'a str, player_id: &'a str },
+    FlagCapture { flag_id: &'a str, player_id: &'a str },
+    FlagDefend { flag_id: &'a str, player_id: &'a str },
+    WoolPickup { wool_id: &'a str, player_id: &'a str },
+    WoolDrop { wool_id: &'a str, player_id: &'a str },
+    WoolCapture { wool_id: &'a str, player_id: &'a str },
+    WoolDefend { wool_id: &'a str, player_id: &'a str },
+    ControlPointCapture { point_id: &'a str, party_name: &'a str, player_ids: Vec<&'a str> }
+}
+
+pub enum ObjectiveRejection {
+    // always dropped, whatever the validation mode
+    NotParticipating(String),
+    Invalid(String)
+}
+
+impl<'a> ObjectiveEvent<'a> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DestroyableDamage { .. } => "destroyable damage",
+            Self::DestroyableDestroy { .. } => "destroyable destroy",
+            Self::CoreLeak { .. } => "core leak",
+            Self::FlagPickup { .. } => "flag pickup",
+            Self::FlagDrop { .. } => "flag drop",
+            Self::FlagCapture { .. } => "flag capture",
+            Self::FlagDefend { .. } => "flag defend",
+            Self::WoolPickup { .. } => "wool pickup",
+            Self::WoolDrop { .. } => "wool drop",
+            Self::WoolCapture { .. } => "wool capture",
+            Self::WoolDefend { .. } => "wool defend",
+            Self::ControlPointCapture { .. } => "control point capture"
+        }
+    }
+
+    fn player_ids(&self) -> Vec<&'a str> {
+        match self {
+            Self::DestroyableDestroy { player_ids, .. } | Self::CoreLeak { player_ids, .. } | Self::ControlPointCapture { player_ids, .. } => player_ids.clone(),
+            Self::DestroyableDamage { player_id, .. } | Self::FlagPickup { player_id, .. } | Self::FlagDrop { player_id, .. }
+                | Self::FlagCapture { player_id, .. } | Self::FlagDefend { player_id, .. } | Self::WoolPickup { player_id, .. }
+                | Self::WoolDrop { player_id, .. } | Self::WoolCapture { player_id, .. } | Self::WoolDefend { player_id, .. } => vec![*player_id]
+        }
+    }
+
+    // maps loaded without goals can't be checked for unknown objectives
+    fn is_known(&self, current_match: &Match) -> bool {
+        let goals = match &current_match.level.goals {
+            Some(goals) => goals,
+            None => return true
+        };
+        match self {
+            Self::DestroyableDamage { destroyable_id, .. } | Self::DestroyableDestroy { destroyable_id, .. } => goals.destroyables.iter().any(|goal| goal.id == *destroyable_id),
+            Self::CoreLeak { core_id, .. } => goals.cores.iter().any(|goal| goal.id == *core_id),
+            Self::FlagPickup { flag_id, .. } | Self::FlagDrop { flag_id, .. } | Self::FlagCapture { flag_id, .. } | Self::FlagDefend { flag_id, .. } => goals.flags.iter().any(|goal| goal.id == *flag_id),
+            Self::WoolPickup { wool_id, .. } | Self::WoolDrop { wool_id, .. } | Self::WoolCapture { wool_id, .. } | Self::WoolDefend { wool_id, .. } => goals.wools.iter().any(|goal| goal.id == *wool_id),
+            Self::ControlPointCapture { point_id, .. } => goals.control_points.iter().any(|goal| goal.id == *point_id)
+        }
+    }
+
+    pub fn check(&self, current_match: &Match) -> Result<(), ObjectiveRejection> {
+        if let Some(player_id) = self.player_ids().into_iter().find(|player_id| !current_match.participants.contains_key(*player_id)) {
+            return Err(ObjectiveRejection::NotParticipating(format!("{} is not in the match", player_id)));
+        };
+        let invalid = |reason: String| Err(ObjectiveRejection::Invalid(reason));
+        if current_match.get_state() != MatchState::InProgress {
+            return invalid(String::from("the match is not in progress"));
+        };
+        if !self.is_known(current_match) {
+            return invalid(String::from("the objective is not on the map"));
+        };
+        let state = &current_match.objectives;
+        let carrier_is = |carriers: &HashMap<String, String>, id: &str, player_id: &str| carriers.get(id).map(|carrier| carrier == player_id).unwrap_or(false);
+        match self {
+            Self::DestroyableDamage { destroyable_id, .. } | Self::DestroyableDestroy { destroyable_id, .. } if state.destroyed.contains(*destroyable_id) => {
+                invalid(format!("destroyable {} was already destroyed", destroyable_id))
+            },
+            Self::CoreLeak { core_id, .. } if state.leaked_cores.contains(*core_id) => invalid(format!("core {} already leaked", core_id)),
+            Self::FlagPickup { flag_id, .. } if state.flag_carriers.contains_key(*flag_id) => {
+                invalid(format!("flag {} is already carried by {}", flag_id, state.flag_carriers[*flag_id]))
+            },
+            Self::FlagDrop { flag_id, player_id } if !carrier_is(&state.flag_carriers, flag_id, player_id) => invalid(format!("{} is not carrying flag {}", player_id, flag_id)),
+            Self::FlagCapture { flag_id, player_id } if !carrier_is(&state.flag_carriers, flag_id, player_id) => invalid(format!("{} captured flag {} without carrying it", player_id, flag_id)),
+            Self::WoolPickup { wool_id, .. } | Self::WoolCapture { wool_id, .. } if state.placed_wools.contains(*wool_id) => invalid(format!("wool {} was already placed", wool_id)),
+            Self::WoolCapture { wool_id, player_id } if state.wool_carriers.contains_key(*wool_id) && !state.carries_wool(wool_id, player_id) => {
+                invalid(format!("{} captured wool {} without carrying it", player_id, wool_id))
+            },
+            Self::WoolDrop { wool_id, player_id } if !state.carries_wool(wool_id, player_id) => invalid(format!("{} is not carrying wool {}", player_id, wool_id)),
+            Self::ControlPointCapture { point_id, party_name, .. } if state.point_owners.get(*point_id).map(|owner| owner == party_name).unwrap_or(false) => {
+                invalid(format!("control point {} is already held by {}", point_id, party_name))
+            },
+            _ => Ok(())
+        }
+    }
+
+    pub fn apply(&self, state: &mut ObjectiveState) {
+        match self {
+            Self::DestroyableDestroy { destroyable_id, .. } => { state.destroyed.insert(destroyable_id.to_string()); },
+            Self::CoreLeak { core_id, .. } => { state.leaked_cores.insert(core_id.to_string()); },
+            Self::FlagPickup { flag_id, player_id } => { state.flag_carriers.insert(flag_id.to_string(), player_id.to_string()); },
+            Self::FlagDrop { flag_id, .. } | Self::FlagCapture { flag_id, .. } => { state.flag_carriers.remove(*flag_id); },
+            Self::WoolPickup { wool_id, player_id } => { state.wool_carriers.entry(wool_id.to_string()).or_default().insert(player_id.to_string()); },
+            Self::WoolDrop { wool_id, player_id } => {
+                if let Some(carriers) = state.wool_carriers.get_mut(*wool_id) {
+                    carriers.remove(*player_id);
+                    if carriers.is_empty() {
+                        state.wool_carriers.remove(*wool_id);
+                    };
+                };
+            },
+            Self::WoolCapture { wool_id, .. } => {
+                state.wool_carriers.remove(*wool_id);
+                state.placed_wools.insert(wool_id.to_string());
+            },
+            Self::ControlPointCapture { point_id, party_name, .. } => { state.point_owners.insert(point_id.to_string(), party_name.to_string()); },
+            Self::DestroyableDamage { .. } | Self::FlagDefend { .. } | Self::WoolDefend { .. } => {}
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{doc, from_bson, Bson};
+
+    use super::*;
+
+    #[test]
+    fn wools_keep_every_carrier() {
+        let mut state = ObjectiveState::default();
+        ObjectiveEvent::WoolPickup { wool_id: "red", player_id: "a" }.apply(&mut state);
+        ObjectiveEvent::WoolPickup { wool_id: "red", player_id: "b" }.apply(&mut state);
+        assert!(state.carries_wool("red", "a") && state.carries_wool("red", "b"));
+
+        ObjectiveEvent::WoolDrop { wool_id: "red", player_id: "a" }.apply(&mut state);
+        assert!(!state.carries_wool("red", "a"));
+        assert!(state.carries_wool("red", "b"));
+
+        ObjectiveEvent::WoolDrop { wool_id: "red", player_id: "b" }.apply(&mut state);
+        assert!(state.wool_carriers.is_empty());
+    }
+
+    #[test]
+    fn carriers_are_released() {
+        let mut state = ObjectiveState::default();
+        ObjectiveEvent::FlagPickup { flag_id: "blue", player_id: "a" }.apply(&mut state);
+        ObjectiveEvent::WoolPickup { wool_id: "red", player_id: "a" }.apply(&mut state);
+        ObjectiveEvent::WoolPickup { wool_id: "red", player_id: "b" }.apply(&mut state);
+        ObjectiveEvent::WoolPickup { wool_id: "lime", player_id: "a" }.apply(&mut state);
+
+        state.release_carrier("a");
+        assert!(state.flag_carriers.is_empty());
+        assert!(state.carries_wool("red", "b"));
+        assert!(!state.carries_wool("red", "a"));
+        assert!(!state.wool_carriers.contains_key("lime"));
+    }
+
+    #[test]
+    fn single_wool_carriers_still_load() {
+        let stored = doc! {
+            "destroyed": [], "leakedCores": [], "placedWools": [], "flagCarriers": {}, "pointOwners": {},
+            "woolCarriers": { "red": "a", "lime": ["b", "c"] }
+        };
+        let state : ObjectiveState = from_bson(Bson::Document(stored)).unwrap();
+        assert!(state.carries_wool("red", "a"));
+        assert!(state.carries_wool("lime", "b") && state.carries_wool("lime", "c"));
+    }
+}
@@ -17,7 +17,7 @@ impl<'a> ParticipantContext<'a> {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum PlayerMatchResult {
     Win,
@@ -1,4 +1,4 @@
-use crate::{socket::{player::{player_listener::PlayerListener, player_events::{PlayerDeathData, PlayerChatData, ChatChannel}}, r#match::match_events::{MatchEndData, BigStats}, server::server_context::ServerContext}, util::{time::get_u64_time_millis}, database::models::{death::DamageCause, participant::{Duel, Participant}, r#match::{Match, DestroyableGoal}}};
+use crate::{socket::{player::{player_listener::PlayerListener, player_events::{PlayerDeathData, PlayerChatData, ChatChannel}}, r#match::match_events::{MatchEndData, BigStats}, server::server_context::ServerContext}, database::models::{death::DamageCause, participant::{Duel, Participant}, r#match::{Match, DestroyableGoal}}};
 
 
 use async_trait::async_trait;
@@ -34,6 +34,16 @@ impl PlayerListener for ParticipantStatListener {
         };
     }
 
+    async fn on_assist(
+        &self,
+        _server_context: &mut ServerContext, 
+        _current_match: &mut Match, 
+        context: &mut Self::Context, 
+        _data: &mut PlayerDeathData
+    ) {
+        context.stats.assists += 1;
+    }
+
     async fn on_death(
         &self,
         _server_context: &mut ServerContext, 
@@ -109,24 +119,24 @@ impl PlayerListener for ParticipantStatListener {
 
     async fn on_party_join(
         &self,
-        _server_context: &mut ServerContext, 
+        server_context: &mut ServerContext, 
         _current_match: &mut Match, 
         context: &mut Self::Context, 
         _party_name: String
     ) {
-        if context.last_left_party_at.is_some() {
-            let time_away = get_u64_time_millis() - context.last_left_party_at.unwrap();
+        if let Some(last_left_party_at) = context.last_left_party_at {
+            let time_away = server_context.event_time.saturating_sub(last_left_party_at);
             context.stats.time_away += time_away;
         };
     }
 
     async fn on_party_leave(
         &self,
-        _server_context: &mut ServerContext, 
+        server_context: &mut ServerContext, 
         _current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        context.stats.game_playtime += get_u64_time_millis() - context.joined_party_at.unwrap();
+        context.stats.game_playtime += server_context.event_time.saturating_sub(context.joined_party_at.unwrap());
     }
 
     async fn on_core_leak(
@@ -279,9 +289,8 @@ impl PlayerListener for ParticipantStatListener {
         context.stats.damage_given_bow = big_stats.damage_given_bow;
 
         let is_playing = context.party_name.is_some();
-        let joined_party_at = context.joined_party_at;
-        if is_playing && joined_party_at.is_some() {
-            context.stats.game_playtime += current_match.ended_at.unwrap() - joined_party_at.unwrap();
+        if let (true, Some(joined_party_at)) = (is_playing, context.joined_party_at) {
+            context.stats.game_playtime += current_match.ended_at.unwrap().millis().saturating_sub(joined_party_at);
         };
     }
 }
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::database::models::player::SimplePlayer;
+
+use super::player_events::PlayerDamageData;
+
+pub struct DamageContribution {
+    // environmental damage has no attacker but still counts towards the damage a victim took
+    pub attacker: Option<SimplePlayer>,
+    pub damage: f64,
+    pub at: u64
+}
+
+// hits kept per victim, past this the oldest are dropped so a victim who takes damage for a long time
+// without dying doesn't grow the log without bound
+pub const MAX_CONTRIBUTIONS_PER_VICTIM : usize = 64;
+
+// recent damage taken per victim on one server, the basis for crediting anyone other than the final blow
+// and for attributing void deaths nobody was credited for
+#[derive(Default)]
+pub struct DamageLog {
+    contributions: HashMap<String, Vec<DamageContribution>>
+}
+
+impl DamageLog {
+    // hits older than `window` ms can no longer be credited and are dropped as new ones come in
+    pub fn record(&mut self, data: &PlayerDamageData, at: u64, window: u64) {
+        if data.damage <= 0.0 {
+            return;
+        };
+        let contributions = self.contributions.entry(data.victim.id.clone()).or_default();
+        contributions.retain(|contribution| at.saturating_sub(contribution.at) <= window);
+        if contributions.len() >= MAX_CONTRIBUTIONS_PER_VICTIM {
+            contributions.drain(..=contributions.len() - MAX_CONTRIBUTIONS_PER_VICTIM);
+        };
+        contributions.push(DamageContribution {
+            attacker: data.attacker.clone(),
+            damage: data.damage,
+            at
+        });
+    }
+
+    // whoever last hit the victim within `window` ms, for a void death the server sent without an attacker
+    pub fn last_attacker(&self, victim_id: &str, now: u64, window: u64) -> Option<SimplePlayer> {
+        self.contributions.get(victim_id)?.iter().rev()
+            .take_while(|contribution| now.saturating_sub(contribution.at) <= window)
+            .find_map(|contribution| contribution.attacker.as_ref().filter(|attacker| attacker.id != victim_id))
+            .cloned()
+    }
+
+    // attackers whose share of the damage the victim took within `window` ms reaches `min_share`,
+    // the victim's log is cleared so the next life starts fresh
+    pub fn take_assists(&mut self, victim_id: &str, killer_id: Option<&str>, now: u64, window: u64, min_share: f64) -> Vec<SimplePlayer> {
+        let contributions = match self.contributions.remove(victim_id) {
+            Some(contributions) => contributions,
+            None => return Vec::new()
+        };
+        let recent : Vec<&DamageContribution> = contributions.iter().filter(|contribution| now.saturating_sub(contribution.at) <= window).collect();
+        let total : f64 = recent.iter().map(|contribution| contribution.damage).sum();
+        if total <= 0.0 {
+            return Vec::new();
+        };
+
+        let mut dealt : Vec<(SimplePlayer, f64)> = Vec::new();
+        for contribution in recent {
+            let attacker = match &contribution.attacker {
+                Some(attacker) if attacker.id != victim_id && Some(attacker.id.as_str()) != killer_id => attacker,
+                _ => continue
+            };
+            match dealt.iter_mut().find(|(player, _)| player.id == attacker.id) {
+                Some((_, damage)) => *damage += contribution.damage,
+                None => dealt.push((attacker.clone(), contribution.damage))
+            };
+        };
+        dealt.into_iter().filter(|(_, damage)| damage / total >= min_share).map(|(player, _)| player).collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.contributions.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::models::death::DamageCause;
+
+    use super::*;
+
+    fn player(id: &str) -> SimplePlayer {
+        SimplePlayer { name: id.to_uppercase(), id: id.to_owned() }
+    }
+
+    fn hit(log: &mut DamageLog, attacker: Option<&str>, damage: f64, at: u64) {
+        log.record(&PlayerDamageData { victim: player("victim"), attacker: attacker.map(player), damage, cause: DamageCause::Melee, weapon: None }, at, 15_000);
+    }
+
+    #[test]
+    fn assists_leave_out_the_killer_and_small_shares() {
+        let mut log = DamageLog::default();
+        hit(&mut log, Some("killer"), 10.0, 1_000);
+        hit(&mut log, Some("helper"), 6.0, 2_000);
+        hit(&mut log, Some("chip"), 1.0, 3_000);
+        hit(&mut log, None, 3.0, 4_000);
+
+        let assists = log.take_assists("victim", Some("killer"), 5_000, 15_000, 0.25);
+        assert_eq!(assists.iter().map(|assister| assister.id.as_str()).collect::<Vec<_>>(), vec!["helper"]);
+        assert!(log.take_assists("victim", Some("killer"), 5_000, 15_000, 0.25).is_empty());
+    }
+
+    #[test]
+    fn void_deaths_go_to_the_last_attacker_in_the_window() {
+        let mut log = DamageLog::default();
+        hit(&mut log, Some("first"), 4.0, 1_000);
+        hit(&mut log, Some("last"), 4.0, 2_000);
+        hit(&mut log, None, 2.0, 3_000);
+
+        assert_eq!(log.last_attacker("victim", 4_000, 15_000).map(|attacker| attacker.id), Some("last".to_owned()));
+        assert_eq!(log.last_attacker("victim", 20_000, 15_000), None);
+        assert_eq!(log.last_attacker("someone else", 4_000, 15_000), None);
+    }
+
+    #[test]
+    fn each_victim_keeps_a_bounded_log() {
+        let mut log = DamageLog::default();
+        for at in 0..(MAX_CONTRIBUTIONS_PER_VICTIM as u64 * 2) {
+            hit(&mut log, Some("attacker"), 1.0, at);
+        };
+        assert_eq!(log.contributions["victim"].len(), MAX_CONTRIBUTIONS_PER_VICTIM);
+
+        hit(&mut log, Some("attacker"), 1.0, 100_000);
+        assert_eq!(log.contributions["victim"].len(), 1);
+    }
+}
@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+struct RepeatKills {
+    count: u32,
+    last_at: u64
+}
+
+// repeated kills of the same victim per attacker on one server, the basis for diminishing kill xp
+#[derive(Default)]
+pub struct KillDecay {
+    pairs: HashMap<(String, String), RepeatKills>
+}
+
+impl KillDecay {
+    // records the kill and returns the share of kill xp it is worth: 1.0 for the first kill,
+    // multiplied by `factor` for every repeat until `min_credit`. the pair resets after `window` ms without a kill
+    pub fn credit(&mut self, attacker_id: &str, victim_id: &str, now: u64, window: u64, factor: f64, min_credit: f64) -> f64 {
+        let repeats = self.pairs.entry((attacker_id.to_owned(), victim_id.to_owned())).or_insert(RepeatKills { count: 0, last_at: now });
+        if now.saturating_sub(repeats.last_at) > window {
+            repeats.count = 0;
+        };
+        let credit = factor.powi(repeats.count as i32).max(min_credit).min(1.0);
+        repeats.count += 1;
+        repeats.last_at = now;
+        credit
+    }
+
+    pub fn prune(&mut self, now: u64, window: u64) {
+        self.pairs.retain(|_, repeats| now.saturating_sub(repeats.last_at) <= window);
+    }
+}
@@ -4,7 +4,7 @@
 
 
 
-use crate::{database::models::{player::{Player}, r#match::Match}, socket::{server::server_context::ServerContext, event_type::EventType}};
+use crate::{database::models::{player::{Player}, r#match::Match}, socket::{server::{server_context::ServerContext, notifier::Notification}, event_type::EventType}};
 
 use super::player_events::MessageData;
 
@@ -25,7 +25,7 @@ impl<'a> PlayerContext<'a> {
             sound,
             player_ids: vec![self.profile.id.clone()],
         };
-        server_context.call(&EventType::Message, message_data).await;
+        server_context.notifier.dispatch(Notification::new(&EventType::Message, message_data)).await;
     }
 }
 
@@ -35,5 +35,5 @@ pub async fn send_message_to_player(server_context: &mut ServerContext, player:
         sound,
         player_ids: vec![player.id.clone()],
     };
-    server_context.call(&EventType::Message, message_data).await;
+    server_context.notifier.dispatch(Notification::new(&EventType::Message, message_data)).await;
 }
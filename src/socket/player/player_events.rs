@@ -1,6 +1,6 @@
 use serde::{Serialize, Deserialize};
 
-use crate::database::models::{player::SimplePlayer, death::DamageCause};
+use crate::{database::models::{player::SimplePlayer, death::DamageCause, punishment::Punishment, achievement::AchievementRarity}, socket::leaderboard::ScoreType};
 
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -11,7 +11,26 @@ pub struct PlayerDeathData {
     pub entity: Option<String>,
     pub distance: Option<u32>,
     pub key: String,
-    pub cause: DamageCause
+    pub cause: DamageCause,
+    // share of kill xp the attacker receives, lowered by kill decay for repeated kills of the same victim
+    #[serde(skip, default = "full_kill_credit")]
+    pub kill_credit: f64
+}
+
+fn full_kill_credit() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerDamageData {
+    pub victim: SimplePlayer,
+    #[serde(default)]
+    pub attacker: Option<SimplePlayer>,
+    pub damage: f64,
+    pub cause: DamageCause,
+    #[serde(default)]
+    pub weapon: Option<String>
 }
 
 impl PlayerDeathData {
@@ -81,7 +100,46 @@ pub struct PlayerXPGainData {
     pub player_id: String,
     pub gain: u32,
     pub reason: String,
-    pub notify: bool
+    pub notify: bool,
+    // how many gains this notification summarizes
+    #[serde(default = "single_xp_gain")]
+    pub count: u32
+}
+
+fn single_xp_gain() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerPrestigeData {
+    pub player_id: String,
+    pub prestige: u32,
+    pub display: String,
+    pub unlocked_tag_id: Option<String>
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerRankGrantData {
+    pub player_id: String,
+    pub rank_id: String,
+    pub automatic: bool
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerMilestoneData {
+    pub player_id: String,
+    pub tag_id: String,
+    pub stat: ScoreType,
+    pub threshold: u64
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerPunishmentData {
+    pub punishment: Punishment
 }
 
 #[derive(Serialize, Deserialize)]
@@ -98,3 +156,14 @@ pub struct PlayerAchievementData {
     pub achievement_id: String, 
     pub completion_time: u64
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AchievementUnlockData {
+    pub player: SimplePlayer,
+    pub achievement_id: String,
+    pub achievement_name: String,
+    pub rarity: AchievementRarity,
+    // false when only the server the achievement was earned on is notified
+    pub network: bool
+}
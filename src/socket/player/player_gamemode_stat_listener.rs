@@ -1,4 +1,4 @@
-use crate::{socket::{r#match::match_events::MatchEndData, server::server_context::ServerContext, participant::participant_context::PlayerMatchResult}, database::models::{level::LevelGamemode, player::{GamemodeStats, Player}, death::DamageCause, r#match::{Match, DestroyableGoal}}};
+use crate::{socket::{r#match::match_events::MatchEndData, server::server_context::ServerContext}, database::models::{player::Player, death::DamageCause, r#match::{Match, DestroyableGoal}}, util::tracking::StatCategory};
 
 use super::{player_listener::PlayerListener, player_events::PlayerDeathData};
 
@@ -17,10 +17,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         first_blood: bool
     ) { 
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Kills);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.kills += 1;
 
@@ -47,10 +46,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         first_blood: bool
     ) { 
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Kills);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.deaths += 1;
 
@@ -79,10 +77,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         amount: u32
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Kills);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 let prev_amount = stats.killstreaks.get(&amount.to_string()).unwrap_or(&0).to_owned();
                 stats.killstreaks.insert(amount.to_string(), prev_amount + 1);
@@ -98,10 +95,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         _destroyable: &DestroyableGoal, 
         block_count: u32
     ) {
-        let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+        let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
         for gamemode in gamemodes {
-            let mut default_gamemode_stats = GamemodeStats::default();
-            let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+            let stats = context.gamemode_stats.entry(gamemode).or_default();
 
             stats.objectives.destroyable_block_destroys += block_count;
         };
@@ -115,10 +111,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         _percentage: f32, 
         _block_count: u32
     ) {
-        let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+        let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
         for gamemode in gamemodes {
-            let mut default_gamemode_stats = GamemodeStats::default();
-            let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+            let stats = context.gamemode_stats.entry(gamemode).or_default();
 
             stats.objectives.destroyable_destroys += 1;
         };
@@ -133,10 +128,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         block_count: u32,
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.core_leaks += 1;
                 stats.objectives.core_block_destroys += block_count;
@@ -152,10 +146,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         _contributors: u32
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.control_point_captures += 1;
             };
@@ -170,10 +163,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         held_time: u64
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.flag_captures += 1;
                 stats.objectives.total_flag_hold_time += held_time;
@@ -188,10 +180,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         context: &mut Self::Context
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.flag_pickups += 1;
             };
@@ -206,10 +197,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         held_time: u64
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.flag_drops += 1;
                 stats.objectives.total_flag_hold_time += held_time;
@@ -224,10 +214,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         context: &mut Self::Context
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.flag_defends += 1;
             };
@@ -242,10 +231,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         _held_time: u64
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.wool_captures += 1;
             };
@@ -259,10 +247,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         context: &mut Self::Context
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.wool_pickups += 1;
             };
@@ -277,10 +264,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         _held_time: u64
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.wool_drops += 1;
             };
@@ -294,10 +280,9 @@ impl PlayerListener for PlayerGamemodeStatListener {
         context: &mut Self::Context
     ) {
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Objectives);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
 
                 stats.objectives.wool_defends += 1;
             };
@@ -306,16 +291,16 @@ impl PlayerListener for PlayerGamemodeStatListener {
 
     async fn on_match_end_v2(
         &self,
-        _server_context: &mut ServerContext, 
+        server_context: &mut ServerContext, 
         current_match: &mut Match, 
         context: &mut Self::Context, 
         end_data: &mut MatchEndData
     ) { 
         {
-            let gamemodes = if !current_match.is_tracking_stats() { vec![LevelGamemode::Arcade] } else { current_match.level.gamemodes.clone() };
+            let gamemodes = current_match.get_credited_gamemodes(StatCategory::Activity);
             for gamemode in gamemodes {
-                let mut default_gamemode_stats = GamemodeStats::default();
-                let stats = context.gamemode_stats.get_mut(&gamemode).unwrap_or(&mut default_gamemode_stats);
+                let win_outcomes = gamemode.win_outcomes();
+                let stats = context.gamemode_stats.entry(gamemode).or_default();
                 let big_stats = end_data.get_stats_for_participant(&context.id);
                 for (block, freq) in big_stats.blocks.blocks_broken.iter() {
                     stats.blocks_broken.insert(block.to_owned(), freq.to_owned());
@@ -332,27 +317,14 @@ impl PlayerListener for PlayerGamemodeStatListener {
 
                 let participant = current_match.get_participant(&context.id);
 
-                let minimum_playtime = (0.10 * (current_match.get_length() as f64)).min(60_000.0);
-                let is_playing = participant.party_name.is_some();
+                let presence = participant.get_presence(&*current_match, &server_context.api_state.config.options.match_participation);
 
                 let match_result = participant.get_match_result(&*current_match, end_data);
-                let f64_game_playtime = participant.stats.game_playtime as f64;
-                if f64_game_playtime > minimum_playtime {
-                    match match_result {
-                        PlayerMatchResult::Tie => { stats.ties += 1; },
-                        PlayerMatchResult::Win => { stats.wins += 1; },
-                        PlayerMatchResult::Lose => { stats.losses += 1; }
-                        _ => {}
-                    }
+                if presence.participated {
+                    stats.record_outcomes(&end_data.get_outcomes(&context.id, &match_result), &win_outcomes);
                 };
 
-                let time_elapsed_before_joining = (participant.first_joined_match_at - current_match.started_at.unwrap()).max(0);
-                let present_at_start = (time_elapsed_before_joining as f64) < minimum_playtime;
-
-                if f64_game_playtime > minimum_playtime { stats.matches += 1; }
-                if present_at_start { stats.matches_present_start += 1; }
-                if participant.stats.time_away < 20_000 && is_playing { stats.matches_present_full += 1; }
-                if is_playing { stats.matches_present_end += 1; }
+                stats.record_presence(&presence);
 
                 stats.game_playtime += participant.stats.game_playtime;
             };
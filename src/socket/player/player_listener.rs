@@ -16,6 +16,15 @@ pub trait PlayerListener : Sync {
         _first_blood: bool
     ) {}
 
+    // the victim's recent attackers other than the killer, see DamageLog
+    async fn on_assist(
+        &self, 
+        _server_context: &mut ServerContext, 
+        _current_match: &mut Match, 
+        _context: &mut Self::Context, 
+        _data: &mut PlayerDeathData
+    ) {}
+
     async fn on_death(
         &self, 
         _server_context: &mut ServerContext, 
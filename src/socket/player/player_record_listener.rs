@@ -1,4 +1,4 @@
-use crate::{database::models::{player::{PlayerRecord, FirstBloodRecord, ProjectileRecord, Player}, death::DamageCause, r#match::Match}, socket::{server::server_context::ServerContext, r#match::match_events::MatchEndData}, util::time::get_u64_time_millis};
+use crate::{database::models::{player::{PlayerRecord, FirstBloodRecord, ProjectileRecord, Player}, death::DamageCause, r#match::Match}, socket::{server::server_context::ServerContext, r#match::match_events::MatchEndData}};
 
 use super::{player_listener::PlayerListener, player_events::PlayerDeathData};
 
@@ -10,19 +10,19 @@ impl PlayerListener for PlayerRecordListener {
 
     async fn on_kill(
         &self,
-        _server_context: &mut ServerContext, 
+        server_context: &mut ServerContext, 
         current_match: &mut Match, 
         context: &mut Self::Context, 
         data: &mut PlayerDeathData, 
         first_blood: bool
     ) { 
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking_records(server_context.api_state.config.options.allow_untracked_records) {
                 return;
             };
 
             if first_blood {
-                let time = get_u64_time_millis() - current_match.started_at.unwrap();
+                let time = current_match.elapsed_at(server_context.event_time);
                 let record_beat = match context.stats.records.fastest_first_blood.as_ref() {
                     Some(first_blood_record) => {
                         time < first_blood_record.time
@@ -62,12 +62,12 @@ impl PlayerListener for PlayerRecordListener {
 
     async fn on_wool_place(
         &self, 
-        _server_context: &mut ServerContext, 
+        server_context: &mut ServerContext, 
         current_match: &mut Match, 
         context: &mut Self::Context, 
         held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking_records(server_context.api_state.config.options.allow_untracked_records) {
             return;
         }
 
@@ -83,12 +83,12 @@ impl PlayerListener for PlayerRecordListener {
 
     async fn on_flag_place(
         &self, 
-        _server_context: &mut ServerContext, 
+        server_context: &mut ServerContext, 
         current_match: &mut Match, 
         context: &mut Self::Context, 
         held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking_records(server_context.api_state.config.options.allow_untracked_records) {
             return;
         }
 
@@ -102,15 +102,60 @@ impl PlayerListener for PlayerRecordListener {
         }
     }
 
+    async fn on_core_leak(
+        &self, 
+        server_context: &mut ServerContext, 
+        current_match: &mut Match, 
+        context: &mut Self::Context, 
+        _percentage: f32, 
+        _block_count: u32
+    ) {
+        if !current_match.is_tracking_records(server_context.api_state.config.options.allow_untracked_records) {
+            return;
+        }
+
+        let time = current_match.elapsed_at(server_context.event_time);
+        let record_time = &context.stats.records.fastest_core_leak;
+        if PlayerRecord::is_beaten(record_time, time) {
+            context.stats.records.fastest_core_leak = Some(PlayerRecord { 
+                match_id: current_match.id.clone(), 
+                player: context.to_simple(), 
+                value: time 
+            });
+        }
+    }
+
+    async fn on_control_point_capture(
+        &self, 
+        server_context: &mut ServerContext, 
+        current_match: &mut Match, 
+        context: &mut Self::Context, 
+        _contributors: u32, 
+    ) {
+        if !current_match.is_tracking_records(server_context.api_state.config.options.allow_untracked_records) {
+            return;
+        }
+
+        let time = current_match.elapsed_at(server_context.event_time);
+        let record_time = &context.stats.records.fastest_control_point_capture;
+        if PlayerRecord::is_beaten(record_time, time) {
+            context.stats.records.fastest_control_point_capture = Some(PlayerRecord { 
+                match_id: current_match.id.clone(), 
+                player: context.to_simple(), 
+                value: time 
+            });
+        }
+    }
+
     async fn on_match_end_v2(
         &self,
-        _server_context: &mut ServerContext, 
+        server_context: &mut ServerContext, 
         current_match: &mut Match, 
         context: &mut Self::Context, 
         _end_data: &mut MatchEndData
     ) { 
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking_records(server_context.api_state.config.options.allow_untracked_records) {
                 return;
             }
 
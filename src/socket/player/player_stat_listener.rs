@@ -1,4 +1,4 @@
-use crate::{socket::{r#match::match_events::MatchEndData, server::server_context::ServerContext, participant::participant_context::{PlayerMatchResult}}, database::models::{death::DamageCause, player::Player, r#match::{Match, DestroyableGoal}}};
+use crate::{socket::{r#match::match_events::MatchEndData, server::server_context::ServerContext}, database::models::{death::DamageCause, player::Player, match_outcome::MatchOutcome, r#match::{Match, DestroyableGoal}}, util::tracking::StatCategory};
 
 use super::{player_context::{send_message_to_player}, player_listener::PlayerListener, player_events::{PlayerDeathData, PlayerChatData, ChatChannel}};
 use async_trait::async_trait;
@@ -18,7 +18,7 @@ impl PlayerListener for PlayerStatListener {
         first_blood: bool
     ) { 
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking(StatCategory::Kills) {
                 return;
             };
 
@@ -32,6 +32,19 @@ impl PlayerListener for PlayerStatListener {
         };
     }
 
+    async fn on_assist(
+        &self,
+        _server_context: &mut ServerContext, 
+        current_match: &mut Match, 
+        context: &mut Self::Context, 
+        _data: &mut PlayerDeathData
+    ) {
+        if !current_match.is_tracking(StatCategory::Kills) {
+            return;
+        };
+        context.stats.assists += 1;
+    }
+
     async fn on_chat(
         &self,
         _server_context: &mut ServerContext, 
@@ -40,7 +53,7 @@ impl PlayerListener for PlayerStatListener {
         data: &mut PlayerChatData
     ) {
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking(StatCategory::Activity) {
                 return;
             };
         };
@@ -60,7 +73,7 @@ impl PlayerListener for PlayerStatListener {
         first_blood: bool
     ) { 
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking(StatCategory::Kills) {
                 return;
             };
 
@@ -85,7 +98,7 @@ impl PlayerListener for PlayerStatListener {
         amount: u32
     ) {
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking(StatCategory::Kills) {
                 return;
             };
 
@@ -102,7 +115,7 @@ impl PlayerListener for PlayerStatListener {
         amount: u32
     ) {
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking(StatCategory::Kills) {
                 return;
             };
 
@@ -119,7 +132,7 @@ impl PlayerListener for PlayerStatListener {
         _destroyable: &DestroyableGoal, 
         block_count: u32
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
         context.stats.objectives.destroyable_block_destroys += block_count;
@@ -133,7 +146,7 @@ impl PlayerListener for PlayerStatListener {
         _percentage: f32, 
         _block_count: u32
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
         context.stats.objectives.destroyable_destroys += 1;
@@ -147,7 +160,7 @@ impl PlayerListener for PlayerStatListener {
         _percentage: f32, 
         block_count: u32
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
         context.stats.objectives.core_leaks += 1;
@@ -161,7 +174,7 @@ impl PlayerListener for PlayerStatListener {
         context: &mut Self::Context, 
         _contributors: u32, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -175,7 +188,7 @@ impl PlayerListener for PlayerStatListener {
         context: &mut Self::Context, 
         held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -189,7 +202,7 @@ impl PlayerListener for PlayerStatListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -203,7 +216,7 @@ impl PlayerListener for PlayerStatListener {
         context: &mut Self::Context,
         held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -217,7 +230,7 @@ impl PlayerListener for PlayerStatListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -231,7 +244,7 @@ impl PlayerListener for PlayerStatListener {
         context: &mut Self::Context, 
         _held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -244,7 +257,7 @@ impl PlayerListener for PlayerStatListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -258,7 +271,7 @@ impl PlayerListener for PlayerStatListener {
         context: &mut Self::Context,
         _held_time: u64, 
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -271,7 +284,7 @@ impl PlayerListener for PlayerStatListener {
         current_match: &mut Match, 
         context: &mut Self::Context
     ) {
-        if !current_match.is_tracking_stats() {
+        if !current_match.is_tracking(StatCategory::Objectives) {
             return;
         };
 
@@ -286,7 +299,7 @@ impl PlayerListener for PlayerStatListener {
         end_data: &mut MatchEndData
     ) {
         {
-            if !current_match.is_tracking_stats() {
+            if !current_match.is_tracking(StatCategory::Activity) {
                 return;
             };
 
@@ -312,40 +325,23 @@ impl PlayerListener for PlayerStatListener {
             context.stats.damage_taken = big_stats.damage_taken;
             context.stats.damage_given_bow = big_stats.damage_given_bow;
 
-            let min_playtime = (0.10 * (current_match.get_length() as f64)).min(60_000.0);
-            let is_playing = participant.party_name.is_some();
-            if (participant.stats.game_playtime as f64) > min_playtime {
-                match match_result {
-                    PlayerMatchResult::Tie => context.stats.ties += 1,
-                    PlayerMatchResult::Win => context.stats.wins += 1,
-                    PlayerMatchResult::Lose => context.stats.losses += 1,
-                    _ => {}
-                }
+            let presence = participant.get_presence(current_match, &server_context.api_state.config.options.match_participation);
+            if presence.participated {
+                context.stats.record_outcomes(&end_data.get_outcomes(participant_id, &match_result), &[MatchOutcome::win()]);
             } else {
                 // server_context.send_message(&context, "Your stats were not affected by the outcome of this match as you did not participate for long enough.");
                 send_message_to_player(server_context, context, "Your stats were not affected by the outcome of this match as you did not participate for long enough.", Option::None).await;
                 // context.send_message(server_context, "Your stats were not affected by the outcome of this match as you did not participate for long enough.", Option::None).await;
             };
 
-            let time_elapsed_before_joining = participant.first_joined_match_at.saturating_sub(current_match.started_at.unwrap_or(0));
-
-            if (participant.stats.game_playtime as f64) > min_playtime {
-                context.stats.matches += 1;
-            };
-
-            if (time_elapsed_before_joining as f64) < min_playtime {
-                context.stats.matches_present_start += 1;
-            };
-
-            if participant.stats.time_away < 20000 && is_playing {
-                context.stats.matches_present_full += 1;
-            };
-
-            if is_playing {
-                context.stats.matches_present_end += 1;
-            };
+            context.stats.record_presence(&presence);
 
+            let min_playtime = server_context.api_state.config.options.leaderboard_min_playtime;
+            let was_eligible = context.is_leaderboard_eligible(min_playtime);
             context.stats.game_playtime += participant.stats.game_playtime;
+            if !was_eligible && context.is_leaderboard_eligible(min_playtime) {
+                server_context.leaderboards().include_player(context).await;
+            };
         };
     }
 }
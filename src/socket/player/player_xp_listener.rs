@@ -38,127 +38,135 @@ impl PlayerListener for PlayerXPListener {
     async fn on_kill(
         &self, 
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context, 
-        _data: &mut PlayerDeathData, 
+        data: &mut PlayerDeathData, 
         first_blood: bool
     ) { 
-        context.add_xp(server_context, XP_KILL, &String::from("Kill"), true, false).await;
-        if first_blood { context.add_xp(server_context, XP_FIRST_BLOOD, &String::from("First blood"), true, false).await;  };
+        let xp = (XP_KILL as f64 * data.kill_credit).round() as u32;
+        context.add_xp(server_context, current_match, xp, &String::from("Kill"), true, false).await;
+        if first_blood { context.add_xp(server_context, current_match, XP_FIRST_BLOOD, &String::from("First blood"), true, false).await;  };
     }
 
     async fn on_death(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context, 
         _data: &mut PlayerDeathData, 
         _first_blood: bool
     ) { 
-        context.add_xp(server_context, XP_DEATH, &String::from("Death"), false, false).await;
+        context.add_xp(server_context, current_match, XP_DEATH, &String::from("Death"), false, false).await;
     }
 
     async fn on_killstreak(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context, 
         amount: u32
     ) {
-        context.add_xp(server_context, XP_KILLSTREAK_COEFFICIENT * amount, &format!("Killstreak x{}", amount.to_string()), true, false).await;
+        context.add_xp(server_context, current_match, XP_KILLSTREAK_COEFFICIENT * amount, &format!("Killstreak x{}", amount), true, false).await;
     }
 
     async fn on_destroyable_damage(
         &self, 
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context, 
         destroyable: &DestroyableGoal, 
         block_count: u32
     ) {
-        let xp = (XP_DESTROYABLE_WHOLE / destroyable.breaks_required) * block_count;
-        context.add_xp(server_context, xp, &String::from("Damaged objective"), true, false).await;
+        let xp = (server_context.api_state.config.options.objective_xp.destroyable / destroyable.breaks_required.max(1)) * block_count;
+        context.add_xp(server_context, current_match, xp, &String::from("Damaged objective"), true, false).await;
     }
 
     async fn on_wool_place(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context, 
         _held_time: u64
     ) { 
-        context.add_xp(server_context, XP_WOOL_OBJECTIVE, &String::from("Captured wool"), true, false).await;
+        let xp = server_context.api_state.config.options.objective_xp.wool_capture;
+        context.add_xp(server_context, current_match, xp, &String::from("Captured wool"), true, false).await;
     }
 
     async fn on_wool_pickup(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context
     ) { 
-        context.add_xp(server_context, XP_WOOL_OBJECTIVE, &String::from("Picked up wool"), true, false).await;
+        let xp = server_context.api_state.config.options.objective_xp.wool_pickup;
+        context.add_xp(server_context, current_match, xp, &String::from("Picked up wool"), true, false).await;
     }
 
     async fn on_wool_defend(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context
     ) { 
-        context.add_xp(server_context, XP_WOOL_OBJECTIVE, &String::from("Defended wool"), true, false).await;
+        let xp = server_context.api_state.config.options.objective_xp.wool_defend;
+        context.add_xp(server_context, current_match, xp, &String::from("Defended wool"), true, false).await;
     }
 
     async fn on_flag_place(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context,
         held_time: u64
     ) { 
-        let xp = XP_FLAG_OBJECTIVE + (XP_FLAG_TIME_BOUNS - ((held_time / 1000) as u32));
-        context.add_xp(server_context, xp, &String::from("Captured flag"), true, false).await;
+        let rewards = &server_context.api_state.config.options.objective_xp;
+        let xp = rewards.flag_capture + rewards.flag_capture_time_bonus.saturating_sub((held_time / 1000) as u32);
+        context.add_xp(server_context, current_match, xp, &String::from("Captured flag"), true, false).await;
     }
 
     async fn on_flag_pickup(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context
     ) { 
-        context.add_xp(server_context, XP_FLAG_OBJECTIVE, &String::from("Picked up flag"), true, false).await;
+        let xp = server_context.api_state.config.options.objective_xp.flag_pickup;
+        context.add_xp(server_context, current_match, xp, &String::from("Picked up flag"), true, false).await;
     }
 
     async fn on_flag_defend(
         &self,
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context
     ) { 
-        context.add_xp(server_context, XP_FLAG_OBJECTIVE, &String::from("Defended flag"), true, false).await;
+        let xp = server_context.api_state.config.options.objective_xp.flag_defend;
+        context.add_xp(server_context, current_match, xp, &String::from("Defended flag"), true, false).await;
     }
 
     async fn on_control_point_capture(
         &self, 
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context, 
         contributors: u32, 
     ) {
         let others = contributors + 1;
-        let xp = u32::max(XP_POINT_CAPTURE_MAX - (others * 10), 20);
-        context.add_xp(server_context, xp, &String::from("Captured point"), true, false).await;
+        let max = server_context.api_state.config.options.objective_xp.control_point_capture;
+        let xp = u32::max(max.saturating_sub(others * 10), 20.min(max));
+        context.add_xp(server_context, current_match, xp, &String::from("Captured point"), true, false).await;
     }
 
     async fn on_core_leak(
         &self, 
         server_context: &mut ServerContext, 
-        _current_match: &mut Match, 
+        current_match: &mut Match, 
         context: &mut Self::Context, 
         percentage: f32, 
         _block_count: u32,
     ) {
-        let xp : f32 = percentage * (XP_DESTROYABLE_WHOLE as f32);
-        context.add_xp(server_context, xp as u32, &String::from("Leaked core"), true, false).await;
+        let xp : f32 = percentage * (server_context.api_state.config.options.objective_xp.core_leak as f32);
+        context.add_xp(server_context, current_match, xp as u32, &String::from("Leaked core"), true, false).await;
     }
 
     async fn on_match_end_v2(
@@ -169,7 +177,7 @@ impl PlayerListener for PlayerXPListener {
         end_data: &mut MatchEndData
     ) { 
         {
-            let minimum_playtime = (0.10 * (current_match.get_length() as f64)).min(60_000.0);
+            let minimum_playtime = current_match.get_minimum_playtime(&server_context.api_state.config.options.match_participation);
 
             let eligible_for_result_xp = current_match.participants.values().filter(
                 |participant| participant.id == context.id && (participant.stats.game_playtime as f64) > minimum_playtime
@@ -184,13 +192,13 @@ impl PlayerListener for PlayerXPListener {
 
             match match_result {
                 PlayerMatchResult::Win => {
-                    context.add_xp(server_context, XP_WIN, &String::from("Victory"), true, true).await;
+                    context.add_xp(server_context, current_match, XP_WIN, &String::from("Victory"), true, true).await;
                 },
                 PlayerMatchResult::Lose => {
-                    context.add_xp(server_context, XP_LOSS, &String::from("Defeat"), true, true).await;
+                    context.add_xp(server_context, current_match, XP_LOSS, &String::from("Defeat"), true, true).await;
                 },
                 PlayerMatchResult::Tie => {
-                    context.add_xp(server_context, XP_DRAW, &String::from("Tie"), true, true).await;
+                    context.add_xp(server_context, current_match, XP_DRAW, &String::from("Tie"), true, true).await;
                 },
                 _ => {}
             };
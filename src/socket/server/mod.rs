@@ -1,2 +1,5 @@
 pub mod server_context;
+pub mod notifier;
 pub mod server_events;
+pub mod xp_gain_buffer;
+pub mod sweeper;
@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use futures::{stream::SplitSink, SinkExt};
+use rocket::serde::json::{serde_json, Value};
+use serde::{Serialize, Deserialize};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{WebSocketStream, tungstenite::{Message, protocol::{CloseFrame, frame::coding::CloseCode}}};
+
+use crate::{socket::{event_type::EventType, socket_encoding::SocketEncoding}, util::string::deflate_string};
+
+// an event for the connected server, already serialized so notifiers can be boxed
+pub struct Notification {
+    pub event: EventType,
+    pub data: Value
+}
+
+impl Notification {
+    pub fn new<T: Serialize>(event: &EventType, data: T) -> Self {
+        Notification { event: event.clone(), data: serde_json::to_value(data).unwrap_or_default() }
+    }
+}
+
+// where everything the API tells a connected server goes, one per connection. the default sends it down
+// the socket, other implementations can record, forward or wrap it
+#[async_trait]
+pub trait Notifier {
+    async fn dispatch(&mut self, notification: Notification);
+
+    // once the connection is done with, nothing is dispatched after this
+    async fn close(&mut self) {}
+}
+
+pub struct SocketNotifier {
+    pub sink: SplitSink<WebSocketStream<TcpStream>, Message>,
+    pub encoding: SocketEncoding
+}
+
+#[async_trait]
+impl Notifier for SocketNotifier {
+    async fn dispatch(&mut self, notification: Notification) {
+        let packet = Packet { event: notification.event, data: notification.data };
+        let body = self.encoding.encode(&packet).unwrap();
+        let binary = Message::Binary(deflate_string(&body).unwrap());
+        let _ = self.sink.send(binary).await;
+    }
+
+    async fn close(&mut self) {
+        let _ = self.sink.send(Message::Close(Some(CloseFrame { code: CloseCode::Normal, reason: std::borrow::Cow::Borrowed("Connection closed") }))).await;
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Packet<T> {
+    #[serde(rename = "e")]
+    event: EventType,
+    #[serde(rename = "d")]
+    data: T
+}
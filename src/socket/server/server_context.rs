@@ -1,20 +1,56 @@
 use std::sync::Arc;
 
-use futures::SinkExt;
-use serde::{Serialize, Deserialize};
-use rocket::serde::json::serde_json;
-use tokio::net::TcpStream;
-use tokio_tungstenite::{WebSocketStream, tungstenite::Message};
+use futures::stream::SplitStream;
+use serde::Serialize;
+use rocket::serde::json::{serde_json, Value};
+use tokio::{net::TcpStream, sync::broadcast};
+use tokio_tungstenite::WebSocketStream;
 
-use crate::{database::models::r#match::Match, socket::event_type::EventType, util::string::deflate_string, MarsAPIState};
+use crate::{database::models::{r#match::Match, participant::Participant, player::Player}, socket::{event_type::EventType, leaderboard::MarsLeaderboards, player::player_events::PlayerXPGainData, socket_encoding::SocketEncoding}, util::time::get_u64_time_millis, MarsAPIState};
+
+use super::{notifier::Notifier, xp_gain_buffer::XpGainBuffer};
 
 pub struct ServerContext {
     pub id: String,
     pub api_state: Arc<MarsAPIState>,
-    pub stream: WebSocketStream<TcpStream>
+    // what the server sends, everything going back to it goes through `notifier`
+    pub stream: SplitStream<WebSocketStream<TcpStream>>,
+    pub notifier: Box<dyn Notifier + Send + Sync>,
+    // set by the token the server connected with, `None` is the default realm
+    pub realm: Option<String>,
+    pub encoding: SocketEncoding,
+    pub xp_gains: XpGainBuffer,
+    // when the event being routed happened, validated against the API's clock. it never goes backwards
+    // so durations between two events of the same connection can't come out negative
+    pub event_time: u64
 }
 
 impl ServerContext {
+    // the boards of this server's realm
+    pub fn leaderboards(&self) -> &MarsLeaderboards {
+        self.api_state.leaderboards_for(&self.realm)
+    }
+
+    // the participant's player with this server's realm swapped in, see `Player::swap_realm_stats`
+    pub async fn load_player(&self, participant: &Participant) -> Player {
+        let mut player = participant.get_player(&self.api_state).await;
+        player.swap_realm_stats(&self.realm);
+        player
+    }
+
+    // swaps the realm back out before caching, the stored player is returned
+    pub async fn store_player(&self, participant: &Participant, player: &Player) -> Player {
+        let mut player = player.clone();
+        player.swap_realm_stats(&self.realm);
+        participant.set_player(&self.api_state, &player).await;
+        player
+    }
+
+    pub fn advance_event_time(&mut self, at: u64) -> u64 {
+        self.event_time = self.event_time.max(at);
+        self.event_time
+    }
+
     pub async fn set_current_match_id(&self, match_id: &String) {
         self.api_state.redis.set(&self.get_current_match_id_key(), match_id).await;
     }
@@ -31,11 +67,25 @@ impl ServerContext {
         self.api_state.redis.get(&format!("match:{}", self.get_current_match_id().await.unwrap_or_else(|| "null".to_owned()))).await.ok()
     }
 
-    pub async fn call<T: Serialize>(&mut self, event_type: &EventType, data: T) {
-        let packet = Packet { event: event_type.clone(), data };
-        let body = serde_json::to_string(&packet).unwrap();
-        let binary = Message::Binary(deflate_string(body.as_bytes()).unwrap());
-        let _ = self.stream.send(binary).await;
+    // fans the event out to every other connected server, this server is not included
+    pub fn broadcast<T: Serialize>(&self, event_type: &EventType, data: T) {
+        let data = match serde_json::to_value(data) {
+            Ok(data) => data,
+            Err(_) => return
+        };
+        // only fails when no other connection is subscribed
+        let _ = self.api_state.server_broadcast.send(ServerBroadcast { origin_server_id: self.id.clone(), event: event_type.clone(), data });
+    }
+
+    // gains that notify the player are coalesced for `xp.notify-window` ms, the rest are sent right away
+    pub async fn notify_xp_gain(&mut self, data: PlayerXPGainData) {
+        let window = self.api_state.config.options.xp_notify_window;
+        self.xp_gains.notify(self.notifier.as_mut(), data, get_u64_time_millis(), window).await;
+    }
+
+    pub async fn flush_xp_gains(&mut self) {
+        let window = self.api_state.config.options.xp_notify_window;
+        self.xp_gains.flush(self.notifier.as_mut(), get_u64_time_millis(), window).await;
     }
 
     fn get_current_match_id_key(&self) -> String {
@@ -47,10 +97,22 @@ impl ServerContext {
     }
 }
 
-#[derive(Serialize, Deserialize)]
-struct Packet<T> {
-    #[serde(rename = "e")]
-    event: EventType,
-    #[serde(rename = "d")]
-    data: T
+#[derive(Clone)]
+pub struct ServerBroadcast {
+    pub origin_server_id: String,
+    pub event: EventType,
+    pub data: Value
+}
+
+impl ServerBroadcast {
+    const API_ORIGIN : &'static str = "api";
+
+    // for events raised by http requests, no server is excluded
+    pub fn send_from_api<T: Serialize>(sender: &broadcast::Sender<ServerBroadcast>, event_type: &EventType, data: T) {
+        let data = match serde_json::to_value(data) {
+            Ok(data) => data,
+            Err(_) => return
+        };
+        let _ = sender.send(ServerBroadcast { origin_server_id: Self::API_ORIGIN.to_owned(), event: event_type.clone(), data });
+    }
 }
@@ -0,0 +1,108 @@
+use std::collections::{HashMap, HashSet};
+
+use futures::future::join_all;
+use mongodb::bson::doc;
+
+use crate::{database::{Database, models::{player::Player, r#match::{AbandonReason, MatchState}, session::Session, rank_threshold::{grant_rank_thresholds, announce_rank_grants}, rank::Rank, milestone::grant_milestones}}, socket::r#match::abandonment::abandon_match, util::{r#macro::unwrap_helper, time::{get_u64_time_millis, Timestamp}}, MarsAPIState};
+
+// ends every session still open on the server at `ended_at` and credits the playtime up to then,
+// returns how many players and sessions were written
+pub async fn close_hanging_sessions(state: &MarsAPIState, server_id: &str, ended_at: u64) -> (usize, usize) {
+    let mut hanging_sessions = Database::consume_cursor_into_owning_vec_option(state.database.sessions.find(doc! {
+        "serverId": server_id,
+        "endedAt": null
+    }, None).await.ok()).await;
+    let mut sessions_to_write : Vec<Session> = Vec::new();
+    let mut players_to_write : Vec<Player> = Vec::new();
+    let mut rank_grants : Vec<Vec<Rank>> = Vec::new();
+
+    for hanging_session in hanging_sessions.iter_mut() {
+        hanging_session.ended_at = Some(Timestamp::from_millis(ended_at).max(hanging_session.resumed_at.unwrap_or(hanging_session.created_at)));
+        let playtime = hanging_session.part_length().unwrap_or(0);
+        hanging_session.playtime += playtime;
+        sessions_to_write.push(hanging_session.to_owned());
+
+        let mut cached_player = unwrap_helper::continue_default!(state.player_cache.get(&state.database, &hanging_session.player.name).await);
+        cached_player.stats_in_mut(&hanging_session.realm).server_playtime += playtime;
+        let granted_ranks = grant_rank_thresholds(state, &mut cached_player).await;
+        grant_milestones(state, &mut cached_player).await;
+        rank_grants.push(granted_ranks);
+        players_to_write.push(cached_player);
+    }
+
+    // unfortunately rust's mongo driver doesn't support bulk writes yet so that's sad
+    {
+        // through the cache, a copy left there would be written back over these on the player's next event
+        let player_tasks : Vec<_> = players_to_write.iter().map(|player| {
+            state.player_cache.set(&state.database, &player.name, player, true)
+        }).collect();
+        join_all(player_tasks).await;
+        let session_tasks : Vec<_> = sessions_to_write.iter().map(|session| {
+            state.database.sessions.replace_one(doc! {
+                "_id": &session.id
+            }, session, None)
+        }).collect();
+        join_all(session_tasks).await;
+    }
+    for (player, granted_ranks) in players_to_write.iter().zip(rank_grants.iter()) {
+        announce_rank_grants(state, player, granted_ranks);
+    };
+
+    (players_to_write.len(), sessions_to_write.len())
+}
+
+// decides once per server whether it is dead, so its open sessions and its match are always judged the same way
+pub async fn sweep(state: &MarsAPIState) {
+    let now = get_u64_time_millis();
+    let stale_after = state.config.options.session_stale_after;
+    let empty_timeout = state.config.options.abandonment.empty_timeout;
+
+    let open_matches = state.database.get_open_matches().await;
+    let mut server_ids : HashSet<String> = state.database.get_servers_with_active_sessions().await.into_iter().collect();
+    server_ids.extend(open_matches.iter().map(|open_match| open_match.server_id.clone()));
+
+    let mut last_alive_times : HashMap<String, Option<u64>> = HashMap::new();
+    let mut current_match_ids : HashMap<String, Option<String>> = HashMap::new();
+    for server_id in server_ids.iter() {
+        last_alive_times.insert(server_id.clone(), state.redis.get_unchecked::<u64>(&format!("server:{}:last_alive_time", server_id)).await);
+        current_match_ids.insert(server_id.clone(), state.redis.get_unchecked::<String>(&format!("server:{}:current_match_id", server_id)).await);
+    };
+    let is_stale = |server_id: &str| Session::is_server_stale(last_alive_times.get(server_id).copied().flatten(), now, stale_after);
+
+    let mut abandoned = 0;
+    for open_match in open_matches {
+        // the cached copy is the live one, the stored copy is only written at load and end
+        let mut current_match = state.match_cache.query(&open_match.id).await.unwrap_or(open_match);
+        if matches!(current_match.get_state(), MatchState::Post | MatchState::Abandoned) {
+            continue;
+        };
+        let server_id = current_match.server_id.clone();
+        // a match waiting to start on a quiet server is left alone, the server may just have nobody on it
+        let abandonment = if current_match_ids.get(&server_id).cloned().flatten().as_ref() != Some(&current_match.id) {
+            Some((AbandonReason::Replaced, now))
+        } else if current_match.get_state() != MatchState::InProgress {
+            None
+        } else if is_stale(&server_id) {
+            Some((AbandonReason::ServerTimeout, last_alive_times.get(&server_id).copied().flatten().unwrap_or(now)))
+        } else {
+            current_match.get_emptied_at()
+                .filter(|emptied_at| now.saturating_sub(*emptied_at) > empty_timeout)
+                .map(|emptied_at| (AbandonReason::Empty, emptied_at))
+        };
+        if let Some((reason, at)) = abandonment {
+            abandon_match(state, &mut current_match, reason, at).await;
+            abandoned += 1;
+        };
+    };
+
+    let mut closed = 0;
+    for server_id in server_ids.iter().filter(|server_id| is_stale(server_id)) {
+        let ended_at = last_alive_times.get(server_id).copied().flatten().unwrap_or(now);
+        let (_, sessions) = close_hanging_sessions(state, server_id, ended_at).await;
+        closed += sessions;
+    };
+
+    if abandoned > 0 || closed > 0 {
+        info!("Sweeper abandoned {} matches and closed {} sessions", abandoned, closed);
+    };
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use crate::socket::{event_type::EventType, player::player_events::PlayerXPGainData};
+
+use super::notifier::{Notification, Notifier};
+
+struct PendingXpGain {
+    gain: u32,
+    // reasons in the order they were first seen, with how often each occurred
+    reasons: Vec<(String, u32)>,
+    count: u32,
+    first_at: u64
+}
+
+impl PendingXpGain {
+    fn combined_reason(&self) -> String {
+        self.reasons.iter().map(|(reason, occurrences)| {
+            if *occurrences > 1 { format!("{} x{}", reason, occurrences) } else { reason.clone() }
+        }).collect::<Vec<String>>().join(", ")
+    }
+}
+
+// player-facing xp notifications held back so a burst of gains is shown as one
+#[derive(Default)]
+pub struct XpGainBuffer {
+    pending: HashMap<String, PendingXpGain>
+}
+
+impl XpGainBuffer {
+    // gains that don't notify the player, or with no window to coalesce in, are dispatched right away
+    pub async fn notify(&mut self, notifier: &mut (dyn Notifier + Send + Sync), data: PlayerXPGainData, now: u64, window: u64) {
+        if !data.notify || window == 0 {
+            notifier.dispatch(Notification::new(&EventType::PlayerXpGain, data)).await;
+            return;
+        };
+        self.push(data, now);
+    }
+
+    pub async fn flush(&mut self, notifier: &mut (dyn Notifier + Send + Sync), now: u64, window: u64) {
+        for data in self.take_due(now, window) {
+            notifier.dispatch(Notification::new(&EventType::PlayerXpGain, data)).await;
+        };
+    }
+
+    pub fn push(&mut self, data: PlayerXPGainData, now: u64) {
+        let pending = self.pending.entry(data.player_id).or_insert(PendingXpGain { gain: 0, reasons: Vec::new(), count: 0, first_at: now });
+        pending.gain += data.gain;
+        pending.count += data.count;
+        match pending.reasons.iter_mut().find(|(reason, _)| *reason == data.reason) {
+            Some((_, occurrences)) => *occurrences += 1,
+            None => pending.reasons.push((data.reason, 1))
+        };
+    }
+
+    // one summarized notification per player whose first buffered gain is at least `window` ms old
+    pub fn take_due(&mut self, now: u64, window: u64) -> Vec<PlayerXPGainData> {
+        let due : Vec<String> = self.pending.iter()
+            .filter(|(_, pending)| now.saturating_sub(pending.first_at) >= window)
+            .map(|(player_id, _)| player_id.clone())
+            .collect();
+        due.into_iter().filter_map(|player_id| {
+            let pending = self.pending.remove(&player_id)?;
+            Some(PlayerXPGainData { reason: pending.combined_reason(), player_id, gain: pending.gain, notify: true, count: pending.count })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+
+    use crate::socket::{event_type::EventType, player::player_events::PlayerXPGainData, server::notifier::{Notification, Notifier}};
+
+    use super::XpGainBuffer;
+
+    #[derive(Default)]
+    struct RecordingNotifier {
+        dispatched: Vec<Notification>
+    }
+
+    #[async_trait]
+    impl Notifier for RecordingNotifier {
+        async fn dispatch(&mut self, notification: Notification) {
+            self.dispatched.push(notification);
+        }
+    }
+
+    fn gain(gain: u32, reason: &str, notify: bool) -> PlayerXPGainData {
+        PlayerXPGainData { player_id: String::from("player"), gain, reason: String::from(reason), notify, count: 1 }
+    }
+
+    #[test]
+    fn gains_reach_the_notifier_once_coalesced() {
+        let mut notifier = RecordingNotifier::default();
+        let mut buffer = XpGainBuffer::default();
+        block_on(async {
+            buffer.notify(&mut notifier, gain(5, "Silent", false), 0, 1_000).await;
+            buffer.notify(&mut notifier, gain(10, "Kill", true), 0, 1_000).await;
+            buffer.notify(&mut notifier, gain(10, "Kill", true), 500, 1_000).await;
+            buffer.flush(&mut notifier, 900, 1_000).await;
+            assert_eq!(notifier.dispatched.len(), 1);
+            buffer.flush(&mut notifier, 1_000, 1_000).await;
+        });
+        assert_eq!(notifier.dispatched.len(), 2);
+        assert!(notifier.dispatched.iter().all(|notification| matches!(notification.event, EventType::PlayerXpGain)));
+        assert_eq!(notifier.dispatched[0].data["gain"], 5);
+        assert_eq!(notifier.dispatched[1].data["gain"], 20);
+        assert_eq!(notifier.dispatched[1].data["reason"], "Kill x2");
+    }
+}
@@ -0,0 +1,56 @@
+use rocket::serde::json::{serde_json, Value};
+use serde::Serialize;
+use strum_macros::{Display, EnumString};
+
+// picked by the server with `encoding=` when it connects, packets are zlib compressed either way.
+// msgpack carries the same `{ e, d }` packets as json, only smaller and cheaper to parse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum SocketEncoding {
+    Json,
+    Msgpack
+}
+
+impl SocketEncoding {
+    pub fn encode<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            Self::Json => serde_json::to_vec(value)?,
+            // fields by name, the events are read by name on both sides
+            Self::Msgpack => rmp_serde::to_vec_named(value)?
+        })
+    }
+
+    // decoded into a json value so the router doesn't care which encoding a packet came in
+    pub fn decode(&self, data: &[u8]) -> anyhow::Result<Value> {
+        Ok(match self {
+            Self::Json => serde_json::from_slice(data)?,
+            Self::Msgpack => rmp_serde::from_slice(data)?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::serde::json::{json, serde_json};
+
+    use crate::socket::player::player_events::PlayerDeathData;
+
+    use super::SocketEncoding;
+
+    #[test]
+    fn both_encodings_route_the_same_event() {
+        let packet = json!({ "e": "PLAYER_DEATH", "t": 1_700_000_000_000u64, "d": {
+            "victim": { "name": "Victim", "id": "victim" },
+            "attacker": { "name": "Attacker", "id": "attacker" },
+            "weapon": "BOW", "entity": null, "distance": 42, "key": "PROJECTILE", "cause": "FALL"
+        } });
+        let from_json = SocketEncoding::Json.decode(&SocketEncoding::Json.encode(&packet).unwrap()).unwrap();
+        let from_msgpack = SocketEncoding::Msgpack.decode(&SocketEncoding::Msgpack.encode(&packet).unwrap()).unwrap();
+        assert_eq!(from_json, from_msgpack);
+        // the router hands listeners `d` as a string, which is what ends up credited
+        assert_eq!(from_json["d"].to_string(), from_msgpack["d"].to_string());
+        let death: PlayerDeathData = serde_json::from_str(&from_msgpack["d"].to_string()).unwrap();
+        assert_eq!(death.distance, Some(42));
+        assert_eq!(death.attacker.map(|attacker| attacker.id), Some(String::from("attacker")));
+    }
+}
@@ -1,31 +1,37 @@
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 
 use std::io::{Read};
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::StreamExt;
 use log::info;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::Mutex;
+use tokio::sync::broadcast::error::RecvError;
 
 
 use tokio_tungstenite::WebSocketStream;
 use tokio_tungstenite::tungstenite::handshake::server::{Request, Response, ErrorResponse};
-use tokio_tungstenite::tungstenite::http::Response as HttpResponse;
+use tokio_tungstenite::tungstenite::http::{HeaderValue, Response as HttpResponse};
 use flate2::read::ZlibDecoder;
-use tokio_tungstenite::tungstenite::protocol::CloseFrame;
-use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
 
 use crate::MarsAPIState;
 use crate::socket::event_type::EventType;
+use crate::socket::event_time::EventTime;
+use crate::socket::socket_encoding::SocketEncoding;
 use crate::socket::socket_router::SocketRouter;
 use crate::util::error::ApiErrorResponder;
 use crate::util::r#macro::unwrap_helper;
 use crate::util::time::get_u64_time_millis;
 
-use rocket::serde::json::{serde_json, Value};
+use rocket::serde::json::serde_json;
 
-use super::server::server_context::ServerContext;
+use super::server::{notifier::{Notification, SocketNotifier}, server_context::ServerContext, xp_gain_buffer::XpGainBuffer};
+
+// how often coalesced xp notifications are checked, bounds how late past its window one is sent
+const XP_FLUSH_INTERVAL_MS : u64 = 250;
 
 pub struct SocketState {
     pub api_state: Arc<MarsAPIState>
@@ -33,6 +39,8 @@ pub struct SocketState {
 
 pub struct SocketSession {
     pub server_id: String,
+    pub realm: Option<String>,
+    pub encoding: SocketEncoding,
     pub api_state: Arc<MarsAPIState>,
     // concurrent access may be possible
     pub connected_servers: Arc<Mutex<HashSet<ServerContext>>>
@@ -53,7 +61,7 @@ pub async fn setup_socket(
         tokio::select! {
             socket_accept_result = socket.accept() => {
                 if let Ok((stream, _)) = socket_accept_result {
-                    let mut session_state : SocketSession = SocketSession { server_id: "".to_owned(), connected_servers: Arc::clone(&connected_servers), api_state: socket_state.api_state.clone() };
+                    let mut session_state : SocketSession = SocketSession { server_id: "".to_owned(), realm: None, encoding: SocketEncoding::Json, connected_servers: Arc::clone(&connected_servers), api_state: socket_state.api_state.clone() };
                     let ws_stream = match tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
                         verify_connection(&socket_state, &mut session_state, request, response)
                     }).await {
@@ -76,18 +84,44 @@ async fn accept_connection(
     ws_stream: WebSocketStream<TcpStream>, 
     socket_session: SocketSession
 ) -> anyhow::Result<()> {
-    info!("Accepted WebSocket connection from server {}", socket_session.server_id.clone());
+    info!("Accepted WebSocket connection from server {} ({})", socket_session.server_id.clone(), socket_session.encoding);
     let server_id = socket_session.server_id.clone();
+    let metrics = socket_session.api_state.metrics.clone();
+    metrics.connection_opened();
     let server = {
+        let (sink, stream) = ws_stream.split();
+        let notifier = Box::new(SocketNotifier { sink, encoding: socket_session.encoding });
         let server = ServerContext {
-            id: socket_session.server_id.clone(), api_state: socket_session.api_state.clone(), stream: ws_stream 
+            id: socket_session.server_id.clone(), api_state: socket_session.api_state.clone(), stream, notifier, realm: socket_session.realm.clone(), encoding: socket_session.encoding, xp_gains: XpGainBuffer::default(), event_time: get_u64_time_millis()
         };
         server
     };
     
     let mut router = SocketRouter::new(server);
 
-    while let Some(msg) = router.server.stream.next().await {
+    let mut server_broadcast = socket_session.api_state.server_broadcast.subscribe();
+    let mut xp_flush = tokio::time::interval(Duration::from_millis(XP_FLUSH_INTERVAL_MS));
+    loop {
+        let msg = tokio::select! {
+            msg = router.server.stream.next() => match msg {
+                Some(msg) => msg,
+                None => break
+            },
+            broadcast = server_broadcast.recv() => {
+                match broadcast {
+                    Ok(broadcast) if broadcast.origin_server_id != server_id => {
+                        router.server.notifier.dispatch(Notification { event: broadcast.event, data: broadcast.data }).await;
+                    },
+                    Err(RecvError::Lagged(skipped)) => warn!("Server {} skipped {} broadcasts", server_id, skipped),
+                    _ => {}
+                };
+                continue;
+            },
+            _ = xp_flush.tick() => {
+                router.server.flush_xp_gains().await;
+                continue;
+            }
+        };
         let msg = unwrap_helper::continue_default!(msg.ok());
         let data = match msg {
             tokio_tungstenite::tungstenite::Message::Binary(data) => data,
@@ -95,14 +129,12 @@ async fn accept_connection(
         };
 
         let mut zlib_decoder = ZlibDecoder::new(data.as_slice());
-        let mut decompressed = String::new();
-        if let Err(_) = zlib_decoder.read_to_string(&mut decompressed) {
+        let mut decompressed = Vec::new();
+        if zlib_decoder.read_to_end(&mut decompressed).is_err() {
             continue;
         };
-        let text = decompressed;
-
 
-        let json_object : Value = unwrap_helper::continue_default!(serde_json::from_str(&text).ok());
+        let json_object = unwrap_helper::continue_default!(router.server.encoding.decode(&decompressed).ok());
         let event = {
             let e_val = json_object.get("e");
             if e_val.is_none() {
@@ -118,13 +150,29 @@ async fn accept_connection(
             d_val.unwrap().to_owned()
         };
         let socket_data_serialized = socket_data.to_string();
+        let sent_at = json_object.get("t").and_then(|sent_at| sent_at.as_u64());
+        let now = get_u64_time_millis();
+        let event_time = match router.server.api_state.config.options.clock_skew.validate(sent_at, now, event.is_phase_event()) {
+            EventTime::Accepted(at) => at,
+            EventTime::Clamped(sent_at, at) => {
+                warn!("[{}:{}] Event sent at {} is {}ms off the API clock, clamped to {}", server_id, event, sent_at, sent_at.abs_diff(now), at);
+                at
+            },
+            EventTime::Rejected(sent_at) => {
+                warn!("[{}:{}] Event sent at {} is {}ms off the API clock, dropped", server_id, event, sent_at, sent_at.abs_diff(now));
+                metrics.record_rejected_event("clock_skew");
+                continue;
+            }
+        };
+        router.server.advance_event_time(event_time);
 
         router.route(&event, socket_data).await;
         router.server.set_last_time_alive(get_u64_time_millis()).await;
         info!("[{}:{}] {}", server_id, event, socket_data_serialized);
     }
     info!("WebSocket connection closed from server {}", socket_session.server_id.clone());
-    let _ = router.server.stream.close(Some(CloseFrame { code: CloseCode::Normal, reason: std::borrow::Cow::Borrowed("Connection closed")  })).await;
+    router.server.notifier.close().await;
+    metrics.connection_closed();
 
     Ok(())
 }
@@ -137,10 +185,37 @@ fn verify_connection(socket_state: &SocketState, socket_session: &mut SocketSess
         let hash_query : HashMap<String, String> = url::form_urlencoded::parse(query_string.as_bytes()).into_owned().collect();
         let server_id = unwrap_helper::return_default!(hash_query.get("id"), Err(build_response_from_error_responder(ApiErrorResponder::unauthorized()))).to_owned();
         let token = unwrap_helper::return_default!(hash_query.get("token"), Err(build_response_from_error_responder(ApiErrorResponder::unauthorized()))).to_owned();
-        if token != socket_state.api_state.config.token {
-            return Err(build_response_from_error_responder(ApiErrorResponder::unauthorized()));
+        let config = &socket_state.api_state.config;
+        let realm = match (config.verify_token(&token), config.verify_realm_token(&token)) {
+            (Some(version), _) => {
+                info!("Server {} authenticated with the {} token", server_id, version);
+                None
+            },
+            (None, Some(realm)) => {
+                info!("Server {} authenticated with the token of realm {}", server_id, realm);
+                Some(realm)
+            },
+            (None, None) => {
+                warn!("Rejected socket connection from server {} with an unknown or expired token", server_id);
+                return Err(build_response_from_error_responder(ApiErrorResponder::unauthorized()));
+            }
+        };
+        // json unless asked otherwise, an encoding this API doesn't know falls back to json as well
+        let encoding = match hash_query.get("encoding") {
+            Some(requested) => SocketEncoding::from_str(requested).unwrap_or_else(|_| {
+                warn!("Server {} asked for unknown socket encoding '{}', using json", server_id, requested);
+                SocketEncoding::Json
+            }),
+            None => SocketEncoding::Json
+        };
+        // tells the server which encoding it got, it has to send packets in it too
+        let mut response = response;
+        if let Ok(header) = HeaderValue::from_str(&encoding.to_string()) {
+            response.headers_mut().insert("X-Socket-Encoding", header);
         };
         socket_session.server_id = server_id;
+        socket_session.realm = realm;
+        socket_session.encoding = encoding;
         return Ok(response);
     } else {
         return Err(build_response_from_error_responder(ApiErrorResponder::unauthorized()));
@@ -6,19 +6,27 @@ use rocket::serde::{json::{serde_json, Value}, DeserializeOwned};
 
 use uuid::Uuid;
 
-use crate::{database::models::{death::Death, achievement::Achievement, r#match::{FirstBlood, MatchState}, participant::{Participant, SimpleParticipant}, player::{AchievementData, Player}}, socket::r#match::match_phase_listener::MatchPhaseListener, util::{r#macro::unwrap_helper, time::get_u64_time_millis}};
+use crate::{database::models::{death::{DamageCause, Death}, r#match::{AbandonReason, FirstBlood, Match, MatchState}, participant::{Participant, SimpleParticipant, KillCounts}, player::{AchievementData, Player}, damage_event::DamageEvent, milestone::grant_milestones}, socket::r#match::{abandonment::abandon_match, match_phase_listener::MatchPhaseListener}, util::{r#macro::unwrap_helper, time::Timestamp, tracking::StatCategory}};
 
-use super::{event_type::EventType, leaderboard::leaderboard_listener::LeaderboardListener, map::map_record_listener::MapRecordListener, r#match::match_events::{MatchEndData, MatchStartData}, objective::objective_events::{ControlPointCaptureData, CoreLeakData, DestroyableDamageData, DestroyableDestroyData, FlagDropData, FlagEventData, WoolDropData, WoolEventData}, participant::{participant_party_listener::ParticipantPartyListener, participant_stat_listener::ParticipantStatListener}, player::{player_events::{KillstreakData, PartyJoinData, PartyLeaveData, PlayerAchievementData, PlayerChatData, PlayerDeathData}, player_gamemode_stat_listener::PlayerGamemodeStatListener, player_listener::PlayerListener, player_record_listener::PlayerRecordListener, player_stat_listener::PlayerStatListener, player_xp_listener::PlayerXPListener}, server::{server_context::ServerContext, server_events::MatchLoadData}, update::player_update_listener::PlayerUpdateListener};
+use super::{event_type::EventType, leaderboard::leaderboard_listener::LeaderboardListener, map::map_record_listener::MapRecordListener, r#match::match_events::{MatchEndData, MatchStartData}, objective::{objective_events::{ControlPointCaptureData, CoreLeakData, DestroyableDamageData, DestroyableDestroyData, FlagDropData, FlagEventData, WoolDropData, WoolEventData}, objective_validation::{ObjectiveEvent, ObjectiveRejection, ObjectiveValidation}}, participant::{participant_party_listener::ParticipantPartyListener, participant_stat_listener::ParticipantStatListener}, player::{damage_log::DamageLog, kill_decay::KillDecay, player_events::{AchievementUnlockData, PlayerDamageData, KillstreakData, PartyJoinData, PartyLeaveData, PlayerAchievementData, PlayerChatData, PlayerDeathData}, player_gamemode_stat_listener::PlayerGamemodeStatListener, player_listener::PlayerListener, player_record_listener::PlayerRecordListener, player_stat_listener::PlayerStatListener, player_xp_listener::PlayerXPListener}, server::{notifier::Notification, server_context::ServerContext, server_events::MatchLoadData}, update::player_update_listener::PlayerUpdateListener};
 use crate::database::Database;
 
+// total kills that received reduced xp, across all servers
+pub const KILL_DECAY_METRIC_KEY : &str = "metrics:kill-decay";
+
 pub struct SocketRouter {
     pub server: ServerContext,
     pub participant_listeners: Vec<Box<dyn PlayerListener<Context = Participant> + Send + Sync>>,
-    pub player_listeners: Vec<Box<dyn PlayerListener<Context = Player> + Send + Sync>>
+    pub player_listeners: Vec<Box<dyn PlayerListener<Context = Player> + Send + Sync>>,
+    pub damage_log: DamageLog,
+    pub kill_decay: KillDecay,
+    pub current_match_id: Option<String>
 }
 
 pub enum SocketError {
     InvalidMatchState,
+    // an objective event that doesn't fit the match, already logged with the reason
+    RejectedObjective(String),
     Unknown(String)
 }
 
@@ -26,6 +34,7 @@ impl SocketError {
     fn message(&self) -> String {
         match self {
             Self::InvalidMatchState => String::from("Encountered invalid match state or missing match"),
+            Self::RejectedObjective(reason) => format!("Rejected objective event: {}", reason),
             Self::Unknown(msg) => msg.clone()
         }
     }
@@ -47,7 +56,10 @@ impl SocketRouter {
                 Box::new(PlayerXPListener {}),
                 Box::new(PlayerRecordListener {}),
                 Box::new(PlayerUpdateListener {}),
-            ]
+            ],
+            damage_log: DamageLog::default(),
+            kill_decay: KillDecay::default(),
+            current_match_id: None
         }
     }
 
@@ -57,6 +69,7 @@ impl SocketRouter {
             EventType::MatchStart =>                            self.on_match_start(Self::parse_data(data)).await,
             EventType::MatchEnd =>                              self.on_match_end(Self::parse_data(data)).await,
             EventType::PlayerDeath =>                           self.on_player_death(Self::parse_data(data)).await,
+            EventType::PlayerDamage =>                          self.on_player_damage(Self::parse_data(data)).await,
             EventType::PlayerChat =>                            self.on_player_chat(Self::parse_data(data)).await,
             EventType::Killstreak =>                            self.on_killstreak(Self::parse_data(data)).await,
             EventType::PartyJoin =>                             self.on_party_join(Self::parse_data(data)).await,
@@ -74,16 +87,24 @@ impl SocketRouter {
             EventType::WoolDefend =>                            self.on_wool_defend(Self::parse_data(data)).await,
             EventType::ControlPointCapture =>                   self.on_control_point_capture(Self::parse_data(data)).await,
             EventType::AchievementEarn =>                       self.on_achievement_complete(Self::parse_data(data)).await,
-            _ => {warn!("Event (srv {}) fell through router: {} - {}", self.server.id, event_type, data.to_string()); return}
+            _ => {
+                warn!("Event (srv {}) fell through router: {} - {}", self.server.id, event_type, data.to_string());
+                self.server.api_state.metrics.record_rejected_event("unrouted");
+                return
+            }
         };
+        let metrics = self.server.api_state.metrics.clone();
+        metrics.record_event(&event_type.to_string());
         match response {
             Err(socket_error) => {
                 match socket_error {
                     SocketError::InvalidMatchState => {
-                        self.server.call(&EventType::ForceMatchEnd, ()).await;
+                        metrics.record_rejected_event("invalid_match_state");
+                        self.server.notifier.dispatch(Notification::new(&EventType::ForceMatchEnd, ())).await;
                         let match_id = self.get_match_id().await;
                         warn!("Forcing match end for Match ID: {}. Caused by {}: {}", match_id, event_type.to_string(), socket_error.message());
                     },
+                    SocketError::RejectedObjective(_) => metrics.record_rejected_event("objective"),
                     SocketError::Unknown(_text) => {
                     }
                 }
@@ -93,6 +114,9 @@ impl SocketRouter {
     }
 
     async fn on_match_load(&mut self, data: MatchLoadData) -> Result<(), SocketError> {
+        self.damage_log.clear();
+        self.current_match_id = None;
+        self.kill_decay.prune(self.server.event_time, self.server.api_state.config.options.kill_decay_window);
         MatchPhaseListener { server: &mut self.server }.on_load(data).await
     }
 
@@ -105,15 +129,29 @@ impl SocketRouter {
             Ok(current_match) => current_match,
             Err(socket_error) => return Err(socket_error)
         };
+        let min_playtime = self.server.api_state.config.options.leaderboard_min_playtime;
+        for participant in current_match.participants.values_mut() {
+            let player = self.server.api_state.player_cache.get(&self.server.api_state.database, &participant.get_name_lower()).await;
+            participant.leaderboard_eligible = player.map(|player| !player.frozen && player.is_leaderboard_eligible_in(&self.server.realm, min_playtime));
+        };
         self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         Ok(())
     }
 
     async fn on_match_end(&mut self, mut data: MatchEndData) -> Result<(), SocketError> {
+        self.damage_log.clear();
         let mut current_match = unwrap_helper::return_default!(self.server.get_match().await, Err(SocketError::InvalidMatchState));
         if current_match.get_state() != MatchState::InProgress {
             return Err(SocketError::InvalidMatchState);
         };
+        // a rejected result is abandoned instead of ended, so nothing credited at match end is credited.
+        // kills, deaths and assists are taken back, other credits made while it ran stay
+        if let Err(reason) = self.server.api_state.config.options.result_verification.verify(&current_match, &data) {
+            warn!("({}) Rejected the result of match {}: {}", self.server.id, current_match.id, reason);
+            self.server.api_state.metrics.record_rejected_event("invalid_result");
+            abandon_match(&self.server.api_state, &mut current_match, AbandonReason::InvalidResult, self.server.event_time).await;
+            return Ok(());
+        };
         current_match = match (MatchPhaseListener { server: &mut self.server }.on_end(&data, current_match)) {
             Ok(current_match) => current_match,
             Err(socket_error) => return Err(socket_error)
@@ -130,27 +168,32 @@ impl SocketRouter {
                 for participant_listener in self.participant_listeners.iter() {
                      participant_listener.on_match_end_v2(&mut self.server, &mut current_match, &mut participant, &mut data).await;
                 };
+                let result = current_match.get_participant_match_result(&participant, &data);
+                current_match.results.insert(participant.id.clone(), result);
                 current_match.save_participants(vec![participant.clone()]);
             };
-            let mut player = participant.get_player(&*self.server.api_state).await;
+            let mut player = self.server.load_player(&participant).await;
             {
-                for player_listener in self.player_listeners.iter() {
+                for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                     player_listener.on_match_end_v2(&mut self.server, &mut current_match, &mut player, &mut data).await;
                 };
+                grant_milestones(&self.server.api_state, &mut player).await;
 
-                participant.set_player(&*self.server.api_state, &player).await;
+                profiles.push(self.server.store_player(&participant, &player).await);
             };
-            profiles.push(player);
         }
 
         if profiles.len() > 0 {
-            let mut tasks : Vec<_> = Vec::new();
-            for profile in profiles.iter() {
-                tasks.push(self.server.api_state.database.save(profile));
-            };
-            join_all(tasks).await;
+            let api_state = &self.server.api_state;
+            join_all(profiles.iter().map(|profile| async move {
+                let _permit = api_state.match_end_writes.acquire().await;
+                api_state.database.save(profile).await;
+            })).await;
         };
 
+        current_match.scoreboard = current_match.get_scoreboard();
+        self.server.api_state.death_writer.flush_match(&current_match.id).await;
+
         {
             self.server.api_state.database.save(&current_match.level).await;
             self.server.api_state.match_cache.set_with_expiry(&self.server.api_state.database, &current_match.id, &current_match, true, Some(3_600_000)).await;
@@ -165,9 +208,28 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        // the server only knows who pushed a player into the void if it tracked them, otherwise their last attacker gets the kill
+        if data.attacker.is_none() && data.cause == DamageCause::Void {
+            let window = self.server.api_state.config.options.assist_window;
+            data.attacker = self.damage_log.last_attacker(&data.victim.id, self.server.event_time, window)
+                .filter(|attacker| current_match.participants.contains_key(&attacker.id));
+        };
+
         let is_first_blood = current_match.first_blood.is_none() && data.is_murder();
         if is_first_blood {
-            current_match.first_blood = Some(FirstBlood { attacker: data.attacker.as_ref().unwrap().clone(), victim: data.victim.clone(), date: get_u64_time_millis() } );
+            current_match.first_blood = Some(FirstBlood { attacker: data.attacker.as_ref().unwrap().clone(), victim: data.victim.clone(), date: self.server.event_time } );
+        };
+
+        if let (true, Some(attacker)) = (data.is_murder(), data.attacker.as_ref()) {
+            let options = &self.server.api_state.config.options;
+            if options.kill_decay_enabled {
+                data.kill_credit = self.kill_decay.credit(
+                    &attacker.id, &data.victim.id, self.server.event_time, options.kill_decay_window, options.kill_decay_factor, options.kill_decay_min_credit
+                );
+                if data.kill_credit < 1.0 {
+                    self.server.api_state.redis.increment(KILL_DECAY_METRIC_KEY).await;
+                };
+            };
         };
 
         if data.is_murder() {
@@ -188,16 +250,40 @@ impl SocketRouter {
                 // player_context = PlayerStatListener::on_kill(&mut self.server, player_context, &mut data, is_first_blood).await;
                 // attacker.set_player(&*self.server.api_state, &player_context.profile).await;
 
-                let mut player = attacker.get_player(&*self.server.api_state).await;
-                for player_listener in self.player_listeners.iter() {
+                let mut player = self.server.load_player(&attacker).await;
+                for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                     player_listener.on_kill(&mut self.server, &mut current_match, &mut player, &mut data, is_first_blood).await;
                 };
-                attacker.set_player(&*self.server.api_state, &player).await;
+                Self::credit_profile(&mut current_match, &mut attacker, &player, true, |counts| counts.kills += 1);
+                self.server.store_player(&attacker, &player).await;
 
             };
         };
 
+        {
+            let options = &self.server.api_state.config.options;
+            let killer_id = if data.is_murder() { data.attacker.as_ref().map(|attacker| attacker.id.as_str()) } else { None };
+            let assisters = self.damage_log.take_assists(
+                &data.victim.id, killer_id, self.server.event_time, options.assist_window, options.assist_damage_share
+            );
+            for assister in assisters {
+                let mut assisting = unwrap_helper::continue_default!(current_match.participants.get(&assister.id).cloned());
+                for participant_listener in self.participant_listeners.iter() {
+                     participant_listener.on_assist(&mut self.server, &mut current_match, &mut assisting, &mut data).await;
+                };
+                current_match.save_participants(vec![assisting.clone()]);
+
+                let mut player = self.server.load_player(&assisting).await;
+                for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
+                    player_listener.on_assist(&mut self.server, &mut current_match, &mut player, &mut data).await;
+                };
+                Self::credit_profile(&mut current_match, &mut assisting, &player, false, |counts| counts.assists += 1);
+                self.server.store_player(&assisting, &player).await;
+            };
+        };
+
         let mut victim = current_match.participants.get(&data.victim.id).unwrap().to_owned();
+        current_match.objectives.release_carrier(&victim.id);
 
         {
             for participant_listener in self.participant_listeners.iter() {
@@ -206,15 +292,16 @@ impl SocketRouter {
             current_match.save_participants(vec![victim.clone()]);
         };
         {
-            let mut player = victim.get_player(&*self.server.api_state).await;
-            for player_listener in self.player_listeners.iter() {
+            let mut player = self.server.load_player(&victim).await;
+            for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                 player_listener.on_death(&mut self.server, &mut current_match, &mut player, &mut data, is_first_blood).await;
             };
-            victim.set_player(&*self.server.api_state, &player).await;
+            Self::credit_profile(&mut current_match, &mut victim, &player, true, |counts| counts.deaths += 1);
+            self.server.store_player(&victim, &player).await;
         };
 
         {
-            self.server.api_state.database.insert_one(&Death {
+            let death = Death {
                 id: Uuid::new_v4().to_string(),
                 victim: data.victim.clone(),
                 attacker: data.attacker.clone(),
@@ -225,8 +312,9 @@ impl SocketRouter {
                 cause: data.cause.clone(),
                 server_id: self.server.id.clone(),
                 match_id: current_match.id.clone(),
-                created_at: get_u64_time_millis(),
-            }).await;
+                created_at: Timestamp::from_millis(self.server.event_time),
+            };
+            self.server.api_state.death_writer.record(death);
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
 
@@ -234,6 +322,38 @@ impl SocketRouter {
         Ok(())
     }
 
+    async fn on_player_damage(&mut self, data: PlayerDamageData) -> Result<(), SocketError> {
+        let now = self.server.event_time;
+        self.damage_log.record(&data, now, self.server.api_state.config.options.assist_window);
+        if self.server.api_state.damage_events.is_some() {
+            let match_id = self.current_match_id().await;
+            let event = DamageEvent {
+                id: Uuid::new_v4().to_string(),
+                victim_id: data.victim.id,
+                attacker_id: data.attacker.map(|attacker| attacker.id),
+                weapon: data.weapon,
+                cause: data.cause,
+                damage: data.damage,
+                server_id: self.server.id.clone(),
+                match_id,
+                created_at: Timestamp::from_millis(now)
+            };
+            if let Some(damage_events) = &self.server.api_state.damage_events {
+                // dropped when the writer is behind
+                let _ = damage_events.try_send(event);
+            };
+        };
+        Ok(())
+    }
+
+    // remembered per match so damage events do not cost a redis lookup each
+    async fn current_match_id(&mut self) -> Option<String> {
+        if self.current_match_id.is_none() {
+            self.current_match_id = self.server.get_current_match_id().await;
+        };
+        self.current_match_id.clone()
+    }
+
     async fn on_player_chat(&mut self, mut data: PlayerChatData) -> Result<(), SocketError> {
         let mut current_match = unwrap_helper::return_default!(self.server.get_match().await, Err(SocketError::InvalidMatchState));
         let participant = match current_match.participants.get(&data.player.id) {
@@ -252,9 +372,11 @@ impl SocketRouter {
 
         {
             let mut player = unwrap_helper::return_default!(self.server.api_state.player_cache.get(&self.server.api_state.database, &data.player.name).await, Ok(()));
-            for player_listener in self.player_listeners.iter() {
+            player.swap_realm_stats(&self.server.realm);
+            for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                 player_listener.on_chat(&mut self.server, &mut current_match, &mut player, &mut data).await;
             };
+            player.swap_realm_stats(&self.server.realm);
             self.server.api_state.player_cache.set(&self.server.api_state.database, &player.name, &player, false).await;
         };
 
@@ -271,26 +393,26 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
         let mut participant = current_match.participants.get(&data.player.id).unwrap().to_owned();
-        let mut player = participant.get_player(&*self.server.api_state).await;
+        let mut player = self.server.load_player(&participant).await;
         if data.ended {
             for participant_listener in self.participant_listeners.iter() {
                  participant_listener.on_killstreak_end(&mut self.server, &mut current_match, &mut participant, data.amount).await;
             };
-            for player_listener in self.player_listeners.iter() {
+            for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                 player_listener.on_killstreak_end(&mut self.server, &mut current_match, &mut player, data.amount).await;
             };
         } else {
             for participant_listener in self.participant_listeners.iter() {
                  participant_listener.on_killstreak(&mut self.server, &mut current_match, &mut participant, data.amount).await;
             };
-            for player_listener in self.player_listeners.iter() {
+            for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                 player_listener.on_killstreak(&mut self.server, &mut current_match, &mut player, data.amount).await;
             };
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -311,14 +433,15 @@ impl SocketRouter {
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_party_join(&mut self.server, &mut current_match, &mut participant, data.party_name.clone()).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        participant.leaderboard_eligible = Some(!player.frozen && player.is_leaderboard_eligible(self.server.api_state.config.options.leaderboard_min_playtime));
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_party_join(&mut self.server, &mut current_match, &mut player, data.party_name.clone()).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -334,14 +457,15 @@ impl SocketRouter {
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_party_leave(&mut self.server, &mut current_match, &mut participant).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_party_leave(&mut self.server, &mut current_match, &mut player).await;
         };
 
         {
+            current_match.objectives.release_carrier(&participant.id);
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -353,6 +477,7 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::DestroyableDamage { destroyable_id: &data.destroyable_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         let destroyable = unwrap_helper::return_default!(
             unwrap_helper::return_default!(
@@ -363,14 +488,14 @@ impl SocketRouter {
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_destroyable_damage(&mut self.server, &mut current_match, &mut participant, &destroyable, data.damage).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_destroyable_damage(&mut self.server, &mut current_match, &mut player, &destroyable, data.damage).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -378,6 +503,7 @@ impl SocketRouter {
 
     async fn on_destroyable_destroy(&mut self, data: DestroyableDestroyData) -> Result<(), SocketError> {
         let mut current_match = unwrap_helper::return_default!(self.server.get_match().await, Err(SocketError::InvalidMatchState));
+        self.validate_objective(&mut current_match, ObjectiveEvent::DestroyableDestroy { destroyable_id: &data.destroyable_id, player_ids: data.contributions.iter().map(|contribution| contribution.player_id.as_str()).collect() })?;
         for contribution in data.contributions.iter() {
             let mut participant = current_match.participants.get(&contribution.player_id).unwrap().clone();
             for participant_listener in self.participant_listeners.iter() {
@@ -389,8 +515,8 @@ impl SocketRouter {
                      contribution.block_count
                 ).await;
             };
-            let mut player = participant.get_player(&*self.server.api_state).await;
-            for player_listener in self.player_listeners.iter() {
+            let mut player = self.server.load_player(&participant).await;
+            for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                 player_listener.on_destroyable_destroy(
                     &mut self.server, 
                     &mut current_match, 
@@ -402,7 +528,7 @@ impl SocketRouter {
 
             {
                 current_match.save_participants(vec![participant.clone()]);
-                participant.set_player(&*self.server.api_state, &player).await;
+                self.server.store_player(&participant, &player).await;
             };
         };
         self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
@@ -411,6 +537,7 @@ impl SocketRouter {
 
     async fn on_core_leak(&mut self, data: CoreLeakData) -> Result<(), SocketError> {
         let mut current_match = unwrap_helper::return_default!(self.server.get_match().await, Err(SocketError::InvalidMatchState));
+        self.validate_objective(&mut current_match, ObjectiveEvent::CoreLeak { core_id: &data.core_id, player_ids: data.contributions.iter().map(|contribution| contribution.player_id.as_str()).collect() })?;
         for contribution in data.contributions.iter() {
             let mut participant = current_match.participants.get(&contribution.player_id).unwrap().clone();
             for participant_listener in self.participant_listeners.iter() {
@@ -422,8 +549,8 @@ impl SocketRouter {
                      contribution.block_count
                 ).await;
             };
-            let mut player = participant.get_player(&*self.server.api_state).await;
-            for player_listener in self.player_listeners.iter() {
+            let mut player = self.server.load_player(&participant).await;
+            for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                 player_listener.on_core_leak(
                     &mut self.server, 
                     &mut current_match, 
@@ -435,7 +562,7 @@ impl SocketRouter {
 
             {
                 current_match.save_participants(vec![participant.clone()]);
-                participant.set_player(&*self.server.api_state, &player).await;
+                self.server.store_player(&participant, &player).await;
             };
         };
         self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
@@ -445,18 +572,19 @@ impl SocketRouter {
     async fn on_flag_place(&mut self, data: FlagDropData) -> Result<(), SocketError> {
         let mut current_match = unwrap_helper::return_default!(self.server.get_match().await, Err(SocketError::InvalidMatchState));
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::FlagCapture { flag_id: &data.flag_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_flag_place(&mut self.server, &mut current_match, &mut participant, data.held_time).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_flag_place(&mut self.server, &mut current_match, &mut player, data.held_time).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -468,18 +596,19 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::FlagPickup { flag_id: &data.flag_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_flag_pickup(&mut self.server, &mut current_match, &mut participant).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_flag_pickup(&mut self.server, &mut current_match, &mut player).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -488,18 +617,19 @@ impl SocketRouter {
     async fn on_flag_drop(&mut self, data: FlagDropData) -> Result<(), SocketError> {
         let mut current_match = unwrap_helper::return_default!(self.server.get_match().await, Err(SocketError::InvalidMatchState));
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::FlagDrop { flag_id: &data.flag_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_flag_drop(&mut self.server, &mut current_match, &mut participant, data.held_time).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_flag_drop(&mut self.server, &mut current_match, &mut player, data.held_time).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -511,18 +641,19 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::FlagDefend { flag_id: &data.flag_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_flag_defend(&mut self.server, &mut current_match, &mut participant).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_flag_defend(&mut self.server, &mut current_match, &mut player).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -534,18 +665,19 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::WoolCapture { wool_id: &data.wool_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_wool_place(&mut self.server, &mut current_match, &mut participant, data.held_time).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_wool_place(&mut self.server, &mut current_match, &mut player, data.held_time).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -557,18 +689,19 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::WoolPickup { wool_id: &data.wool_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_wool_pickup(&mut self.server, &mut current_match, &mut participant).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_wool_pickup(&mut self.server, &mut current_match, &mut player).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -580,18 +713,19 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::WoolDrop { wool_id: &data.wool_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_wool_drop(&mut self.server, &mut current_match, &mut participant, data.held_time).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_wool_drop(&mut self.server, &mut current_match, &mut player, data.held_time).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -603,18 +737,19 @@ impl SocketRouter {
             return Err(SocketError::InvalidMatchState);
         };
 
+        self.validate_objective(&mut current_match, ObjectiveEvent::WoolDefend { wool_id: &data.wool_id, player_id: &data.player_id })?;
         let mut participant = current_match.participants.get(&data.player_id).unwrap().clone();
         for participant_listener in self.participant_listeners.iter() {
              participant_listener.on_wool_defend(&mut self.server, &mut current_match, &mut participant).await;
         };
-        let mut player = participant.get_player(&*self.server.api_state).await;
-        for player_listener in self.player_listeners.iter() {
+        let mut player = self.server.load_player(&participant).await;
+        for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
             player_listener.on_wool_defend(&mut self.server, &mut current_match, &mut player).await;
         };
 
         {
             current_match.save_participants(vec![participant.clone()]);
-            participant.set_player(&*self.server.api_state, &player).await;
+            self.server.store_player(&participant, &player).await;
             self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
         };
         Ok(())
@@ -625,6 +760,7 @@ impl SocketRouter {
         if current_match.get_state() != MatchState::InProgress {
             return Err(SocketError::InvalidMatchState);
         };
+        self.validate_objective(&mut current_match, ObjectiveEvent::ControlPointCapture { point_id: &data.point_id, party_name: &data.party_name, player_ids: data.player_ids.iter().map(|player_id| player_id.as_str()).collect() })?;
         for capturer in data.player_ids.iter() {
             let mut participant = current_match.participants.get(capturer).unwrap().clone();
             for participant_listener in self.participant_listeners.iter() {
@@ -635,8 +771,8 @@ impl SocketRouter {
                      data.player_ids.len() as u32, 
                 ).await;
             };
-            let mut player = participant.get_player(&*self.server.api_state).await;
-            for player_listener in self.player_listeners.iter() {
+            let mut player = self.server.load_player(&participant).await;
+            for player_listener in Self::crediting(&self.player_listeners, &player).iter() {
                 player_listener.on_control_point_capture(
                     &mut self.server, 
                     &mut current_match, 
@@ -647,7 +783,7 @@ impl SocketRouter {
 
             {
                 current_match.save_participants(vec![participant.clone()]);
-                participant.set_player(&*self.server.api_state, &player).await;
+                self.server.store_player(&participant, &player).await;
             };
         }
         self.server.api_state.match_cache.set(&self.server.api_state.database, &current_match.id, &current_match, false).await;
@@ -656,21 +792,84 @@ impl SocketRouter {
 
     async fn on_achievement_complete(&mut self, data: PlayerAchievementData) -> Result<(), SocketError> {
         // confirm the achievement exists
-        let achievement_exists = Database::find_by_id(&self.server.api_state.database.achievements, data.achievement_id.as_str()).await.is_some();
-        if !achievement_exists {
-            return Ok(());
-        }
+        let achievement = unwrap_helper::return_default!(
+            Database::find_by_id(&self.server.api_state.database.achievements, data.achievement_id.as_str()).await,
+            Ok(())
+        );
         let mut player = unwrap_helper::return_default!(
             self.server.api_state.player_cache.get(&self.server.api_state.database, data.player.name.as_str()).await,
             Ok(())
         );
+        // re-granting keeps the original completion and never announces again
+        if player.stats.achievements.contains_key(&achievement.id) {
+            return Ok(());
+        };
         player.stats.achievements.insert(data.achievement_id.clone(), AchievementData {
             completion_time: data.completion_time
         });
         self.server.api_state.player_cache.set(&self.server.api_state.database, &player.name, &player, true).await;
+
+        let network = achievement.is_network_broadcast(&self.server.api_state.config.options.achievement_broadcast_rarity);
+        let unlock = AchievementUnlockData { 
+            player: player.to_simple(), 
+            achievement_id: achievement.id.clone(), 
+            achievement_name: achievement.name.clone(), 
+            rarity: achievement.rarity.clone(), 
+            network 
+        };
+        if network {
+            self.server.broadcast(&EventType::AchievementUnlock, &unlock);
+        };
+        self.server.notifier.dispatch(Notification::new(&EventType::AchievementUnlock, unlock)).await;
+        Ok(())
+    }
+
+    // `Err` when the event has to be dropped, see `ObjectiveValidation`
+    fn validate_objective(&self, current_match: &mut Match, event: ObjectiveEvent) -> Result<(), SocketError> {
+        let mode = self.server.api_state.config.options.objective_validation;
+        match event.check(current_match) {
+            Err(ObjectiveRejection::NotParticipating(reason)) => {
+                if mode != ObjectiveValidation::Off {
+                    warn!("({}) Dropped {} in match {}: {}", self.server.id, event.name(), current_match.id, reason);
+                };
+                return Err(SocketError::RejectedObjective(reason));
+            },
+            Err(ObjectiveRejection::Invalid(reason)) if mode == ObjectiveValidation::Strict => {
+                warn!("({}) Rejected {} in match {}: {}", self.server.id, event.name(), current_match.id, reason);
+                return Err(SocketError::RejectedObjective(reason));
+            },
+            Err(ObjectiveRejection::Invalid(reason)) if mode == ObjectiveValidation::Lenient => {
+                warn!("({}) Credited invalid {} in match {}: {}", self.server.id, event.name(), current_match.id, reason);
+            },
+            _ => {}
+        };
+        event.apply(&mut current_match.objectives);
         Ok(())
     }
 
+    // frozen players are still tracked as participants, nothing is credited to their profile
+    fn crediting<'a>(
+        player_listeners: &'a [Box<dyn PlayerListener<Context = Player> + Send + Sync>], 
+        player: &Player
+    ) -> &'a [Box<dyn PlayerListener<Context = Player> + Send + Sync>] {
+        if player.frozen { &[] } else { player_listeners }
+    }
+
+    // what the player listeners just added to the profile, kept so an abandoned match can take it back.
+    // `gamemodes` when the per-gamemode stats count the event as well
+    fn credit_profile(current_match: &mut Match, participant: &mut Participant, player: &Player, gamemodes: bool, credit: impl Fn(&mut KillCounts)) {
+        if player.frozen {
+            return;
+        };
+        if gamemodes {
+            credit(&mut participant.credited.gamemodes);
+        };
+        if current_match.is_tracking(StatCategory::Kills) {
+            credit(&mut participant.credited.profile);
+        };
+        current_match.save_participants(vec![participant.clone()]);
+    }
+
     fn parse_data<T: DeserializeOwned>(data: Value) -> T {
         let debug_res = format!("Socket passed malformed data.. {data:?}");
         serde_json::from_value(data).expect(&debug_res)
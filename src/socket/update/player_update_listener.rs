@@ -1,4 +1,4 @@
-use crate::{database::models::{r#match::{DestroyableGoal, Match}, player::Player}, socket::{event_type::EventType, r#match::match_events::MatchEndData, player::{player_events::{PlayerChatData, PlayerDeathData}, player_listener::PlayerListener}, server::server_context::ServerContext}};
+use crate::{database::models::{r#match::{DestroyableGoal, Match}, player::Player}, socket::{event_type::EventType, r#match::match_events::MatchEndData, player::{player_events::{PlayerChatData, PlayerDeathData}, player_listener::PlayerListener}, server::{server_context::ServerContext, notifier::Notification}}};
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 
@@ -73,7 +73,7 @@ impl PlayerUpdateListener {
         data: PlayerUpdateData,
         reason: PlayerUpdateReason
     ) {
-        server_context.call(&EventType::PlayerUpdate, PlayerUpdate {updated: player, data, reason }).await;
+        server_context.notifier.dispatch(Notification::new(&EventType::PlayerUpdate, PlayerUpdate {updated: player.without_senior_notes(), data, reason })).await;
     }
 }
 
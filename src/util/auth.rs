@@ -1,6 +1,6 @@
 use rocket::{request::{FromRequest, self}, Request, http::Status};
 
-use crate::MarsAPIState;
+use crate::{MarsAPIState, config::MarsConfig, util::error::ApiErrorResponder};
 
 struct TokenType;
 impl TokenType {
@@ -8,10 +8,45 @@ impl TokenType {
     pub const API_TOKEN: &'static str = "API-Token";
 }
 
+// the main token only, realm tokens are turned away
 pub struct AuthorizationToken {
     pub server_id: String
 }
 
+// for routes that are kept apart per realm (sessions and stat corrections), also taking a realm's token
+pub struct RealmAuthorizationToken {
+    pub server_id: String,
+    // `None` for servers on the main token
+    pub realm: Option<String>
+}
+
+// scoped to destructive or staff-only operations, separate from the token shared with game servers
+pub struct AdminAuthorizationToken;
+
+// the realms a read may look at. requests without a token and those on the main token see the default realm,
+// a realm's token sees that realm and only the admin token can look across realms
+pub struct RealmScope {
+    pub realm: Option<String>,
+    pub cross_realm: bool
+}
+
+impl RealmScope {
+    // `requested` is a `realm` query parameter, `None` stays in the caller's own realm
+    pub fn resolve(&self, config: &MarsConfig, requested: Option<&str>) -> Result<Option<String>, ApiErrorResponder> {
+        let requested = match requested {
+            Some(requested) => requested,
+            None => return Ok(self.realm.clone())
+        };
+        if !config.is_realm(requested) {
+            return Err(ApiErrorResponder::validation_error_with_message("Unknown realm"));
+        };
+        if self.cross_realm || self.realm.as_deref() == Some(requested) {
+            return Ok(Some(requested.to_owned()));
+        };
+        Err(ApiErrorResponder::create_anonymous_error(Status::Forbidden, "Looking at another realm needs the admin token"))
+    }
+}
+
 pub struct AuthorizationError {
     problem: String
 }
@@ -22,45 +57,125 @@ impl std::fmt::Debug for AuthorizationError {
     }
 }
 
-fn create_failure_outcome(status: Status, error: String) -> request::Outcome<AuthorizationToken, AuthorizationError> {
+fn create_failure_outcome<T>(status: Status, error: String) -> request::Outcome<T, AuthorizationError> {
     request::Outcome::Error((status, AuthorizationError { problem: error }))
 }
 
+// the server id and the realm of the token, realm tokens only pass when `allow_realms` is set
+fn verify_server_token(req: &Request<'_>, allow_realms: bool) -> request::Outcome<(String, Option<String>), AuthorizationError> {
+    let header_map = req.headers();
+    let server_id = header_map.get_one("Mars-Server-ID").map(String::from);
+    let config = if let Some(state) = req.rocket().state::<MarsAPIState>() { 
+        &state.config 
+    } else {
+        return create_failure_outcome(Status::InternalServerError, String::from("Internal error"))
+    };
+    match header_map.get_one("Authorization") {
+        Some(value) => {
+            let parts = value.split(" ").collect::<Vec<&str>>();
+            if parts.len() < 2 {
+                return create_failure_outcome(Status::Unauthorized, String::from("Malformed Authorization header"));
+            };
+            let token_type = parts[0];
+            let provided_token = parts[1];
+
+            match token_type {
+                TokenType::API_TOKEN => {
+                    if server_id.is_none() {
+                        return create_failure_outcome(Status::Unauthorized, String::from("Missing server ID"));
+                    };
+                    let realm = if config.verify_token(provided_token).is_some() {
+                        None
+                    } else if let Some(realm) = config.verify_realm_token(provided_token) {
+                        if !allow_realms {
+                            return create_failure_outcome(Status::Forbidden, String::from("A realm's token can't be used here"));
+                        };
+                        Some(realm)
+                    } else {
+                        return create_failure_outcome(Status::Unauthorized, String::from("Wrong token bro"));
+                    };
+                    request::Outcome::Success((server_id.unwrap(), realm))
+                },
+                TokenType::BEARER => create_failure_outcome(Status::Unauthorized, String::from("Unsupported token type")),
+                _ => create_failure_outcome(Status::Unauthorized, String::from("Unknown token type"))
+            }
+        },
+        None => create_failure_outcome(Status::Unauthorized, String::from("Did not provide authorization header"))
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for AuthorizationToken {
     type Error = AuthorizationError;
 
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, AuthorizationError> {
-        let header_map = req.headers();
-        let server_id = if let Some(id) = header_map.get_one("Mars-Server-ID") { Some(String::from(id)) } else { None };
-        let actual_token = if let Some(state) = req.rocket().state::<MarsAPIState>() { 
-            &state.config.token 
-        } else {
-            return create_failure_outcome(Status::InternalServerError, String::from("Internal error"))
+        verify_server_token(req, false).map(|(server_id, _)| AuthorizationToken { server_id })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RealmAuthorizationToken {
+    type Error = AuthorizationError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, AuthorizationError> {
+        verify_server_token(req, true).map(|(server_id, realm)| RealmAuthorizationToken { server_id, realm })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminAuthorizationToken {
+    type Error = AuthorizationError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, AuthorizationError> {
+        let admin_token = match req.rocket().state::<MarsAPIState>() {
+            Some(state) => match &state.config.admin_token {
+                Some(admin_token) => admin_token,
+                None => return create_failure_outcome(Status::Forbidden, String::from("Admin operations are disabled"))
+            },
+            None => return create_failure_outcome(Status::InternalServerError, String::from("Internal error"))
         };
-        match header_map.get_one("Authorization") {
+        match req.headers().get_one("Authorization") {
             Some(value) => {
                 let parts = value.split(" ").collect::<Vec<&str>>();
                 if parts.len() < 2 {
                     return create_failure_outcome(Status::Unauthorized, String::from("Malformed Authorization header"));
                 };
-                let token_type = parts[0];
-                let provided_token = parts[1];
-
-                match token_type {
+                match parts[0] {
                     TokenType::API_TOKEN => {
-                        if server_id.is_none() {
-                            return create_failure_outcome(Status::Unauthorized, String::from("Missing server ID"));
-                        } else if actual_token != provided_token {
+                        if admin_token != parts[1] {
                             return create_failure_outcome(Status::Unauthorized, String::from("Wrong token bro"));
                         };
-                        request::Outcome::Success(AuthorizationToken { server_id: server_id.unwrap() })
+                        request::Outcome::Success(AdminAuthorizationToken)
                     },
-                    TokenType::BEARER => create_failure_outcome(Status::Unauthorized, String::from("Unsupported token type")),
-                    _ => create_failure_outcome(Status::Unauthorized, String::from("Unknown token type"))
+                    _ => create_failure_outcome(Status::Unauthorized, String::from("Unsupported token type"))
                 }
             },
             None => create_failure_outcome(Status::Unauthorized, String::from("Did not provide authorization header"))
         }
     }
 }
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RealmScope {
+    type Error = AuthorizationError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, AuthorizationError> {
+        let config = match req.rocket().state::<MarsAPIState>() {
+            Some(state) => &state.config,
+            None => return create_failure_outcome(Status::InternalServerError, String::from("Internal error"))
+        };
+        // the endpoints are public, a header that is malformed or has a token we don't know reads like no header
+        let anonymous = RealmScope { realm: None, cross_realm: false };
+        let provided_token = match req.headers().get_one("Authorization").map(|value| value.split(" ").collect::<Vec<&str>>()) {
+            Some(parts) if parts.len() >= 2 && parts[0] == TokenType::API_TOKEN => parts[1],
+            _ => return request::Outcome::Success(anonymous)
+        };
+        if config.admin_token.as_deref() == Some(provided_token) {
+            request::Outcome::Success(RealmScope { realm: None, cross_realm: true })
+        } else if let Some(realm) = config.verify_realm_token(provided_token) {
+            request::Outcome::Success(RealmScope { realm: Some(realm), cross_realm: false })
+        } else {
+            request::Outcome::Success(anonymous)
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use rocket::{data::{self, Data, FromData, Limits, ToByteUnit}, http::Status, serde::json::serde_json, Request};
+use serde::de::DeserializeOwned;
+
+use crate::config::MarsConfigOptions;
+
+use super::error::ApiErrorResponder;
+
+// which of the `body-limits.*` options an endpoint's body is held to. the limits are handed to rocket
+// under `NAME` by `limits`, so the guard reads them from the request like `Json` reads `limits.json`
+pub trait BodyLimit {
+    const NAME: &'static str;
+    fn of(options: &MarsConfigOptions) -> usize;
+}
+
+// arrays of small items, e.g. stat corrections
+pub struct BatchLimit;
+
+// bulk uploads like map loads, which legitimately carry the most
+pub struct ImportLimit;
+
+impl BodyLimit for BatchLimit {
+    const NAME: &'static str = "batch";
+
+    fn of(options: &MarsConfigOptions) -> usize {
+        options.body_limit_batch
+    }
+}
+
+impl BodyLimit for ImportLimit {
+    const NAME: &'static str = "import";
+
+    fn of(options: &MarsConfigOptions) -> usize {
+        options.body_limit_import
+    }
+}
+
+// rocket's limits with `body-limits.default` as `json` and the named limits alongside it
+pub fn limits(options: &MarsConfigOptions) -> Limits {
+    Limits::default()
+        .limit("json", options.body_limit_default.bytes())
+        .limit(BatchLimit::NAME, BatchLimit::of(options).bytes())
+        .limit(ImportLimit::NAME, ImportLimit::of(options).bytes())
+}
+
+// left in the request cache for the 413 catcher, which has no other way to know which limit was hit
+struct ExceededLimit(Option<usize>);
+
+// `Json` with its own limit. bodies past the limit are refused with a 413 instead of being cut short,
+// everything else gets the default `limits.json`, which is `body-limits.default`
+pub struct LimitedJson<T, L: BodyLimit> {
+    pub body: T,
+    limit: PhantomData<L>
+}
+
+impl<T, L: BodyLimit> LimitedJson<T, L> {
+    pub fn into_inner(self) -> T {
+        self.body
+    }
+}
+
+impl<T, L: BodyLimit> Deref for LimitedJson<T, L> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.body
+    }
+}
+
+pub enum LimitedJsonError {
+    TooLarge(usize),
+    Io(std::io::Error),
+    Parse(serde_json::Error)
+}
+
+impl std::fmt::Debug for LimitedJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge(limit) => write!(f, "Body Error: larger than {} bytes", limit),
+            Self::Io(e) => write!(f, "Body Error: {}", e),
+            Self::Parse(e) => write!(f, "Body Error: {}", e)
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned, L: BodyLimit> FromData<'r> for LimitedJson<T, L> {
+    type Error = LimitedJsonError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = req.limits().get(L::NAME).unwrap_or(Limits::JSON).as_u64() as usize;
+        let body = match data.open(limit.bytes()).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                req.local_cache(|| ExceededLimit(Some(limit)));
+                return data::Outcome::Error((Status::PayloadTooLarge, LimitedJsonError::TooLarge(limit)));
+            },
+            Err(e) => return data::Outcome::Error((Status::BadRequest, LimitedJsonError::Io(e)))
+        };
+        match serde_json::from_str::<T>(&body) {
+            Ok(body) => data::Outcome::Success(LimitedJson { body, limit: PhantomData }),
+            Err(e) => data::Outcome::Error((Status::UnprocessableEntity, LimitedJsonError::Parse(e)))
+        }
+    }
+}
+
+#[catch(413)]
+pub fn payload_too_large(req: &Request) -> ApiErrorResponder {
+    let limit = req.local_cache(|| ExceededLimit(None)).0
+        .or_else(|| req.limits().get("json").map(|limit| limit.as_u64() as usize));
+    let message = match limit {
+        Some(limit) => format!("The request body is larger than the {} byte limit for this endpoint", limit),
+        None => String::from("The request body is too large")
+    };
+    ApiErrorResponder::create_anonymous_error(Status::PayloadTooLarge, &message)
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::{http::{ContentType, Status}, local::asynchronous::Client, serde::json::Json};
+
+    use crate::config::MarsConfigOptions;
+
+    use super::{limits, payload_too_large, LimitedJson, BatchLimit};
+
+    #[post("/batch", data = "<body>")]
+    fn batch(body: LimitedJson<Vec<u32>, BatchLimit>) -> String {
+        body.len().to_string()
+    }
+
+    #[post("/plain", data = "<body>")]
+    fn plain(body: Json<Vec<u32>>) -> String {
+        body.len().to_string()
+    }
+
+    async fn client() -> Client {
+        let options = MarsConfigOptions { body_limit_default: 16, body_limit_batch: 64, ..Default::default() };
+        let config = rocket::Config::figment().merge(("limits", limits(&options)));
+        let rocket = rocket::custom(config).mount("/", routes![batch, plain]).register("/", catchers![payload_too_large]);
+        Client::untracked(rocket).await.unwrap()
+    }
+
+    fn numbers(count: usize) -> String {
+        format!("[{}]", vec!["1"; count].join(","))
+    }
+
+    #[rocket::async_test]
+    async fn bodies_over_their_limit_are_refused_with_413() {
+        let client = client().await;
+        // 41 bytes, over the default but within the batch limit
+        let body = numbers(20);
+        let response = client.post("/batch").header(ContentType::JSON).body(&body).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_string().await.unwrap(), "20");
+
+        let response = client.post("/batch").header(ContentType::JSON).body(numbers(40)).dispatch().await;
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        assert!(response.into_string().await.unwrap().contains("64 byte limit"));
+
+        let response = client.post("/plain").header(ContentType::JSON).body(&body).dispatch().await;
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        assert!(response.into_string().await.unwrap().contains("16 byte limit"));
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::Cursor;
+
+use rocket::{fairing::{Fairing, Info, Kind}, http::Header, Request, Response};
+use strum_macros::{Display, EnumString};
+
+use super::string::{deflate_string, gzip_string};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    Gzip,
+    Deflate
+}
+
+impl CompressionAlgorithm {
+    fn encode(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => gzip_string(data),
+            Self::Deflate => deflate_string(data)
+        }
+    }
+
+    fn other(&self) -> Self {
+        match self {
+            Self::Gzip => Self::Deflate,
+            Self::Deflate => Self::Gzip
+        }
+    }
+}
+
+// compresses sized JSON and text bodies over `threshold` bytes, streamed bodies are left alone so they stay streamed
+pub struct CompressionFairing {
+    pub threshold: usize,
+    pub preferred: CompressionAlgorithm
+}
+
+impl CompressionFairing {
+    // the preferred algorithm wins whenever the client accepts it, `q=0` counts as a refusal
+    fn negotiate(&self, accept_encoding: &str) -> Option<CompressionAlgorithm> {
+        let accepted : Vec<String> = accept_encoding.split(',').filter_map(|encoding| {
+            let mut parts = encoding.split(';');
+            let name = parts.next()?.trim().to_lowercase();
+            let refused = parts.filter_map(|param| param.trim().strip_prefix("q="))
+                .any(|quality| quality.trim().parse::<f32>().map(|quality| quality <= 0.0).unwrap_or(false));
+            if refused { None } else { Some(name) }
+        }).collect();
+        let accepts = |algorithm: CompressionAlgorithm| accepted.iter().any(|name| name == "*" || *name == algorithm.to_string());
+        if accepts(self.preferred) {
+            Some(self.preferred)
+        } else if accepts(self.preferred.other()) {
+            Some(self.preferred.other())
+        } else {
+            None
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for CompressionFairing {
+    fn info(&self) -> Info {
+        Info { name: "Response compression", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.headers().contains("Content-Encoding") {
+            return;
+        };
+        let compressible = response.content_type().map(|content_type| content_type.is_json() || content_type.top() == "text").unwrap_or(false);
+        let large = response.body().preset_size().map(|size| size >= self.threshold).unwrap_or(false);
+        if !compressible || !large {
+            return;
+        };
+        let algorithm = match request.headers().get_one("Accept-Encoding").and_then(|accept_encoding| self.negotiate(accept_encoding)) {
+            Some(algorithm) => algorithm,
+            None => return
+        };
+
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Could not read response body for compression: {}", e);
+                return;
+            }
+        };
+        match algorithm.encode(&body) {
+            Ok(compressed) => {
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+                response.set_header(Header::new("Content-Encoding", algorithm.to_string()));
+            },
+            Err(e) => {
+                warn!("Could not compress response body: {}", e);
+                response.set_sized_body(body.len(), Cursor::new(body));
+            }
+        };
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use mongodb::bson::{doc, Document};
+use rocket::{response::{self, Response, Responder}, Request, http::{Status, ContentType}, serde::json::Json};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{MarsAPIState, util::{error::ApiErrorResponder, time::get_u64_time_millis}};
+
+const CONFIRMATION_KEY_PREFIX : &str = "confirmation:";
+const CONFIRMATION_COLLECTION_NAME : &str = "confirmations";
+
+// what the first request of a destructive operation gets back, nothing has been changed yet
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfirmationRequired {
+    pub token: String,
+    pub operation: String,
+    // documents or entries the operation would touch, by what they are
+    pub effect: BTreeMap<String, u64>,
+    pub expires_at: u64
+}
+
+pub enum Confirmable<T> {
+    Pending(ConfirmationRequired),
+    Done(T)
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Confirmable<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let (response, status) = match self {
+            Confirmable::Pending(pending) => (Json(pending).respond_to(req)?, Status::Accepted),
+            Confirmable::Done(done) => (Json(done).respond_to(req)?, Status::Ok)
+        };
+        Response::build_from(response)
+            .header(ContentType::JSON)
+            .status(status)
+            .ok()
+    }
+}
+
+// `binding` names the operation and every parameter that changes what it does,
+// the token handed back is only accepted for that same binding
+pub async fn request_confirmation(state: &MarsAPIState, binding: &str, effect: BTreeMap<String, u64>) -> ConfirmationRequired {
+    let ttl = state.config.options.confirmation_ttl;
+    let token = Uuid::new_v4().simple().to_string();
+    let expires_at = get_u64_time_millis() + ttl;
+    if !state.redis.set_with_expiry(&format!("{}{}", CONFIRMATION_KEY_PREFIX, token), &binding, Some(ttl as usize)).await {
+        store_confirmation(state, &token, binding, expires_at).await;
+    };
+    ConfirmationRequired {
+        token,
+        operation: binding.to_owned(),
+        effect,
+        expires_at
+    }
+}
+
+// tokens that could not be written to redis are kept in mongo instead, so confirming keeps working while it is down
+async fn store_confirmation(state: &MarsAPIState, token: &str, binding: &str, expires_at: u64) {
+    let collection = state.database.mongo.collection::<Document>(CONFIRMATION_COLLECTION_NAME);
+    let _ = collection.delete_many(doc! { "expiresAt": { "$lt": get_u64_time_millis() as i64 } }, None).await;
+    if let Err(e) = collection.insert_one(doc! { "_id": token, "binding": binding, "expiresAt": expires_at as i64 }, None).await {
+        warn!("Could not store confirmation token for '{}': {}", binding, e);
+    };
+}
+
+// deleting the document is what consumes it, so this too only succeeds once per token
+async fn consume_stored_confirmation(state: &MarsAPIState, binding: &str, token: &str) -> bool {
+    let collection = state.database.mongo.collection::<Document>(CONFIRMATION_COLLECTION_NAME);
+    matches!(collection.find_one_and_delete(doc! {
+        "_id": token,
+        "binding": binding,
+        "expiresAt": { "$gt": get_u64_time_millis() as i64 }
+    }, None).await, Ok(Some(_)))
+}
+
+pub async fn consume_confirmation(state: &MarsAPIState, binding: &str, token: &str) -> Result<(), ApiErrorResponder> {
+    let key = format!("{}{}", CONFIRMATION_KEY_PREFIX, token);
+    let bound = state.redis.get_unchecked::<String>(&key).await;
+    if bound.is_none() && consume_stored_confirmation(state, binding, token).await {
+        return Ok(());
+    };
+    if bound.as_deref() != Some(binding) {
+        warn!("Rejected confirmation token for '{}', it was issued for {:?}", binding, bound);
+        return Err(ApiErrorResponder::validation_error_with_message("The confirmation token is invalid, expired, or was issued for a different operation"));
+    };
+    // only whoever deletes the key gets to run the operation, so a token cannot be used twice
+    match state.redis.delete_key(&key).await {
+        Ok(true) => Ok(()),
+        _ => Err(ApiErrorResponder::validation_error_with_message("The confirmation token has already been used"))
+    }
+}
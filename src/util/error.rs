@@ -2,6 +2,10 @@ use serde::{Serialize, Deserialize};
 use rocket::{response::{self, Response, Responder}, Request, http::{Status, ContentType}, serde::json::Json};
 use strum_macros::Display;
 
+use crate::database::decode::LoadError;
+
+use super::validation::FieldError;
+
 pub struct ApiErrorResponder {
     pub status: Status,
     pub error: ApiErrorV2
@@ -15,7 +19,7 @@ impl ApiErrorResponder {
     fn create_api_error_responder(status: Status, api_exception_type: &ApiExceptionType, message: &str) -> Self {
         ApiErrorResponder {
             status,
-            error: ApiErrorV2 { code: api_exception_type.to_owned(), message: String::from(message), error: true }
+            error: ApiErrorV2 { code: api_exception_type.to_owned(), message: String::from(message), error: true, fields: Vec::new() }
         }
     }
 
@@ -103,6 +107,16 @@ impl ApiErrorResponder {
         )
     }
 
+    pub fn field_validation_errors(fields: Vec<FieldError>) -> Self {
+        let mut responder = ApiErrorResponder::create_api_error_responder(
+            Status::BadRequest, 
+            &ApiExceptionType::ValidationError, 
+            &format!("{} field(s) are invalid", fields.len())
+        );
+        responder.error.fields = fields;
+        responder
+    }
+
     pub fn unauthorized() -> Self {
         ApiErrorResponder::create_api_error_responder(
             Status::Unauthorized, 
@@ -151,6 +165,62 @@ impl ApiErrorResponder {
         )
     }
 
+    pub fn tag_slot_incompatible() -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::BadRequest, 
+            &ApiExceptionType::TagSlotIncompatible, 
+            "The tag cannot be used in that slot"
+        )
+    }
+
+    pub fn tag_level_locked(min_level: u32, level: u32) -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::Forbidden, 
+            &ApiExceptionType::TagLevelLocked, 
+            &format!("The tag unlocks at level {} (currently {})", min_level, level)
+        )
+    }
+
+    pub fn report_missing() -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::NotFound, 
+            &ApiExceptionType::ReportMissing, 
+            "The report does not exist"
+        )
+    }
+
+    pub fn report_rate_limited() -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::TooManyRequests, 
+            &ApiExceptionType::ReportRateLimited, 
+            "You are submitting reports too quickly"
+        )
+    }
+
+    pub fn punishment_length_not_allowed(message: &str) -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::Forbidden, 
+            &ApiExceptionType::PunishmentLengthNotAllowed, 
+            message
+        )
+    }
+
+    pub fn punishment_conflict(message: &str) -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::Conflict, 
+            &ApiExceptionType::PunishmentConflict, 
+            message
+        )
+    }
+
+    pub fn prestige_below_cap(level: u32, cap: u32) -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::BadRequest, 
+            &ApiExceptionType::PrestigeBelowCap, 
+            &format!("Prestiging requires level {} (currently {})", cap, level)
+        )
+    }
+
     pub fn achievement_missing() -> Self {
         ApiErrorResponder::create_api_error_responder(
             Status::NotFound, 
@@ -166,6 +236,22 @@ impl ApiErrorResponder {
             "An achievement already exists with that name"
         )
     }
+
+    pub fn load_failed(e: &LoadError) -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::InternalServerError,
+            &ApiExceptionType::DocumentUnloadable, 
+            &e.to_string()
+        )
+    }
+
+    pub fn cache_unavailable() -> Self {
+        ApiErrorResponder::create_api_error_responder(
+            Status::ServiceUnavailable,
+            &ApiExceptionType::CacheUnavailable, 
+            "Leaderboards are temporarily unavailable"
+        )
+    }
 }
 
 impl<'r> Responder<'r, 'static> for ApiErrorResponder {
@@ -183,7 +269,9 @@ impl<'r> Responder<'r, 'static> for ApiErrorResponder {
 pub struct ApiErrorV2 {
     code: ApiExceptionType,
     message: String,
-    error: bool
+    error: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<FieldError>
 }
 
 #[derive(Responder)]
@@ -230,10 +318,19 @@ enum ApiExceptionType {
     TagMissing,
     TagAlreadyPresent,
     TagNotPresent,
+    TagSlotIncompatible,
+    TagLevelLocked,
     MapMissing,
     AchievementConflict,
     AchievementMising,
     PunishmentMissing,
+    PunishmentLengthNotAllowed,
+    PunishmentConflict,
     NoteMissing,
+    ReportMissing,
+    ReportRateLimited,
+    PrestigeBelowCap,
+    CacheUnavailable,
+    DocumentUnloadable,
     Anonymous
 }
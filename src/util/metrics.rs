@@ -0,0 +1,109 @@
+use std::{collections::BTreeMap, fmt::Write, sync::{Arc, Mutex, atomic::{AtomicI64, AtomicU64, Ordering}}, time::Duration};
+
+use mongodb::event::command::{CommandEventHandler, CommandFailedEvent};
+
+// counters since the API started, rendered in the prometheus text format by `/metrics`. shared through
+// the redis adapter, which the caches and leaderboards already hold, and the api state for everything else
+#[derive(Default)]
+pub struct Metrics {
+    events: LabeledCounter,
+    rejected_events: LabeledCounter,
+    cache_hits: LabeledCounter,
+    cache_misses: LabeledCounter,
+    redis_errors: AtomicU64,
+    mongo_errors: AtomicU64,
+    leaderboard_writes: AtomicU64,
+    leaderboard_write_micros: AtomicU64,
+    connections: AtomicI64
+}
+
+#[derive(Default)]
+struct LabeledCounter(Mutex<BTreeMap<String, u64>>);
+
+impl LabeledCounter {
+    fn increment(&self, label: &str) {
+        let mut counts = match self.0.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner()
+        };
+        *counts.entry(label.to_owned()).or_insert(0) += 1;
+    }
+
+    fn snapshot(&self) -> BTreeMap<String, u64> {
+        match self.0.lock() {
+            Ok(counts) => counts.clone(),
+            Err(poisoned) => poisoned.into_inner().clone()
+        }
+    }
+}
+
+impl Metrics {
+    pub fn record_event(&self, event: &str) {
+        self.events.increment(event);
+    }
+
+    // `reason` is a short fixed name, e.g. `clock_skew`, never anything taken from the event
+    pub fn record_rejected_event(&self, reason: &str) {
+        self.rejected_events.increment(reason);
+    }
+
+    pub fn record_cache_lookup(&self, resource: &str, hit: bool) {
+        if hit { self.cache_hits.increment(resource) } else { self.cache_misses.increment(resource) };
+    }
+
+    pub fn record_redis_error(&self) {
+        self.redis_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_leaderboard_write(&self, took: Duration) {
+        self.leaderboard_writes.fetch_add(1, Ordering::Relaxed);
+        self.leaderboard_write_micros.fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn connection_opened(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        render_labeled(&mut out, "mars_events_processed_total", "Socket events routed, by event type", "event", &self.events.snapshot());
+        render_labeled(&mut out, "mars_events_rejected_total", "Socket events dropped before or while routing, by reason", "reason", &self.rejected_events.snapshot());
+        render_labeled(&mut out, "mars_cache_hits_total", "Cache lookups answered by redis, by resource", "resource", &self.cache_hits.snapshot());
+        render_labeled(&mut out, "mars_cache_misses_total", "Cache lookups that fell through to mongo, by resource", "resource", &self.cache_misses.snapshot());
+        render_single(&mut out, "mars_redis_errors_total", "Redis operations that failed or were turned away", "counter", self.redis_errors.load(Ordering::Relaxed) as f64);
+        render_single(&mut out, "mars_mongo_errors_total", "Mongo commands that failed", "counter", self.mongo_errors.load(Ordering::Relaxed) as f64);
+        let _ = writeln!(out, "# HELP mars_leaderboard_write_seconds Time spent writing leaderboard increments to redis");
+        let _ = writeln!(out, "# TYPE mars_leaderboard_write_seconds summary");
+        let _ = writeln!(out, "mars_leaderboard_write_seconds_sum {}", self.leaderboard_write_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+        let _ = writeln!(out, "mars_leaderboard_write_seconds_count {}", self.leaderboard_writes.load(Ordering::Relaxed));
+        render_single(&mut out, "mars_server_connections", "Game servers connected to the socket", "gauge", self.connections.load(Ordering::Relaxed) as f64);
+        out
+    }
+}
+
+fn render_single(out: &mut String, name: &str, help: &str, kind: &str, value: f64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn render_labeled(out: &mut String, name: &str, help: &str, label: &str, counts: &BTreeMap<String, u64>) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for (value, count) in counts.iter() {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value.replace('\\', "\\\\").replace('"', "\\\""), count);
+    };
+}
+
+// handed to the mongo client, so every failed command is counted wherever it came from
+pub struct MongoCommandMetrics(pub Arc<Metrics>);
+
+impl CommandEventHandler for MongoCommandMetrics {
+    fn handle_command_failed_event(&self, _event: CommandFailedEvent) {
+        self.0.mongo_errors.fetch_add(1, Ordering::Relaxed);
+    }
+}
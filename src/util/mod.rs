@@ -7,3 +7,9 @@ pub mod time;
 pub mod r#macro;
 pub mod responder;
 pub mod webhook;
+pub mod compression;
+pub mod weapon;
+pub mod tracking;
+pub mod confirmation;
+pub mod body_limit;
+pub mod metrics;
@@ -1,6 +1,8 @@
 use serde::{Serialize, Deserialize};
 use rocket::{response::{self, Response, Responder}, Request, http::{Status, ContentType}, serde::json::Json};
 
+use crate::database::decode::{DecodeFailure, LoadedDocuments};
+
 pub struct JsonResponder<T> {
     pub response: T,
     pub status: Status
@@ -32,3 +34,70 @@ impl<'r, T: Serialize> Responder<'r, 'static> for JsonResponder<T> {
             .ok()
     }
 }
+
+const MAX_SKIPPED_IDS : usize = 20;
+
+// wraps a list response with how many items exist in total and how many were sent, as
+// `X-Total-Count` and `X-Returned-Count`. the body is left as it is
+pub struct ListResponder<R> {
+    pub inner: R,
+    pub total: u64,
+    pub returned: usize,
+    // documents that could not be deserialized, sent as `X-Skipped-Count` and `X-Skipped-Ids`
+    pub skipped: Vec<DecodeFailure>
+}
+
+impl<R> ListResponder<R> {
+    pub fn new(inner: R, returned: usize, total: u64) -> Self {
+        Self { inner, total, returned, skipped: Vec::new() }
+    }
+
+    pub fn skipping(mut self, failures: Vec<DecodeFailure>) -> Self {
+        self.skipped = failures;
+        self
+    }
+}
+
+impl<T: Serialize> ListResponder<Json<Vec<T>>> {
+    // everything there is, skipped documents count toward the total
+    pub fn loaded(loaded: LoadedDocuments<T>) -> Self {
+        let returned = loaded.documents.len();
+        ListResponder::new(Json(loaded.documents), returned, (returned + loaded.failures.len()) as u64).skipping(loaded.failures)
+    }
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for ListResponder<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build_from(self.inner.respond_to(req)?);
+        response
+            .raw_header("X-Total-Count", self.total.to_string())
+            .raw_header("X-Returned-Count", self.returned.to_string());
+        if !self.skipped.is_empty() {
+            let ids : Vec<&str> = self.skipped.iter().filter_map(|failure| failure.id.as_deref()).take(MAX_SKIPPED_IDS).collect();
+            response
+                .raw_header("X-Skipped-Count", self.skipped.len().to_string())
+                .raw_header("X-Skipped-Ids", ids.join(","));
+        };
+        response.ok()
+    }
+}
+
+// lets clients and proxies reuse a response for `max_age` seconds
+pub struct CachedResponder<R> {
+    pub inner: R,
+    pub max_age: u64
+}
+
+impl<R> CachedResponder<R> {
+    pub fn new(inner: R, max_age: u64) -> Self {
+        Self { inner, max_age }
+    }
+}
+
+impl<'r, R: Responder<'r, 'static>> Responder<'r, 'static> for CachedResponder<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build_from(self.inner.respond_to(req)?)
+            .raw_header("Cache-Control", format!("public, max-age={}", self.max_age))
+            .ok()
+    }
+}
@@ -1,6 +1,6 @@
 use std::io::Write;
 
-use flate2::{write::ZlibEncoder, Compression};
+use flate2::{write::{GzEncoder, ZlibEncoder}, Compression};
 
 pub fn to_utf8_byte_array(text: &String) -> &[u8] {
     text.as_bytes()
@@ -12,3 +12,69 @@ pub fn deflate_string(data: &[u8]) -> anyhow::Result<Vec<u8>> {
     encoder.write_all(data)?;
     Ok(encoder.finish()?)
 }
+
+pub fn gzip_string(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::new(6));
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+// for user input placed in a mongo `$regex`, every character it would read as syntax is escaped
+pub fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}-/#".contains(c) {
+            escaped.push('\\');
+        };
+        escaped.push(c);
+    };
+    escaped
+}
+
+// drops `&x` and `§x` formatting codes, for checking what readers actually see. only valid codes
+// are dropped, so an `&` in plain text like "Tom & Jerry" stays
+pub fn strip_color_codes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if (c == '&' || c == '§') && chars.peek().is_some_and(|code| is_format_code(*code)) {
+            chars.next();
+            continue;
+        };
+        stripped.push(c);
+    };
+    stripped
+}
+
+fn is_format_code(code: char) -> bool {
+    matches!(code.to_ascii_lowercase(), '0'..='9' | 'a'..='f' | 'k'..='o' | 'r')
+}
+
+// levenshtein distance by chars, case-sensitive
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b : Vec<char> = b.chars().collect();
+    let mut previous : Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        };
+        std::mem::swap(&mut previous, &mut current);
+    };
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_valid_format_codes_are_stripped() {
+        assert_eq!(strip_color_codes("&cHacking &lfly"), "Hacking fly");
+        assert_eq!(strip_color_codes("§4red §Rreset"), "red reset");
+        assert_eq!(strip_color_codes("Tom & Jerry"), "Tom & Jerry");
+        assert_eq!(strip_color_codes("&x &z &"), "&x &z &");
+    }
+}
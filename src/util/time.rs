@@ -1,5 +1,158 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fmt, time::{SystemTime, UNIX_EPOCH}};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, Visitor}};
+use strum_macros::EnumString;
 
 pub fn get_u64_time_millis() -> u64 {
     u64::try_from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis()).unwrap_or(u64::MAX)
 }
+
+// smallest unit formatted playtimes are rounded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum PlaytimeRounding {
+    Seconds,
+    Minutes,
+    Hours
+}
+
+// whether playtimes that look like they were written in seconds are scaled up at startup, see `Database::migrate_playtime_seconds`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "kebab-case", ascii_case_insensitive)]
+pub enum PlaytimeSecondsMigration {
+    Off,
+    DryRun,
+    Apply
+}
+
+impl PlaytimeRounding {
+    fn unit_millis(&self) -> u64 {
+        match self {
+            Self::Seconds => 1_000,
+            Self::Minutes => 60_000,
+            Self::Hours => 3_600_000
+        }
+    }
+}
+
+// playtimes are stored in milliseconds, this rounds to the nearest `rounding` unit and renders
+// days down to that unit, e.g. `2d 3h 15m`. zero parts are left out
+pub fn format_playtime(millis: u64, rounding: PlaytimeRounding) -> String {
+    let unit = rounding.unit_millis();
+    let rounded = millis.saturating_add(unit / 2) / unit * unit;
+    let parts = [("d", 86_400_000), ("h", 3_600_000), ("m", 60_000), ("s", 1_000)];
+    let mut remaining = rounded;
+    let mut formatted : Vec<String> = Vec::new();
+    for (suffix, part_millis) in parts.iter().filter(|(_, part_millis)| *part_millis >= unit) {
+        let count = remaining / part_millis;
+        remaining %= part_millis;
+        if count > 0 {
+            formatted.push(format!("{}{}", count, suffix));
+        };
+    };
+    if formatted.is_empty() {
+        let (suffix, _) = parts.iter().find(|(_, part_millis)| *part_millis == unit).unwrap_or(&("m", 60_000));
+        return format!("0{}", suffix);
+    };
+    formatted.join(" ")
+}
+
+// epoch millis. older documents stored these as doubles, any numeric form is read back and they are
+// always written as integers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(get_u64_time_millis())
+    }
+
+    pub fn from_millis(millis: u64) -> Self {
+        Timestamp(millis)
+    }
+
+    pub fn millis(self) -> u64 {
+        self.0
+    }
+
+    // `None` past the end of what chrono can represent
+    pub fn to_datetime(self) -> Option<DateTime<Utc>> {
+        Utc.timestamp_millis_opt(i64::try_from(self.0).ok()?).single()
+    }
+
+    pub fn millis_since(self, earlier: Timestamp) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl From<u64> for Timestamp {
+    fn from(millis: u64) -> Self {
+        Timestamp(millis)
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // bson has no unsigned integers, every realistic timestamp fits in an i64
+        serializer.serialize_i64(i64::try_from(self.0).unwrap_or(i64::MAX))
+    }
+}
+
+struct TimestampVisitor;
+
+impl<'de> Visitor<'de> for TimestampVisitor {
+    type Value = Timestamp;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a millisecond timestamp")
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Timestamp, E> {
+        Ok(Timestamp(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Timestamp, E> {
+        Ok(Timestamp(u64::try_from(value).unwrap_or(0)))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Timestamp, E> {
+        if !value.is_finite() || value < 0.0 {
+            return Ok(Timestamp(0));
+        };
+        Ok(Timestamp(value.round() as u64))
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TimestampVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mongodb::bson::{self, Bson};
+
+    use super::*;
+
+    #[test]
+    fn out_of_range_timestamps_are_clamped() {
+        assert_eq!(bson::to_bson(&Timestamp::from_millis(u64::MAX)).unwrap(), Bson::Int64(i64::MAX));
+        assert_eq!(bson::from_bson::<Timestamp>(Bson::Int64(-1)).unwrap(), Timestamp::from_millis(0));
+        assert_eq!(bson::from_bson::<Timestamp>(Bson::Double(-5.0)).unwrap(), Timestamp::from_millis(0));
+        assert_eq!(bson::from_bson::<Timestamp>(Bson::Double(f64::NAN)).unwrap(), Timestamp::from_millis(0));
+        assert_eq!(bson::from_bson::<Timestamp>(Bson::Double(1e30)).unwrap(), Timestamp::from_millis(u64::MAX));
+        assert_eq!(Timestamp::from_millis(u64::MAX).to_datetime(), None);
+        assert_eq!(Timestamp::from_millis(5).millis_since(Timestamp::from_millis(10)), 0);
+    }
+
+    #[test]
+    fn stored_numbers_read_back_as_the_same_millis() {
+        let millis = 1_700_000_000_123;
+        assert_eq!(bson::from_bson::<Timestamp>(Bson::Double(millis as f64)).unwrap().millis(), millis);
+        assert_eq!(bson::from_bson::<Timestamp>(Bson::Int64(millis as i64)).unwrap().millis(), millis);
+        assert_eq!(bson::to_bson(&Timestamp::from_millis(millis)).unwrap(), Bson::Int64(millis as i64));
+        assert_eq!(Timestamp::from_millis(millis).to_datetime().map(|datetime| datetime.timestamp_millis()), Some(millis as i64));
+    }
+}
@@ -0,0 +1,70 @@
+use std::{collections::HashMap, str::FromStr};
+
+use serde::{Serialize, Deserialize};
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
+
+use crate::database::models::level::LevelGamemode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display, EnumString, EnumIter)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum StatCategory {
+    Xp,
+    // wool, flag, point, core and destroyable stats
+    Objectives,
+    // kills, deaths, assists, first bloods, killstreaks and weapon stats
+    Kills,
+    // personal and map records
+    Records,
+    // playtime, matches played, results, messages and blocks
+    Activity
+}
+
+// which stat categories each gamemode credits, gamemodes that are not configured credit everything.
+// by default arcade only credits xp, which is what the old match-wide tracking check did
+#[derive(Debug, Clone)]
+pub struct StatTracking {
+    gamemodes: HashMap<LevelGamemode, Vec<StatCategory>>
+}
+
+impl Default for StatTracking {
+    fn default() -> Self {
+        let mut gamemodes = HashMap::new();
+        gamemodes.insert(LevelGamemode::Arcade, vec![StatCategory::Xp]);
+        StatTracking { gamemodes }
+    }
+}
+
+impl StatTracking {
+    // `categories` is a comma separated list of category names, `none` or an empty value credits nothing
+    pub fn insert(&mut self, gamemode_key: &str, categories: &str) {
+        let gamemode = LevelGamemode::from_key(&gamemode_key.trim().to_uppercase());
+        let categories = categories.split(',')
+            .map(|category| category.trim())
+            .filter(|category| !category.is_empty() && !category.eq_ignore_ascii_case("none"))
+            .filter_map(|category| match StatCategory::from_str(category) {
+                Ok(category) => Some(category),
+                Err(_) => {
+                    warn!("Unknown stat category '{}' for gamemode {}", category, gamemode.key());
+                    None
+                }
+            })
+            .collect();
+        self.gamemodes.insert(gamemode, categories);
+    }
+
+    pub fn categories_for(&self, gamemode: &LevelGamemode) -> Vec<StatCategory> {
+        match self.gamemodes.get(gamemode) {
+            Some(categories) => categories.clone(),
+            None => StatCategory::iter().collect()
+        }
+    }
+
+    // a level with several gamemodes only credits what every one of them credits
+    pub fn tracked(&self, gamemodes: &[LevelGamemode]) -> Vec<StatCategory> {
+        StatCategory::iter().filter(|category| {
+            gamemodes.iter().all(|gamemode| self.categories_for(gamemode).contains(category))
+        }).collect()
+    }
+}
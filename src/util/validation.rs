@@ -1,5 +1,9 @@
 use std::fmt::Display;
 
+use serde::{Deserialize, Serialize};
+
+use super::error::ApiErrorResponder;
+
 pub fn verbose_result_ok<T, E: Display>(context: String, result: Result<T, E>) -> Option<T> {
     match result {
         Ok(t) => Some(t),
@@ -8,4 +12,61 @@ pub fn verbose_result_ok<T, E: Display>(context: String, result: Result<T, E>) -
             None
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    pub field: String,
+    pub message: String
+}
+
+const COLOR_CODE_PREFIXES : [char; 2] = ['&', '§'];
+const COLOR_CODES : &str = "0123456789abcdefklmnor";
+
+// collects every failed check so a request is rejected once with all of its bad fields
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(&mut self, field: &str, valid: bool, message: &str) -> &mut Self {
+        if !valid {
+            self.errors.push(FieldError { field: field.to_owned(), message: message.to_owned() });
+        };
+        self
+    }
+
+    pub fn not_blank(&mut self, field: &str, value: &str) -> &mut Self {
+        self.check(field, !value.trim().is_empty(), "must not be blank")
+    }
+
+    pub fn max_length(&mut self, field: &str, value: &str, max: usize) -> &mut Self {
+        self.check(field, value.chars().count() <= max, &format!("must be at most {} characters", max))
+    }
+
+    // every `&` or `§` has to be followed by a valid minecraft formatting code
+    pub fn color_codes(&mut self, field: &str, value: &str) -> &mut Self {
+        let mut chars = value.chars();
+        let mut valid = true;
+        while let Some(c) = chars.next() {
+            if COLOR_CODE_PREFIXES.contains(&c) {
+                valid &= chars.next().map(|code| COLOR_CODES.contains(code.to_ascii_lowercase())).unwrap_or(false);
+            };
+        };
+        self.check(field, valid, "contains an invalid color code")
+    }
+
+    pub fn finish(&mut self) -> Result<(), ApiErrorResponder> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiErrorResponder::field_validation_errors(std::mem::take(&mut self.errors)))
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+// display names for the raw material keys weapon stats are stored under. raw keys that map to the
+// same display name (e.g. enchanted and plain variants) are counted as one weapon when read
+#[derive(Debug, Clone, Default)]
+pub struct WeaponNames {
+    names: HashMap<String, String>
+}
+
+impl WeaponNames {
+    pub fn insert(&mut self, raw: &str, display_name: &str) {
+        let display_name = display_name.trim();
+        if !display_name.is_empty() {
+            self.names.insert(raw.trim().to_uppercase(), display_name.to_owned());
+        };
+    }
+
+    // unmapped keys fall back to title case, `DIAMOND_SWORD` becomes `Diamond Sword`
+    pub fn display_name(&self, raw: &str) -> String {
+        if let Some(display_name) = self.names.get(&raw.to_uppercase()) {
+            return display_name.clone();
+        };
+        let title_cased = raw.split(['_', ' ']).filter(|word| !word.is_empty()).map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars.flat_map(|c| c.to_lowercase())).collect::<String>(),
+                None => String::new()
+            }
+        }).collect::<Vec<String>>().join(" ");
+        if title_cased.is_empty() { String::from("Unknown") } else { title_cased }
+    }
+
+    pub fn collapse(&self, counts: &HashMap<String, u32>) -> HashMap<String, u32> {
+        let mut collapsed : HashMap<String, u32> = HashMap::new();
+        for (raw, count) in counts.iter() {
+            *collapsed.entry(self.display_name(raw)).or_insert(0) += count;
+        };
+        collapsed
+    }
+
+    // ranked after collapsing, so variants of one weapon count together
+    pub fn top(&self, counts: &HashMap<String, u32>, n: usize) -> Vec<(String, u32)> {
+        top_counts(&self.collapse(counts), n)
+    }
+}
+
+// highest counts first, ties by key so the order is the same on every request
+pub fn top_counts(counts: &HashMap<String, u32>, n: usize) -> Vec<(String, u32)> {
+    let mut ranked : Vec<(String, u32)> = counts.iter().map(|(key, count)| (key.clone(), *count)).collect();
+    ranked.sort_by(|(key_a, count_a), (key_b, count_b)| count_b.cmp(count_a).then_with(|| key_a.cmp(key_b)));
+    ranked.truncate(n);
+    ranked
+}
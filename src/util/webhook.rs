@@ -3,13 +3,14 @@
 use std::error::Error;
 
 use anyhow::anyhow;
-use crate::database::models::{player::SimplePlayer, punishment::{Punishment, StaffNote}};
+use crate::{config::AvatarSource, database::models::{player::SimplePlayer, punishment::{Punishment, StaffNote}}};
 use serde::Serialize;
 
 pub struct WebhookUtils {
     pub reports_webhook_client: Option<WebhookClient>,
     pub punishments_webhook_client: Option<WebhookClient>,
-    pub notes_webhook_client: Option<WebhookClient>
+    pub notes_webhook_client: Option<WebhookClient>,
+    pub avatars: AvatarSource
 }
 
 impl WebhookUtils {
@@ -18,11 +19,13 @@ impl WebhookUtils {
     const COLOR_PUNISHMENT_REVERTED : u32 = 0x00FF4C;
     const COLOR_NEW_NOTE : u32 = 0xFF77FF;
     const COLOR_DEL_NOTE : u32 = 0xFF4F55;
+    const COLOR_EVASION_SUSPECT : u32 = 0xFF9900;
 
     pub fn new(
         reports_webhook_url: &Option<String>, 
         punishments_webhook_url: &Option<String>,
-        notes_webhook_url: &Option<String>
+        notes_webhook_url: &Option<String>,
+        avatars: AvatarSource
     ) -> Self {
         Self {
             reports_webhook_client: reports_webhook_url.as_ref().map(|url| {
@@ -33,7 +36,8 @@ impl WebhookUtils {
             }),
             notes_webhook_client: notes_webhook_url.as_ref().map(|url| {
                 WebhookClient { url: url.to_owned(), client: reqwest::Client::new() }
-            })
+            }),
+            avatars
         }
     }
 
@@ -50,10 +54,10 @@ impl WebhookUtils {
             embed
                 .color(Self::COLOR_NEW_REPORT)
                 .title(format!("New report (on {})", server_id))
-                .thumbnail(target.get_mini_icon_url())
+                .thumbnail(target.get_mini_icon_url(&self.avatars))
                 .footer(DiscordEmbedFooter { 
                     text: format!("Reported by {}", &reporter.name), 
-                    icon_url: Some(reporter.get_mini_icon_url())
+                    icon_url: Some(reporter.get_mini_icon_url(&self.avatars))
                 })
                 .add_field(
                     DiscordEmbedField { 
@@ -99,7 +103,7 @@ impl WebhookUtils {
                     text: format!("Pun ID: {}", punishment.id), 
                     icon_url: None 
                 })
-                .thumbnail(punishment.target.get_mini_icon_url())
+                .thumbnail(punishment.target.get_mini_icon_url(&self.avatars))
                 .add_field(
                     DiscordEmbedField { 
                         name: String::from("Target"), 
@@ -112,6 +116,7 @@ impl WebhookUtils {
                         name: String::from("Staff"), 
                         value: 
                             if let Some(punisher) = &punishment.punisher { punisher.name.to_owned() } 
+                            else if punishment.automated { String::from("Automatic (needs review)") }
                             else { String::from("Console") },
                         inline: true 
                     }
@@ -161,7 +166,7 @@ impl WebhookUtils {
                             text: format!("Pun ID: {}", punishment.id), 
                             icon_url: None 
                         })
-                        .thumbnail(punishment.target.get_mini_icon_url())
+                        .thumbnail(punishment.target.get_mini_icon_url(&self.avatars))
                         .add_field(
                             DiscordEmbedField { 
                                 name: String::from("Target"), 
@@ -218,7 +223,7 @@ impl WebhookUtils {
                     text: format!("Player note ID: {}", note.id), 
                     icon_url: None 
                 })
-                .thumbnail(player.get_mini_icon_url())
+                .thumbnail(player.get_mini_icon_url(&self.avatars))
                 .add_field(
                     DiscordEmbedField { 
                         name: String::from("Target"), 
@@ -260,7 +265,7 @@ impl WebhookUtils {
                     text: format!("Player note ID: {}", note.id), 
                     icon_url: None 
                 })
-                .thumbnail(player.get_mini_icon_url())
+                .thumbnail(player.get_mini_icon_url(&self.avatars))
                 .add_field(
                     DiscordEmbedField { 
                         name: String::from("Target"), 
@@ -288,6 +293,41 @@ impl WebhookUtils {
         }
     }
 
+    // sent to the punishments channel, nothing is done to the player until staff look at it
+    pub async fn send_evasion_suspect_webhook(
+        &self,
+        server_id: &String,
+        player: &SimplePlayer,
+        similar_banned: &[SimplePlayer]
+    ) {
+        if let Some(punishments_client) = &self.punishments_webhook_client {
+            let mut embed = DiscordEmbed::default();
+            embed
+                .color(Self::COLOR_EVASION_SUSPECT)
+                .title(format!("Possible ban evasion (on {})", server_id))
+                .description(String::from("Name similarity only, check before punishing"))
+                .thumbnail(player.get_mini_icon_url(&self.avatars))
+                .add_field(
+                    DiscordEmbedField {
+                        name: String::from("Player"),
+                        value: escape_markdown(&player.name, false),
+                        inline: true
+                    }
+                )
+                .add_field(
+                    DiscordEmbedField {
+                        name: String::from("Similar to banned"),
+                        value: similar_banned
+                            .iter()
+                            .map(|banned| escape_markdown(&banned.name, false))
+                            .collect::<Vec<String>>()
+                            .join("\n"),
+                        inline: true
+                    }
+                );
+            let _ = punishments_client.send(WebhookMessage::default().add_embed(embed)).await;
+        }
+    }
 }
 
 fn escape_markdown(s: &String, html_mode: bool) -> String {